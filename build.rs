@@ -0,0 +1,83 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Build script: records build metadata shown by "minipng --version --verbose".
+// MINIPNG_GIT_HASH is the short commit hash (or "unknown" outside a git checkout),
+// MINIPNG_BUILD_EPOCH the build time in seconds since the Unix epoch (SOURCE_DATE_EPOCH when set, for reproducible builds),
+// and MINIPNG_OXIPNG_VERSION / MINIPNG_IMAGE_VERSION the versions locked in Cargo.lock.
+// Cargo.lock isn't committed, but cargo writes it next to Cargo.toml while resolving dependencies, before build scripts run.
+// Where there is none (e.g. the package is built from outside its own directory), both versions are reported as "unknown".
+
+fn main()
+{
+	let manifest_dir: PathBuf = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap_or_default());
+	
+	println!("cargo:rustc-env=MINIPNG_GIT_HASH={}", git_short_hash(&manifest_dir));
+	println!("cargo:rustc-env=MINIPNG_BUILD_EPOCH={}", build_epoch());
+	
+	let lock: String = std::fs::read_to_string(manifest_dir.join("Cargo.lock")).unwrap_or_default();
+	println!("cargo:rustc-env=MINIPNG_OXIPNG_VERSION={}", locked_version(&lock, "oxipng"));
+	println!("cargo:rustc-env=MINIPNG_IMAGE_VERSION={}", locked_version(&lock, "image"));
+	
+	// Rebuild the metadata when the checked out commit or the lock file changes.
+	// Only existing paths are watched, a missing one would make cargo rerun the script on every build.
+	println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+	let watched: [PathBuf; 3] = [manifest_dir.join("Cargo.lock"), manifest_dir.join(".git/HEAD"), git_head_ref(&manifest_dir)];
+	for path in &watched
+	{
+		if path.is_file()
+		{
+			println!("cargo:rerun-if-changed={}", path.display());
+		}
+	}
+}
+
+/// Short hash of the checked out commit, or "unknown" if git or the repository isn't available.
+fn git_short_hash(manifest_dir: &Path) -> String
+{
+	let output: Option<std::process::Output> = Command::new("git").args(["rev-parse", "--short", "HEAD"]).current_dir(manifest_dir).output().ok();
+	match output
+	{
+		Some(output) if output.status.success() => String::from_utf8_lossy(&output.stdout).trim().to_string(),
+		_ => "unknown".to_string(),
+	}
+}
+
+/// File of the branch HEAD points to (e.g. .git/refs/heads/main), so new commits trigger a rebuild.
+fn git_head_ref(manifest_dir: &Path) -> PathBuf
+{
+	let head: String = std::fs::read_to_string(manifest_dir.join(".git/HEAD")).unwrap_or_default();
+	match head.trim().strip_prefix("ref: ")
+	{
+		Some(reference) => manifest_dir.join(".git").join(reference),
+		None => PathBuf::new(),
+	}
+}
+
+/// Build time in seconds since the Unix epoch, taken from SOURCE_DATE_EPOCH when it is set.
+fn build_epoch() -> u64
+{
+	if let Ok(epoch) = std::env::var("SOURCE_DATE_EPOCH") && let Ok(seconds) = epoch.trim().parse::<u64>()
+	{
+		return seconds;
+	}
+	
+	SystemTime::now().duration_since(UNIX_EPOCH).map(|duration| duration.as_secs()).unwrap_or(0)
+}
+
+/// Version of a package in Cargo.lock, or "unknown" if it isn't listed.
+fn locked_version(lock: &str, package: &str) -> String
+{
+	let name_line: String = format!("name = \"{}\"", package);
+	let mut lines: std::str::Lines = lock.lines();
+	while let Some(line) = lines.next()
+	{
+		if line.trim() == name_line && let Some(version) = lines.next().and_then(|next| next.trim().strip_prefix("version = \""))
+		{
+			return version.trim_end_matches('"').to_string();
+		}
+	}
+	
+	"unknown".to_string()
+}
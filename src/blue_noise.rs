@@ -0,0 +1,313 @@
+// Blue-noise threshold texture for ordered-style dithering.
+// Unlike the Bayer matrix, blue noise has no regular structure, so it avoids the visible cross-hatch pattern.
+// Generated with the void-and-cluster method (Ulichney, 1993) on a 64x64 torus, so it tiles seamlessly.
+
+/// Width and height of the blue-noise texture.
+pub const BLUE_NOISE_SIZE: usize = 64;
+
+/// Blue-noise ranks scaled to 0-255 (each value appears 16 times).
+static BLUE_NOISE_MATRIX: [[u8; BLUE_NOISE_SIZE]; BLUE_NOISE_SIZE] =
+[
+	[
+		 52, 210, 190,  39,  96, 198,  76, 121, 193,  46, 165,  16,  38,  61, 215, 108, 203,  76, 139,  39,  82, 109,  59,  22, 180, 107, 158,  86, 132,  69,  33,  92,
+		 55,  18, 102, 234, 168,  81,   3, 202,  96, 192,  72,  22, 206,  34, 218,  18, 122, 231, 142, 105, 205,  26, 251, 138,  36, 108, 205, 180, 246, 106, 167, 234,
+	],
+	[
+		129, 145,  70, 245, 151, 168,  22, 226,   5, 178, 113, 148, 196, 127, 179,  45,  19, 120, 223, 153, 178, 164, 244,  92, 232,   4, 192, 255,  43, 203, 237, 120,
+		173, 198, 218, 187, 115, 145,  47, 108, 253, 138, 175, 237,  86, 149,  59, 254,  94, 179,  64,   1,  44, 229,  98, 159, 216, 241,  75,  48,  88,  65,   7, 201,
+	],
+	[
+		 23, 103, 222, 117,  29,  60, 131, 210, 101,  71, 246,  85, 225,   0, 142,  68, 236, 192,  55,  99,   2, 208,  47, 119, 138,  65,  28, 116, 175, 102, 156,   4,
+		245,  44,  85,  30,  66, 208, 230, 128,  27,  57,   9, 113,  45, 127, 196,  77, 155,  31, 241, 188, 148,  70, 196,  82,  23, 122,   2, 153, 222,  38, 176,  93,
+	],
+	[
+		 61, 163,  10, 177,  83, 230, 184,  42, 159,  31, 203,  52, 170, 102, 254, 157,  92, 169,  29, 239,  69, 128, 195,  34, 162, 202, 221, 146,  15,  60, 215,  78,
+		142, 111, 159, 130, 248,  15,  89, 183,  76, 213, 157, 223, 187, 243,   4, 171,  48, 212, 130,  87, 114, 174,  12, 133,  59, 166, 199, 236, 139, 124, 212, 253,
+	],
+	[
+		188,  42, 242, 205,  50, 109, 251,  89, 143, 236, 134, 118,  22,  76,  35, 207,  12, 134, 112, 213, 146,  19, 229,  80, 248,  99,  51,  83, 233, 188, 129,  25,
+		183, 228,  11,  57, 195, 151,  38, 169, 241, 143,  91,  65,  19,  97, 135, 230, 103,  21, 164,  55, 249,  35, 220, 235, 187,  91, 112,  29, 186,  17,  79, 148,
+	],
+	[
+		231, 122,  90, 139, 156,   1, 125,  18, 188,  62,   9, 215, 192, 230, 181, 122,  60, 245,  46, 185,  88, 174, 106, 152,   8, 181, 125, 166,  36,  96, 250,  49,
+		 67, 205,  94, 172, 219, 101, 118,  52,   4, 194,  35, 123, 164, 209,  38,  64, 116, 198, 226,   7, 208, 126, 102,  50, 145, 254,  42,  69, 101, 161,  51, 110,
+	],
+	[
+		 30,  74,  16, 196,  65, 214, 174,  75, 222, 107, 164,  94, 150,  47, 139,  86, 221, 194,  77,  10, 253,  40,  61, 216,  24,  73, 241, 211,   2, 141, 201, 164,
+		106, 242,  37, 136,  73,  24, 232, 210, 132, 104, 228, 251, 179,  80, 151, 185, 245, 141,  71,  96, 152,  77, 162,  20, 210,   8, 171, 229, 208, 244,   3, 217,
+	],
+	[
+		170, 131, 182, 238,  36, 100, 243,  48, 198,  37, 249,  27,  67, 241,   4, 105,  21, 150, 166, 123, 140, 225, 116, 190, 134,  45, 108, 195,  65, 118,  85,  13,
+		148, 179, 122,   0, 255, 185, 161,  62,  83, 155,  25,  49, 111,  10, 219,  28,  85,  16, 177,  44, 193,  28, 180,  65, 117,  84, 133,  55, 120,  89, 138, 197,
+	],
+	[
+		102, 225,  54, 160, 114, 146,  22, 131, 152,  86, 180, 113, 128, 176, 159, 204, 233,  33,  65,  97, 201,  28, 165,  93, 233, 175, 145,  26, 157, 220,  43, 232,
+		 30,  81, 223,  50, 145,  87, 202,  13, 245, 176,  72, 200, 146,  61, 235, 129,  53, 160, 215, 119, 240, 228, 137, 245, 198, 224, 157, 193,  25, 180,  40,  63,
+	],
+	[
+		149,   8,  94,  26, 216,  81, 202, 170,   6, 229,  54, 207, 222,  80,  41,  58, 117, 173, 218, 242,   1,  74,  53, 204,   9,  82, 255,  55,  99, 239, 185, 130,
+		209,  62, 194, 159, 107,  29, 128,  45, 115, 220, 137,  16, 212,  89, 170, 192,  98, 251, 135,  12, 107,  57,  89,   2, 104,  35,  73,  13, 147, 233,  78, 246,
+	],
+	[
+		117, 204, 251,  69, 188, 232,  58,  97, 120,  70, 138,  13,  25, 100, 252, 186,  89, 134,  49, 108, 183, 145, 247, 126, 155,  34, 216, 124, 169,   7,  72, 109,
+		167,  20,  93, 240, 211,  67, 173, 237,  94,  34, 188, 101, 124, 247,   0, 113,  33, 202,  64,  81, 170,  39, 209, 147, 175,  49, 249,  95, 205, 108, 163,  17,
+	],
+	[
+		185,  48, 168, 138, 123,  40,  17, 254, 212, 186, 237, 155, 169, 197, 143,  10, 239,  18, 197,  31, 160,  87, 214,  19, 110,  69, 183,  90,  20, 204, 152,  53,
+		252, 141, 119,  41,  12, 226, 192, 147,   4, 166,  58, 227,  40, 160,  75,  47, 151, 225,  24, 186, 220, 156, 125,  26, 190, 114, 166, 218, 126,  33,  56, 213,
+	],
+	[
+		131,  29,  82,   5, 152, 106, 178, 160,  33,  89,  45, 107,  65,  36, 121,  70, 211, 148,  79, 127, 225,  62,  39, 173, 238, 199,  47, 226, 136, 242,  35,  86,
+		215,   6, 186, 170, 132,  81, 102,  52, 208, 253,  82, 153,  26, 184, 206, 232, 174, 123,  91, 142,   6, 253,  75, 227,  62, 238, 138,  10,  64, 178, 241,  86,
+	],
+	[
+		223, 104, 234, 199, 244, 219,  64, 136, 114,   0, 202, 248, 131, 216, 229,  95, 162,  55, 178, 250,  14, 116, 191,  97, 133,   6, 148, 103,  64, 176, 116, 196,
+		101, 227,  75,  59, 249, 155,  19, 122,  71, 133, 198, 115, 242,  67, 138, 104,  11,  56, 239, 196, 112,  51,  97, 201,  18,  88,  40,  80, 197, 143,   0, 157,
+	],
+	[
+		196,  65, 175,  55,  92,  14,  78, 195, 223, 150,  75, 175,  20,  52, 180,   3, 111, 234,  41, 100, 208, 141, 233,  52,  76, 166, 248,  27, 191,  80,  17, 160,
+		 48,  30, 147, 109, 190,  35, 213, 234, 181,  24,   8,  97,  51, 128,  20,  86, 212,  38, 163,  69,  30, 177, 135, 162, 120, 183, 155, 222, 254,  98, 114,  43,
+	],
+	[
+		 24, 122, 144,  35, 164, 131,  46, 242,  23,  56, 232,  97, 145,  84, 194,  32, 132, 203,  21, 156,  70,   3, 161,  30, 206, 114, 213,  42, 127, 220, 144, 245,
+		123, 173, 235, 202,   2,  95, 168,  46, 106, 146, 224, 161, 214, 191, 237, 149, 182, 247, 132, 103, 232, 214,  43, 247,   3, 210, 107,  24,  51, 169,  71, 232,
+	],
+	[
+		181,  11, 252, 214, 114, 204, 182, 102, 168, 122, 189,  11, 113, 236, 153, 254, 171,  61,  87, 189, 125, 222,  84, 253, 182,  15,  91, 158, 234,   0,  59, 206,
+		 72,  13,  87,  53, 125, 222, 140,  83, 247,  60, 176,  41,  77,  14, 169,  62, 116,  79,   8, 205, 152,  14,  84,  67, 146,  56, 234, 131, 188,  14, 208, 136,
+	],
+	[
+		 80, 159, 100,  73,   4, 231,  30, 143,  84, 213,  39, 163, 208,  65,  44,  77, 101, 215, 143, 245,  47, 175, 102,  59, 129, 144,  71,  53, 110, 180,  97,  38,
+		185, 217, 137, 244, 158,  69,  16, 195,  26, 205,  89, 122, 243, 109,  32, 227,  46, 195,  26,  90, 170, 115, 192, 223, 101, 172,  34,  76, 118, 151, 242,  93,
+	],
+	[
+		200, 227,  51, 189, 149,  89,  61, 251,   7,  70, 135, 247,  27, 127, 222,  19, 119,   8, 231,  31, 112,  19, 152, 199,  39, 225, 242, 172, 201,  81, 250, 132,
+		111, 163,  27, 104,  42, 183, 239, 112, 129, 155, 230,   0, 141, 200,  93, 135, 161, 221, 146, 250,  54,  33, 239, 126,  21, 203,  92, 248, 216,  61,  32,  47,
+	],
+	[
+		111,  26, 134, 245,  39, 175, 126, 159, 192, 227, 104,  53,  91, 186, 166, 140, 198, 180,  55, 163,  79, 212, 240,   8, 118, 187,  27,  10, 138,  32, 156,  18,
+		224,  63, 197,  79, 210,   9, 172,  51,  75,  36, 187,  67, 173,  52, 251,  73,   5, 100, 123,  66, 184, 140,  76, 160,  47, 186, 135, 157,   4, 103, 167, 221,
+	],
+	[
+		  7, 173,  63, 117, 210,  21, 221,  47, 115,  16, 178, 155, 202,   2, 107, 246,  36,  71,  98, 202, 135, 184,  66,  95, 165,  77, 105, 211, 122, 230,  50, 192,
+		 88,   5, 252, 150, 116, 228,  91, 147, 217, 255,  20,  98, 212, 152,  15, 185, 206, 174,  38, 228, 210,   1, 106, 217,  12, 230,  68,  27, 198, 181, 126, 145,
+	],
+	[
+		192, 239,  83, 155, 101, 196,  76,  94, 237, 141,  36, 220,  74, 238,  59,  86, 227, 158, 127, 249,   1,  41, 146, 219,  48, 247, 153,  61,  92, 169,  74, 241,
+		144, 123, 172,  34,  56, 132,  23, 199, 105, 167, 136, 116,  42, 233, 127, 111,  58, 239,  80,  17,  93, 166, 245,  62, 149,  88, 111, 237,  54,  84, 253,  69,
+	],
+	[
+		 94, 215,  35,  12, 235, 137,   2, 171, 206,  60,  84, 118,  22, 134, 175,  14, 146,  48,  20, 219,  88, 121, 235,  23, 131, 195,  34, 222, 142,  12, 205, 105,
+		181,  45, 216,  98, 194, 165, 245,  66,  44,  11, 227,  81, 192,  28,  87, 218,  23, 139, 159, 119, 193, 132,  41, 181, 121, 207, 173,  39, 140, 225,  18,  42,
+	],
+	[
+		118, 139, 182, 166,  53,  67, 249, 109,  29, 162, 255, 196, 148,  42, 216, 115, 206, 191, 108, 177,  60, 167, 198, 104, 174,  85,   6, 178, 253,  41, 117,  21,
+		 60, 232,  15,  73, 237,   2,  83, 123, 185, 209,  58, 156, 243,  64, 169, 148,  45, 197, 103, 255,  53,  24, 202,  79,  30, 251,   9, 158, 123, 101, 210, 161,
+	],
+	[
+		243,  22, 106, 224, 125, 191, 149,  44, 184, 129,   7, 225, 100, 187,  66,  91, 252,  29,  78, 240, 151,  33,  75,  15,  58, 233, 112, 128,  68, 190, 220, 161,
+		134,  85, 156, 112, 182, 140, 221,  33, 151,  90, 130, 177,   3, 203,  99, 247,  75, 222,   7, 178,  69, 151, 235,  96, 142,  57, 219,  74, 194,   1, 177,  58,
+	],
+	[
+		196,  48,  74, 204,  89,  15, 228,  79, 215,  95,  69,  49, 156, 243,   9, 125, 164,  57, 137,  10, 204, 114, 255, 213, 143, 160, 203,  50,  99, 150,  78,  33,
+		246, 195, 210, 128,  28,  51, 161, 107, 233, 250,  24, 109,  39, 140, 120,  13, 185, 129,  36,  87, 227, 110, 214,  19, 169, 108, 187,  92, 246,  32, 149,  81,
+	],
+	[
+		218, 153,   5, 237,  37, 160, 119,  24, 245, 144, 175, 113,  28,  82, 171,  37, 221,  99, 185, 230,  45,  95, 133, 187,  39,  72, 243,  25, 212,   0, 231, 177,
+		 95,  10,  41,  63, 253,  94, 204,  69,   7,  50, 187,  72, 219, 235,  52, 160, 207,  63, 242, 168, 137,   3, 186, 126, 241,  43,  16, 135,  63, 231, 109, 130,
+	],
+	[
+		254,  93, 171, 136,  63, 179,  99, 199,  58,  14, 209, 237, 193, 137, 204, 234, 149,  18, 121, 157,  66, 175,   3, 226, 121,  12,  92, 185, 133, 165, 110,  54,
+		143, 119, 227, 169, 189,  20, 218, 174, 120, 198, 142, 165,  94, 192,  82,  22, 112,  95, 153,  27, 199,  48,  61, 157,  72, 201, 226, 117, 165, 203,  41,  11,
+	],
+	[
+		119,  31, 190, 110, 247, 220,  47, 133, 166,  87,  39, 123,   0,  60, 106,  73,  50, 198,  83, 215,  29, 238,  81,  54, 103, 171, 147, 227,  44,  64, 249,  23,
+		203,  71, 153, 106,  81, 145, 131,  40, 100,  80, 226,  11, 124,  31, 253, 174, 226,  46, 214, 121,  78, 105, 252,  90,  10, 144, 177,  51,  22,  88, 185,  68,
+	],
+	[
+		160, 207,  53,  78,  23, 150,   3, 236, 106, 187, 225, 160,  94, 250, 181,  24, 133, 245,   5, 190, 108, 146, 165, 210, 196, 251,  30,  80, 199, 122,  88, 186,
+		217,  35, 244,   4,  56, 233,  14, 248, 156,  29, 240,  59, 212, 149,  68, 130,   9, 144, 179,  16, 224, 171, 207,  29, 232, 101,  82, 213, 151, 240, 137, 224,
+	],
+	[
+		 17,  98, 145, 215, 126, 194,  83, 211,  67,  29, 140,  75, 216,  43, 152, 210, 115, 170,  95,  58, 249, 125,  20,  37, 136,  62, 115, 175,  15, 237, 154,   7,
+		128, 171,  95, 196, 210, 112, 180,  49, 203, 133, 178, 113,  42, 163, 100, 200, 233,  85,  62, 247,  38, 139, 115, 189, 162, 131,  35, 250,   3, 105,  56, 174,
+	],
+	[
+		238,  42, 250,  13,  64, 168,  38, 119, 155, 253,  53,  16, 176, 126,  11,  88, 230,  31, 140,  43, 179,  71, 220,  90, 158,   6, 217,  99, 141,  38, 222, 107,
+		 80,  48, 139,  27, 123, 162,  73,  91, 219,  65,  17,  89, 244,   3, 183,  53,  33, 109, 187, 158,  95,  68,   5,  46, 218,  57, 182,  69, 125, 190,  30,  79,
+	],
+	[
+		129, 197, 115, 177, 230, 102, 244, 183,   9, 201, 113, 229,  99, 193, 240,  52,  68, 200, 154, 224, 204,   9, 102, 235, 183,  76, 246,  53, 209,  72, 167,  59,
+		254, 183, 229,  65, 241,  34, 192,   1, 108, 168, 144, 189, 206,  77, 118, 249, 137, 208,   7, 126, 197, 235, 151,  80, 246,  18, 110, 159, 205,  92, 218, 154,
+	],
+	[
+		  6,  71, 157,  87,  49, 141,  26,  93, 134,  78, 169, 147,  64,  32, 164, 136, 184, 103,  19,  78, 114, 167,  52, 131, 198,  43, 153, 127, 179,  27, 196, 135,
+		 19, 205, 156,  14,  85, 146, 225, 128, 246,  27, 232,  50, 129, 223, 156,  21, 168,  74, 239,  50,  25, 214, 179, 123,  97, 197, 142, 236,  11,  43, 231, 108,
+	],
+	[
+		 54, 213,  32, 189,   8, 219, 162,  58, 238,  40,  19, 212, 246, 119,  82, 221,   3, 250, 127, 233,  34, 149, 254,  28, 117,  15, 225, 105,   4,  93, 240, 119,
+		 42,  96, 113, 178, 214, 102,  58, 173,  40, 154,  71, 103,  14,  37,  94,  63, 216,  99, 148, 174,  87, 108,  60,  14, 166, 224,  29,  85, 171,  63, 144, 181,
+	],
+	[
+		244, 138, 233, 107, 127, 197,  73, 206, 223, 106, 189,  89,  47,   8, 204, 110,  41, 160,  60, 177,  87, 191,  64, 212, 173,  86,  66, 187, 232, 157,  82, 147,
+		223,   2,  72, 132,  45, 252,  10, 204,  81, 120, 215, 195, 171, 141, 235, 192,  44,  14, 118, 222,  37, 255, 134, 208,  41,  73,  52, 135, 253, 119, 202,  25,
+	],
+	[
+		100,  84, 169,  65, 255,  41, 115, 146,   0, 173, 124, 158, 138, 235, 178, 150,  74, 210,  98,  13, 216, 135,   1,  99, 238, 144, 207, 134,  34,  49, 211,  62,
+		187, 166, 243, 194,  24, 161, 140, 188,  97, 240,   6,  57, 255, 112,  76, 177, 132, 248, 202,  67, 141,   4, 193, 155, 239, 116, 189, 211,  97,  16,  75, 164,
+	],
+	[
+		 44,   2, 208,  17, 152,  96, 179,  23,  84,  55, 251,  70,  24, 101,  56,  28, 194, 238, 143,  48, 244, 111,  74, 159,  38,  55,  22, 247, 114, 172,  10, 251,
+		104,  35, 209,  56,  92, 114, 223,  65,  19, 134, 180,  86, 159,  23, 209,   1, 153,  58,  28, 165, 185,  77, 100,  21,  88, 176,   1, 150,  37, 184, 227, 127,
+	],
+	[
+		195, 116, 183,  55, 225,  31, 243, 132, 231, 196,  35, 219, 200, 165, 224, 133,  90,  17, 118, 164, 200,  27, 187, 221, 122, 196, 165,  75,  96, 201, 126,  26,
+		138,  79, 122, 149, 236,  77,  34, 170,  48, 209, 149,  32, 221,  47,  91, 124, 228, 105,  85, 233, 115, 242,  54, 225, 129,  64, 246, 105, 218,  57, 154, 249,
+	],
+	[
+		 67, 147, 240, 129,  77, 163, 191,  66, 154,  93, 113, 144,  14,  83, 120, 253,  64, 185,  36, 229,  83,  58, 147, 250,   7, 104, 229,  13, 152, 221,  70, 179,
+		159, 230,   6, 182,  17, 200, 126, 247, 104, 230, 114,  67, 130, 198, 244,  70, 184,  40, 195,   8, 158,  44, 213, 145, 168,  25, 194,  79, 133,   8,  91,  30,
+	],
+	[
+		220, 104,  25,  88, 212, 109,  47,   5, 207,  20, 169,  51, 244, 187,  40,   4, 168, 217, 104, 130,  11, 173,  93,  44, 178,  83, 128, 184,  57,  37, 238,  90,
+		 51, 215,  99,  44, 164, 217, 144,   2, 189,  78,  21, 249, 176, 101, 146,  16, 167, 218, 138,  95, 127,  17, 180, 110,  35, 229,  49, 157, 236, 113, 207, 176,
+	],
+	[
+		 13,  50, 201, 171,  12, 142, 221, 124, 103, 238, 182, 129,  73, 100, 154, 205, 139,  76,  49, 153, 207, 240, 113, 139,  64, 205,  28, 254, 137, 108, 193,  19,
+		115, 197,  66, 250, 109,  71,  53,  93, 156,  40, 201, 164,   6,  59,  34, 119, 239,  54,  26, 209, 252,  62, 200,  72,  95, 121, 204,  18, 172,  43, 141,  76,
+	],
+	[
+		231, 158, 135, 252,  39,  71, 177, 248,  59,  79,  38, 211,   8, 229,  55, 111, 234,  21, 248, 190,  69,  32, 216,  18, 234, 160,  46, 214,  86,   6, 163, 144,
+		243, 130,  32, 175, 135,  21, 240, 178, 226, 124, 139,  90, 236, 191, 226, 156,  83, 110,  72, 172, 149,  84, 223,   5, 186, 147, 254,  88,  66, 191, 247, 123,
+	],
+	[
+		 94, 188,  61, 118, 227, 193,  90,  31, 162, 147, 225, 115, 140, 174, 193,  31,  92, 178, 118,   6,  99, 166, 124, 193,  75, 145, 116, 172,  69, 234, 208,  42,
+		 79,   9, 155, 228,  87, 195, 117,  30,  61, 213,  15,  49, 109,  73, 134,  11, 181, 203, 243,  46, 117,  32, 159, 134, 239,  56,  11, 130, 101,  23, 162,  36,
+	],
+	[
+		211,   2,  81,  28, 100, 151,   7, 133, 200,  18, 188,  65,  25,  86, 243, 131,  63, 161, 223, 142,  56, 227,  85,  40,   0, 244,  98,  22, 190, 124,  60, 176,
+		 96, 188, 211,  51,  13, 150, 208, 163, 100,  76, 243, 152, 206, 173,  43, 213,  99,  21, 141,   3, 193, 236, 103,  44, 209,  80, 177, 217, 199, 225,  58, 109,
+	],
+	[
+		172, 148, 241, 198, 165,  55, 244, 213,  46, 106,  93, 254, 165,  47, 217,   1, 201,  42,  81,  27, 203, 246, 151, 184, 110, 202,  55, 223, 152,  33, 105, 220,
+		252,  68, 121, 103, 238,  80,  43, 253,   5, 188, 170, 120,  26, 223,  85, 255,  61, 164, 125, 229, 179,  90,  15, 168, 113,  28, 154,  39, 116, 145,   9, 236,
+	],
+	[
+		 68,  46, 127, 217,  17, 110, 182,  82, 122, 232,   9, 205, 125, 150,  70,  98, 145, 251, 186, 128, 107,  12,  48, 134, 231, 161,  13,  90, 247, 135,   3, 160,
+		 20, 146,  36, 167, 184,  63, 130, 111, 144, 224,  37,  64, 102,   0, 128, 148,  33, 198,  79, 106,  67,  54, 201, 251,  68, 227,  95, 244,  73, 184,  85, 133,
+	],
+	[
+		 18, 185,  93,  73, 141,  40, 236,  68, 170, 154,  56, 179,  36, 109, 227, 170, 114,  17, 214,  70, 156, 176,  96,  66,  31,  79, 121, 182,  45, 203,  83, 116,
+		 50, 201, 224, 136,   6, 219, 199,  24,  54,  89, 133, 200, 247, 160, 191, 111, 238,  50, 220,  25, 155, 217, 143, 120, 186, 137,   1, 163,  51,  31, 253, 201,
+	],
+	[
+		119, 221,  29, 251, 177, 207, 130,   1,  33, 220, 137,  77, 239,  12, 209,  30, 181,  51,  93, 239,  37, 219, 195, 254, 209, 172, 141, 215,  71, 167, 228, 192,
+		241,  92,  76,  28, 247,  95, 176, 159, 234, 212,  11,  82, 176,  52,  70,  18,  91, 184, 136, 249, 174,   8,  37,  84,  23,  58, 197, 124, 174, 211,  99, 158,
+	],
+	[
+		233,  56, 105, 156,  12,  60,  98, 197,  88, 249,  21, 101, 191, 159,  88,  60, 235, 124, 163,   5, 137,  84,  21, 114,   4,  59, 241,  36, 110,  23,  57, 139,
+		 13, 173, 111,  56, 153, 120,  38,  71, 104, 182, 151, 239,  32, 140, 230, 204, 169,  11, 118,  43,  94, 129, 230, 161, 241, 214, 107,  18, 224, 140,  12,  41,
+	],
+	[
+		167, 135, 193,  82, 119, 228, 161, 142, 184, 113, 203,  65, 131,  45, 252, 140,  76, 193, 205, 111,  62, 228, 167, 128, 154, 102, 192,  15, 131, 252,  98, 183,
+		 40, 127, 233, 190, 210,  83,  10, 243, 126,  20,  46, 115,  97, 214, 121,  39, 154,  62, 210,  75, 194, 109, 207,  49,  98, 148,  74, 236,  87,  61, 112,  78,
+	],
+	[
+		208,   2, 244,  46, 213,  34, 240,  17,  54,  41, 166, 231,   3, 177, 117,  15, 153,  25,  42, 249, 148, 189,  49,  75, 237,  43,  91, 229, 158, 208,  75, 152,
+		218,  68,   1, 140,  46, 168, 226, 191,  60, 206, 170,  68, 195,   6,  79, 252, 103, 224, 144, 234,  22, 181,  67,   5, 175,  32, 184,  44, 155, 193, 248, 180,
+	],
+	[
+		 69,  24,  92, 145, 170,  69, 108,  79, 217, 124, 147,  84, 215,  95, 200, 220, 105, 231,  90, 175,  16, 100,  33, 204, 179, 219, 143,  64, 176,   5, 117,  31,
+		245, 164, 204,  97, 255,  19, 107, 147,  88, 137, 250, 220,  26, 163, 185, 131,  29,  86,   2, 164,  56, 152, 243, 139, 116, 254, 130, 206,   9, 121,  28, 147,
+	],
+	[
+		230, 125, 221, 181,   7, 131, 194, 248, 174,   8, 238,  29,  57, 157,  37,  68, 183,  54, 130,  73, 222, 120, 246, 137,  10,  25, 121,  81,  48, 190, 226,  87,
+		 54, 107,  24,  78, 180, 128,  50, 215,  33,   2, 101, 123, 148,  60, 237,  50, 174, 198, 115, 248,  35,  91, 218,  80, 199,  21,  92,  67, 168, 239,  48,  98,
+	],
+	[
+		163,  37, 113,  53, 205,  97,  20, 153,  63, 206, 103, 188, 138, 246, 127,   9, 242, 166, 214,   2, 197, 158,  86,  55, 107, 171, 200, 250, 105,  27, 129, 199,
+		144, 186, 123,  36, 223, 158,  73, 241, 165, 181,  77,  41, 228,  90, 110,  13, 216,  66, 137, 187, 102, 128,  13,  45, 162,  56, 229, 142, 105, 215,  80, 200,
+	],
+	[
+		 59, 188, 254,  85, 158, 224,  45, 136,  88,  35, 117,  74,  20, 169, 109,  82, 146,  30, 114, 141,  63,  26, 231, 212,  72, 238, 161,  36, 148, 234, 167,  66,
+		  8, 217, 240, 150,  62, 194,   9, 117, 202,  57, 235, 191,  16, 207, 139, 157, 242,  41,  77,  16, 229, 209, 178, 236, 108, 189, 211,   4,  38, 182,  14, 132,
+	],
+	[
+		 22, 150,  12,  67, 237,  30, 121, 234, 183, 162, 254, 222, 199,  48, 234, 209, 193,  45,  93, 255, 172,  40, 183, 149, 130,   0,  90,  61, 213,  14,  96, 246,
+		 43, 174,  84, 102,  16, 235,  94, 141,  27, 106, 153, 130, 171,  70,  33, 190,  97, 123, 206, 167,  52, 157,  66, 122,  34, 151,  74, 126, 250, 156, 111, 235,
+	],
+	[
+		170, 211, 104, 140, 191, 172,  76, 213,  10,  52, 143,  92,   1, 179,  63,  98,  19, 228, 155,  78, 207, 110,  94,  18, 194,  45, 222, 138, 119, 183,  77, 112,
+		136,  21,  52, 208, 132, 170,  42, 212, 248,  86,  11,  49, 255, 116, 224,  82,   5, 150, 252,  30,  89, 142,   1,  84, 248,  17,  96, 176,  53,  87, 222,  71,
+	],
+	[
+		 94, 244, 128,  44,   3, 112,  57, 100, 197,  25,  70, 124, 153,  34, 134, 162, 120, 181,  57, 127,   8, 241,  53, 121, 245, 175, 103,  22, 197,  50, 162, 205,
+		228, 154, 189, 252, 112,  78, 182,  61, 125, 162, 195, 216,  97,  22, 164,  54, 232, 179,  62, 194, 108, 240, 201, 182, 217, 136, 232, 199,  25, 143, 190,  34,
+	],
+	[
+		 51, 199,  78, 161, 218, 249, 149, 132, 243, 167, 211, 238, 103, 219, 251,  74,   5, 214, 237,  22, 190, 139, 219, 157,  63,  81, 232, 154,  72, 253,   4,  32,
+		 90, 122,  71,   0,  31, 231, 154,   7, 223,  74,  38, 145,  63, 182, 203, 136, 104,  20, 118, 218,  45, 132,  24, 101,  58, 168,  44, 117,  66, 242,   0, 120,
+	],
+	[
+		180,  17, 229,  28,  91, 203,  16,  37,  84, 111,  43, 186,  57,  15, 202,  89, 142,  40, 106,  72, 167,  85,  29, 201,   6, 114, 188,  35, 216, 125, 143, 236,
+		181,  60, 221, 166, 143,  49, 207, 105,  23, 173, 233, 112,   0, 240, 124,  39, 211,  74, 146,   7, 172,  72, 226,  37, 157,  77,  11, 214, 104, 165, 206, 135,
+	],
+	[
+		100, 147,  61, 186, 124,  70, 176, 228, 189, 154,   6,  78, 171, 116, 189,  52, 242, 173, 200, 151, 226,  46,  99, 172, 250, 142,  15,  96,  59, 177,  82,  46,
+		106,  23, 202,  96, 194,  85, 118, 246, 188,  91, 134, 199,  81, 154,  26,  89, 248, 158, 236,  96, 190, 245, 150, 113, 196, 125, 255, 186, 151,  83,  42, 219,
+	],
+	[
+		251, 172, 112, 237,  47, 159, 107,  55,  27, 218, 125, 139, 231,  23, 150,  31, 129,  96,  62,  26, 117, 133, 235,  70,  38, 126, 206, 168, 242, 111,  10, 209,
+		163, 250, 126,  39, 242, 133,  25,  68, 148,  54,  13, 249,  47, 226,  68, 191, 169,  50,  32, 129,  59,  15,  87, 211,   5, 231,  92,  32,  57, 228,  14,  72,
+	],
+	[
+		 31,  86,   4, 136, 216,  12, 255, 142,  91, 241,  66, 207,  98, 248,  83, 163, 230,   7, 184, 252, 212,  12, 191, 149, 217,  77,  49, 224,  20, 150, 189, 226,
+		136,  74, 152,  10,  59, 180, 225, 161,  36, 220, 121, 162, 102, 178, 138, 110,   9, 200,  80, 221, 165, 120, 180,  51,  67, 171, 143,  19, 132, 195, 118, 155,
+	],
+];
+
+/// Get the blue-noise threshold for a pixel, tiled across the image and centered around zero (-128 to 127).
+pub fn blue_noise_threshold(x: u32, y: u32) -> i16
+{
+	BLUE_NOISE_MATRIX[y as usize % BLUE_NOISE_SIZE][x as usize % BLUE_NOISE_SIZE] as i16 - 128
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+	
+	#[test]
+	fn texture_tiles_across_image_edges_centered_around_zero()
+	{
+		let size: u32 = BLUE_NOISE_SIZE as u32;
+		
+		// Pixels past each tile edge repeat the texture from its start, including at odd image widths.
+		for (x, y) in [(0, 0), (size - 1, 0), (0, size - 1), (size - 1, size - 1), (17, 42)]
+		{
+			let threshold: i16 = blue_noise_threshold(x, y);
+			assert_eq!(blue_noise_threshold(x + size, y), threshold);
+			assert_eq!(blue_noise_threshold(x, y + size), threshold);
+			assert_eq!(blue_noise_threshold(x + 3 * size, y + 5 * size), threshold);
+		}
+		assert_eq!(blue_noise_threshold(u32::MAX, u32::MAX), blue_noise_threshold(size - 1, size - 1));
+		
+		let mut sum: i64 = 0;
+		let mut min: i16 = i16::MAX;
+		let mut max: i16 = i16::MIN;
+		for y in 0..size
+		{
+			for x in 0..size
+			{
+				let threshold: i16 = blue_noise_threshold(x, y);
+				sum += threshold as i64;
+				min = min.min(threshold);
+				max = max.max(threshold);
+			}
+		}
+		
+		assert_eq!((min, max), (-128, 127));
+		let mean: f64 = sum as f64 / (BLUE_NOISE_SIZE * BLUE_NOISE_SIZE) as f64;
+		assert!(mean.abs() < 1.0, "mean threshold {}", mean);
+	}
+}
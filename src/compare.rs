@@ -0,0 +1,266 @@
+use image::{DynamicImage, ImageFormat, RgbaImage};
+use std::io::Cursor;
+
+use crate::error::{MinifyError, Result};
+use crate::minify::{self, DitheringMode, MinifyOptions};
+use crate::dithering::ImageClass;
+
+// Before/after comparison images and dithering previews for evaluating quality settings.
+// The source is minified in memory and never written back, and the comparison image carries no marker.
+
+/// Width of the divider between the original and the minified image, in pixels.
+const DIVIDER_WIDTH: u32 = 4;
+
+/// Color of the divider (opaque mid gray).
+const DIVIDER_COLOR: [u8; 4] = [128, 128, 128, 255];
+
+/// Dithering modes shown by a preview, with their labels, in tile order (left to right, top to bottom).
+pub const PREVIEW_MODES: [(DitheringMode, &str); 4] = [(DitheringMode::None, "NONE"), (DitheringMode::Ordered, "ORDERED"), (DitheringMode::FloydSteinberg, "FLOYD"), (DitheringMode::MedianCut, "MEDIAN")];
+
+/// Size of one pixel of the label font, in image pixels.
+const LABEL_SCALE: u32 = 2;
+
+/// Space around the label text, in image pixels.
+const LABEL_PADDING: u32 = 3;
+
+/// Height of the label strip above each preview tile (the font is 5 pixels high).
+pub const LABEL_HEIGHT: u32 = 5 * LABEL_SCALE + 2 * LABEL_PADDING;
+
+/// Background and text color of the labels.
+const LABEL_BACKGROUND: [u8; 4] = [32, 32, 32, 255];
+const LABEL_COLOR: [u8; 4] = [255, 255, 255, 255];
+
+/// Minify PNG data and render the original (left) next to the result (right).
+/// Returns the comparison image encoded as PNG together with the dithering mode used.
+pub fn compare_bytes(source_data: &[u8], options: &MinifyOptions) -> Result<(Vec<u8>, DitheringMode)>
+{
+	let (minified_data, effective_dithering, _, _): (Vec<u8>, DitheringMode, u8, Option<ImageClass>) = minify::minify_bytes(source_data, options)?;
+	
+	let before: RgbaImage = image::load_from_memory(source_data)
+		.map_err(|e| MinifyError::Decode(format!("Failed to decode PNG: {}", e)))?
+		.to_rgba8();
+	let after: RgbaImage = image::load_from_memory(&minified_data)
+		.map_err(|e| MinifyError::Decode(format!("Failed to decode minified PNG: {}", e)))?
+		.to_rgba8();
+	
+	let comparison: RgbaImage = side_by_side(&before, &after);
+	
+	let mut buffer: Vec<u8> = Vec::new();
+	DynamicImage::ImageRgba8(comparison).write_to(&mut Cursor::new(&mut buffer), ImageFormat::Png)
+		.map_err(|e| MinifyError::Encode(format!("Failed to encode comparison image: {}", e)))?;
+	
+	Ok((buffer, effective_dithering))
+}
+
+/// Place two images next to each other with a thin divider in between.
+/// The result is as tall as the taller image; the area below the shorter one is transparent.
+pub fn side_by_side(left: &RgbaImage, right: &RgbaImage) -> RgbaImage
+{
+	let (left_width, left_height): (u32, u32) = left.dimensions();
+	let (right_width, right_height): (u32, u32) = right.dimensions();
+	let height: u32 = left_height.max(right_height);
+	let right_x: u32 = left_width + DIVIDER_WIDTH;
+	
+	let mut combined: RgbaImage = RgbaImage::new(right_x + right_width, height);
+	for y in 0..height
+	{
+		for x in left_width..right_x
+		{
+			combined.put_pixel(x, y, image::Rgba(DIVIDER_COLOR));
+		}
+	}
+	
+	for (x, y, pixel) in left.enumerate_pixels()
+	{
+		combined.put_pixel(x, y, *pixel);
+	}
+	
+	for (x, y, pixel) in right.enumerate_pixels()
+	{
+		combined.put_pixel(right_x + x, y, *pixel);
+	}
+	
+	combined
+}
+
+/// Quantize PNG data with each of PREVIEW_MODES at the current quality and tile the results into one labeled image.
+/// Returns the montage encoded as PNG together with the number of distinct colors each mode produced.
+#[allow(clippy::type_complexity)]
+pub fn preview_bytes(source_data: &[u8], options: &MinifyOptions) -> Result<(Vec<u8>, Vec<(DitheringMode, usize)>)>
+{
+	let source: RgbaImage = image::load_from_memory(source_data)
+		.map_err(|e| MinifyError::Decode(format!("Failed to decode PNG: {}", e)))?
+		.to_rgba8();
+	
+	let mut tiles: Vec<RgbaImage> = Vec::with_capacity(PREVIEW_MODES.len());
+	let mut color_counts: Vec<(DitheringMode, usize)> = Vec::with_capacity(PREVIEW_MODES.len());
+	for (mode, _) in PREVIEW_MODES
+	{
+		let tile: RgbaImage = minify::quantize_rgba(source.clone(), mode, options)?;
+		let mut colors: std::collections::HashSet<[u8; 4]> = std::collections::HashSet::new();
+		for pixel in tile.pixels()
+		{
+			colors.insert(pixel.0);
+		}
+		color_counts.push((mode, colors.len()));
+		tiles.push(tile);
+	}
+	
+	let montage: RgbaImage = preview_montage(&tiles, source.width(), source.height());
+	
+	let mut buffer: Vec<u8> = Vec::new();
+	DynamicImage::ImageRgba8(montage).write_to(&mut Cursor::new(&mut buffer), ImageFormat::Png)
+		.map_err(|e| MinifyError::Encode(format!("Failed to encode preview image: {}", e)))?;
+	
+	Ok((buffer, color_counts))
+}
+
+/// Position (x, y) of the index-th tile of a preview montage built from width x height tiles.
+/// Tiles are laid out two per row, each below its label strip and separated by dividers.
+pub fn preview_tile_origin(index: usize, width: u32, height: u32) -> (u32, u32)
+{
+	let column: u32 = index as u32 % 2;
+	let row: u32 = index as u32 / 2;
+	(column * (width + DIVIDER_WIDTH), row * (LABEL_HEIGHT + height + DIVIDER_WIDTH) + LABEL_HEIGHT)
+}
+
+/// Tile the quantized images (labeled with PREVIEW_MODES) into one image, two per row.
+fn preview_montage(tiles: &[RgbaImage], width: u32, height: u32) -> RgbaImage
+{
+	let rows: u32 = (tiles.len() as u32).div_ceil(2);
+	let mut montage: RgbaImage = RgbaImage::from_pixel(2 * width + DIVIDER_WIDTH, rows * (LABEL_HEIGHT + height) + (rows - 1) * DIVIDER_WIDTH, image::Rgba(DIVIDER_COLOR));
+	
+	for (index, (tile, (_, label))) in tiles.iter().zip(PREVIEW_MODES).enumerate()
+	{
+		let (tile_x, tile_y): (u32, u32) = preview_tile_origin(index, width, height);
+		
+		// Label strip, with the text clipped to the tile width.
+		for y in tile_y - LABEL_HEIGHT..tile_y
+		{
+			for x in tile_x..tile_x + width
+			{
+				montage.put_pixel(x, y, image::Rgba(LABEL_BACKGROUND));
+			}
+		}
+		draw_label(&mut montage, label, tile_x + LABEL_PADDING, tile_y - LABEL_HEIGHT + LABEL_PADDING, tile_x + width);
+		
+		for (x, y, pixel) in tile.enumerate_pixels()
+		{
+			montage.put_pixel(tile_x + x, tile_y + y, *pixel);
+		}
+	}
+	
+	montage
+}
+
+/// Draw upper-case text with the built-in 3x5 font, scaled by LABEL_SCALE, stopping at max_x.
+fn draw_label(image: &mut RgbaImage, text: &str, x: u32, y: u32, max_x: u32)
+{
+	let mut cursor_x: u32 = x;
+	for character in text.chars()
+	{
+		let rows: [u8; 5] = glyph(character);
+		for (row, bits) in rows.iter().enumerate()
+		{
+			for column in 0..3
+			{
+				if bits & (0b100 >> column) == 0
+				{
+					continue;
+				}
+				
+				for dy in 0..LABEL_SCALE
+				{
+					for dx in 0..LABEL_SCALE
+					{
+						let (px, py): (u32, u32) = (cursor_x + column * LABEL_SCALE + dx, y + row as u32 * LABEL_SCALE + dy);
+						if px < max_x && py < image.height()
+						{
+							image.put_pixel(px, py, image::Rgba(LABEL_COLOR));
+						}
+					}
+				}
+			}
+		}
+		
+		// Three columns of the glyph and one of spacing.
+		cursor_x += 4 * LABEL_SCALE;
+	}
+}
+
+/// Rows of a 3x5 font glyph (bit 2 is the left column), for the letters used by the preview labels.
+fn glyph(character: char) -> [u8; 5]
+{
+	match character
+	{
+		'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+		'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+		'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+		'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+		'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+		'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+		'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+		'N' => [0b101, 0b111, 0b111, 0b111, 0b101],
+		'O' => [0b111, 0b101, 0b101, 0b101, 0b111],
+		'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+		'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+		_ => [0; 5],
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+	use crate::test_utils;
+	
+	#[test]
+	fn preview_tiles_four_distinct_quantizations()
+	{
+		let source: RgbaImage = test_utils::noisy_ramps(48, 40);
+		let options: MinifyOptions = MinifyOptions::builder().quality(40).build().expect("Valid options");
+		
+		let (preview, color_counts): (Vec<u8>, Vec<(DitheringMode, usize)>) = preview_bytes(&test_utils::encode_png(&source), &options).expect("Preview failed");
+		let montage: RgbaImage = test_utils::decode_png(&preview);
+		assert_eq!(montage.dimensions(), (2 * 48 + DIVIDER_WIDTH, 2 * (LABEL_HEIGHT + 40) + DIVIDER_WIDTH));
+		assert_eq!(color_counts.len(), PREVIEW_MODES.len());
+		
+		let mut tiles: Vec<RgbaImage> = Vec::new();
+		for (index, (mode, _)) in PREVIEW_MODES.iter().enumerate()
+		{
+			let (x, y): (u32, u32) = preview_tile_origin(index, 48, 40);
+			let tile: RgbaImage = image::imageops::crop_imm(&montage, x, y, 48, 40).to_image();
+			assert_ne!(tile, source);
+			
+			// Median cut may settle ties between equally common colors differently from run to run, so only its size is checked.
+			if *mode != DitheringMode::MedianCut
+			{
+				assert_eq!(tile, minify::quantize_rgba(source.clone(), *mode, &options).expect("Quantization failed"), "{:?}", mode);
+			}
+			tiles.push(tile);
+		}
+		
+		for first in 0..tiles.len()
+		{
+			for second in first + 1..tiles.len()
+			{
+				assert_ne!(tiles[first], tiles[second], "{:?} and {:?} look the same", PREVIEW_MODES[first].0, PREVIEW_MODES[second].0);
+			}
+		}
+	}
+	
+	#[test]
+	fn comparison_is_twice_as_wide_with_matching_heights()
+	{
+		let source: RgbaImage = test_utils::photo_like(64, 40);
+		let source_data: Vec<u8> = test_utils::encode_png(&source);
+		let options: MinifyOptions = MinifyOptions::builder().quality(45).fast_deflate(true).build().expect("Valid options");
+		
+		let (comparison_data, _): (Vec<u8>, DitheringMode) = compare_bytes(&source_data, &options).expect("Comparison failed");
+		let comparison: RgbaImage = test_utils::decode_png(&comparison_data);
+		
+		assert_eq!(comparison.dimensions(), (2 * 64 + DIVIDER_WIDTH, 40));
+		assert_eq!(image::imageops::crop_imm(&comparison, 0, 0, 64, 40).to_image(), source);
+		assert!(!minify::is_already_minified(&comparison_data).expect("Marker check failed").0);
+	}
+}
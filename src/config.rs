@@ -0,0 +1,188 @@
+use anyhow::{anyhow, Result};
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::minify;
+
+/// Name of the project configuration file discovered by walking up from the current directory.
+pub const CONFIG_FILE_NAME: &str = ".minipng.toml";
+
+/// Project defaults loaded from a .minipng.toml file.
+/// Every value is optional; command line flags always take precedence.
+#[derive(Debug, Clone, Default)]
+pub struct Config
+{
+	/// Default quality level (1-100, or 0 for auto).
+	pub quality: Option<u8>,
+	
+	/// Default dithering mode name.
+	pub dithering: Option<String>,
+	
+	/// Default smoothing radius (0.0-5.0).
+	pub smooth: Option<f32>,
+	
+	/// Default denoise setting.
+	pub denoise: Option<bool>,
+	
+	/// Default lossless setting.
+	pub lossless: Option<bool>,
+	
+	/// Exclude patterns applied during directory scanning.
+	pub excludes: Option<Vec<String>>,
+	
+	/// Number of files to process in parallel (0 = all cores).
+	pub jobs: Option<usize>,
+}
+
+/// Find the nearest configuration file, walking up from the given directory.
+pub fn discover_config_file(start_dir: &Path) -> Option<PathBuf>
+{
+	let mut current: Option<&Path> = Some(start_dir);
+	while let Some(dir) = current
+	{
+		let candidate: PathBuf = dir.join(CONFIG_FILE_NAME);
+		if candidate.is_file()
+		{
+			return Some(candidate);
+		}
+		
+		current = dir.parent();
+	}
+	
+	None
+}
+
+/// Load and parse a configuration file.
+pub fn load_config(path: &Path) -> Result<Config>
+{
+	let text: String = fs::read_to_string(path)
+		.map_err(|e| anyhow!("Failed to read config file {}: {}", path.display(), e))?;
+	
+	parse_config(&text).map_err(|e| anyhow!("Invalid config file {}: {}", path.display(), e))
+}
+
+/// Parse configuration text.
+/// Supports the TOML subset needed here: top-level `key = value` pairs with strings, numbers, booleans and string arrays.
+pub fn parse_config(text: &str) -> Result<Config>
+{
+	let mut config: Config = Config::default();
+	
+	for (index, raw_line) in text.lines().enumerate()
+	{
+		let line_number: usize = index + 1;
+		let line: &str = strip_comment(raw_line).trim();
+		if line.is_empty()
+		{
+			continue;
+		}
+		
+		if line.starts_with('[')
+		{
+			return Err(anyhow!("line {}: tables are not supported", line_number));
+		}
+		
+		let (key, value): (&str, &str) = line.split_once('=').ok_or_else(|| anyhow!("line {}: expected key = value", line_number))?;
+		let key: &str = key.trim();
+		let value: &str = value.trim();
+		
+		match key
+		{
+			"quality" => config.quality = Some(parse_quality(value, line_number)?),
+			"dithering" => config.dithering = Some(parse_string(value, line_number)?),
+			"smooth" => config.smooth = Some(value.parse::<f32>().map_err(|_| anyhow!("line {}: smooth must be a number between 0.0 and 5.0", line_number))?),
+			"denoise" => config.denoise = Some(parse_bool(value, line_number)?),
+			"lossless" => config.lossless = Some(parse_bool(value, line_number)?),
+			"excludes" | "exclude" => config.excludes = Some(parse_string_array(value, line_number)?),
+			"jobs" => config.jobs = Some(value.parse::<usize>().map_err(|_| anyhow!("line {}: jobs must be a non-negative integer", line_number))?),
+			_ => return Err(anyhow!("line {}: unknown key '{}'", line_number, key)),
+		}
+	}
+	
+	Ok(config)
+}
+
+/// Remove a trailing `#` comment, ignoring `#` characters inside quoted strings.
+fn strip_comment(line: &str) -> &str
+{
+	let mut in_string: bool = false;
+	for (index, c) in line.char_indices()
+	{
+		if c == '"'
+		{
+			in_string = !in_string;
+		}
+		else if c == '#' && !in_string
+		{
+			return &line[..index];
+		}
+	}
+	
+	line
+}
+
+/// Parse a double-quoted string value.
+fn parse_string(value: &str, line_number: usize) -> Result<String>
+{
+	if value.len() >= 2 && value.starts_with('"') && value.ends_with('"')
+	{
+		return Ok(value[1..value.len() - 1].to_string());
+	}
+	
+	Err(anyhow!("line {}: expected a quoted string", line_number))
+}
+
+/// Parse the quality: an integer, or the string "auto" to pick the quality per image.
+fn parse_quality(value: &str, line_number: usize) -> Result<u8>
+{
+	if value.starts_with('"')
+	{
+		return match parse_string(value, line_number)?.to_lowercase().as_str()
+		{
+			"auto" => Ok(minify::AUTO_QUALITY),
+			_ => Err(anyhow!("line {}: quality must be an integer between 1 and 100, or \"auto\"", line_number)),
+		};
+	}
+	
+	value.parse::<u8>().map_err(|_| anyhow!("line {}: quality must be an integer between 1 and 100, or \"auto\"", line_number))
+}
+
+/// Parse a boolean value.
+fn parse_bool(value: &str, line_number: usize) -> Result<bool>
+{
+	match value
+	{
+		"true" => Ok(true),
+		"false" => Ok(false),
+		_ => Err(anyhow!("line {}: expected true or false", line_number)),
+	}
+}
+
+/// Parse a single-line array of quoted strings, e.g. ["*.min.png", "build/*"].
+fn parse_string_array(value: &str, line_number: usize) -> Result<Vec<String>>
+{
+	if !value.starts_with('[') || !value.ends_with(']')
+	{
+		return Err(anyhow!("line {}: expected an array of strings", line_number));
+	}
+	
+	let inner: &str = value[1..value.len() - 1].trim();
+	let mut result: Vec<String> = Vec::new();
+	if inner.is_empty()
+	{
+		return Ok(result);
+	}
+	
+	for item in inner.split(',')
+	{
+		let item: &str = item.trim();
+		if item.is_empty()
+		{
+			continue; // Allow a trailing comma.
+		}
+		
+		result.push(parse_string(item, line_number)?);
+	}
+	
+	Ok(result)
+}
@@ -1,4 +1,5 @@
-use image::{DynamicImage, GenericImageView};
+use crate::error::{MinifyError, Result};
+use image::RgbaImage;
 use crate::minify::DitheringMode;
 
 /// Image characteristics that help determine optimal dithering.
@@ -21,20 +22,266 @@ pub struct ImageAnalysis
 	pub detail_frequency: f64,
 }
 
-/// Analyzes an image and recommends the best dithering mode.
+/// Thresholds used by the automatic dithering mode selection.
+/// Defaults are tuned for typical content and can be overridden with --thresholds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SelectionThresholds
+{
+	/// Gradient magnitude below which an image is considered smooth.
+	pub smooth_gradient: f64,
+	
+	/// Edge density below which an image is considered simple.
+	pub low_edge: f64,
+	
+	/// Local variance below which an image is considered uniform.
+	pub low_variance: f64,
+	
+	/// Color diversity below which an image is considered flat.
+	pub low_color_diversity: u32,
+	
+	/// Color diversity above which an image has many distinct colors.
+	pub moderate_color_diversity: u32,
+	
+	/// Color diversity above which an image is considered highly colorful.
+	pub high_color_diversity: u32,
+	
+	/// Detail frequency above which an image is considered photo-like.
+	pub high_detail_frequency: f64,
+	
+	/// Edge density above which an image is considered photo-like.
+	pub photo_edge: f64,
+	
+	/// Edge density above which an image is considered highly complex.
+	pub complex_edge: f64,
+	
+	/// Local variance above which an image is considered highly complex.
+	pub complex_variance: f64,
+	
+	/// Edge density below which moderately smooth images use ordered dithering.
+	pub ordered_edge: f64,
+}
+
+impl SelectionThresholds
+{
+	/// Create thresholds with the default tuned values.
+	#[allow(clippy::new_without_default)]
+	pub fn new() -> Self
+	{
+		SelectionThresholds
+		{
+			smooth_gradient: 5.0,
+			low_edge: 0.15,
+			low_variance: 200.0,
+			low_color_diversity: 100,
+			moderate_color_diversity: 300,
+			high_color_diversity: 500,
+			high_detail_frequency: 0.25,
+			photo_edge: 0.35,
+			complex_edge: 0.4,
+			complex_variance: 600.0,
+			ordered_edge: 0.3,
+		}
+	}
+	
+	/// Parse a comma-separated list of key=value overrides on top of the defaults.
+	/// Example: "photo_edge=0.3,low_variance=150".
+	pub fn parse(spec: &str) -> Result<Self>
+	{
+		let mut thresholds: SelectionThresholds = SelectionThresholds::new();
+		
+		for pair in spec.split(',')
+		{
+			let pair: &str = pair.trim();
+			if pair.is_empty()
+			{
+				continue;
+			}
+			
+			let (key, value): (&str, &str) = pair.split_once('=').ok_or_else(|| MinifyError::InvalidOptions(format!("Invalid threshold '{}': expected key=value", pair)))?;
+			let key: &str = key.trim();
+			let value: &str = value.trim();
+			
+			match key
+			{
+				"smooth_gradient" => thresholds.smooth_gradient = parse_threshold_f64(key, value)?,
+				"low_edge" => thresholds.low_edge = parse_threshold_f64(key, value)?,
+				"low_variance" => thresholds.low_variance = parse_threshold_f64(key, value)?,
+				"low_color_diversity" => thresholds.low_color_diversity = parse_threshold_u32(key, value)?,
+				"moderate_color_diversity" => thresholds.moderate_color_diversity = parse_threshold_u32(key, value)?,
+				"high_color_diversity" => thresholds.high_color_diversity = parse_threshold_u32(key, value)?,
+				"high_detail_frequency" => thresholds.high_detail_frequency = parse_threshold_f64(key, value)?,
+				"photo_edge" => thresholds.photo_edge = parse_threshold_f64(key, value)?,
+				"complex_edge" => thresholds.complex_edge = parse_threshold_f64(key, value)?,
+				"complex_variance" => thresholds.complex_variance = parse_threshold_f64(key, value)?,
+				"ordered_edge" => thresholds.ordered_edge = parse_threshold_f64(key, value)?,
+				_ => return Err(MinifyError::InvalidOptions(format!("Unknown threshold '{}'", key))),
+			}
+		}
+		
+		Ok(thresholds)
+	}
+}
+
+/// Kind of content an image holds, as far as the quality it needs goes (see classify_image).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageClass
+{
+	/// Photo-like content: high-frequency detail and many colors, which show quantization artifacts.
+	Photo,
+	
+	/// Flat content: UI assets, icons, illustrations and smooth gradients with few distinct colors.
+	Flat,
+	
+	/// Content in between that fits neither class (e.g. moderately detailed illustrations).
+	Other,
+}
+
+/// Name of an image class as used by --quality-per-type.
+pub fn image_class_to_string(class: ImageClass) -> &'static str
+{
+	match class
+	{
+		ImageClass::Photo => "photo",
+		ImageClass::Flat => "flat",
+		ImageClass::Other => "other",
+	}
+}
+
+/// Quality levels per image class (--quality-per-type).
+/// Images of a class without a quality, and images that fit neither class (ImageClass::Other), keep the regular quality level.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct QualityPerType
+{
+	/// Quality level (1-100) for photo-like images.
+	pub photo: Option<u8>,
+	
+	/// Quality level (1-100) for flat images.
+	pub flat: Option<u8>,
+}
+
+impl QualityPerType
+{
+	/// Parse a comma-separated list of class=quality pairs.
+	/// Example: "photo=70,flat=35".
+	pub fn parse(spec: &str) -> Result<Self>
+	{
+		let mut qualities: QualityPerType = QualityPerType::default();
+		
+		for pair in spec.split(',')
+		{
+			let pair: &str = pair.trim();
+			if pair.is_empty()
+			{
+				continue;
+			}
+			
+			let (key, value): (&str, &str) = pair.split_once('=').ok_or_else(|| MinifyError::InvalidOptions(format!("Invalid quality mapping '{}': expected class=quality", pair)))?;
+			let quality: u8 = match value.trim().parse::<u8>()
+			{
+				Ok(quality) if (1..=100).contains(&quality) => quality,
+				_ => return Err(MinifyError::InvalidOptions(format!("Invalid quality for class '{}': {} (expected 1-100)", key.trim(), value.trim()))),
+			};
+			
+			match key.trim()
+			{
+				"photo" => qualities.photo = Some(quality),
+				"flat" => qualities.flat = Some(quality),
+				_ => return Err(MinifyError::InvalidOptions(format!("Unknown image class '{}' (expected photo or flat)", key.trim()))),
+			}
+		}
+		
+		if qualities.photo.is_none() && qualities.flat.is_none()
+		{
+			return Err(MinifyError::InvalidOptions("Quality mapping is empty (expected e.g. photo=70,flat=35)".to_string()));
+		}
+		
+		Ok(qualities)
+	}
+	
+	/// Quality level mapped to an image class, if any.
+	pub fn quality_for(&self, class: ImageClass) -> Option<u8>
+	{
+		match class
+		{
+			ImageClass::Photo => self.photo,
+			ImageClass::Flat => self.flat,
+			ImageClass::Other => None,
+		}
+	}
+}
+
+/// Parse a non-negative floating point threshold value.
+fn parse_threshold_f64(key: &str, value: &str) -> Result<f64>
+{
+	let parsed: f64 = value.parse::<f64>().map_err(|_| MinifyError::InvalidOptions(format!("Invalid value for threshold '{}': {}", key, value)))?;
+	if !parsed.is_finite() || parsed < 0.0
+	{
+		return Err(MinifyError::InvalidOptions(format!("Threshold '{}' must be a non-negative number", key)));
+	}
+	Ok(parsed)
+}
+
+/// Parse an integer threshold value.
+fn parse_threshold_u32(key: &str, value: &str) -> Result<u32>
+{
+	value.parse::<u32>().map_err(|_| MinifyError::InvalidOptions(format!("Invalid value for threshold '{}': {}", key, value)))
+}
+
+/// Analyzes an already decoded RGBA buffer and recommends the best dithering mode.
 /// Returns the optimal mode from: None, Ordered, FloydSteinberg, or MedianCut.
-pub fn recommend_dithering_mode(img: &DynamicImage) -> DitheringMode
+pub fn recommend_dithering_mode(rgba: &RgbaImage, width: u32, height: u32, thresholds: &SelectionThresholds) -> DitheringMode
 {
-	let analysis: ImageAnalysis = analyze_image(img);
-	select_optimal_mode(&analysis)
+	let analysis: ImageAnalysis = analyze_image(rgba, width, height);
+	select_optimal_mode(&analysis, thresholds)
 }
 
-/// Analyze key image characteristics.
-fn analyze_image(img: &DynamicImage) -> ImageAnalysis
+/// Pick a quality level (30-75) for an image from its detail frequency and color diversity.
+/// Flat images (UI, icons, simple gradients) get an aggressive quality, detailed and colorful photos a higher one.
+pub fn recommend_quality(rgba: &RgbaImage, width: u32, height: u32) -> u8
 {
-	let rgba: image::RgbaImage = img.to_rgba8();
-	let (width, height): (u32, u32) = img.dimensions();
+	const MIN_AUTO_QUALITY: f64 = 30.0;
+	const MAX_AUTO_QUALITY: f64 = 75.0;
 	
+	if width == 0 || height == 0
+	{
+		return MIN_AUTO_QUALITY as u8;
+	}
+	
+	// Detail frequency saturates at 0.5 (half the samples are high-contrast), color diversity at 1000 buckets.
+	let detail: f64 = (calculate_detail_frequency(rgba, width, height) / 0.5).min(1.0);
+	let colors: f64 = (calculate_color_diversity(rgba) as f64 / 1000.0).min(1.0);
+	let score: f64 = 0.6 * detail + 0.4 * colors;
+	
+	(MIN_AUTO_QUALITY + score * (MAX_AUTO_QUALITY - MIN_AUTO_QUALITY)).round() as u8
+}
+
+/// Classify an image as photo-like or flat from its analysis metrics, with the same thresholds as the dithering selection.
+/// Images in between are ImageClass::Other.
+pub fn classify_image(rgba: &RgbaImage, width: u32, height: u32, thresholds: &SelectionThresholds) -> ImageClass
+{
+	let analysis: ImageAnalysis = analyze_image(rgba, width, height);
+	
+	// Smooth gradients and images with few colors.
+	let smooth: bool = analysis.gradient_smoothness < thresholds.smooth_gradient && analysis.edge_density < thresholds.low_edge && analysis.local_variance < thresholds.low_variance;
+	if smooth || analysis.color_diversity < thresholds.low_color_diversity
+	{
+		return ImageClass::Flat;
+	}
+	
+	// Fine detail over many colors, or highly complex content.
+	let detailed: bool = analysis.detail_frequency > thresholds.high_detail_frequency && analysis.color_diversity > thresholds.moderate_color_diversity;
+	let complex: bool = analysis.edge_density > thresholds.complex_edge && analysis.local_variance > thresholds.complex_variance && analysis.color_diversity > thresholds.high_color_diversity;
+	if detailed || complex
+	{
+		return ImageClass::Photo;
+	}
+	
+	ImageClass::Other
+}
+
+/// Analyze key image characteristics.
+fn analyze_image(rgba: &RgbaImage, width: u32, height: u32) -> ImageAnalysis
+{
 	if width == 0 || height == 0
 	{
 		return ImageAnalysis
@@ -48,19 +295,19 @@ fn analyze_image(img: &DynamicImage) -> ImageAnalysis
 	}
 	
 	// Calculate gradient smoothness.
-	let gradient_smoothness: f64 = calculate_gradient_smoothness(&rgba, width, height);
+	let gradient_smoothness: f64 = calculate_gradient_smoothness(rgba, width, height);
 	
 	// Calculate edge density.
-	let edge_density: f64 = calculate_edge_density(&rgba, width, height);
+	let edge_density: f64 = calculate_edge_density(rgba, width, height);
 	
 	// Calculate color diversity.
-	let color_diversity: u32 = calculate_color_diversity(&rgba);
+	let color_diversity: u32 = calculate_color_diversity(rgba);
 	
 	// Calculate local variance.
-	let local_variance: f64 = calculate_local_variance(&rgba, width, height);
+	let local_variance: f64 = calculate_local_variance(rgba, width, height);
 	
 	// Calculate detail frequency.
-	let detail_frequency: f64 = calculate_detail_frequency(&rgba, width, height);
+	let detail_frequency: f64 = calculate_detail_frequency(rgba, width, height);
 	
 	ImageAnalysis
 	{
@@ -114,10 +361,10 @@ fn calculate_edge_density(rgba: &image::RgbaImage, width: u32, height: u32) -> f
 	let mut edge_count: u32 = 0;
 	let mut total_pixels: u32 = 0;
 	
-	// Sample every 4th pixel for performance.
-	for y in (1..height-1).step_by(4)
+	// Sample every 4th pixel for performance (images under 3 pixels wide or tall have no interior pixels to sample).
+	for y in (1..height.saturating_sub(1)).step_by(4)
 	{
-		for x in (1..width-1).step_by(4)
+		for x in (1..width.saturating_sub(1)).step_by(4)
 		{
 			let gradient: f64 = calculate_pixel_gradient(rgba, x, y);
 			
@@ -252,10 +499,10 @@ fn calculate_detail_frequency(rgba: &image::RgbaImage, width: u32, height: u32)
 	let mut high_freq_count: u32 = 0;
 	let mut total_samples: u32 = 0;
 	
-	// Sample every 4th pixel.
-	for y in (2..height-2).step_by(4)
+	// Sample every 4th pixel (images under 5 pixels wide or tall have no pixels far enough from the border).
+	for y in (2..height.saturating_sub(2)).step_by(4)
 	{
-		for x in (2..width-2).step_by(4)
+		for x in (2..width.saturating_sub(2)).step_by(4)
 		{
 			// Check for high-frequency detail (rapid changes).
 			let center: &image::Rgba<u8> = rgba.get_pixel(x, y);
@@ -292,53 +539,49 @@ fn calculate_detail_frequency(rgba: &image::RgbaImage, width: u32, height: u32)
 
 /// Select optimal dithering mode based on analysis.
 /// Chooses between None, Ordered, FloydSteinberg and MedianCut.
-fn select_optimal_mode(analysis: &ImageAnalysis) -> DitheringMode
+fn select_optimal_mode(analysis: &ImageAnalysis, thresholds: &SelectionThresholds) -> DitheringMode
 {
-	// Thresholds tuned for optimal results.
-	const SMOOTH_GRADIENT_THRESHOLD: f64 = 5.0;
-	const LOW_EDGE_THRESHOLD: f64 = 0.15;
-	const LOW_VARIANCE_THRESHOLD: f64 = 200.0;
-	const LOW_COLOR_DIVERSITY: u32 = 100;
-	const MODERATE_COLOR_DIVERSITY: u32 = 300;
-	const HIGH_COLOR_DIVERSITY: u32 = 500;
-	const HIGH_DETAIL_FREQUENCY: f64 = 0.25;
-	const PHOTO_EDGE_THRESHOLD: f64 = 0.35;
-	
 	// Decision tree based on image characteristics.
 	
 	// Very smooth gradient with low complexity -> No dithering (cleanest).
-	if analysis.gradient_smoothness < SMOOTH_GRADIENT_THRESHOLD && analysis.edge_density < LOW_EDGE_THRESHOLD && analysis.local_variance < LOW_VARIANCE_THRESHOLD
+	// With many distinct shades, plain quantization would band, so use blue noise instead.
+	if analysis.gradient_smoothness < thresholds.smooth_gradient && analysis.edge_density < thresholds.low_edge && analysis.local_variance < thresholds.low_variance
 	{
+		if analysis.color_diversity > thresholds.moderate_color_diversity
+		{
+			return DitheringMode::BlueNoise;
+		}
+		
 		return DitheringMode::None;
 	}
 	
 	// Simple image with few colors -> No dithering.
-	if analysis.color_diversity < LOW_COLOR_DIVERSITY && analysis.edge_density < LOW_EDGE_THRESHOLD
+	if analysis.color_diversity < thresholds.low_color_diversity && analysis.edge_density < thresholds.low_edge
 	{
 		return DitheringMode::None;
 	}
 	
 	// Many distinct colors but not photo-like -> MedianCut (great for logos, illustrations, UI).
 	// High color diversity with moderate edges suggests distinct color regions rather than smooth gradients.
-	if analysis.color_diversity > MODERATE_COLOR_DIVERSITY  && analysis.color_diversity < HIGH_COLOR_DIVERSITY && analysis.edge_density > LOW_EDGE_THRESHOLD  && analysis.edge_density < PHOTO_EDGE_THRESHOLD && analysis.detail_frequency < HIGH_DETAIL_FREQUENCY
+	if analysis.color_diversity > thresholds.moderate_color_diversity && analysis.color_diversity < thresholds.high_color_diversity && analysis.edge_density > thresholds.low_edge && analysis.edge_density < thresholds.photo_edge && analysis.detail_frequency < thresholds.high_detail_frequency
 	{
 		return DitheringMode::MedianCut;
 	}
 	
 	// Photo-like with high detail frequency -> Floyd-Steinberg for best quality.
-	if analysis.detail_frequency > HIGH_DETAIL_FREQUENCY && analysis.edge_density > PHOTO_EDGE_THRESHOLD && analysis.color_diversity > MODERATE_COLOR_DIVERSITY
+	if analysis.detail_frequency > thresholds.high_detail_frequency && analysis.edge_density > thresholds.photo_edge && analysis.color_diversity > thresholds.moderate_color_diversity
 	{
 		return DitheringMode::FloydSteinberg;
 	}
 	
 	// High complexity photo-like content -> Floyd-Steinberg.
-	if analysis.edge_density > 0.4 && analysis.local_variance > 600.0 && analysis.color_diversity > HIGH_COLOR_DIVERSITY
+	if analysis.edge_density > thresholds.complex_edge && analysis.local_variance > thresholds.complex_variance && analysis.color_diversity > thresholds.high_color_diversity
 	{
 		return DitheringMode::FloydSteinberg;
 	}
 	
 	// Moderately complex but still fairly smooth -> Ordered dithering.
-	if analysis.gradient_smoothness < SMOOTH_GRADIENT_THRESHOLD * 2.0 && analysis.edge_density < 0.3
+	if analysis.gradient_smoothness < thresholds.smooth_gradient * 2.0 && analysis.edge_density < thresholds.ordered_edge
 	{
 		return DitheringMode::Ordered;
 	}
@@ -346,3 +589,30 @@ fn select_optimal_mode(analysis: &ImageAnalysis) -> DitheringMode
 	// Default: Ordered dithering as safe middle ground.
 	DitheringMode::Ordered
 }
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+	
+	#[test]
+	fn lowering_photo_edge_flips_a_borderline_image_to_floyd_steinberg()
+	{
+		// Detailed and colorful, but with slightly fewer edges than the default photo threshold asks for.
+		let borderline: ImageAnalysis = ImageAnalysis
+		{
+			gradient_smoothness: 20.0,
+			edge_density: 0.32,
+			color_diversity: 600,
+			local_variance: 300.0,
+			detail_frequency: 0.3,
+		};
+		
+		let defaults: SelectionThresholds = SelectionThresholds::new();
+		assert_eq!(select_optimal_mode(&borderline, &defaults), DitheringMode::Ordered);
+		
+		let lowered: SelectionThresholds = SelectionThresholds::parse("photo_edge=0.3").expect("Valid thresholds");
+		assert_eq!(lowered, SelectionThresholds { photo_edge: 0.3, ..defaults });
+		assert_eq!(select_optimal_mode(&borderline, &lowered), DitheringMode::FloydSteinberg);
+	}
+}
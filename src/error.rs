@@ -0,0 +1,147 @@
+use std::fmt;
+
+/// Errors returned by the minification core.
+/// The command line tool converts them into anyhow errors with `?`, library callers can match on the kind of failure.
+#[derive(Debug)]
+pub enum MinifyError
+{
+	/// The data doesn't start with the PNG signature.
+	InvalidSignature,
+	
+	/// The PNG structure is damaged (chunk lengths, missing IHDR or IEND).
+	Malformed(String),
+	
+	/// The image data couldn't be decoded.
+	Decode(String),
+	
+	/// The output couldn't be encoded or optimized.
+	Encode(String),
+	
+	/// The output didn't match the source when checked with verify.
+	Verification(String),
+	
+	/// The data already carries a MiniPNG marker (for callers that don't re-minify marked files).
+	AlreadyMinified,
+	
+	/// The options are invalid or contradict each other.
+	InvalidOptions(String),
+	
+	/// Reading or writing a file failed.
+	Io
+	{
+		/// What was being done, e.g. "Failed to read source file".
+		context: String,
+		
+		/// The underlying I/O error.
+		source: std::io::Error,
+	},
+}
+
+impl MinifyError
+{
+	/// Wrap an I/O error with a description of what was being done.
+	pub fn io(context: &str, source: std::io::Error) -> Self
+	{
+		MinifyError::Io { context: context.to_string(), source }
+	}
+	
+	/// Name the stage that failed in front of the message (e.g. "quantizing: ..."), keeping the kind of error.
+	/// Errors without a message are returned as they are.
+	pub fn context(self, stage: &str) -> Self
+	{
+		match self
+		{
+			MinifyError::Malformed(message) => MinifyError::Malformed(format!("{}: {}", stage, message)),
+			MinifyError::Decode(message) => MinifyError::Decode(format!("{}: {}", stage, message)),
+			MinifyError::Encode(message) => MinifyError::Encode(format!("{}: {}", stage, message)),
+			MinifyError::Verification(message) => MinifyError::Verification(format!("{}: {}", stage, message)),
+			MinifyError::InvalidOptions(message) => MinifyError::InvalidOptions(format!("{}: {}", stage, message)),
+			MinifyError::Io { context, source } => MinifyError::Io { context: format!("{}: {}", stage, context), source },
+			MinifyError::InvalidSignature | MinifyError::AlreadyMinified => self,
+		}
+	}
+}
+
+impl fmt::Display for MinifyError
+{
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+	{
+		match self
+		{
+			MinifyError::InvalidSignature => write!(f, "Invalid PNG signature"),
+			MinifyError::Malformed(message) => write!(f, "{}", message),
+			MinifyError::Decode(message) => write!(f, "{}", message),
+			MinifyError::Encode(message) => write!(f, "{}", message),
+			MinifyError::Verification(message) => write!(f, "{}", message),
+			MinifyError::AlreadyMinified => write!(f, "PNG is already minified"),
+			MinifyError::InvalidOptions(message) => write!(f, "{}", message),
+			MinifyError::Io { context, source } => write!(f, "{}: {}", context, source),
+		}
+	}
+}
+
+impl std::error::Error for MinifyError
+{
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)>
+	{
+		match self
+		{
+			MinifyError::Io { source, .. } => Some(source),
+			_ => None,
+		}
+	}
+}
+
+/// Result type of the minification core.
+pub type Result<T> = std::result::Result<T, MinifyError>;
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+	use crate::minify::{self, MinifyOptions};
+	use crate::test_utils;
+	
+	#[test]
+	fn each_failure_path_returns_its_own_kind()
+	{
+		let options: MinifyOptions = MinifyOptions::builder().quality(40).fast_deflate(true).build().expect("Valid options");
+		let source: Vec<u8> = test_utils::encode_png(&test_utils::photo_like(32, 32));
+		
+		let idat_data: usize = source.windows(4).position(|window: &[u8]| window == b"IDAT").expect("Missing IDAT") + 4;
+		
+		assert!(matches!(minify::minify_bytes(b"GIF89a, not a PNG", &options), Err(MinifyError::InvalidSignature)));
+		
+		// The chunk walk finds the IDAT chunk cut short, decoding finds the pixel data cut short.
+		let truncated: &[u8] = &source[..idat_data + 16];
+		assert!(matches!(minify::is_already_minified(truncated), Err(MinifyError::Malformed(_))));
+		assert!(matches!(minify::minify_bytes(truncated, &options), Err(MinifyError::Decode(_))));
+		
+		// Damaged compressed pixel data: the structure is intact, but decoding fails.
+		let mut corrupted: Vec<u8> = source.clone();
+		for byte in &mut corrupted[idat_data..idat_data + 16]
+		{
+			*byte = !*byte;
+		}
+		assert!(matches!(minify::minify_bytes(&corrupted, &options), Err(MinifyError::Decode(_))));
+		
+		assert!(matches!(MinifyOptions::builder().quality(101).build(), Err(MinifyError::InvalidOptions(_))));
+	}
+	
+	#[cfg(feature = "cli")]
+	#[test]
+	fn missing_files_are_io_errors_with_their_source()
+	{
+		let error: MinifyError = minify::read_minification_info(std::path::Path::new("does/not/exist.png")).expect_err("A missing file should be an error");
+		assert!(matches!(&error, MinifyError::Io { source, .. } if source.kind() == std::io::ErrorKind::NotFound), "{}", error);
+		assert!(std::error::Error::source(&error).is_some());
+	}
+	
+	#[test]
+	fn context_keeps_the_kind_of_error()
+	{
+		let error: MinifyError = MinifyError::Decode("Bad data".to_string()).context("decoding source PNG");
+		assert!(matches!(&error, MinifyError::Decode(message) if message == "decoding source PNG: Bad data"));
+		assert!(matches!(MinifyError::AlreadyMinified.context("checking marker"), MinifyError::AlreadyMinified));
+	}
+}
@@ -0,0 +1,113 @@
+// Hilbert curve scan order for error diffusion.
+// The curve visits every pixel of a square through steps to adjacent pixels and keeps turning,
+// so the diffused error has no preferred direction (unlike row-by-row scans, even serpentine ones).
+// Rectangular images are covered with a row (or column) of square power-of-two tiles, each walked by its own curve,
+// and the tile positions outside the image are skipped.
+
+/// Visit order of all pixels of a width x height image along Hilbert curves, each pixel exactly once.
+pub fn hilbert_order(width: u32, height: u32) -> Vec<(u32, u32)>
+{
+	let mut order: Vec<(u32, u32)> = Vec::with_capacity(width as usize * height as usize);
+	if width == 0 || height == 0
+	{
+		return order;
+	}
+	
+	// One side of the image fits in a single tile, so the tiles form a single row or column.
+	let side: u32 = width.min(height).next_power_of_two();
+	let tiles_x: u32 = width.div_ceil(side);
+	let tiles_y: u32 = height.div_ceil(side);
+	for tile in 0..tiles_x * tiles_y
+	{
+		let (tile_x, tile_y): (u32, u32) = ((tile % tiles_x) * side, (tile / tiles_x) * side);
+		for index in 0..side as u64 * side as u64
+		{
+			let (x, y): (u32, u32) = hilbert_point(side, index);
+			if tile_x + x < width && tile_y + y < height
+			{
+				order.push((tile_x + x, tile_y + y));
+			}
+		}
+	}
+	
+	order
+}
+
+/// Position of the index-th point of the Hilbert curve filling a side x side square (side is a power of two).
+fn hilbert_point(side: u32, index: u64) -> (u32, u32)
+{
+	let mut x: u32 = 0;
+	let mut y: u32 = 0;
+	let mut remaining: u64 = index;
+	let mut size: u32 = 1;
+	while size < side
+	{
+		let rx: u32 = (1 & (remaining / 2)) as u32;
+		let ry: u32 = (1 & (remaining ^ rx as u64)) as u32;
+		
+		// Rotate the quadrant so the sub-curves join up.
+		if ry == 0
+		{
+			if rx == 1
+			{
+				x = size - 1 - x;
+				y = size - 1 - y;
+			}
+			std::mem::swap(&mut x, &mut y);
+		}
+		
+		x += size * rx;
+		y += size * ry;
+		remaining /= 4;
+		size *= 2;
+	}
+	
+	(x, y)
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+	
+	/// Checks that an order lists every pixel of the image exactly once.
+	fn visits_every_pixel_once(order: &[(u32, u32)], width: u32, height: u32) -> bool
+	{
+		let mut visited: Vec<bool> = vec![false; width as usize * height as usize];
+		for &(x, y) in order
+		{
+			let index: usize = y as usize * width as usize + x as usize;
+			if x >= width || y >= height || visited[index]
+			{
+				return false;
+			}
+			visited[index] = true;
+		}
+		
+		visited.iter().all(|&seen| seen)
+	}
+	
+	#[test]
+	fn square_order_visits_every_pixel_once_through_neighbors()
+	{
+		let order: Vec<(u32, u32)> = hilbert_order(32, 32);
+		
+		assert!(visits_every_pixel_once(&order, 32, 32));
+		assert_eq!(order[0], (0, 0));
+		for step in order.windows(2)
+		{
+			let distance: u32 = step[0].0.abs_diff(step[1].0) + step[0].1.abs_diff(step[1].1);
+			assert_eq!(distance, 1, "{:?} -> {:?} is not a step to a neighbor", step[0], step[1]);
+		}
+	}
+	
+	#[test]
+	fn rectangular_order_visits_every_pixel_once()
+	{
+		for (width, height) in [(1, 1), (10, 7), (7, 10), (100, 3), (33, 64)]
+		{
+			assert!(visits_every_pixel_once(&hilbert_order(width, height), width, height), "{}x{}", width, height);
+		}
+		assert!(hilbert_order(0, 5).is_empty());
+	}
+}
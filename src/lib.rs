@@ -0,0 +1,49 @@
+// Core of MiniPNG: minification of in-memory PNG data (see minify::minify_bytes).
+// Without the "cli" feature, it has no file system, thread pool or terminal access, so it also builds for wasm32-unknown-unknown
+// with `cargo build --lib --no-default-features`. The command line tool (main.rs) and minify::read_minification_info require the "cli" feature.
+
+pub mod compare;
+pub mod error;
+pub mod minify;
+pub mod dithering;
+pub mod median;
+pub mod octree;
+pub mod palette;
+pub mod blue_noise;
+pub mod hilbert;
+pub mod utils
+{
+	pub mod color_utils;
+	pub mod crc_utils;
+	pub mod hash_utils;
+	pub mod par_utils;
+	pub mod time_utils;
+}
+
+#[cfg(test)]
+mod test_utils;
+
+#[cfg(test)]
+mod tests
+{
+	use std::path::PathBuf;
+	use std::process::{Command, Output};
+	
+	/// Check the core without the "cli" feature, as a wasm32 build compiles it.
+	/// Uses its own target directory so it doesn't wait for the lock held by the running build.
+	#[test]
+	fn core_builds_without_the_cli_feature()
+	{
+		let cargo: String = std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+		let target_dir: PathBuf = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("target").join("core-check");
+		let output: Output = Command::new(cargo)
+			.args(["check", "--lib", "--no-default-features", "--offline", "--quiet", "--target-dir"])
+			.arg(&target_dir)
+			.current_dir(env!("CARGO_MANIFEST_DIR"))
+			.env("RUSTFLAGS", "-D warnings")
+			.output()
+			.expect("Failed to run cargo");
+		
+		assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+	}
+}
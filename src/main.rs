@@ -1,77 +1,434 @@
 use anyhow::{anyhow, Result};
 use rayon::prelude::*;
+use std::io::IsTerminal;
 use std::sync::{Arc, Mutex};
 
-mod minify;
-mod dithering;
-mod median;
+mod config;
+// The library's test images, shared with the binary's tests (which use only some of them).
+#[cfg(test)]
+#[allow(dead_code)]
+mod test_utils;
 mod utils
 {
 	pub mod arg_utils;
-	pub mod crc_utils;
+	pub mod backup_utils;
+	pub mod cache_utils;
 	pub mod file_utils;
-	pub mod time_utils;
+	pub mod ignore_utils;
+	pub mod log_utils;
+	pub mod progress_utils;
+	pub mod report_utils;
+	pub mod stream_utils;
+	pub mod term_utils;
+	pub use minipng::utils::time_utils;
 }
+use minipng::{dithering, minify};
 use utils::arg_utils::{Args, Mode, determine_mode};
-use utils::file_utils::{is_png_file, find_png_files_in_dir, prepare_specific_png_files, process_file};
-use utils::time_utils::format_timestamp;
+use utils::backup_utils::BackupStore;
+use utils::cache_utils::AnalysisCache;
+use utils::progress_utils::ProgressLog;
+use utils::file_utils::{find_png_files_in_dir, prepare_specific_png_files, dedupe_png_files, redirect_targets, process_file, TimeBudget};
+use utils::report_utils::{calculate_reduction_pct, ReportFormat};
+use utils::stream_utils::{finish_walk, fold_stream, stream_png_files, StreamTotals};
+use utils::term_utils::{paint, StatusColor};
+use utils::time_utils::{format_timestamp, format_duration, format_iso8601};
+
+/// println! for human-readable output, which goes to stderr instead while a machine-readable report (--report) owns stdout.
+/// The line is also written to the --log-file log, if any.
+macro_rules! sayln
+{
+	() =>
+	{
+		sayln!("")
+	};
+	($($arg:tt)*) =>
+	{{
+		if $crate::utils::term_utils::human_output_to_stderr()
+		{
+			eprintln!($($arg)*);
+		}
+		else
+		{
+			println!($($arg)*);
+		}
+		if $crate::utils::log_utils::log_enabled()
+		{
+			$crate::utils::log_utils::log_line(&format!($($arg)*));
+		}
+	}};
+}
+
+/// eprintln! for errors and warnings, also written to the --log-file log.
+macro_rules! errln
+{
+	($($arg:tt)*) =>
+	{{
+		eprintln!($($arg)*);
+		if $crate::utils::log_utils::log_enabled()
+		{
+			$crate::utils::log_utils::log_line(&format!($($arg)*));
+		}
+	}};
+}
+
+/// Exit code when one or more files failed.
+const EXIT_FILE_ERRORS: u8 = 2;
+
+/// Exit code when no file got smaller and --fail-on-no-savings is set.
+const EXIT_NO_SAVINGS: u8 = 3;
 
 /// Minify PNG files with imperceptible quality loss.
-fn main() -> Result<()>
+/// Exits with 0 on success, 1 on a fatal error (such as invalid arguments), EXIT_FILE_ERRORS when some files failed
+/// and EXIT_NO_SAVINGS when nothing was saved with --fail-on-no-savings.
+fn main() -> Result<std::process::ExitCode>
 {
+	// Record the start time for the summary.
+	let start_time: std::time::Instant = std::time::Instant::now();
+	
 	// Parse command line arguments.
 	let args: Args = Args::parse()?;
 	
+	run(args, start_time)
+}
+
+/// Run with the parsed command line arguments, timing the run (and --time-budget) from start_time.
+fn run(mut args: Args, start_time: std::time::Instant) -> Result<std::process::ExitCode>
+{
+	// Merge project defaults from .minipng.toml (command line flags take precedence).
+	let config_path: Option<std::path::PathBuf> = args.load_config()?;
+	
+	// Resolve a named quality preset (an explicit --quality wins).
+	args.apply_preset(args.is_explicitly_set("--quality") || args.is_explicitly_set("-q"));
+	
 	// Validate parameters using the centralized validation method.
 	args.validate()?;
 	
+	// Mirror the output to the log file, if requested.
+	if let Some(path) = &args.log_file
+	{
+		utils::log_utils::open_log(path)?;
+	}
+	
+	// Keep stdout for the machine-readable report, if any, and enable colored status labels when writing to a terminal.
+	utils::term_utils::set_human_output_to_stderr(args.report != ReportFormat::Human);
+	utils::term_utils::init_color(args.no_color);
+	
+	// Undo an earlier run from its backups instead of minifying.
+	if let Some(backup_dir) = &args.restore
+	{
+		return run_restore(backup_dir);
+	}
+	
+	// Show the recorded minification settings instead of minifying.
+	if args.info
+	{
+		return Ok(run_info(&args.files));
+	}
+	
+	// Limit the number of files processed in parallel if requested.
+	if args.jobs > 0
+	{
+		rayon::ThreadPoolBuilder::new().num_threads(args.jobs).build_global()
+			.map_err(|e| anyhow!("Failed to configure thread pool: {}", e))?;
+	}
+	
 	// Parse dithering mode.
 	let dithering_mode: minify::DitheringMode = match args.dithering.to_lowercase().as_str()
 	{
 		"auto" => minify::DitheringMode::Auto,
 		"none" => minify::DitheringMode::None,
 		"ordered" => minify::DitheringMode::Ordered,
+		"bluenoise" | "blue-noise" => minify::DitheringMode::BlueNoise,
 		"floyd" | "floyd-steinberg" => minify::DitheringMode::FloydSteinberg,
 		"mediancut" | "median" => minify::DitheringMode::MedianCut,
-		_ => return Err(anyhow!("Invalid dithering mode. Use: auto, none, ordered, floyd, or median")),
+		_ => return Err(anyhow!("Invalid dithering mode. Use: auto, none, ordered, bluenoise, floyd, or median")),
+	};
+	
+	// Load the fixed palette to map colors onto, if one was given.
+	let fixed_palette: Option<Vec<[u8; 3]>> = match &args.palette_from
+	{
+		Some(path) if !args.lossless =>
+		{
+			let palette_data: Vec<u8> = std::fs::read(path)
+				.map_err(|e| anyhow!("Failed to read palette file {}: {}", path.display(), e))?;
+			Some(minipng::palette::parse_palette(&palette_data)
+				.map_err(|e| anyhow!("Invalid palette file {}: {}", path.display(), e))?)
+		},
+		_ => None,
 	};
 	
+	// Collect the minification settings (the quality level only applies to lossy mode).
+	let mut options_builder: minify::MinifyOptionsBuilder = minify::MinifyOptions::builder()
+		.lossless(args.lossless)
+		.dithering(dithering_mode)
+		.thresholds(args.thresholds)
+		.quality_per_type(args.quality_per_type)
+		.smooth_radius(args.smooth)
+		.denoise(args.denoise)
+		.denoise_strength(args.denoise_strength)
+		.denoise_block(args.denoise_block)
+		.grayscale(args.grayscale)
+		.alpha_threshold(args.alpha_threshold)
+		.dither_alpha(args.dither_alpha)
+		.quantize_alpha(args.quantize_alpha)
+		.linear(args.linear)
+		.gamma(args.gamma.unwrap_or(minipng::utils::color_utils::SRGB_GAMMA))
+		.premultiply(args.premultiply)
+		.flatten(args.flatten)
+		.verify(args.verify)
+		.diff_pixels(args.diff_pixels)
+		.indexed(args.indexed)
+		.keep_bit_depth(args.keep_bit_depth)
+		.strip_all(args.strip_all)
+		.min_reduction(args.min_reduction)
+		.always_write(args.always_write)
+		.skip_unchanged_output(args.skip_unchanged_output)
+		.also_webp(args.also_webp)
+		.safe(args.safe)
+		.max_pixels(args.max_pixels)
+		.interlace(args.interlace)
+		.scan(args.scan)
+		.mark_noop(args.mark_noop)
+		.fallback_lossless(args.fallback_lossless && !args.lossless)
+		.auto_mode(args.auto_mode && !args.lossless)
+		.strip_trailing(args.strip_trailing)
+		.deterministic(args.deterministic)
+		.hashes(args.manifest.is_some())
+		.temp_dir(args.temp_dir.clone())
+		.marker_text(args.marker_text.clone())
+		.if_exists(args.if_exists)
+		.force_writable(args.force_writable)
+		.reuse_settings(args.reuse_settings)
+		.reminify_before(args.reminify_before)
+		.keep_phys(args.keep_phys)
+		.keep_text(args.keep_text)
+		.refine(args.refine)
+		.sample_step(args.sample_step)
+		.quantizer(args.quantizer)
+		.median_colors(args.median_colors)
+		.channel_factors(args.factors)
+		.fast_deflate(args.estimate)
+		.fixed_palette(fixed_palette.clone());
+	if !args.lossless
+	{
+		options_builder = options_builder.quality(args.quality);
+	}
+	let options: minify::MinifyOptions = options_builder.build()?;
+	
+	// Pipeline mode: read from stdin, write to stdout, report on stderr.
+	if args.stdin
+	{
+		run_stdin_pipeline(&options)?;
+		return Ok(std::process::ExitCode::SUCCESS);
+	}
+	
+	// Comparison mode: write the input next to its minified version, leaving the input untouched.
+	if let Some(compare_path) = &args.compare
+	{
+		run_compare(&args.files[0], compare_path, &options)?;
+		return Ok(std::process::ExitCode::SUCCESS);
+	}
+	
+	// Preview mode: write the input quantized with each dithering mode, leaving the input untouched.
+	if let Some(preview_path) = &args.preview
+	{
+		run_preview(&args.files[0], preview_path, &options)?;
+		return Ok(std::process::ExitCode::SUCCESS);
+	}
+	
+	// Palette mode: write the median cut palette of the input, leaving the input untouched.
+	if let Some(dump_path) = &args.dump_palette
+	{
+		run_dump_palette(&args.files[0], dump_path, &options)?;
+		return Ok(std::process::ExitCode::SUCCESS);
+	}
+	
 	// Determine the mode of operation.
-	let mode: Mode = determine_mode(&args, is_png_file)?;
+	let mode: Mode = determine_mode(&args)?;
 	
 	// Print the processing settings with logical grouping.
-	println!("Settings:");
-	println!("----------------------------------------");
+	sayln!("Settings:");
+	sayln!("----------------------------------------");
 	
 	// 1. Input/Output Parameters.
-	println!("INPUT/OUTPUT:");
+	sayln!("INPUT/OUTPUT:");
 	if let Some(dir) = &args.dir
 	{
-		println!("  - Directory: {}", dir.display());
+		sayln!("  - Directory: {}", dir.display());
 	}
-	let mode_desc: &str = match determine_mode(&args, is_png_file)?
+	let mode_desc: &str = match &mode
 	{
 		Mode::Directory(_) => "Directory Mode",
 		Mode::Files(_) => "Specific Files Mode",
+		Mode::Combined(_, _) => "Directory + Specific Files Mode",
+	};
+	sayln!("  - Mode: {}", mode_desc);
+	match &args.output
+	{
+		Some(output) => sayln!("  - Output: {}", output.display()),
+		None => sayln!("  - In-place: {}", if args.inplace { "Yes" } else { "No" }),
+	}
+	let if_exists_desc: &str = match args.if_exists
+	{
+		minify::IfExists::Skip => "Skip",
+		minify::IfExists::Overwrite => "Overwrite",
+		minify::IfExists::Error => "Error",
 	};
-	println!("  - Mode: {}", mode_desc);
-	println!("  - In-place: {}", if args.inplace { "Yes" } else { "No" });
+	sayln!("  - Existing targets: {}", if_exists_desc);
+	if args.skip_unchanged_output
+	{
+		sayln!("  - Unreduced files: Not written to the output");
+	}
+	if args.also_webp
+	{
+		sayln!("  - WebP: Also written next to each output (lossless)");
+	}
+	sayln!("  - Read-only targets: {}", if args.force_writable { "Write (restore read-only afterward)" } else { "Skip" });
+	if !args.excludes.is_empty()
+	{
+		sayln!("  - Excludes: {}", args.excludes.join(", "));
+	}
+	if args.no_recurse
+	{
+		sayln!("  - Subdirectories: Not scanned");
+	}
+	if let Some(path) = &config_path
+	{
+		sayln!("  - Config: {}", path.display());
+	}
+	sayln!("  - Analysis cache: {}", if args.cache { utils::cache_utils::CACHE_FILE_NAME } else { "Disabled" });
+	if let Some(dir) = &args.backup_dir
+	{
+		sayln!("  - Backup directory: {}", dir.display());
+	}
+	if args.resume
+	{
+		sayln!("  - Resume: Skipping files listed in {} and recording completed ones", utils::progress_utils::PROGRESS_FILE_NAME);
+	}
+	if let Some(order) = args.sort
+	{
+		let sort_desc: &str = match order
+		{
+			utils::file_utils::SortOrder::Name => "By name",
+			utils::file_utils::SortOrder::Size => "Largest files first",
+			utils::file_utils::SortOrder::Savings => "Summary lists all files by bytes saved",
+		};
+		sayln!("  - Sort: {}", sort_desc);
+	}
+	if let Some(budget) = args.time_budget
+	{
+		sayln!("  - Time budget: {} (no new files are started after that)", format_duration(budget));
+	}
+	if let Some(dir) = &args.temp_dir
+	{
+		sayln!("  - Temporary files: {}", dir.display());
+	}
+	if let Some(marker_text) = &args.marker_text
+	{
+		sayln!("  - Marker text: {}", marker_text);
+	}
 	
 	// 2. Operation Mode Parameters.
-	println!("\nOPERATION:");
-	println!("  - Lossless: {}", if args.lossless { "Yes" } else { "Off" });
-	println!("  - Force re-minify: {}", if args.force { "Yes" } else { "Off" });
-	println!("  - Skip already-minified: {}", if args.skip { "Yes" } else { "Off" });
+	sayln!("\nOPERATION:");
+	sayln!("  - Lossless: {}", if args.lossless { "Yes" } else { "Off" });
+	sayln!("  - Force re-minify: {}", if args.force { "Yes" } else { "Off" });
+	if args.interactive
+	{
+		sayln!("  - Interactive: Yes (ask for each already-minified file)");
+	}
+	if args.reuse_settings
+	{
+		sayln!("  - Reuse recorded settings: Yes (when re-minifying)");
+	}
+	if let Some(cutoff) = args.reminify_before
+	{
+		sayln!("  - Re-minify files minified before: {}", format_timestamp(&format_iso8601(cutoff)));
+	}
+	sayln!("  - Skip already-minified: {}", if args.skip { "Yes" } else { "Off" });
+	sayln!("  - Verify output: {}", if args.verify { "Yes" } else { "Off" });
+	if args.diff_pixels
+	{
+		sayln!("  - Pixel diff: Yes (outputs with any changed pixel are rejected and the original kept)");
+	}
+	sayln!("  - Strip chunks: {}", if args.strip_all { "All ancillary chunks" } else { "Safe" });
+	if args.min_reduction > 0.0
+	{
+		sayln!("  - Minimum reduction: {}%", args.min_reduction);
+	}
+	if args.always_write
+	{
+		sayln!("  - Always write: Yes (even when not smaller)");
+	}
+	if args.mark_noop && !args.lossless
+	{
+		sayln!("  - Mark unreduced files: Yes (later runs skip them)");
+	}
+	if args.fallback_lossless && !args.lossless
+	{
+		sayln!("  - Lossless fallback: Yes (when lossy output isn't smaller)");
+	}
+	if args.auto_mode && !args.lossless
+	{
+		sayln!("  - Auto mode: Yes (lossy only when {}% smaller than lossless and at least {} dB PSNR)", minify::AUTO_MODE_MIN_SAVINGS * 100.0, minify::AUTO_MODE_MIN_PSNR);
+	}
+	if args.strip_trailing
+	{
+		sayln!("  - Strip trailing data: Yes (bytes after IEND)");
+	}
+	sayln!("  - Bit depth: {}", if args.keep_bit_depth { "Kept (no bit depth, color type or palette reduction)" } else { "Reduced when possible" });
+	if args.deterministic
+	{
+		sayln!("  - Deterministic output: Yes (fixed filter, single-threaded optimizer, no marker timestamp)");
+	}
+	let interlace_desc: &str = match args.interlace
+	{
+		minify::InterlaceMode::None => "None (de-interlace)",
+		minify::InterlaceMode::Adam7 => "Adam7",
+		minify::InterlaceMode::Keep => "Keep source interlacing",
+	};
+	sayln!("  - Interlacing: {}", interlace_desc);
+	if let Some(max_pixels) = args.max_pixels
+	{
+		sayln!("  - Maximum image size: {} pixels", max_pixels);
+	}
+	if args.safe
+	{
+		sayln!("  - Safe mode: Yes (files already quantized by another tool are minified losslessly)");
+	}
+	if args.jobs > 0
+	{
+		sayln!("  - Parallel jobs: {}", args.jobs);
+	}
+	else
+	{
+		sayln!("  - Parallel jobs: Auto ({} cores)", rayon::current_num_threads());
+	}
+	if args.threads_per_file > 0
+	{
+		sayln!("  - Optimizer threads per file: {}", args.threads_per_file);
+	}
 	
 	// 3. Image Quality Parameters.
-	println!("\nIMAGE QUALITY:");
+	sayln!("\nIMAGE QUALITY:");
 	if !args.lossless
 	{
-		println!("  - Quality: {}", args.quality);
+		if args.quality == minify::AUTO_QUALITY
+		{
+			sayln!("  - Quality: Auto");
+		}
+		else if let Some(preset) = args.preset && args.quality == preset.quality()
+		{
+			sayln!("  - Quality: {} (preset {})", args.quality, preset.name());
+		}
+		else
+		{
+			sayln!("  - Quality: {}", args.quality);
+		}
 		
 		// Add quality level description.
 		let quality_desc: &str = match args.quality
 		{
+			minify::AUTO_QUALITY => "Picked per image - flat images get smaller files, detailed photos higher quality",
 			1..=40 => "Aggressive minification - smallest files, good quality (DEFAULT)",
 			41..=55 => "Balanced minification - small files, very good quality",
 			56..=65 => "High quality minification - medium files, excellent quality",
@@ -79,7 +436,12 @@ fn main() -> Result<()>
 			76..=100 => "Maximum quality - largest files, perfect quality",
 			_ => "Custom quality level",
 		};
-		println!("    ({}", quality_desc);
+		sayln!("    ({}", quality_desc);
+		if let Some(qualities) = args.quality_per_type
+		{
+			let describe = |quality: Option<u8>| quality.map_or("--quality".to_string(), |quality| quality.to_string());
+			sayln!("  - Quality per image class: photo {}, flat {}, other --quality", describe(qualities.photo), describe(qualities.flat));
+		}
 		
 		// Add downsampling factor info.
 		let downsampling_factor: i32 = match args.quality
@@ -89,7 +451,12 @@ fn main() -> Result<()>
 			56..=70 => 12,
 			_ => 8,
 		};
-		println!("     Downsampling: ÷{}, Colors reduced for minification)", downsampling_factor);
+		match args.factors
+		{
+			Some([r, g, b]) => sayln!("     Downsampling: R ÷{}, G ÷{}, B ÷{} (per-channel factors)", r, g, b),
+			None if args.quality == minify::AUTO_QUALITY => sayln!("     Downsampling: follows the quality picked for each image)"),
+			None => sayln!("     Downsampling: ÷{}, Colors reduced for minification)", downsampling_factor),
+		}
 		
 		// Add dithering mode info.
 		let dithering_desc: &str = match dithering_mode
@@ -97,56 +464,147 @@ fn main() -> Result<()>
 			minify::DitheringMode::Auto => "Auto (analyzes image to select best mode)",
 			minify::DitheringMode::None => "None (clean gradients, may show banding)",
 			minify::DitheringMode::Ordered => "Ordered/Bayer (balanced pattern)",
+			minify::DitheringMode::BlueNoise => "Blue Noise (balanced, no visible pattern)",
 			minify::DitheringMode::FloydSteinberg => "Floyd-Steinberg (best for photos)",
 			minify::DitheringMode::MedianCut => "Median Cut (excellent palette quality)",
 		};
-		println!("  - Dithering: {}", dithering_desc);
+		sayln!("  - Dithering: {}", dithering_desc);
+		if let Some(colors) = args.median_colors
+		{
+			sayln!("     Median cut palette: {} colors", colors);
+		}
+		if args.scan == minify::ScanOrder::Hilbert
+		{
+			sayln!("     Error diffusion order: Hilbert curve");
+		}
+		if let (Some(path), Some(palette)) = (&args.palette_from, &fixed_palette)
+		{
+			sayln!("  - Fixed palette: {} ({} colors)", path.display(), palette.len());
+		}
+		if dithering_mode == minify::DitheringMode::Auto && args.thresholds != dithering::SelectionThresholds::new()
+		{
+			sayln!("    (Using custom auto-selection thresholds)");
+		}
 	}
 	else
 	{
-		println!("  - Quality: Perfect (lossless PNG optimization only)");
-		println!("  - Dithering: N/A (lossless mode)");
+		sayln!("  - Quality: Perfect (lossless PNG optimization only)");
+		sayln!("  - Dithering: N/A (lossless mode)");
 	}
 	
 	// 4. Advanced Image Processing Parameters.
-	println!("\nADVANCED PROCESSING:");
+	sayln!("\nADVANCED PROCESSING:");
 	// Add smoothing info.
 	if args.smooth > 0.0
 	{
-		println!("  - Smoothing: {:.1} (Gaussian blur before quantization)", args.smooth);
+		sayln!("  - Smoothing: {:.1} (Gaussian blur before quantization)", args.smooth);
 	}
 	else
 	{
-		println!("  - Smoothing: Off");
+		sayln!("  - Smoothing: Off");
 	}
 	
 	// Add denoising info.
 	if args.denoise
 	{
-		println!("  - Denoising: Yes (removes dithering artifacts in gradients)");
+		sayln!("  - Denoising: Yes (removes dithering artifacts in gradients)");
+		if args.denoise_strength != minify::DEFAULT_DENOISE_STRENGTH || args.denoise_block != minify::DEFAULT_DENOISE_BLOCK
+		{
+			sayln!("     Strength: {:.2}, block size: {}", args.denoise_strength, args.denoise_block);
+		}
+	}
+	else
+	{
+		sayln!("  - Denoising: Off");
+	}
+	
+	// Add grayscale info.
+	if args.grayscale
+	{
+		sayln!("  - Grayscale: Forced (color is discarded)");
+	}
+	else
+	{
+		sayln!("  - Grayscale: Auto (gray images are stored as grayscale)");
+	}
+	
+	// Add gamma-correct quantization info.
+	sayln!("  - Linear light: {}", if args.linear { "Enabled (gamma-correct quantization)" } else { "Disabled" });
+	if let Some(gamma) = args.gamma
+	{
+		sayln!("  - Gamma: {}", gamma);
+	}
+	
+	// Add premultiplied alpha info.
+	sayln!("  - Premultiplied alpha: {}", if args.premultiply { "Enabled" } else { "Disabled" });
+	
+	// Add flattening info.
+	if let Some([r, g, b]) = args.flatten
+	{
+		sayln!("  - Flatten: Onto #{:02X}{:02X}{:02X} (no alpha channel)", r, g, b);
+	}
+	
+	// Add alpha quantization info.
+	sayln!("  - Alpha quantization: {}", if args.quantize_alpha { "Enabled" } else { "Disabled" });
+	
+	// Add indexed output info.
+	sayln!("  - Indexed output: {}", if args.indexed { "Enabled (palette PNG, up to 256 colors)" } else { "Disabled" });
+	
+	// Add physical dimensions info.
+	sayln!("  - Keep pHYs (DPI): {}", if args.keep_phys { "Yes" } else { "Off" });
+	
+	// Add text metadata info.
+	sayln!("  - Keep text metadata: {}", if args.keep_text { "Yes" } else { "Off" });
+	
+	// Add palette quantizer info.
+	if args.quantizer == minify::Quantizer::Octree
+	{
+		sayln!("  - Palette quantizer: Octree (median dithering and indexed output)");
+	}
+	
+	// Add palette refinement info.
+	sayln!("  - Palette refinement: {}", if args.refine { "Enabled (k-means after median cut)" } else { "Disabled" });
+	
+	// Add median cut sampling info.
+	if args.sample_step > 0
+	{
+		sayln!("  - Sample step: every {} pixel(s)", args.sample_step);
 	}
 	else
 	{
-		println!("  - Denoising: Off");
+		sayln!("  - Sample step: Auto (by image size)");
+	}
+	
+	// Add binary alpha info.
+	if let Some(threshold) = args.alpha_threshold
+	{
+		sayln!("  - Binary alpha: Threshold {}{}", threshold, if args.dither_alpha { " (dithered)" } else { "" });
 	}
-	println!("----------------------------------------");
+	sayln!("----------------------------------------");
 	
 	// Show minification info.
-	println!();
+	sayln!();
 	if args.lossless
 	{
-		println!("Minification mode: Lossless optimization");
-		println!("  - Removes unnecessary metadata.");
-		println!("  - Optimizes PNG compression (Zopfli algorithm).");
-		println!("  - Preserves perfect image quality.");
-		println!("  - Expected reduction: 10-30%.");
+		sayln!("Minification mode: Lossless optimization");
+		sayln!("  - Removes unnecessary metadata.");
+		sayln!("  - Optimizes PNG compression (Zopfli algorithm).");
+		sayln!("  - Preserves perfect image quality.");
+		sayln!("  - Expected reduction: 10-30%.");
 	}
 	else
 	{
-		println!("Minification mode: Lossy (Quality {})", args.quality);
-		println!("  - Reduces color palette through quantization.");
-		println!("  - Applies aggressive PNG optimization.");
-		println!("  - Maintains excellent visual quality.");
+		if args.quality == minify::AUTO_QUALITY
+		{
+			sayln!("Minification mode: Lossy (Quality picked per image)");
+		}
+		else
+		{
+			sayln!("Minification mode: Lossy (Quality {})", args.quality);
+		}
+		sayln!("  - Reduces color palette through quantization.");
+		sayln!("  - Applies aggressive PNG optimization.");
+		sayln!("  - Maintains excellent visual quality.");
 		let expected_reduction: &str = match args.quality
 		{
 			1..=40 => "70-77%",
@@ -156,91 +614,196 @@ fn main() -> Result<()>
 			76..=100 => "30-50%",
 			_ => "varies",
 		};
-		println!("  - Expected reduction: {}.", expected_reduction);
+		sayln!("  - Expected reduction: {}.", expected_reduction);
+	}
+	if args.reminify_before.is_some()
+	{
+		sayln!("  - Files already minified by this tool will be skipped unless minified before the cutoff.");
+	}
+	else
+	{
+		sayln!("  - Files already minified by this tool will be skipped.");
+	}
+	sayln!();
+	
+	// Scan root for the analysis cache, backups and progress file (the current directory when only files are given).
+	let cache_root: std::path::PathBuf = match &mode
+	{
+		Mode::Directory(Some(dir)) | Mode::Combined(dir, _) => dir.clone(),
+		Mode::Directory(None) | Mode::Files(_) => std::path::PathBuf::from("."),
+	};
+	
+	// Load the analysis cache when asked for (validation ensures there is a scanned directory).
+	let cache: Option<AnalysisCache> = if args.cache { Some(AnalysisCache::load(&cache_root)) } else { None };
+	
+	// Back up files before they are overwritten, mirroring their paths relative to the scan root.
+	let backup: Option<BackupStore> = match &args.backup_dir
+	{
+		Some(dir) => Some(BackupStore::open(dir, &cache_root)?),
+		None => None,
+	};
+	
+	// Stream huge directories through a bounded queue instead of collecting every file and result first.
+	if args.stream && let Mode::Directory(dir) = &mode
+	{
+		let dir: std::path::PathBuf = dir.clone().unwrap_or_else(|| std::path::PathBuf::from("."));
+		return run_stream(&dir, &args, options, cache.as_ref(), backup.as_ref(), start_time);
 	}
-	println!("  - Files already minified by this tool will be skipped.");
-	println!();
 	
 	// Discover PNG files to process.
-	let (png_files, explicit_files): (Vec<utils::file_utils::PngFile>, bool) = match mode
+	let (mut png_files, explicit_files): (Vec<utils::file_utils::PngFile>, bool) = match mode
 	{
 		Mode::Directory(dir) =>
 		{
 			if let Some(d) = &dir
 			{
-				println!("Scanning directory '{}' for PNG files...", d.display());
+				sayln!("Scanning directory '{}' for PNG files...", d.display());
 			}
 			else
 			{
-				println!("Scanning current directory for PNG files...");
+				sayln!("Scanning current directory for PNG files...");
 			}
 			
-			(find_png_files_in_dir(dir.as_deref(), args.inplace)?, false)
+			(find_png_files_in_dir(dir.as_deref(), args.inplace, &args.excludes, !args.no_ignore, args.follow_symlinks, !args.no_recurse)?, false)
 		},
 		Mode::Files(files) =>
 		{
-			println!("Processing {} specified PNG files...", files.len());
+			sayln!("Processing {} specified PNG files...", files.len());
 			(prepare_specific_png_files(&files, args.inplace), true)
 		},
+		Mode::Combined(dir, files) =>
+		{
+			sayln!("Scanning directory '{}' for PNG files plus {} specified PNG files...", dir.display(), files.len());
+			if !dir.is_dir()
+			{
+				return Err(anyhow!("Not a directory: {}", dir.display()));
+			}
+			
+			// A directory without PNG files is fine here since the specified files still contribute, but scan errors are not.
+			let mut combined: Vec<utils::file_utils::PngFile> = find_png_files_in_dir(Some(&dir), args.inplace, &args.excludes, !args.no_ignore, args.follow_symlinks, !args.no_recurse)?;
+			combined.extend(prepare_specific_png_files(&files, args.inplace));
+			(combined, false)
+		},
 	};
 	
+	// Process each file only once, even if it was given several times or is reachable by several paths.
+	let duplicate_count: usize = dedupe_png_files(&mut png_files);
+	
+	// Never process the backups themselves when the backup directory is inside the scanned tree.
+	if let Some(backup) = &backup
+	{
+		png_files.retain(|file| !backup.contains(&file.source_path));
+	}
+	
+	// Write to the output file or directory instead of in place.
+	if let Some(output) = &args.output
+	{
+		redirect_targets(&mut png_files, output, &cache_root, explicit_files)?;
+	}
+	if duplicate_count > 0
+	{
+		sayln!("Removed {} duplicate file(s).", duplicate_count);
+	}
+	
+	// Put the files in the requested processing order.
+	if let Some(order) = args.sort
+	{
+		utils::file_utils::sort_png_files(&mut png_files, order);
+	}
+	
+	// Leave out the files an interrupted run already completed, and record the ones completed now.
+	let progress: Option<ProgressLog> = if args.resume { Some(ProgressLog::open(&cache_root)?) } else { None };
+	if let Some(progress) = &progress
+	{
+		let completed_count: usize = progress.retain_pending(&mut png_files);
+		if completed_count > 0
+		{
+			sayln!("Resuming: skipping {} file(s) completed by an earlier run.", completed_count);
+		}
+	}
+	
 	// Display discovered files.
-	println!("Found {} PNG files to process:", png_files.len());
+	sayln!("Found {} PNG files to process:", png_files.len());
 	for file in &png_files
 	{
 		if file.source_path == file.target_path
 		{
-			println!("  - {} (in-place)", file.source_path.display());
+			sayln!("  - {} (in-place)", file.source_path.display());
 		}
 		else
 		{
-			println!("  - {} -> {}", file.source_path.display(), file.target_path.display());
+			sayln!("  - {} -> {}", file.source_path.display(), file.target_path.display());
 		}
 	}
-	println!();
+	sayln!();
+	
+	// Stop after discovery when only the file list was asked for.
+	if args.list_only
+	{
+		return Ok(std::process::ExitCode::SUCCESS);
+	}
+	
+	// Split the thread pool between the files processed at once and oxipng's work on each of them,
+	// unless the number of optimizer threads per file was given.
+	let concurrent_files: usize = if args.interactive { 1 } else { png_files.len() };
+	let threads_per_file: usize = threads_per_file(&args, rayon::current_num_threads(), concurrent_files);
+	let options: minify::MinifyOptions = minify::MinifyOptions { optimizer_threads: threads_per_file, ..options };
+	if args.verbose
+	{
+		sayln!("Optimizer threads per file: {}", threads_per_file);
+	}
+	
+	// Only estimate the savings when asked, without writing anything.
+	if args.estimate
+	{
+		return Ok(run_estimate(&png_files, &options, args.force));
+	}
 	
 	// Process each file in parallel.
 	let results: Arc<Mutex<Vec<minify::ProcessingResult>>> = Arc::new(Mutex::new(Vec::new()));
 	let errors: Arc<Mutex<Vec<(String, String)>>> = Arc::new(Mutex::new(Vec::new()));
 	
-	println!("Processing files...");
+	sayln!("Processing files...");
 	
 	// Create a progress counter.
 	let total_files: usize = png_files.len();
 	let processed: Arc<Mutex<usize>> = Arc::new(Mutex::new(0));
 	
-	let quality: u8 = args.quality;
-	let lossless: bool = args.lossless;
 	let force_reminify: bool = args.force;
 	let skip_without_prompting: bool = args.skip;
-	let smooth_radius: f32 = args.smooth;
-	let denoise: bool = args.denoise;
+	let verbose: bool = args.verbose;
+	let top_count: usize = args.top;
+	let csv_path: Option<std::path::PathBuf> = args.csv.clone();
+	let time_budget: Option<TimeBudget> = args.time_budget.map(|budget: std::time::Duration| TimeBudget::new(start_time, budget));
+	let manifest_path: Option<std::path::PathBuf> = args.manifest.clone();
+	let fail_on_no_savings: bool = args.fail_on_no_savings;
 	
 	// Check if quality was explicitly set (not default 40).
-	let quality_explicitly_set: bool = Args::is_explicitly_set("--quality") || Args::is_explicitly_set("-q");
+	let quality_explicitly_set: bool = args.is_explicitly_set("--quality") || args.is_explicitly_set("-q");
 	
 	// Check if lossless was explicitly set.
-	let lossless_explicitly_set: bool = Args::is_explicitly_set("--lossless");
+	let lossless_explicitly_set: bool = args.is_explicitly_set("--lossless");
 	
 	// Determine if we should prompt:
-	// 1. NOT if --force or --skip is set
+	// 1. NOT if --force, --skip or --reminify-before is set
 	// 2. Single file explicitly specified (minipng image.png)
 	// 3. Single file found + quality explicitly set (minipng --quality 40 when one file in directory)
 	// 4. Single file found + lossless explicitly set (minipng --lossless when one file in directory)
 	let is_single_file: bool = total_files == 1;
 	let parameters_explicitly_set: bool = quality_explicitly_set || lossless_explicitly_set;
-	let should_prompt_on_skip: bool = !force_reminify && !skip_without_prompting && is_single_file && (explicit_files || parameters_explicitly_set);
+	let should_prompt_on_skip: bool = !force_reminify && !skip_without_prompting && args.reminify_before.is_none() && is_single_file && (explicit_files || parameters_explicitly_set);
 	
 	// Process single file separately (non-parallel) to allow prompting or forced re-minification.
 	if should_prompt_on_skip || (force_reminify && is_single_file)
 	{
-		println!("Processing file...");
+		sayln!("Processing file...");
 		
 		let file: &utils::file_utils::PngFile = &png_files[0];
 		let file_path_display: String = file.source_path.display().to_string();
 		
 		// First check if already minified.
-		match process_file(&file.source_path, &file.target_path, lossless, quality, dithering_mode, smooth_radius, denoise, force_reminify)
+		let file_start: std::time::Instant = std::time::Instant::now();
+		match process_file(&file.source_path, &file.target_path, &options, force_reminify, cache.as_ref(), backup.as_ref(), progress.as_ref())
 		{
 			Ok((result, prev_info)) =>
 			{
@@ -251,224 +814,474 @@ fn main() -> Result<()>
 					if force_reminify
 					{
 						// Force mode - file was re-minified without prompt.
-						print_result_message("Re-minified", &file_path_display, result.original_size, result.new_size);
+						print_result_message("", &label_with_mode("Re-minified", &result), &file_path_display, result.original_size, result.new_size, result.re_encoded);
+						if verbose
+						{
+							sayln!("    Time: {}", format_duration(file_start.elapsed()));
+						}
+						
+						results.lock().expect("Results mutex poisoned").push(result);
 					}
 					else
 					{
 						// Prompt mode - ask user what to do.
-						println!("File already minified:");
-						
-						let current_size: u64 = result.original_size;
-						let original_size_before: u64 = if info.reduction_pct > 0.0
-						{
-							(current_size as f64 / (1.0 - info.reduction_pct / 100.0)) as u64
-						}
-						else
-						{
-							current_size
-						};
-						
-						let mode_info: String = if info.lossless
-						{
-							"Lossless".to_string()
-						}
-						else
-						{
-							format!("Quality {} ({})", info.quality.unwrap_or(0), info.dithering_mode.map_or("no dithering", |m| minify::dithering_mode_to_string(m)))
-						};
-						
-						println!("  Mode: {}", mode_info);
-						println!("  Original size: {}", format_bytes(original_size_before));
-						println!("  Current size: {} ({:.1}% reduction)", format_bytes(current_size), info.reduction_pct);
-						if let Some(ref ts) = info.timestamp
-						{
-							println!("  Minified on: {}", format_timestamp(ts));
-						}
-						
-						println!("\nRe-minify? [y/N]: ");
-						
-						let mut input: String = String::new();
-						std::io::stdin().read_line(&mut input).unwrap_or_default();
-						let answer: String = input.trim().to_lowercase();
-						
-						if answer == "y" || answer == "yes"
+						sayln!("File already minified:");
+						if ask_reminify(info, result.original_size, &mut std::io::stdin().lock())
 						{
 							// User chose to re-minify.
-							match process_file(&file.source_path, &file.target_path, lossless, quality, dithering_mode, smooth_radius, denoise, true)
+							let reminify_start: std::time::Instant = std::time::Instant::now();
+							match process_file(&file.source_path, &file.target_path, &options, true, cache.as_ref(), backup.as_ref(), progress.as_ref())
 							{
 								Ok((result, _)) =>
 								{
-									print_result_message("Re-minified", &file_path_display, result.original_size, result.new_size);
+									print_result_message("", &label_with_mode("Re-minified", &result), &file_path_display, result.original_size, result.new_size, result.re_encoded);
+									if verbose
+									{
+										sayln!("    Time: {}", format_duration(reminify_start.elapsed()));
+									}
+									
+									results.lock().expect("Results mutex poisoned").push(result);
 								},
 								Err(err) =>
 								{
-									eprintln!("Error re-minifying {}: {}", file_path_display, err);
+									errln!("{} re-minifying {}: {}", paint("Error", StatusColor::Red), file_path_display, err);
+									errors.lock().expect("Errors mutex poisoned").push((file_path_display, err.to_string()));
 								}
 							}
 						}
 						else
 						{
-							println!("Skipped.");
+							sayln!("{}", paint("Skipped.", StatusColor::Yellow));
+							results.lock().expect("Results mutex poisoned").push(result);
 						}
 					}
 					
-					return Ok(());
+					write_reports(csv_path.as_deref(), manifest_path.as_deref(), cache.as_ref(), &results.lock().expect("Results mutex poisoned"), &errors.lock().expect("Errors mutex poisoned"))?;
+					finish_progress(progress.as_ref(), &errors.lock().expect("Errors mutex poisoned"), true);
+					return Ok(exit_code(&results.lock().expect("Results mutex poisoned"), &errors.lock().expect("Errors mutex poisoned"), fail_on_no_savings));
 				}
 				
-				// File was not previously minified.
-				print_result_message("Minified", &file_path_display, result.original_size, result.new_size);
-			},
+				// File was left untouched (not a PNG, or the target already existed).
+				if let Some(reason) = result.skip_reason
+				{
+					sayln!("{}: {} ({})", paint("Skipped", StatusColor::Yellow), file_path_display, describe_skip_reason(reason));
+					results.lock().expect("Results mutex poisoned").push(result);
+					write_reports(csv_path.as_deref(), manifest_path.as_deref(), cache.as_ref(), &results.lock().expect("Results mutex poisoned"), &errors.lock().expect("Errors mutex poisoned"))?;
+					finish_progress(progress.as_ref(), &errors.lock().expect("Errors mutex poisoned"), true);
+					return Ok(exit_code(&results.lock().expect("Results mutex poisoned"), &errors.lock().expect("Errors mutex poisoned"), fail_on_no_savings));
+				}
+				
+				// File was not previously minified.
+				print_result_message("", &label_with_mode("Minified", &result), &file_path_display, result.original_size, result.new_size, result.re_encoded);
+				if verbose
+				{
+					sayln!("    Time: {}", format_duration(file_start.elapsed()));
+				}
+				
+				results.lock().expect("Results mutex poisoned").push(result);
+			},
 			Err(err) =>
 			{
-				eprintln!("Error processing {}: {}", file_path_display, err);
+				errln!("{} processing {}: {}", paint("Error", StatusColor::Red), file_path_display, err);
+				errors.lock().expect("Errors mutex poisoned").push((file_path_display, err.to_string()));
 			}
 		}
 		
-		return Ok(());
+		write_reports(csv_path.as_deref(), manifest_path.as_deref(), cache.as_ref(), &results.lock().expect("Results mutex poisoned"), &errors.lock().expect("Errors mutex poisoned"))?;
+		finish_progress(progress.as_ref(), &errors.lock().expect("Errors mutex poisoned"), true);
+		return Ok(exit_code(&results.lock().expect("Results mutex poisoned"), &errors.lock().expect("Errors mutex poisoned"), fail_on_no_savings));
 	}
 	
-	// Batch mode: process in parallel with auto-skip (unless --force is set).
-	png_files.into_par_iter().for_each(|file|
+	// Interactive mode: process files one at a time and ask about each already-minified file.
+	if args.interactive
 	{
-		let file_path_display: String = file.source_path.display().to_string();
+		// Without a terminal to answer from, already-minified files are skipped.
+		let can_prompt: bool = std::io::stdin().is_terminal();
+		if !can_prompt
+		{
+			sayln!("Input is not a terminal, already-minified files will be skipped.");
+		}
 		
-		match process_file(&file.source_path, &file.target_path, lossless, quality, dithering_mode, smooth_radius, denoise, force_reminify)
+		for (index, file) in png_files.iter().enumerate()
 		{
-			Ok((result, prev_info)) =>
+			let file_path_display: String = file.source_path.display().to_string();
+			let current: usize = index + 1;
+			
+			let file_start: std::time::Instant = std::time::Instant::now();
+			match process_file(&file.source_path, &file.target_path, &options, false, cache.as_ref(), backup.as_ref(), progress.as_ref())
 			{
-				// Check if file was already minified.
-				if let Some(ref info) = prev_info
+				Ok((result, Some(info))) =>
 				{
-					// File was already minified.
-					if force_reminify
+					sayln!("[{}/{}] File already minified: {}", current, total_files, file_path_display);
+					if can_prompt && ask_reminify(&info, result.original_size, &mut std::io::stdin().lock())
+					{
+						let reminify_start: std::time::Instant = std::time::Instant::now();
+						match process_file(&file.source_path, &file.target_path, &options, true, cache.as_ref(), backup.as_ref(), progress.as_ref())
+						{
+							Ok((result, _)) =>
+							{
+								print_result_message("", &label_with_mode("Re-minified", &result), &file_path_display, result.original_size, result.new_size, result.re_encoded);
+								if verbose
+								{
+									sayln!("    Time: {}", format_duration(reminify_start.elapsed()));
+								}
+								
+								results.lock().expect("Results mutex poisoned").push(result);
+							},
+							Err(err) =>
+							{
+								errln!("{} re-minifying {}: {}", paint("Error", StatusColor::Red), file_path_display, err);
+								errors.lock().expect("Errors mutex poisoned").push((file_path_display, err.to_string()));
+							}
+						}
+					}
+					else
+					{
+						sayln!("{}", paint("Skipped.", StatusColor::Yellow));
+						results.lock().expect("Results mutex poisoned").push(result);
+					}
+				},
+				Ok((result, None)) =>
+				{
+					if let Some(reason) = result.skip_reason
+					{
+						sayln!("[{}/{}] {}: {} ({})", current, total_files, paint("Skipped", StatusColor::Yellow), file_path_display, describe_skip_reason(reason));
+					}
+					else
+					{
+						print_result_message(&format!("[{}/{}] ", current, total_files), &label_with_mode("Minified", &result), &file_path_display, result.original_size, result.new_size, result.re_encoded);
+						if verbose
+						{
+							sayln!("    Time: {}", format_duration(file_start.elapsed()));
+						}
+					}
+					
+					results.lock().expect("Results mutex poisoned").push(result);
+				},
+				Err(err) =>
+				{
+					errln!("{} processing {}: {}", paint("Error", StatusColor::Red), file_path_display, err);
+					errors.lock().expect("Errors mutex poisoned").push((file_path_display, err.to_string()));
+				}
+			}
+		}
+	}
+	else
+	{
+		// Batch mode: process in parallel with auto-skip (unless --force is set).
+		// Workers take the files in list order, so the --sort order decides which files start first.
+		png_files.into_iter().par_bridge().for_each(|file|
+		{
+			// Once the time budget is spent, the remaining files are only counted.
+			if let Some(budget) = &time_budget && !budget.try_start()
+			{
+				return;
+			}
+			
+			let file_path_display: String = file.source_path.display().to_string();
+			
+			let file_start: std::time::Instant = std::time::Instant::now();
+			match process_file(&file.source_path, &file.target_path, &options, force_reminify, cache.as_ref(), backup.as_ref(), progress.as_ref())
+			{
+				Ok((result, prev_info)) =>
+				{
+					let file_elapsed: std::time::Duration = file_start.elapsed();
+					
+					// Check if file was already minified.
+					if let Some(ref info) = prev_info
 					{
-						// Force mode - file was already re-minified, show result.
-						let size_reduction_pct: f64 = calculate_reduction_pct(result.original_size, result.new_size);
+						// File was already minified.
+						if force_reminify
+						{
+							// Force mode - file was already re-minified, show result.
+							let size_reduction_pct: f64 = calculate_reduction_pct(result.original_size, result.new_size);
+							
+							let mut count: std::sync::MutexGuard<usize> = processed.lock().expect("Processed counter mutex poisoned");
+							*count += 1;
+							let current: usize = *count;
+							
+							if result.re_encoded
+							{
+								sayln!("[{}/{}] {}: {} | {} -> {} (re-encoded, not smaller)", current, total_files, paint("Re-encoded", StatusColor::Yellow), file_path_display, format_bytes(result.original_size), format_bytes(result.new_size));
+							}
+							else if result.new_size < result.original_size
+							{
+								sayln!("[{}/{}] {}: {} | {} -> {} ({:.1}% smaller)", current, total_files, paint(&label_with_mode("Re-minified", &result), StatusColor::Green), file_path_display, format_bytes(result.original_size), format_bytes(result.new_size), size_reduction_pct);
+							}
+							else
+							{
+								sayln!("[{}/{}] {}: {}", current, total_files, paint("No reduction", StatusColor::Yellow), file_path_display);
+							}
+							
+							if verbose
+							{
+								sayln!("    Time: {}", format_duration(file_elapsed));
+							}
+							
+							results.lock().expect("Results mutex poisoned").push(result);
+							return;
+						}
 						
+						// Auto-skip mode (batch or --skip flag).
 						let mut count: std::sync::MutexGuard<usize> = processed.lock().expect("Processed counter mutex poisoned");
 						*count += 1;
 						let current: usize = *count;
 						
-						if result.new_size < result.original_size
+						// Calculate what the original size was before minification.
+						let current_size: u64 = result.original_size;
+						let original_size_before: u64 = if info.reduction_pct > 0.0
+						{
+							(current_size as f64 / (1.0 - info.reduction_pct / 100.0)) as u64
+						}
+						else
+						{
+							current_size
+						};
+						
+						// Format sizes.
+						sayln!("[{}/{}] {}: {}", current, total_files, paint("Skipped", StatusColor::Yellow), file_path_display);
+						let mode_info: String = if info.lossless
+						{
+							"Lossless".to_string()
+						}
+						else
+						{
+							format!("Quality {} ({})", info.quality.unwrap_or(0), info.dithering_mode.map_or("no dithering", |m| minify::dithering_mode_to_string(m)))
+						};
+						if info.noop
 						{
-							println!("[{}/{}] Re-minified: {} | {} -> {} ({:.1}% smaller)", current, total_files, file_path_display, format_bytes(result.original_size), format_bytes(result.new_size), size_reduction_pct);
+							sayln!("    Already attempted, no reduction | {}", mode_info);
 						}
 						else
 						{
-							println!("[{}/{}] No reduction: {}", current, total_files, file_path_display);
+							sayln!("    Already minified | {} | Reduction: {:.1}%", mode_info, info.reduction_pct);
+						}
+						sayln!("    Original: {} -> Minified: {}", format_bytes(original_size_before), format_bytes(current_size));
+						if let Some(ref ts) = info.timestamp
+						{
+							sayln!("    Minified on: {}", format_timestamp(ts));
 						}
 						
 						results.lock().expect("Results mutex poisoned").push(result);
 						return;
 					}
 					
-					// Auto-skip mode (batch or --skip flag).
+					// File was left untouched (not a PNG, or the target already existed).
+					if let Some(reason) = result.skip_reason
+					{
+						let mut count: std::sync::MutexGuard<usize> = processed.lock().expect("Processed counter mutex poisoned");
+						*count += 1;
+						sayln!("[{}/{}] {}: {} ({})", *count, total_files, paint("Skipped", StatusColor::Yellow), file_path_display, describe_skip_reason(reason));
+						results.lock().expect("Results mutex poisoned").push(result);
+						return;
+					}
+					
+					// File was not previously minified, or user chose to re-minify.
+					// Calculate the size reduction percentage.
+					let size_reduction_pct: f64 = calculate_reduction_pct(result.original_size, result.new_size);
+					
+					// Update the progress counter.
 					let mut count: std::sync::MutexGuard<usize> = processed.lock().expect("Processed counter mutex poisoned");
 					*count += 1;
 					let current: usize = *count;
 					
-					// Calculate what the original size was before minification.
-					let current_size: u64 = result.original_size;
-					let original_size_before: u64 = if info.reduction_pct > 0.0
+					// Show detailed progress.
+					if result.re_encoded
 					{
-						(current_size as f64 / (1.0 - info.reduction_pct / 100.0)) as u64
+						sayln!("[{}/{}] {}: {} | {} -> {} (re-encoded, not smaller)", current, total_files, paint("Re-encoded", StatusColor::Yellow), file_path_display, format_bytes(result.original_size), format_bytes(result.new_size));
 					}
-					else
+					else if result.noop_marked
 					{
-						current_size
-					};
-					
-					// Format sizes.
-					println!("[{}/{}] Skipped: {}", current, total_files, file_path_display);
-					let mode_info: String = if info.lossless
+						sayln!("[{}/{}] {}: {} (file couldn't be minified further, marked so later runs skip it)", current, total_files, paint("No reduction", StatusColor::Yellow), file_path_display);
+					}
+					else if result.original_size == result.new_size && prev_info.is_none()
 					{
-						"Lossless".to_string()
+						sayln!("[{}/{}] {}: {} (file couldn't be minified further)", current, total_files, paint("No reduction", StatusColor::Yellow), file_path_display);
 					}
-					else
+					else if result.new_size < result.original_size
 					{
-						format!("Quality {} ({})", info.quality.unwrap_or(0), info.dithering_mode.map_or("no dithering", |m| minify::dithering_mode_to_string(m)))
-					};
-					println!("    Already minified | {} | Reduction: {:.1}%", mode_info, info.reduction_pct);
-					println!("    Original: {} -> Minified: {}", format_bytes(original_size_before), format_bytes(current_size));
-					if let Some(ref ts) = info.timestamp
+						sayln!("[{}/{}] {}: {} | {} -> {} ({:.1}% smaller)", current, total_files, paint(&label_with_mode("Minified", &result), StatusColor::Green), file_path_display, format_bytes(result.original_size), format_bytes(result.new_size), size_reduction_pct);
+					}
+					
+					if verbose
 					{
-						println!("    Minified on: {}", format_timestamp(ts));
+						sayln!("    Time: {}", format_duration(file_elapsed));
 					}
 					
 					results.lock().expect("Results mutex poisoned").push(result);
-					return;
-				}
-				
-				// File was not previously minified, or user chose to re-minify.
-				// Calculate the size reduction percentage.
-				let size_reduction_pct: f64 = calculate_reduction_pct(result.original_size, result.new_size);
-				
-				// Update the progress counter.
-				let mut count: std::sync::MutexGuard<usize> = processed.lock().expect("Processed counter mutex poisoned");
-				*count += 1;
-				let current: usize = *count;
-				
-				// Show detailed progress.
-				if result.original_size == result.new_size && prev_info.is_none()
-				{
-					println!("[{}/{}] No reduction: {} (file couldn't be minified further)", current, total_files, file_path_display);
-				}
-				else if result.new_size < result.original_size
+				},
+				Err(err) =>
 				{
-					println!("[{}/{}] Minified: {} | {} -> {} ({:.1}% smaller)", current, total_files, file_path_display, format_bytes(result.original_size), format_bytes(result.new_size), size_reduction_pct);
+					errln!("{} processing {}: {}", paint("Error", StatusColor::Red), file_path_display, err);
+					errors.lock().expect("Errors mutex poisoned").push((file_path_display, err.to_string()));
 				}
-				
-				results.lock().expect("Results mutex poisoned").push(result);
-			},
-			Err(err) =>
-			{
-				eprintln!("Error processing {}: {}", file_path_display, err);
-				errors.lock().expect("Errors mutex poisoned").push((file_path_display, err.to_string()));
 			}
-		}
-	});
+		});
+	}
 	
 	// Convert back to a regular Vec.
 	let results: Vec<minify::ProcessingResult> = Arc::try_unwrap(results).unwrap_or_else(|_| panic!("Failed to unwrap Arc")).into_inner().expect("Mutex poisoned");
 	
 	let errors: Vec<(String, String)> = Arc::try_unwrap(errors).unwrap_or_else(|_| panic!("Failed to unwrap Arc")).into_inner().expect("Mutex poisoned");
 	
+	// Write machine-readable reports.
+	write_reports(csv_path.as_deref(), manifest_path.as_deref(), cache.as_ref(), &results, &errors)?;
+	finish_progress(progress.as_ref(), &errors, time_budget.as_ref().is_none_or(|budget: &TimeBudget| budget.remaining() == 0));
+	
+	// A machine-readable report (or none) replaces the summary.
+	if args.report != ReportFormat::Human
+	{
+		utils::report_utils::write_report(&mut std::io::stdout().lock(), args.report, &results, &errors)?;
+		return Ok(exit_code(&results, &errors, fail_on_no_savings));
+	}
+	
 	// Print summary.
-	println!("\n========================================");
-	println!("MINIFICATION SUMMARY");
-	println!("========================================");
-	println!("Total files processed successfully: {}", results.len());
+	sayln!("\n========================================");
+	sayln!("MINIFICATION SUMMARY");
+	sayln!("========================================");
+	sayln!("Total files processed successfully: {}", results.len());
+	sayln!("Total time: {}", format_duration(start_time.elapsed()));
+	if let Some(budget) = &time_budget && budget.remaining() > 0
+	{
+		sayln!("Time budget reached: {} file(s) processed, {} remaining", total_files - budget.remaining(), budget.remaining());
+	}
 	
 	if !errors.is_empty()
 	{
-		println!("Files with errors: {}", errors.len());
-		println!("\nErrors:");
+		sayln!("Files with errors: {}", errors.len());
+		sayln!("\nErrors:");
 		for (file, error) in &errors
 		{
-			println!("  {}: {}", file, error);
+			sayln!("  {}: {}", file, error);
 		}
 	}
 	
 	if !results.is_empty()
 	{
-		// Count skipped vs minified files.
+		// Count skipped vs minified files, and the files each of the summary lines below is about.
 		let mut minified_count: usize = 0;
 		let mut skipped_count: usize = 0;
+		let mut not_png_count: usize = 0;
+		let mut target_exists_count: usize = 0;
+		let mut read_only_count: usize = 0;
+		let mut too_large_count: usize = 0;
+		let mut unsupported_count: usize = 0;
+		let mut noop_marked_count: usize = 0;
+		let mut lossless_fallback_count: usize = 0;
+		let mut auto_mode_count: usize = 0;
+		let mut auto_mode_lossless_count: usize = 0;
+		let mut trailing_count: usize = 0;
+		let mut trailing_bytes: u64 = 0;
+		let mut checked_count: usize = 0;
+		let mut already_quantized_count: usize = 0;
 		
 		for r in &results
 		{
-			if r.new_size < r.original_size
+			if r.noop_marked
+			{
+				noop_marked_count += 1;
+			}
+			if r.lossless_fallback
+			{
+				lossless_fallback_count += 1;
+			}
+			if r.auto_mode
 			{
-				minified_count += 1;
+				auto_mode_count += 1;
+				if r.lossless
+				{
+					auto_mode_lossless_count += 1;
+				}
+			}
+			if r.trailing_stripped > 0
+			{
+				trailing_count += 1;
+				trailing_bytes += r.trailing_stripped;
+			}
+			if r.skip_reason.is_none() && r.new_size < r.original_size
+			{
+				checked_count += 1;
+			}
+			if r.already_quantized
+			{
+				already_quantized_count += 1;
 			}
-			if r.new_size == r.original_size
+			
+			match r.skip_reason
 			{
-				skipped_count += 1;
+				Some(minify::SkipReason::NotPng) => not_png_count += 1,
+				Some(minify::SkipReason::TargetExists) => target_exists_count += 1,
+				Some(minify::SkipReason::ReadOnly) => read_only_count += 1,
+				Some(minify::SkipReason::TooLarge) => too_large_count += 1,
+				Some(minify::SkipReason::UnsupportedFormat) => unsupported_count += 1,
+				_ =>
+				{
+					if r.new_size < r.original_size
+					{
+						minified_count += 1;
+					}
+					if r.new_size == r.original_size
+					{
+						skipped_count += 1;
+					}
+				},
 			}
 		}
 		
-		println!("\nFiles minified: {}", minified_count);
-		println!("Files skipped (already minified): {}", skipped_count);
+		sayln!("\nFiles minified: {}", minified_count);
+		sayln!("Files skipped (already minified): {}", skipped_count);
+		if not_png_count > 0
+		{
+			sayln!("Files skipped (not valid PNG): {}", not_png_count);
+		}
+		if target_exists_count > 0
+		{
+			sayln!("Files skipped (target already exists): {}", target_exists_count);
+		}
+		if read_only_count > 0
+		{
+			sayln!("Files skipped (target is read-only): {}", read_only_count);
+		}
+		if too_large_count > 0
+		{
+			sayln!("Files skipped (more pixels than --max-pixels): {}", too_large_count);
+		}
+		if unsupported_count > 0
+		{
+			sayln!("Files skipped (unsupported color type or bit depth): {}", unsupported_count);
+		}
+		if noop_marked_count > 0
+		{
+			sayln!("Files not reduced and marked as attempted: {}", noop_marked_count);
+		}
+		if lossless_fallback_count > 0
+		{
+			sayln!("Files minified losslessly (lossy output wasn't smaller): {}", lossless_fallback_count);
+		}
+		if auto_mode_count > 0
+		{
+			sayln!("Auto mode: {} file(s) kept lossy, {} lossless", auto_mode_count - auto_mode_lossless_count, auto_mode_lossless_count);
+		}
+		if trailing_count > 0
+		{
+			sayln!("Trailing data stripped: {} from {} file(s)", format_bytes(trailing_bytes), trailing_count);
+		}
+		
+		// Every written lossless output passed the pixel diff, the others failed with an error.
+		if args.diff_pixels
+		{
+			sayln!("Pixel diff: {} output(s) checked, 0 differing pixels (max channel delta 0)", checked_count);
+		}
+		
+		// Lossy minification of files another tool already quantized compounds the quality loss.
+		if already_quantized_count > 0 && args.safe
+		{
+			sayln!("Files minified losslessly (already quantized by another tool): {}", already_quantized_count);
+		}
+		else if already_quantized_count > 0
+		{
+			sayln!("{}: {} file(s) look already quantized by another tool, quantizing them again may lower their quality further (use --safe to minify them losslessly)", paint("Warning", StatusColor::Yellow), already_quantized_count);
+		}
 		
 		// Calculate total sizes.
 		let mut total_original_size: u64 = 0;
@@ -507,12 +1320,12 @@ fn main() -> Result<()>
 			}
 		};
 		
-		println!("\n----------------------------------------");
-		println!("SIZE STATISTICS");
-		println!("----------------------------------------");
-		println!("Total original size:  {}", format_size(total_original_size));
-		println!("Total final size:     {}", format_size(total_new_size));
-		println!("Total space saved:    {} ({:.1}%)", format_size(total_saved), total_saved_pct);
+		sayln!("\n----------------------------------------");
+		sayln!("SIZE STATISTICS");
+		sayln!("----------------------------------------");
+		sayln!("Total original size:  {}", format_size(total_original_size));
+		sayln!("Total final size:     {}", format_size(total_new_size));
+		sayln!("Total space saved:    {} ({:.1}%)", format_size(total_saved), total_saved_pct);
 		
 		if minified_count > 0
 		{
@@ -530,55 +1343,897 @@ fn main() -> Result<()>
 			}
 			
 			let avg_compression: f64 = calculate_reduction_pct(minified_original, minified_new);
-			println!("Average compression (minified files only): {:.1}%", avg_compression);
+			sayln!("Average compression (minified files only): {:.1}%", avg_compression);
+		}
+		
+		// Show which algorithm each file ended up with (useful with --dithering auto).
+		let mode_counts: Vec<(&str, usize)> = count_modes(&results);
+		if !mode_counts.is_empty()
+		{
+			sayln!("\n----------------------------------------");
+			sayln!("FILES BY ALGORITHM");
+			sayln!("----------------------------------------");
+			for (mode, count) in &mode_counts
+			{
+				sayln!("  {}: {}", mode, count);
+			}
+		}
+		
+		// List every file by bytes saved with --sort savings, otherwise the files that gave the biggest wins.
+		let include_unsaved: bool = args.sort == Some(utils::file_utils::SortOrder::Savings);
+		let by_savings: Vec<&minify::ProcessingResult> = utils::report_utils::sort_by_bytes_saved(&results, include_unsaved);
+		if include_unsaved
+		{
+			sayln!("\n----------------------------------------");
+			sayln!("ALL FILES BY BYTES SAVED");
+			sayln!("----------------------------------------");
+			for (index, r) in by_savings.iter().enumerate()
+			{
+				let saved: u64 = r.original_size.saturating_sub(r.new_size);
+				sayln!("{:>3}. {} | {} saved ({:.1}%)", index + 1, r.path.display(), format_bytes(saved), calculate_reduction_pct(r.original_size, r.new_size));
+			}
+		}
+		else if top_count > 0 && !by_savings.is_empty()
+		{
+			sayln!("\n----------------------------------------");
+			sayln!("TOP {} BY BYTES SAVED", top_count.min(by_savings.len()));
+			sayln!("----------------------------------------");
+			for (index, r) in by_savings.iter().take(top_count).enumerate()
+			{
+				let saved: u64 = r.original_size - r.new_size;
+				sayln!("{:>3}. {} | {} saved ({:.1}%)", index + 1, r.path.display(), format_bytes(saved), calculate_reduction_pct(r.original_size, r.new_size));
+			}
 		}
 		
-		println!("========================================");
+		sayln!("========================================");
 	}
 	
-	Ok(())
+	Ok(exit_code(&results, &errors, fail_on_no_savings))
 }
 
-/// Formats file size in human-readable format.
-fn format_bytes(size: u64) -> String
+/// Process a directory in streaming mode (--stream).
+/// Files are handed to the workers through a bounded queue as the walk finds them, and each result is folded into
+/// running totals and dropped, so memory use stays the same however many files there are.
+/// Files are handled like in batch mode (already-minified ones are skipped unless forced), but the summary only has totals.
+fn run_stream(dir: &std::path::Path, args: &Args, options: minify::MinifyOptions, cache: Option<&AnalysisCache>, backup: Option<&BackupStore>, start_time: std::time::Instant) -> Result<std::process::ExitCode>
 {
-	if size < 1024
+	// The number of files isn't known up front; assume plenty, so each file is optimized on a single thread.
+	let threads_per_file: usize = threads_per_file(args, rayon::current_num_threads(), usize::MAX);
+	let options: minify::MinifyOptions = minify::MinifyOptions { optimizer_threads: threads_per_file, ..options };
+	
+	sayln!("Streaming PNG files from '{}'...", dir.display());
+	let (files, walker): (std::sync::mpsc::Receiver<utils::file_utils::PngFile>, std::thread::JoinHandle<Result<()>>) = stream_png_files(dir.to_path_buf(), args.excludes.clone(), !args.no_ignore, args.follow_symlinks, !args.no_recurse);
+	let processed: Mutex<usize> = Mutex::new(0);
+	
+	let totals: StreamTotals = fold_stream(files, |file: utils::file_utils::PngFile|
 	{
-		format!("{} B", size)
+		// Backups taken by this run must not be processed themselves.
+		if backup.is_some_and(|backup| backup.contains(&file.source_path))
+		{
+			return None;
+		}
+		
+		let file_path_display: String = file.source_path.display().to_string();
+		let outcome: Result<(minify::ProcessingResult, Option<minify::MinificationInfo>)> = process_file(&file.source_path, &file.target_path, &options, args.force, cache, backup, None);
+		
+		let mut count: std::sync::MutexGuard<usize> = processed.lock().expect("Processed counter mutex poisoned");
+		*count += 1;
+		match &outcome
+		{
+			Ok((result, _)) if result.skip_reason.is_some() =>
+			{
+				sayln!("[{}] {}: {} ({})", *count, paint("Skipped", StatusColor::Yellow), file_path_display, describe_skip_reason(result.skip_reason.expect("Skip reason checked above")));
+			},
+			Ok((result, prev_info)) =>
+			{
+				print_result_message(&format!("[{}] ", *count), &label_with_mode(if prev_info.is_some() { "Re-minified" } else { "Minified" }, result), &file_path_display, result.original_size, result.new_size, result.re_encoded);
+			},
+			Err(err) => errln!("[{}] {} processing {}: {}", *count, paint("Error", StatusColor::Red), file_path_display, err),
+		}
+		
+		Some(outcome.map(|(result, _)| result))
+	});
+	finish_walk(walker)?;
+	
+	// A cache that can't be written only costs time on the next run.
+	if let Some(cache) = cache && let Err(e) = cache.save()
+	{
+		errln!("Warning: {}", e);
 	}
-	else if size < 1024 * 1024
+	
+	// The summary is left out with --report none (machine-readable formats are rejected for streaming).
+	if args.report == ReportFormat::Human
 	{
-		format!("{:.1} KB", size as f64 / 1024.0)
+		sayln!("\n========================================");
+		sayln!("MINIFICATION SUMMARY (streamed)");
+		sayln!("========================================");
+		sayln!("Total files processed successfully: {}", totals.processed);
+		sayln!("Total time: {}", format_duration(start_time.elapsed()));
+		if totals.errors > 0
+		{
+			sayln!("Files with errors: {}", totals.errors);
+		}
+		sayln!("Files minified: {}", totals.minified);
+		sayln!("Files skipped: {}", totals.skipped);
+		sayln!("Files not reduced: {}", totals.processed - totals.minified - totals.skipped);
+		sayln!("Total original size:  {}", format_bytes(totals.original_size));
+		sayln!("Total final size:     {}", format_bytes(totals.new_size));
+		sayln!("Total space saved:    {} ({:.1}%)", format_bytes(totals.original_size.saturating_sub(totals.new_size)), calculate_reduction_pct(totals.original_size, totals.new_size));
+		sayln!("========================================");
 	}
-	else
+	
+	if totals.errors > 0
 	{
-		format!("{:.2} MB", size as f64 / (1024.0 * 1024.0))
+		return Ok(std::process::ExitCode::from(EXIT_FILE_ERRORS));
+	}
+	
+	if args.fail_on_no_savings && totals.minified == 0
+	{
+		return Ok(std::process::ExitCode::from(EXIT_NO_SAVINGS));
 	}
+	
+	Ok(std::process::ExitCode::SUCCESS)
+}
+
+/// Number of threads oxipng may use for each file, so files processed at once × optimizer threads stays within the pool.
+/// Batches with at least as many files as threads optimize each file on a single thread (files are independent, so this scales best);
+/// smaller batches, such as a single large image, split the pool between their files.
+fn optimizer_threads(pool_threads: usize, concurrent_files: usize) -> usize
+{
+	let pool_threads: usize = pool_threads.max(1);
+	(pool_threads / concurrent_files.clamp(1, pool_threads)).max(1)
 }
 
-/// Calculate size reduction percentage.
-fn calculate_reduction_pct(original_size: u64, new_size: u64) -> f64
+/// Optimizer threads for each file: --threads-per-file when given, otherwise the pool split by optimizer_threads.
+fn threads_per_file(args: &Args, pool_threads: usize, concurrent_files: usize) -> usize
 {
-	if original_size > 0
+	if args.threads_per_file > 0
 	{
-		(1.0 - (new_size as f64 / original_size as f64)) * 100.0
+		return args.threads_per_file;
 	}
-	else
+	
+	optimizer_threads(pool_threads, concurrent_files)
+}
+
+/// Pick the exit code for a finished run: file errors win over a lack of savings.
+fn exit_code(results: &[minify::ProcessingResult], errors: &[(String, String)], fail_on_no_savings: bool) -> std::process::ExitCode
+{
+	if !errors.is_empty()
+	{
+		return std::process::ExitCode::from(EXIT_FILE_ERRORS);
+	}
+	
+	let mut any_savings: bool = false;
+	for r in results
+	{
+		if r.new_size < r.original_size
+		{
+			any_savings = true;
+			break;
+		}
+	}
+	if fail_on_no_savings && !any_savings
 	{
-		0.0
+		return std::process::ExitCode::from(EXIT_NO_SAVINGS);
 	}
+	
+	std::process::ExitCode::SUCCESS
+}
+
+/// Minify PNG data read from stdin and write the result to stdout.
+/// Skips file discovery, the already-minified prompt and the summary; the result line goes to stderr.
+fn run_stdin_pipeline(options: &minify::MinifyOptions) -> Result<()>
+{
+	pipe_png(&mut std::io::stdin().lock(), &mut std::io::stdout().lock(), options)
 }
 
-/// Print minification result with appropriate message.
-fn print_result_message(prefix: &str, file_path: &str, original_size: u64, new_size: u64)
+/// Minify PNG data read from a reader and write the result to a writer.
+fn pipe_png(input: &mut impl std::io::Read, output: &mut impl std::io::Write, options: &minify::MinifyOptions) -> Result<()>
 {
+	let mut source_data: Vec<u8> = Vec::new();
+	input.read_to_end(&mut source_data)
+		.map_err(|e| anyhow!("Failed to read PNG data from stdin: {}", e))?;
+	
+	// There is no file to leave alone, so an image above the pixel limit is an error.
+	if minify::exceeds_max_pixels(&source_data, options)?
+	{
+		let (width, height): (u32, u32) = minify::png_dimensions(&source_data)?;
+		return Err(anyhow!("Image is {}×{} pixels, more than the --max-pixels limit of {}", width, height, options.max_pixels.unwrap_or(0)));
+	}
+	
+	// Files already quantized by another tool are minified losslessly in safe mode.
+	let safe_options: Option<minify::MinifyOptions> = if options.safe && !options.lossless && minify::is_already_quantized(&source_data)?
+	{
+		Some(minify::MinifyOptions { lossless: true, ..options.clone() })
+	}
+	else
+	{
+		None
+	};
+	let options: &minify::MinifyOptions = safe_options.as_ref().unwrap_or(options);
+	
+	let (output_data, _, _, _): (Vec<u8>, minify::DitheringMode, u8, Option<dithering::ImageClass>) = minify::minify_bytes(&source_data, options)?;
+	
+	output.write_all(&output_data)
+		.map_err(|e| anyhow!("Failed to write PNG data to stdout: {}", e))?;
+	output.flush()
+		.map_err(|e| anyhow!("Failed to write PNG data to stdout: {}", e))?;
+	
+	let original_size: u64 = source_data.len() as u64;
+	let new_size: u64 = output_data.len() as u64;
 	if new_size < original_size
 	{
-		let reduction_pct: f64 = calculate_reduction_pct(original_size, new_size);
-		println!("{}: {} | {} -> {} ({:.1}% smaller)", prefix, file_path, format_bytes(original_size), format_bytes(new_size), reduction_pct);
+		errln!("Minified: <stdin> | {} -> {} ({:.1}% smaller)", format_bytes(original_size), format_bytes(new_size), calculate_reduction_pct(original_size, new_size));
+	}
+	else if options.always_write
+	{
+		errln!("Re-encoded: <stdin> | {} -> {} (re-encoded, not smaller)", format_bytes(original_size), format_bytes(new_size));
 	}
 	else
 	{
-		println!("{}: {} (file couldn't be minified further)", prefix, file_path);
+		errln!("No reduction: <stdin> (data couldn't be minified further)");
+	}
+	
+	Ok(())
+}
+
+/// Write a side-by-side image of a PNG file (left) and its minified version (right).
+/// The source file is only read, and the comparison image is not marked as minified.
+fn run_compare(source_path: &std::path::Path, compare_path: &std::path::Path, options: &minify::MinifyOptions) -> Result<()>
+{
+	let source_data: Vec<u8> = std::fs::read(source_path)
+		.map_err(|e| anyhow!("Failed to read {}: {}", source_path.display(), e))?;
+	
+	let (comparison_data, effective_dithering): (Vec<u8>, minify::DitheringMode) = minipng::compare::compare_bytes(&source_data, options)?;
+	std::fs::write(compare_path, &comparison_data)
+		.map_err(|e| anyhow!("Failed to write {}: {}", compare_path.display(), e))?;
+	
+	let mode: &str = if options.lossless { "lossless" } else { minify::dithering_mode_to_string(effective_dithering) };
+	sayln!("Comparison ({}) written to: {}", mode, compare_path.display());
+	
+	Ok(())
+}
+
+/// Write a labeled montage of a PNG file quantized with each dithering mode, with the number of colors each produced.
+/// The source file is only read, and the montage is not marked as minified.
+fn run_preview(source_path: &std::path::Path, preview_path: &std::path::Path, options: &minify::MinifyOptions) -> Result<()>
+{
+	let source_data: Vec<u8> = std::fs::read(source_path)
+		.map_err(|e| anyhow!("Failed to read {}: {}", source_path.display(), e))?;
+	
+	let (preview_data, color_counts): (Vec<u8>, Vec<(minify::DitheringMode, usize)>) = minipng::compare::preview_bytes(&source_data, options)?;
+	std::fs::write(preview_path, &preview_data)
+		.map_err(|e| anyhow!("Failed to write {}: {}", preview_path.display(), e))?;
+	
+	let quality: String = if options.quality == minify::AUTO_QUALITY { "auto".to_string() } else { options.quality.to_string() };
+	sayln!("Preview (quality {}) written to: {}", quality, preview_path.display());
+	for (mode, colors) in color_counts
+	{
+		sayln!("  {:<10} {} colors", minify::dithering_mode_to_string(mode), colors);
+	}
+	
+	Ok(())
+}
+
+/// Write the median cut palette chosen for a file as hex colors, most used first.
+fn run_dump_palette(source_path: &std::path::Path, dump_path: &std::path::Path, options: &minify::MinifyOptions) -> Result<()>
+{
+	let source_data: Vec<u8> = std::fs::read(source_path)
+		.map_err(|e| anyhow!("Failed to read {}: {}", source_path.display(), e))?;
+	
+	let colors: Vec<[u8; 4]> = minify::median_cut_palette(&source_data, options)?;
+	std::fs::write(dump_path, minipng::palette::format_palette(&colors))
+		.map_err(|e| anyhow!("Failed to write {}: {}", dump_path.display(), e))?;
+	
+	sayln!("Palette ({} colors) written to: {}", colors.len(), dump_path.display());
+	
+	Ok(())
+}
+
+/// Estimate the achievable savings by minifying every file in memory with the fast deflater.
+/// Nothing is written (no files, markers or cache entries). Already-minified files are left out unless force is set.
+#[allow(clippy::type_complexity)]
+fn run_estimate(png_files: &[utils::file_utils::PngFile], options: &minify::MinifyOptions, force: bool) -> std::process::ExitCode
+{
+	sayln!("Estimating savings (fast compression, nothing is written)...");
+	
+	let estimates: Vec<(String, Result<Option<(u64, u64)>>)> = minipng::utils::par_utils::map_items(png_files, |file: &utils::file_utils::PngFile|
+	{
+		(file.source_path.display().to_string(), estimate_file(&file.source_path, options, force))
+	});
+	
+	let mut total_original: u64 = 0;
+	let mut total_estimated: u64 = 0;
+	let mut skipped: usize = 0;
+	let mut failed: usize = 0;
+	for (file_path_display, estimate) in &estimates
+	{
+		match estimate
+		{
+			Ok(Some((original_size, estimated_size))) =>
+			{
+				print_result_message("", "Estimated", file_path_display, *original_size, *estimated_size, false);
+				total_original += original_size;
+				total_estimated += estimated_size;
+			},
+			Ok(None) =>
+			{
+				sayln!("{}: {} (already minified)", paint("Skipped", StatusColor::Yellow), file_path_display);
+				skipped += 1;
+			},
+			Err(err) =>
+			{
+				errln!("{} estimating {}: {}", paint("Error", StatusColor::Red), file_path_display, err);
+				failed += 1;
+			},
+		}
+	}
+	
+	sayln!("\n========================================");
+	sayln!("SAVINGS ESTIMATE");
+	sayln!("========================================");
+	sayln!("  - Files estimated: {}", estimates.len() - skipped - failed);
+	if skipped > 0
+	{
+		sayln!("  - Files skipped (already minified): {}", skipped);
+	}
+	if failed > 0
+	{
+		sayln!("  - Files with errors: {}", failed);
+	}
+	sayln!("  - Total size: {} -> {} ({:.1}% smaller, {} saved)", format_bytes(total_original), format_bytes(total_estimated), calculate_reduction_pct(total_original, total_estimated), format_bytes(total_original.saturating_sub(total_estimated)));
+	sayln!("Real savings are usually slightly better, since a real run compresses with Zopfli.");
+	
+	if failed > 0
+	{
+		std::process::ExitCode::from(EXIT_FILE_ERRORS)
+	}
+	else
+	{
+		std::process::ExitCode::SUCCESS
+	}
+}
+
+/// Estimate the minified size of one file.
+/// Returns its current and estimated size, or None if it is already minified and force isn't set.
+fn estimate_file(path: &std::path::Path, options: &minify::MinifyOptions, force: bool) -> Result<Option<(u64, u64)>>
+{
+	let source_data: Vec<u8> = std::fs::read(path)
+		.map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e))?;
+	
+	if !force && minify::is_already_minified(&source_data)?.0
+	{
+		return Ok(None);
+	}
+	
+	let (minified_data, _, _, _): (Vec<u8>, minify::DitheringMode, u8, Option<dithering::ImageClass>) = minify::minify_bytes(&source_data, options)?;
+	Ok(Some((source_data.len() as u64, minified_data.len() as u64)))
+}
+
+/// Copy the backups taken with --backup-dir back to their original paths.
+fn run_restore(backup_dir: &std::path::Path) -> Result<std::process::ExitCode>
+{
+	sayln!("Restoring files from '{}'...", backup_dir.display());
+	
+	let (restored, errors): (Vec<std::path::PathBuf>, Vec<(String, String)>) = utils::backup_utils::restore(backup_dir)?;
+	for path in &restored
+	{
+		sayln!("{}: {}", paint("Restored", StatusColor::Green), path.display());
+	}
+	for (file, err) in &errors
+	{
+		errln!("{} restoring {}: {}", paint("Error", StatusColor::Red), file, err);
+	}
+	
+	sayln!("\nFiles restored: {}", restored.len());
+	if !errors.is_empty()
+	{
+		sayln!("Files with errors: {}", errors.len());
+		return Ok(std::process::ExitCode::from(EXIT_FILE_ERRORS));
+	}
+	
+	Ok(std::process::ExitCode::SUCCESS)
+}
+
+/// Print the settings recorded in the marker of each file.
+fn run_info(files: &[std::path::PathBuf]) -> std::process::ExitCode
+{
+	let mut failed: bool = false;
+	for (index, path) in files.iter().enumerate()
+	{
+		if index > 0
+		{
+			sayln!();
+		}
+		
+		match minify::read_minification_info(path)
+		{
+			Ok(Some(info)) =>
+			{
+				sayln!("{}: minified by MiniPNG", path.display());
+				if info.lossless
+				{
+					sayln!("  Mode: Lossless");
+				}
+				else
+				{
+					sayln!("  Mode: Lossy");
+					sayln!("  Quality: {}", info.quality.map_or("unknown".to_string(), |q| q.to_string()));
+					sayln!("  Dithering: {}", info.dithering_mode.map_or("none", minify::dithering_mode_to_string));
+				}
+				sayln!("  Reduction: {:.1}%", info.reduction_pct);
+				if let Some(ref ts) = info.timestamp
+				{
+					sayln!("  Minified on: {}", format_timestamp(ts));
+				}
+				if info.marker_text != minify::MARKER_KEYWORD
+				{
+					sayln!("  Marker text: {}", info.marker_text);
+				}
+			},
+			Ok(None) => sayln!("{}: not minified by MiniPNG", path.display()),
+			Err(err) =>
+			{
+				errln!("{} reading {}: {}", paint("Error", StatusColor::Red), path.display(), err);
+				failed = true;
+				continue;
+			},
+		}
+		
+		// List the chunks with their data sizes, to spot bloat like a large iCCP profile or text chunks.
+		let chunks: Result<Vec<(String, usize)>> = std::fs::read(path)
+			.map_err(|e| anyhow!("Failed to read file: {}", e))
+			.and_then(|png_data| minify::list_chunks(&png_data).map_err(|e| anyhow!("{}", e)));
+		match chunks
+		{
+			Ok(chunks) =>
+			{
+				sayln!("  Chunks:");
+				for (chunk_type, size) in chunks
+				{
+					sayln!("    {}: {}", chunk_type, format_bytes(size as u64));
+				}
+			},
+			Err(err) =>
+			{
+				errln!("{} reading chunks of {}: {}", paint("Error", StatusColor::Red), path.display(), err);
+				failed = true;
+			},
+		}
+	}
+	
+	if failed
+	{
+		std::process::ExitCode::from(EXIT_FILE_ERRORS)
+	}
+	else
+	{
+		std::process::ExitCode::SUCCESS
+	}
+}
+
+/// Show what is recorded about an already-minified file and ask whether to re-minify it.
+/// The answer is read as one line from input (stdin). Returns true only if the answer is yes.
+fn ask_reminify(info: &minify::MinificationInfo, current_size: u64, input: &mut impl std::io::BufRead) -> bool
+{
+	let original_size_before: u64 = if info.reduction_pct > 0.0
+	{
+		(current_size as f64 / (1.0 - info.reduction_pct / 100.0)) as u64
+	}
+	else
+	{
+		current_size
+	};
+	
+	let mode_info: String = if info.lossless
+	{
+		"Lossless".to_string()
+	}
+	else
+	{
+		format!("Quality {} ({})", info.quality.unwrap_or(0), info.dithering_mode.map_or("no dithering", |m| minify::dithering_mode_to_string(m)))
+	};
+	
+	sayln!("  Mode: {}", mode_info);
+	sayln!("  Original size: {}", format_bytes(original_size_before));
+	sayln!("  Current size: {} ({:.1}% reduction)", format_bytes(current_size), info.reduction_pct);
+	if let Some(ref ts) = info.timestamp
+	{
+		sayln!("  Minified on: {}", format_timestamp(ts));
+	}
+	
+	sayln!("\nRe-minify? [y/N]: ");
+	
+	let mut line: String = String::new();
+	input.read_line(&mut line).unwrap_or_default();
+	let answer: String = line.trim().to_lowercase();
+	
+	answer == "y" || answer == "yes"
+}
+
+/// Write the requested machine-readable reports and save the analysis cache.
+fn write_reports(csv_path: Option<&std::path::Path>, manifest_path: Option<&std::path::Path>, cache: Option<&AnalysisCache>, results: &[minify::ProcessingResult], errors: &[(String, String)]) -> Result<()>
+{
+	// A cache that can't be written only costs time on the next run.
+	if let Some(cache) = cache && let Err(e) = cache.save()
+	{
+		errln!("Warning: {}", e);
+	}
+	
+	if let Some(path) = csv_path
+	{
+		utils::report_utils::write_csv_report(path, results, errors)?;
+		sayln!("CSV report written to: {}", path.display());
+	}
+	
+	if let Some(path) = manifest_path
+	{
+		utils::report_utils::write_manifest(path, results)?;
+		sayln!("Manifest written to: {}", path.display());
+	}
+	
+	Ok(())
+}
+
+/// Remove the --resume progress file once the run completed every file without errors.
+/// Otherwise it is kept, so the next run with --resume picks up where this one stopped.
+fn finish_progress(progress: Option<&ProgressLog>, errors: &[(String, String)], complete: bool)
+{
+	if let Some(progress) = progress && complete && errors.is_empty() && let Err(e) = progress.clear()
+	{
+		errln!("Warning: {}", e);
+	}
+}
+
+/// Formats file size in human-readable format.
+fn format_bytes(size: u64) -> String
+{
+	if size < 1024
+	{
+		format!("{} B", size)
+	}
+	else if size < 1024 * 1024
+	{
+		format!("{:.1} KB", size as f64 / 1024.0)
+	}
+	else
+	{
+		format!("{:.2} MB", size as f64 / (1024.0 * 1024.0))
+	}
+}
+
+/// Describe why a file was skipped.
+fn describe_skip_reason(reason: minify::SkipReason) -> &'static str
+{
+	match reason
+	{
+		minify::SkipReason::AlreadyMinified => "already minified",
+		minify::SkipReason::TargetExists => "target already exists",
+		minify::SkipReason::NotPng => "not a valid PNG",
+		minify::SkipReason::ReadOnly => "target is read-only, use --force-writable to write it",
+		minify::SkipReason::TooLarge => "image has more pixels than --max-pixels allows",
+		minify::SkipReason::UnsupportedFormat => "color type and bit depth in the header are not a valid PNG combination",
+	}
+}
+
+/// Add the algorithm a file was processed with to a status label, e.g. "Minified (floyd)",
+/// the size of the trailing data removed with --strip-trailing, e.g. "Minified (floyd) [1.0 KB trailing data stripped]",
+/// and the size of the WebP written with --also-webp, e.g. "Minified (floyd) [WebP: 12.3 KB]".
+fn label_with_mode(prefix: &str, result: &minify::ProcessingResult) -> String
+{
+	let mut label: String = mode_label(prefix, result);
+	if result.trailing_stripped > 0
+	{
+		label = format!("{} [{} trailing data stripped]", label, format_bytes(result.trailing_stripped));
+	}
+	
+	if let Some(webp_size) = result.webp_size
+	{
+		label = format!("{} [WebP: {}]", label, format_bytes(webp_size));
+	}
+	
+	label
+}
+
+/// Add the algorithm a file was processed with to a status label.
+fn mode_label(prefix: &str, result: &minify::ProcessingResult) -> String
+{
+	let mode: &str = utils::report_utils::result_mode_name(result);
+	if mode.is_empty()
+	{
+		return prefix.to_string();
+	}
+	
+	// Show that lossless minification won after lossy output wasn't smaller.
+	if result.lossless_fallback
+	{
+		return format!("{} (lossless fallback)", prefix);
+	}
+	
+	// Show which output auto mode kept.
+	if result.auto_mode
+	{
+		return format!("{} (auto: {})", prefix, mode);
+	}
+	
+	// Show the class the file was sorted into and its quality with --quality-per-type.
+	if let (Some(class), Some(quality)) = (result.image_class, result.quality)
+	{
+		return format!("{} ({}, {}, quality {})", prefix, mode, dithering::image_class_to_string(class), quality);
+	}
+	
+	// Show the quality picked for the file when it was chosen automatically.
+	if result.auto_quality && let Some(quality) = result.quality
+	{
+		return format!("{} ({}, quality {})", prefix, mode, quality);
+	}
+	
+	format!("{} ({})", prefix, mode)
+}
+
+/// Count how many files were processed with each algorithm, most used first.
+/// Files skipped as already minified are not counted.
+fn count_modes(results: &[minify::ProcessingResult]) -> Vec<(&'static str, usize)>
+{
+	let mut counts: Vec<(&'static str, usize)> = Vec::new();
+	for r in results
+	{
+		let mode: &'static str = utils::report_utils::result_mode_name(r);
+		if r.skip_reason.is_some() || mode.is_empty()
+		{
+			continue;
+		}
+		
+		match counts.iter_mut().find(|(name, _)| *name == mode)
+		{
+			Some(entry) => entry.1 += 1,
+			None => counts.push((mode, 1)),
+		}
+	}
+	
+	counts.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+	counts
+}
+
+/// Print minification result with appropriate message, after the progress counter (such as "[1/5] "), if any.
+fn print_result_message(counter: &str, prefix: &str, file_path: &str, original_size: u64, new_size: u64, re_encoded: bool)
+{
+	if re_encoded
+	{
+		sayln!("{}{}: {} | {} -> {} (re-encoded, not smaller)", counter, paint("Re-encoded", StatusColor::Yellow), file_path, format_bytes(original_size), format_bytes(new_size));
+	}
+	else if new_size < original_size
+	{
+		let reduction_pct: f64 = calculate_reduction_pct(original_size, new_size);
+		sayln!("{}{}: {} | {} -> {} ({:.1}% smaller)", counter, paint(prefix, StatusColor::Green), file_path, format_bytes(original_size), format_bytes(new_size), reduction_pct);
+	}
+	else
+	{
+		sayln!("{}{}: {} (file couldn't be minified further)", counter, paint(prefix, StatusColor::Yellow), file_path);
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+	
+	#[test]
+	fn png_piped_through_decodes_to_the_same_dimensions()
+	{
+		let rgba: image::RgbaImage = image::RgbaImage::from_fn(64, 48, |x: u32, y: u32| image::Rgba([(x * 4) as u8, (y * 5) as u8, ((x ^ y) * 3) as u8, 255]));
+		let mut source_data: Vec<u8> = Vec::new();
+		image::DynamicImage::ImageRgba8(rgba.clone()).write_to(&mut std::io::Cursor::new(&mut source_data), image::ImageFormat::Png).expect("Failed to encode test image");
+		
+		for lossless in [true, false]
+		{
+			let builder: minify::MinifyOptionsBuilder = if lossless { minify::MinifyOptions::builder().lossless(true) } else { minify::MinifyOptions::builder().quality(80) };
+			let options: minify::MinifyOptions = builder.fast_deflate(true).build().expect("Valid options");
+			let mut output_data: Vec<u8> = Vec::new();
+			pipe_png(&mut source_data.as_slice(), &mut output_data, &options).expect("Pipeline failed");
+			
+			let decoded: image::RgbaImage = image::load_from_memory(&output_data).expect("Output is not a PNG").to_rgba8();
+			assert_eq!(decoded.dimensions(), (64, 48));
+			if lossless
+			{
+				assert_eq!(decoded, rgba);
+			}
+		}
+	}
+	
+	#[test]
+	fn interactive_prompt_follows_each_answer()
+	{
+		let info: minify::MinificationInfo = minify::MinificationInfo
+		{
+			quality: Some(40),
+			dithering_mode: Some(minify::DitheringMode::FloydSteinberg),
+			lossless: false,
+			reduction_pct: 50.0,
+			timestamp: None,
+			noop: false,
+			marker_text: minify::MARKER_KEYWORD.to_string(),
+		};
+		
+		// One answer per already-minified file, read from the same input like stdin in a batch run.
+		let mut input: std::io::Cursor<&[u8]> = std::io::Cursor::new(b"y\nn\n  YES \n\nmaybe\n");
+		let mut answers: Vec<bool> = Vec::new();
+		for _ in 0..6
+		{
+			answers.push(ask_reminify(&info, 1000, &mut input));
+		}
+		
+		// The input ran out before the last question, which counts as no.
+		assert_eq!(answers, [true, false, true, false, false, false]);
+	}
+	
+	#[test]
+	fn list_only_exits_successfully_without_touching_files()
+	{
+		let dir: std::path::PathBuf = utils::file_utils::tests::scratch_dir("list-only");
+		std::fs::create_dir_all(dir.join("sub")).expect("Failed to create subdirectory");
+		let mut originals: Vec<(std::path::PathBuf, Vec<u8>)> = Vec::new();
+		for name in ["a.png", "sub/b.png"]
+		{
+			let rgba: image::RgbaImage = image::RgbaImage::from_fn(32, 32, |x: u32, y: u32| image::Rgba([(x * 8) as u8, (y * 8) as u8, 100, 255]));
+			rgba.save(dir.join(name)).expect("Failed to write test image");
+			originals.push((dir.join(name), std::fs::read(dir.join(name)).expect("Failed to read test image")));
+		}
+		
+		let dir_arg: String = dir.display().to_string();
+		let args: Args = utils::arg_utils::tests::parse(&["--list-only", "--dir", &dir_arg]).expect("Failed to parse arguments");
+		assert_eq!(run(args, std::time::Instant::now()).expect("Run failed"), std::process::ExitCode::SUCCESS);
+		
+		for (path, data) in &originals
+		{
+			assert_eq!(&std::fs::read(path).expect("Failed to read test image"), data, "{}", path.display());
+		}
+		assert_eq!(std::fs::read_dir(&dir).expect("Failed to list directory").count(), 2);
+		
+		let _ = std::fs::remove_dir_all(&dir);
+	}
+	
+	#[test]
+	fn a_file_that_fails_to_decode_sets_the_error_exit_code()
+	{
+		let dir: std::path::PathBuf = utils::file_utils::tests::scratch_dir("exit-code");
+		let rgba: image::RgbaImage = image::RgbaImage::from_fn(32, 32, |x: u32, y: u32| image::Rgba([(x * 8) as u8, (y * 8) as u8, 100, 255]));
+		rgba.save(dir.join("good.png")).expect("Failed to write test image");
+		
+		// A PNG signature followed by garbage passes the signature check but can't be decoded.
+		let mut broken: Vec<u8> = minify::PNG_SIGNATURE.to_vec();
+		broken.extend_from_slice(&[0x5A; 64]);
+		std::fs::write(dir.join("broken.png"), &broken).expect("Failed to write broken file");
+		
+		let dir_arg: String = dir.display().to_string();
+		let args: Args = utils::arg_utils::tests::parse(&["--dir", &dir_arg, "--quality", "40"]).expect("Failed to parse arguments");
+		assert_eq!(run(args, std::time::Instant::now()).expect("Run failed"), std::process::ExitCode::from(EXIT_FILE_ERRORS));
+		
+		let _ = std::fs::remove_dir_all(&dir);
+	}
+	
+	#[test]
+	fn optimizer_threads_split_the_pool_between_concurrent_files()
+	{
+		assert_eq!(optimizer_threads(8, 1), 8);
+		assert_eq!(optimizer_threads(8, 3), 2);
+		assert_eq!(optimizer_threads(8, 100), 1);
+		assert_eq!(optimizer_threads(0, 0), 1);
+	}
+	
+	#[test]
+	fn threads_per_file_overrides_the_split_of_the_pool()
+	{
+		let args: Args = utils::arg_utils::tests::parse(&["--threads-per-file", "5", "a.png"]).expect("Valid arguments");
+		let threads: usize = threads_per_file(&args, 16, 16);
+		assert_eq!(threads, 5);
+		let options: minify::MinifyOptions = minify::MinifyOptions::builder().optimizer_threads(threads).build().expect("Valid options");
+		assert_eq!(options.optimizer_threads, 5);
+		
+		let args: Args = utils::arg_utils::tests::parse(&["a.png"]).expect("Valid arguments");
+		assert_eq!(threads_per_file(&args, 16, 16), 1);
+		assert_eq!(threads_per_file(&args, 16, 1), 16);
+	}
+	
+	#[test]
+	fn estimate_writes_nothing_and_reports_plausible_savings()
+	{
+		let dir: std::path::PathBuf = utils::file_utils::tests::scratch_dir("estimate");
+		let path: std::path::PathBuf = dir.join("photo.png");
+		let rgba: image::RgbaImage = utils::file_utils::tests::photo(96, 64);
+		rgba.save(&path).expect("Failed to write test image");
+		let original: Vec<u8> = std::fs::read(&path).expect("Failed to read test image");
+		
+		let options: minify::MinifyOptions = minify::MinifyOptions::builder().quality(40).fast_deflate(true).build().expect("Valid options");
+		let (original_size, estimated_size): (u64, u64) = estimate_file(&path, &options, false).expect("Estimate failed").expect("File is not minified");
+		assert_eq!(original_size, original.len() as u64);
+		assert!(estimated_size > 0 && estimated_size < original_size, "{} -> {}", original_size, estimated_size);
+		
+		let dir_arg: String = dir.display().to_string();
+		let args: Args = utils::arg_utils::tests::parse(&["--estimate", "--dir", &dir_arg, "--quality", "40"]).expect("Failed to parse arguments");
+		assert_eq!(run(args, std::time::Instant::now()).expect("Run failed"), std::process::ExitCode::SUCCESS);
+		
+		assert_eq!(std::fs::read(&path).expect("Failed to read test image"), original);
+		assert_eq!(std::fs::read_dir(&dir).expect("Failed to list directory").count(), 1);
+		
+		let _ = std::fs::remove_dir_all(&dir);
+	}
+	
+	#[test]
+	fn dumped_palette_has_the_requested_number_of_hex_colors()
+	{
+		let dir: std::path::PathBuf = utils::file_utils::tests::scratch_dir("dump-palette");
+		let source_path: std::path::PathBuf = dir.join("photo.png");
+		let dump_path: std::path::PathBuf = dir.join("palette.txt");
+		let rgba: image::RgbaImage = utils::file_utils::tests::photo(64, 48);
+		rgba.save(&source_path).expect("Failed to write test image");
+		
+		let options: minify::MinifyOptions = minify::MinifyOptions::builder().quality(60).median_colors(Some(16)).build().expect("Valid options");
+		run_dump_palette(&source_path, &dump_path, &options).expect("Palette dump failed");
+		
+		let text: String = std::fs::read_to_string(&dump_path).expect("Failed to read the palette");
+		let mut count: usize = 0;
+		for line in text.lines()
+		{
+			let digits: &str = line.strip_prefix('#').expect("Palette entry without #");
+			assert!(digits.len() == 6 || digits.len() == 8, "{}", line);
+			assert!(u32::from_str_radix(digits, 16).is_ok(), "{}", line);
+			count += 1;
+		}
+		assert_eq!(count, 16);
+		
+		let _ = std::fs::remove_dir_all(&dir);
+	}
+	
+	#[test]
+	fn spent_time_budget_leaves_every_file_untouched_and_exits_successfully()
+	{
+		let dir: std::path::PathBuf = utils::file_utils::tests::scratch_dir("time-budget-run");
+		let mut originals: Vec<(std::path::PathBuf, Vec<u8>)> = Vec::new();
+		for name in ["a.png", "b.png", "c.png"]
+		{
+			let rgba: image::RgbaImage = utils::file_utils::tests::photo(48, 48);
+			rgba.save(dir.join(name)).expect("Failed to write test image");
+			originals.push((dir.join(name), std::fs::read(dir.join(name)).expect("Failed to read test image")));
+		}
+		
+		// The run started long before its one-second budget, so the batch loop starts no file.
+		let dir_arg: String = dir.display().to_string();
+		let args: Args = utils::arg_utils::tests::parse(&["--dir", &dir_arg, "--time-budget", "1", "--quality", "40"]).expect("Failed to parse arguments");
+		let start_time: std::time::Instant = std::time::Instant::now().checked_sub(std::time::Duration::from_secs(60)).expect("Clock too close to its origin");
+		assert_eq!(run(args, start_time).expect("Run failed"), std::process::ExitCode::SUCCESS);
+		
+		for (path, data) in &originals
+		{
+			assert_eq!(&std::fs::read(path).expect("Failed to read test image"), data, "{}", path.display());
+		}
+		
+		let _ = std::fs::remove_dir_all(&dir);
+	}
+	
+	#[test]
+	fn streamed_run_with_report_none_prints_no_summary()
+	{
+		let dir: std::path::PathBuf = utils::file_utils::tests::scratch_dir("stream-report-none");
+		let log_path: std::path::PathBuf = std::env::temp_dir().join(format!("minipng-stream-report-none-{}.log", std::process::id()));
+		let rgba: image::RgbaImage = image::RgbaImage::from_fn(32, 32, |x: u32, y: u32| image::Rgba([(x * 8) as u8, (y * 8) as u8, 100, 255]));
+		rgba.save(dir.join("a.png")).expect("Failed to write test image");
+		
+		// The log mirrors the printed lines, so it shows what the run printed.
+		let _guard: std::sync::MutexGuard<()> = utils::log_utils::tests::LOG_LOCK.lock().expect("Log test mutex poisoned");
+		let dir_arg: String = dir.display().to_string();
+		let log_arg: String = log_path.display().to_string();
+		let args: Args = utils::arg_utils::tests::parse(&["--stream", "--dir", &dir_arg, "--report", "none", "--log-file", &log_arg, "--quality", "40"]).expect("Failed to parse arguments");
+		let outcome: Result<std::process::ExitCode> = run(args, std::time::Instant::now());
+		utils::log_utils::tests::close_log();
+		assert_eq!(outcome.expect("Run failed"), std::process::ExitCode::SUCCESS);
+		
+		let log: String = std::fs::read_to_string(&log_path).expect("Failed to read log");
+		assert!(log.contains(&format!("Streaming PNG files from '{}'", dir_arg)), "{}", log);
+		assert!(!log.contains("MINIFICATION SUMMARY (streamed)"), "{}", log);
+		assert!(!log.contains("Files not reduced: "), "{}", log);
+		
+		let _ = std::fs::remove_file(&log_path);
+		let _ = std::fs::remove_dir_all(&dir);
 	}
 }
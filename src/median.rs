@@ -1,5 +1,24 @@
 use image::RgbaImage;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+
+use crate::utils::color_utils::{from_linear, to_linear};
+use crate::utils::par_utils;
+
+/// Images up to this many pixels (256x256) are sampled in full.
+const SMALL_IMAGE_PIXELS: u64 = 65_536;
+
+/// Images up to this many pixels (1024x1024) sample every 2nd pixel.
+const MEDIUM_IMAGE_PIXELS: u64 = 1_048_576;
+
+/// Images up to this many pixels (4096x4096) sample every 4th pixel, larger ones every 8th.
+const LARGE_IMAGE_PIXELS: u64 = 16_777_216;
+
+/// Maximum number of k-means iterations when refining a palette.
+const REFINE_MAX_ITERATIONS: usize = 8;
+
+/// Work budget (sample-to-palette distance computations) for palette refinement.
+/// Keeps refinement time bounded on large images with many distinct colors.
+const REFINE_WORK_BUDGET: usize = 64_000_000;
 
 /// RGB color representation.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -125,13 +144,19 @@ impl ColorBox
 	}
 	
 	/// Get the weighted average color (using frequency counts for better quality).
-	fn get_average_color(&self) -> Color
+	/// In linear mode (the encoding gamma of the colors), colors are averaged in linear light to avoid a darkening bias.
+	fn get_average_color(&self, linear: Option<f32>) -> Color
 	{
 		if self.colors.is_empty()
 		{
 			return Color::new(0, 0, 0, 255);
 		}
 		
+		if let Some(gamma) = linear
+		{
+			return self.get_linear_average_color(gamma);
+		}
+		
 		let mut sum_r: u64 = 0;
 		let mut sum_g: u64 = 0;
 		let mut sum_b: u64 = 0;
@@ -154,37 +179,81 @@ impl ColorBox
 		
 		Color::new((sum_r / total_count) as u8, (sum_g / total_count) as u8, (sum_b / total_count) as u8, 255)
 	}
+	
+	/// Get the weighted average color computed in linear light.
+	fn get_linear_average_color(&self, gamma: f32) -> Color
+	{
+		let mut sum_r: f64 = 0.0;
+		let mut sum_g: f64 = 0.0;
+		let mut sum_b: f64 = 0.0;
+		let mut total_count: f64 = 0.0;
+		
+		for (color, count) in &self.colors
+		{
+			let count_f64: f64 = *count as f64;
+			sum_r += to_linear(color.r, gamma) as f64 * count_f64;
+			sum_g += to_linear(color.g, gamma) as f64 * count_f64;
+			sum_b += to_linear(color.b, gamma) as f64 * count_f64;
+			total_count += count_f64;
+		}
+		
+		if total_count == 0.0
+		{
+			return Color::new(0, 0, 0, 255);
+		}
+		
+		Color::new(from_linear((sum_r / total_count) as f32, gamma), from_linear((sum_g / total_count) as f32, gamma), from_linear((sum_b / total_count) as f32, gamma), 255)
+	}
+}
+
+/// Pick the color sampling step for an image of the given size.
+/// Small images sample every pixel so rare accent colors aren't missed, larger images sample more sparsely for speed.
+pub fn auto_sample_step(width: u32, height: u32) -> usize
+{
+	let pixel_count: u64 = width as u64 * height as u64;
+	if pixel_count <= SMALL_IMAGE_PIXELS
+	{
+		1
+	}
+	else if pixel_count <= MEDIUM_IMAGE_PIXELS
+	{
+		2
+	}
+	else if pixel_count <= LARGE_IMAGE_PIXELS
+	{
+		4
+	}
+	else
+	{
+		8
+	}
 }
 
 /// Quantize an image using median cut algorithm.
-pub fn quantize_image_with_median(rgba: &RgbaImage, max_colors: usize) -> RgbaImage
+/// Every sample_step-th pixel is sampled in both axes to build the palette (0 picks a step from the image size).
+/// With linear (the encoding gamma of the image), colors are averaged in linear light instead of as encoded.
+/// With refine, the median-cut palette is improved with a few k-means (Lloyd) iterations before mapping.
+pub fn quantize_image_with_median(rgba: &RgbaImage, max_colors: usize, linear: Option<f32>, refine: bool, sample_step: usize) -> RgbaImage
+{
+	quantize_image_with_median_palette(rgba, max_colors, linear, refine, sample_step).0
+}
+
+/// Quantize an image using median cut like quantize_image_with_median, also returning the palette.
+/// The palette holds the distinct RGBA palette colors, most used first.
+#[allow(clippy::reserve_after_initialization)]
+pub fn quantize_image_with_median_palette(rgba: &RgbaImage, max_colors: usize, linear: Option<f32>, refine: bool, sample_step: usize) -> (RgbaImage, Vec<[u8; 4]>)
 {
-	use rayon::prelude::*;
-	
 	let (width, height): (u32, u32) = rgba.dimensions();
-	
-	// Collect unique colors (sampling for speed on large images).
-	let mut color_counts: HashMap<Color, u32> = HashMap::new();
-	
-	// Sample every 4th pixel for speed (16x fewer pixels, still excellent coverage).
-	// Median cut is very tolerant of sampling - we just need color variety, not every pixel.
-	let sample_step: usize = 4;
-	for y in (0..height).step_by(sample_step)
+	if width == 0 || height == 0
 	{
-		for x in (0..width).step_by(sample_step)
-		{
-			let pixel: &image::Rgba<u8> = rgba.get_pixel(x, y);
-			let color: Color = Color::new(pixel[0], pixel[1], pixel[2], pixel[3]);
-			*color_counts.entry(color).or_insert(0) += 1;
-		}
+		return (rgba.clone(), Vec::new());
 	}
 	
 	// Start with all colors in one box (with their frequency counts).
-	let mut initial_colors: Vec<(Color, u32)> = Vec::new();
-	for (color, count) in color_counts
-	{
-		initial_colors.push((color, count));
-	}
+	let initial_colors: Vec<(Color, u32)> = sample_colors(rgba, sample_step);
+	
+	// Keep the sampled colors for refinement (the initial box takes ownership of its copy).
+	let samples: Vec<(Color, u32)> = if refine { initial_colors.clone() } else { Vec::new() };
 	
 	let mut boxes: Vec<ColorBox> = Vec::new();
 	boxes.push(ColorBox::new(initial_colors));
@@ -224,13 +293,139 @@ pub fn quantize_image_with_median(rgba: &RgbaImage, max_colors: usize) -> RgbaIm
 	
 	for b in &boxes
 	{
-		palette.push(b.get_average_color());
+		palette.push(b.get_average_color(linear));
+	}
+	
+	if refine
+	{
+		refine_palette(&mut palette, &samples, linear);
 	}
 	
-	// Create the quantized image using parallel processing with per-row caching.
+	map_to_nearest(rgba, &palette)
+}
+
+/// Collect the distinct colors of every sample_step-th pixel in both axes, with how often each was sampled.
+/// Palette building is very tolerant of sampling - it needs color variety, not every pixel (0 picks a step from the image size).
+pub(crate) fn sample_colors(rgba: &RgbaImage, sample_step: usize) -> Vec<(Color, u32)>
+{
+	let (width, height): (u32, u32) = rgba.dimensions();
+	let mut color_counts: HashMap<Color, u32> = HashMap::new();
+	
+	// The step is kept between 1 and the image size, so at least one pixel is always sampled.
+	let requested_step: usize = if sample_step == 0 { auto_sample_step(width, height) } else { sample_step };
+	let sample_step: usize = requested_step.clamp(1, width.max(height).max(1) as usize);
+	for y in (0..height).step_by(sample_step)
+	{
+		for x in (0..width).step_by(sample_step)
+		{
+			let pixel: &image::Rgba<u8> = rgba.get_pixel(x, y);
+			let color: Color = Color::new(pixel[0], pixel[1], pixel[2], pixel[3]);
+			*color_counts.entry(color).or_insert(0) += 1;
+		}
+	}
+	
+	color_counts.into_iter().collect()
+}
+
+/// Weighted average of colors with their counts, opaque (see ColorBox::get_average_color).
+pub(crate) fn average_color(colors: Vec<(Color, u32)>, linear: Option<f32>) -> Color
+{
+	ColorBox::new(colors).get_average_color(linear)
+}
+
+/// Map every pixel to the nearest color of a quantizer's palette.
+/// Returns the quantized image and the distinct palette colors, most used first (ties keep the palette order).
+#[allow(clippy::type_complexity)]
+pub(crate) fn map_to_nearest(rgba: &RgbaImage, palette: &[Color]) -> (RgbaImage, Vec<[u8; 4]>)
+{
+	let (width, height): (u32, u32) = rgba.dimensions();
+	
+	// Find the closest palette color once per distinct image color, in parallel.
+	// The resulting lookup is shared read-only by all rows, so colors repeated across rows aren't searched again.
+	let mut seen_colors: HashSet<Color> = HashSet::new();
+	let mut distinct_colors: Vec<Color> = Vec::new();
+	for pixel in rgba.pixels()
+	{
+		let color: Color = Color::new(pixel[0], pixel[1], pixel[2], pixel[3]);
+		if seen_colors.insert(color)
+		{
+			distinct_colors.push(color);
+		}
+	}
+	let closest_colors: Vec<Color> = par_utils::map_items(&distinct_colors, |color| find_closest_palette_color(color, palette));
+	let mut lookup: HashMap<Color, Color> = HashMap::with_capacity(distinct_colors.len());
+	for (index, color) in distinct_colors.iter().enumerate()
+	{
+		lookup.insert(*color, closest_colors[index]);
+	}
+	
+	// Create the quantized image using parallel processing.
 	let mut quantized: RgbaImage = RgbaImage::new(width, height);
 	
-	let rows: Vec<(u32, Vec<(u32, image::Rgba<u8>)>)> = (0..height).into_par_iter().map(|y|
+	let rows: Vec<(u32, Vec<(u32, image::Rgba<u8>)>)> = par_utils::map_rows(height, |y|
+	{
+		let mut row_pixels: Vec<(u32, image::Rgba<u8>)> = Vec::with_capacity(width as usize);
+		for x in 0..width
+		{
+			let pixel: &image::Rgba<u8> = rgba.get_pixel(x, y);
+			let quantized_color: Color = lookup[&Color::new(pixel[0], pixel[1], pixel[2], pixel[3])];
+			row_pixels.push((x, image::Rgba([quantized_color.r, quantized_color.g, quantized_color.b, quantized_color.a])));
+		}
+		
+		(y, row_pixels)
+	});
+	
+	// Write all rows to the output image, counting how often each palette color is used.
+	let mut usage: HashMap<[u8; 4], u64> = HashMap::new();
+	for (y, row_pixels) in rows
+	{
+		for (x, pixel) in row_pixels
+		{
+			*usage.entry(pixel.0).or_insert(0) += 1;
+			quantized.put_pixel(x, y, pixel);
+		}
+	}
+	
+	// Distinct palette colors, most used first (ties keep the palette order).
+	let mut colors: Vec<[u8; 4]> = Vec::with_capacity(palette.len());
+	for color in palette
+	{
+		let rgba_color: [u8; 4] = [color.r, color.g, color.b, color.a];
+		if !colors.contains(&rgba_color)
+		{
+			colors.push(rgba_color);
+		}
+	}
+	colors.sort_by_key(|color| std::cmp::Reverse(usage.get(color).copied().unwrap_or(0)));
+	
+	(quantized, colors)
+}
+
+/// Map every pixel to the nearest color of a fixed palette, matching on RGB. Each pixel keeps its own alpha.
+/// With dither, the mapping error is spread to the neighboring pixels (Floyd-Steinberg).
+#[allow(clippy::type_complexity)]
+pub fn map_to_palette(rgba: &RgbaImage, palette: &[[u8; 3]], dither: bool) -> RgbaImage
+{
+	let (width, height): (u32, u32) = rgba.dimensions();
+	if width == 0 || height == 0 || palette.is_empty()
+	{
+		return rgba.clone();
+	}
+	
+	let mut colors: Vec<Color> = Vec::with_capacity(palette.len());
+	for rgb in palette
+	{
+		colors.push(Color::new(rgb[0], rgb[1], rgb[2], 255));
+	}
+	
+	if dither
+	{
+		return map_to_palette_dithered(rgba, &colors);
+	}
+	
+	let mut mapped: RgbaImage = RgbaImage::new(width, height);
+	
+	let rows: Vec<(u32, Vec<(u32, image::Rgba<u8>)>)> = par_utils::map_rows(height, |y|
 	{
 		let mut row_pixels: Vec<(u32, image::Rgba<u8>)> = Vec::with_capacity(width as usize);
 		let mut color_cache: HashMap<Color, Color> = HashMap::new();
@@ -238,36 +433,146 @@ pub fn quantize_image_with_median(rgba: &RgbaImage, max_colors: usize) -> RgbaIm
 		for x in 0..width
 		{
 			let pixel: &image::Rgba<u8> = rgba.get_pixel(x, y);
-			let original_color: Color = Color::new(pixel[0], pixel[1], pixel[2], pixel[3]);
+			let original_color: Color = Color::new(pixel[0], pixel[1], pixel[2], 255);
+			
+			let closest: Color = *color_cache.entry(original_color).or_insert_with(|| find_closest_palette_color(&original_color, &colors));
+			row_pixels.push((x, image::Rgba([closest.r, closest.g, closest.b, pixel[3]])));
+		}
+		
+		(y, row_pixels)
+	});
+	
+	for (y, row_pixels) in rows
+	{
+		for (x, pixel) in row_pixels
+		{
+			mapped.put_pixel(x, y, pixel);
+		}
+	}
+	
+	mapped
+}
+
+/// Map every pixel to the nearest palette color with Floyd-Steinberg error diffusion.
+/// Fully transparent pixels are mapped without spreading their error, since it isn't visible.
+fn map_to_palette_dithered(rgba: &RgbaImage, palette: &[Color]) -> RgbaImage
+{
+	let (width, height): (u32, u32) = rgba.dimensions();
+	let mut mapped: RgbaImage = RgbaImage::new(width, height);
+	
+	// Error carried into the current and the next row, with a spare column on each side.
+	let mut current_errors: Vec<[f32; 3]> = vec![[0.0; 3]; width as usize + 2];
+	let mut next_errors: Vec<[f32; 3]> = vec![[0.0; 3]; width as usize + 2];
+	
+	for y in 0..height
+	{
+		for x in 0..width
+		{
+			let pixel: &image::Rgba<u8> = rgba.get_pixel(x, y);
+			let index: usize = x as usize + 1;
 			
-			// Check cache first.
-			let quantized_color: Color = if let Some(&cached) = color_cache.get(&original_color)
+			let mut wanted: [f32; 3] = [0.0; 3];
+			for channel in 0..3
 			{
-				cached
+				wanted[channel] = (pixel[channel] as f32 + current_errors[index][channel]).clamp(0.0, 255.0);
 			}
-			else // Find closest palette color.
+			
+			let wanted_color: Color = Color::new(wanted[0].round() as u8, wanted[1].round() as u8, wanted[2].round() as u8, 255);
+			let closest: Color = find_closest_palette_color(&wanted_color, palette);
+			mapped.put_pixel(x, y, image::Rgba([closest.r, closest.g, closest.b, pixel[3]]));
+			
+			if pixel[3] == 0
 			{
-				let closest: Color = find_closest_palette_color(&original_color, &palette);
-				color_cache.insert(original_color, closest);
-				closest
-			};
+				continue;
+			}
 			
-			row_pixels.push((x, image::Rgba([quantized_color.r, quantized_color.g, quantized_color.b, quantized_color.a])));
+			let chosen: [u8; 3] = [closest.r, closest.g, closest.b];
+			for channel in 0..3
+			{
+				let error: f32 = wanted[channel] - chosen[channel] as f32;
+				current_errors[index + 1][channel] += error * 7.0 / 16.0;
+				next_errors[index - 1][channel] += error * 3.0 / 16.0;
+				next_errors[index][channel] += error * 5.0 / 16.0;
+				next_errors[index + 1][channel] += error * 1.0 / 16.0;
+			}
 		}
 		
-		(y, row_pixels)
-	}).collect();
+		std::mem::swap(&mut current_errors, &mut next_errors);
+		next_errors.fill([0.0; 3]);
+	}
 	
-	// Write all rows to the output image.
-	for (y, row_pixels) in rows
+	mapped
+}
+
+/// Improve a palette with k-means (Lloyd) iterations over the sampled colors.
+/// Each iteration assigns every sample to its nearest palette color and moves each palette color to the weighted mean of its samples.
+/// The number of iterations is bounded by a work budget, so large images with many colors only get one pass.
+pub(crate) fn refine_palette(palette: &mut [Color], samples: &[(Color, u32)], linear: Option<f32>)
+{
+	if palette.is_empty() || samples.is_empty()
 	{
-		for (x, pixel) in row_pixels
+		return;
+	}
+	
+	let work_per_iteration: usize = samples.len().saturating_mul(palette.len()).max(1);
+	let iterations: usize = (REFINE_WORK_BUDGET / work_per_iteration).clamp(1, REFINE_MAX_ITERATIONS);
+	
+	for _ in 0..iterations
+	{
+		// Assign each sample to its nearest palette color.
+		let mut clusters: Vec<Vec<(Color, u32)>> = vec![Vec::new(); palette.len()];
+		for &(color, count) in samples
 		{
-			quantized.put_pixel(x, y, pixel);
+			let index: usize = find_closest_palette_index(&color, palette);
+			clusters[index].push((color, count));
+		}
+		
+		// Move each palette color to the weighted mean of its cluster (empty clusters keep their color).
+		let mut changed: bool = false;
+		for (index, cluster) in clusters.into_iter().enumerate()
+		{
+			if cluster.is_empty()
+			{
+				continue;
+			}
+			
+			let center: Color = average_color(cluster, linear);
+			if center != palette[index]
+			{
+				palette[index] = center;
+				changed = true;
+			}
+		}
+		
+		if !changed
+		{
+			break;
 		}
 	}
+}
+
+/// Find the index of the closest color in a palette.
+fn find_closest_palette_index(color: &Color, palette: &[Color]) -> usize
+{
+	let mut best_index: usize = 0;
+	let mut best_distance: u64 = u64::MAX;
 	
-	quantized
+	for (index, palette_color) in palette.iter().enumerate()
+	{
+		let distance: u64 = color_distance(color, palette_color);
+		if distance < best_distance
+		{
+			best_distance = distance;
+			best_index = index;
+			
+			if distance == 0
+			{
+				break;
+			}
+		}
+	}
+	
+	best_index
 }
 
 /// Find the closest color in a palette.
@@ -303,3 +608,127 @@ fn color_distance(c1: &Color, c2: &Color) -> u64
 	
 	(dr * dr + dg * dg + db * db) as u64
 }
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+	use crate::test_utils;
+	
+	const RED: [u8; 4] = [255, 0, 0, 255];
+	const GREEN: [u8; 4] = [0, 255, 0, 255];
+	const BLUE: [u8; 4] = [0, 0, 255, 255];
+	const WHITE: [u8; 4] = [255, 255, 255, 255];
+	
+	#[test]
+	fn keeps_colors_that_fit_the_palette()
+	{
+		let rgba: RgbaImage = test_utils::blocks(16, 16, 4, &[RED, GREEN, BLUE, WHITE]);
+		let quantized: RgbaImage = quantize_image_with_median(&rgba, 4, None, false, 1);
+		
+		assert_eq!(quantized, rgba);
+	}
+	
+	#[test]
+	fn reduces_to_the_requested_number_of_colors()
+	{
+		let rgba: RgbaImage = test_utils::gradient(64, 4);
+		let quantized: RgbaImage = quantize_image_with_median(&rgba, 8, None, false, 1);
+		
+		assert!(test_utils::distinct_colors(&quantized).len() <= 8);
+		assert_eq!(quantized.dimensions(), rgba.dimensions());
+	}
+	
+	#[test]
+	fn palette_lists_most_used_color_first()
+	{
+		// Three of every four blocks are red.
+		let rgba: RgbaImage = test_utils::blocks(16, 16, 4, &[RED, RED, RED, BLUE]);
+		let (_, palette): (RgbaImage, Vec<[u8; 4]>) = quantize_image_with_median_palette(&rgba, 2, None, false, 1);
+		
+		assert_eq!(palette, vec![RED, BLUE]);
+	}
+	
+	#[test]
+	fn empty_image_is_returned_unchanged()
+	{
+		let rgba: RgbaImage = RgbaImage::new(0, 0);
+		let (quantized, palette): (RgbaImage, Vec<[u8; 4]>) = quantize_image_with_median_palette(&rgba, 16, None, false, 0);
+		
+		assert_eq!(quantized.dimensions(), (0, 0));
+		assert!(palette.is_empty());
+	}
+	
+	#[test]
+	fn linear_average_of_black_and_white_is_lighter_than_the_midpoint()
+	{
+		let rgba: RgbaImage = test_utils::blocks(16, 16, 8, &[[0, 0, 0, 255], WHITE]);
+		
+		let (_, naive): (RgbaImage, Vec<[u8; 4]>) = quantize_image_with_median_palette(&rgba, 1, None, false, 1);
+		let (_, linear): (RgbaImage, Vec<[u8; 4]>) = quantize_image_with_median_palette(&rgba, 1, Some(crate::utils::color_utils::SRGB_GAMMA), false, 1);
+		
+		// Half the light of white is sRGB 188, well above the naive midpoint of 127.
+		assert!(naive[0][0].abs_diff(127) <= 1, "{}", naive[0][0]);
+		assert!(linear[0][0].abs_diff(188) <= 1, "{}", linear[0][0]);
+	}
+	
+	#[test]
+	fn refinement_lowers_the_color_error_of_median_cut()
+	{
+		let rgba: RgbaImage = RgbaImage::from_fn(64, 64, |x: u32, y: u32| image::Rgba([(x * 4) as u8, (y * 4) as u8, ((x + y) * 2) as u8, 255]));
+		
+		// Sum of squared channel differences between the source and a quantized image.
+		let total_error = |quantized: &RgbaImage| -> u64
+		{
+			let mut error: u64 = 0;
+			for (x, y, pixel) in quantized.enumerate_pixels()
+			{
+				let source: &image::Rgba<u8> = rgba.get_pixel(x, y);
+				for channel in 0..3
+				{
+					let delta: i64 = pixel[channel] as i64 - source[channel] as i64;
+					error += (delta * delta) as u64;
+				}
+			}
+			error
+		};
+		
+		let plain: u64 = total_error(&quantize_image_with_median(&rgba, 16, None, false, 1));
+		let refined: u64 = total_error(&quantize_image_with_median(&rgba, 16, None, true, 1));
+		assert!(refined < plain, "refined {} vs plain {}", refined, plain);
+	}
+	
+	#[test]
+	fn full_sampling_keeps_a_rare_accent_color()
+	{
+		// A single red pixel off the every-4th-pixel grid.
+		let mut rgba: RgbaImage = test_utils::blocks(16, 16, 8, &[WHITE, [40, 40, 40, 255]]);
+		rgba.put_pixel(5, 7, image::Rgba(RED));
+		
+		let full: RgbaImage = quantize_image_with_median(&rgba, 8, None, false, 1);
+		assert_eq!(full.get_pixel(5, 7).0, RED);
+		assert_eq!(full, rgba);
+		
+		// Sparse sampling never sees it.
+		let sparse: RgbaImage = quantize_image_with_median(&rgba, 8, None, false, 4);
+		assert_ne!(sparse.get_pixel(5, 7).0, RED);
+	}
+	
+	#[test]
+	fn shared_lookup_maps_a_large_image_like_a_per_pixel_search()
+	{
+		let rgba: RgbaImage = test_utils::noisy_ramps(512, 384);
+		let mut palette: Vec<Color> = Vec::new();
+		for level in 0..64u8
+		{
+			palette.push(Color::new((level % 4) * 85, (level / 4 % 4) * 85, (level / 16) * 85, 255));
+		}
+		let (quantized, _): (RgbaImage, Vec<[u8; 4]>) = map_to_nearest(&rgba, &palette);
+		
+		for (x, y, pixel) in rgba.enumerate_pixels()
+		{
+			let expected: Color = find_closest_palette_color(&Color::new(pixel[0], pixel[1], pixel[2], pixel[3]), &palette);
+			assert_eq!(quantized.get_pixel(x, y).0, [expected.r, expected.g, expected.b, expected.a], "pixel ({}, {})", x, y);
+		}
+	}
+}
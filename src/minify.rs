@@ -1,22 +1,63 @@
-use anyhow::{anyhow, Result};
+use crate::error::{MinifyError, Result};
 use image::{GenericImageView, ImageFormat};
 use oxipng::{optimize_from_memory, Deflater, Options as OxiOptions};
 use crate::utils::time_utils;
 use crate::utils::crc_utils;
+use crate::utils::color_utils::{from_linear, to_linear, SRGB_GAMMA};
+use crate::utils::par_utils;
 
-use std::fs;
 use std::io::Cursor;
-use std::path::Path;
+use std::path::PathBuf;
 
+use crate::blue_noise;
 use crate::dithering;
+use crate::dithering::{ImageClass, QualityPerType, SelectionThresholds};
+use crate::hilbert;
 use crate::median;
+use crate::octree;
+use crate::palette;
 
-/// Marker string for identifying files minified by this tool.
-/// Includes null terminator as required by PNG tEXt chunks.
-const MARKER_STRING: &str = "MiniPNG by P. Andrian.\0";
+/// Default keyword of the tEXt marker chunk identifying files minified by this tool.
+/// The chunk data is the keyword, a null separator and the minification info.
+pub const MARKER_KEYWORD: &str = "MiniPNG by P. Andrian.";
+
+/// First entry of the minification info in markers with a custom keyword (--marker-text),
+/// which identifies them as MiniPNG markers whatever the keyword says.
+const MARKER_SENTINEL: &str = "minipng=1";
 
 /// PNG signature bytes.
-const PNG_SIGNATURE: &[u8; 8] = b"\x89PNG\r\n\x1a\n";
+pub const PNG_SIGNATURE: &[u8; 8] = b"\x89PNG\r\n\x1a\n";
+
+/// Largest ancillary chunk accepted when walking the chunks of a PNG (10 MB).
+/// Critical chunks are exempt, since very large images legitimately store more than that in a single IDAT.
+const MAX_ANCILLARY_CHUNK_LENGTH: usize = 10_000_000;
+
+/// Name of the list_chunks entry for bytes after the IEND chunk.
+pub const TRAILING_DATA: &str = "(trailing data)";
+
+/// Quality value that picks the quality for each image from its analysis (detail and color diversity).
+pub const AUTO_QUALITY: u8 = 0;
+
+/// Images with at most this many distinct colors look already quantized.
+const QUANTIZED_MAX_COLORS: usize = 256;
+
+/// Images with fewer pixels than this are never considered quantized (small icons naturally have few colors).
+const QUANTIZED_MIN_PIXELS: u64 = 4096;
+
+/// Lowest PSNR (dB) of a lossy output that auto_mode keeps; below it, quantization changed the image too much.
+pub const AUTO_MODE_MIN_PSNR: f64 = 25.0;
+
+/// Share of the lossless output's size that the lossy output must save for auto_mode to keep it.
+pub const AUTO_MODE_MIN_SAVINGS: f64 = 0.2;
+
+/// Error for also_webp in builds without the "webp" feature.
+const WEBP_UNAVAILABLE: &str = "WebP output needs MiniPNG built with the webp feature";
+
+/// Default denoising strength (a noise threshold of 15 and a 3x3 median filter).
+pub const DEFAULT_DENOISE_STRENGTH: f32 = 0.5;
+
+/// Default size of the blocks analyzed for denoising, in pixels.
+pub const DEFAULT_DENOISE_BLOCK: u32 = 8;
 
 /// Dithering mode for lossy compression.
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -31,6 +72,9 @@ pub enum DitheringMode
 	/// Ordered (Bayer) dithering - regular pattern, balanced approach.
 	Ordered,
 	
+	/// Blue-noise dithering - like ordered dithering but without the regular cross-hatch pattern.
+	BlueNoise,
+	
 	/// Median cut color quantization - classic algorithm, excellent palette quality.
 	MedianCut,
 	
@@ -38,924 +82,4126 @@ pub enum DitheringMode
 	Auto,
 }
 
-/// Results of processing a PNG file.
-#[derive(Debug)]
-pub struct ProcessingResult
+/// What to do when the target file already exists (only when writing to a separate target).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IfExists
 {
-	/// The original file size in bytes.
-	pub original_size: u64,
+	/// Skip the file without processing it.
+	Skip,
 	
-	/// The new file size in bytes.
-	pub new_size: u64,
+	/// Overwrite the existing target (default).
+	Overwrite,
+	
+	/// Fail the file with an error.
+	Error,
 }
 
-/// Information about previous minification.
-#[derive(Debug, Clone)]
-pub struct MinificationInfo
+/// Parse an --if-exists policy name.
+pub fn parse_if_exists(policy: &str) -> Option<IfExists>
 {
-	pub quality: Option<u8>,
-	pub dithering_mode: Option<DitheringMode>,
-	pub lossless: bool,
-	pub reduction_pct: f64,
-	pub timestamp: Option<String>,
+	match policy.to_lowercase().as_str()
+	{
+		"skip" => Some(IfExists::Skip),
+		"overwrite" => Some(IfExists::Overwrite),
+		"error" => Some(IfExists::Error),
+		_ => None,
+	}
 }
 
-/// Minifies a PNG file using a combination of techniques.
-///
-/// If lossless is true, only lossless minification is applied.
-/// Otherwise, applies lossy minification with the specified quality level and dithering mode.
-/// 
-/// If force is true, skips the marker check and re-minifies even if already minified.
-/// 
-/// Returns (ProcessingResult, Option<MinificationInfo>) - the second value is Some if file was already minified.
-pub fn minify_png(source_path: &Path, target_path: &Path, lossless_only: bool, quality: u8, dithering_mode: DitheringMode, smooth_radius: f32, denoise: bool, force: bool) -> Result<(ProcessingResult, Option<MinificationInfo>)>
+/// Interlacing of the output.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InterlaceMode
 {
-	// Get the original file size.
-	let original_size: u64 = fs::metadata(source_path)
-		.map_err(|e| anyhow!("Failed to get file metadata: {}", e))?
-		.len();
-	
-	// Read the source file into memory.
-	let source_data: Vec<u8> = fs::read(source_path)
-		.map_err(|e| anyhow!("Failed to read source file: {}", e))?;
-	
-	// Check if this file has already been minified by this tool (unless force is true).
-	let (is_minified, prev_info) = if force
-	{
-		(false, None)
-	}
-	else
-	{
-		is_already_minified(&source_data)?
-	};
-	
-	if is_minified
-	{
-		// File already minified - return info about previous minification.
-		return Ok((ProcessingResult
-		{
-			original_size,
-			new_size: original_size,
-		}, prev_info));
-	}
-	
-	// Apply minification based on mode - quality-first, not size-based.
-	let (minified_data, effective_dithering) = if lossless_only
-	{
-		// Apply lossless minification only.
-		(apply_quality_lossless_minification(&source_data)?, dithering_mode)
-	}
-	else
-	{
-		// Apply lossy minification with specified quality level and dithering mode.
-		apply_quality_lossy_minification(&source_data, quality, dithering_mode, smooth_radius, denoise)?
-	};
+	/// Write non-interlaced output, de-interlacing Adam7 sources (default, usually the smallest).
+	None,
 	
-	// Get size from in-memory buffer (no disk I/O needed!).
-	let new_size: u64 = minified_data.len() as u64;
+	/// Write Adam7-interlaced output, which browsers can show progressively while it loads.
+	Adam7,
 	
-	// Only save the result if it's smaller than the original.
-	if new_size < original_size
-	{
-		// Calculate reduction percentage.
-		let reduction_pct = (1.0 - (new_size as f64 / original_size as f64)) * 100.0;
-		
-		// Add marker with minification info before saving.
-		let marked_data = add_minification_marker_with_info(&minified_data, lossless_only, quality, effective_dithering, reduction_pct)?;
-		
-		// Write directly to target (single disk write!).
-		fs::write(target_path, &marked_data)
-			.map_err(|e| anyhow!("Failed to write to target file: {}", e))?;
-		
-		Ok((ProcessingResult
-		{
-			original_size,
-			new_size,
-		}, None))
-	}
-	else
-	{
-		// Minification didn't reduce size - keep original.
-		if source_path != target_path
-		{
-			fs::copy(source_path, target_path)
-				.map_err(|e| anyhow!("Failed to copy source to target: {}", e))?;
-		}
-		
-		Ok((ProcessingResult
-		{
-			original_size,
-			new_size: original_size,
-		}, None))
-	}
+	/// Keep the interlacing of the source.
+	Keep,
 }
 
-/// Checks if a PNG file has already been minified by this tool.
-/// Returns (is_minified, minification_info).
-fn is_already_minified(png_data: &[u8]) -> Result<(bool, Option<MinificationInfo>)>
+/// Order in which error diffusion (Floyd-Steinberg) visits the pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScanOrder
 {
-	// Check for PNG signature.
-	if png_data.len() < 8 || &png_data[0..8] != PNG_SIGNATURE
-	{
-		return Ok((false, None));
-	}
-	
-	// Look for our custom tEXt chunk marker.
-	let mut pos = 8;
-	while pos + 12 <= png_data.len()
-	{
-		// Read chunk length (4 bytes, big-endian).
-		let length: usize = u32::from_be_bytes([png_data[pos], png_data[pos + 1], png_data[pos + 2], png_data[pos + 3]]) as usize;
-		
-		// Read chunk type (4 bytes).
-		let chunk_type = &png_data[pos + 4..pos + 8];
-		
-		// Check if this is our marker chunk.
-		if chunk_type == b"tEXt"
-		{
-			// Check if the chunk data contains our marker.
-			let chunk_data_end = pos + 8 + length;
-			if chunk_data_end <= png_data.len()
-			{
-				let chunk_data = &png_data[pos + 8..chunk_data_end];
-				
-				// Look for our marker: "MiniPNG by P. Andrian.\0"
-				if chunk_data.starts_with(MARKER_STRING.as_bytes())
-				{
-					// Parse the minification info from the marker.
-					let info: Option<MinificationInfo> = parse_minification_info(chunk_data);
-					return Ok((true, info));
-				}
-			}
-		}
-		
-		// Move to next chunk (length + type + data + CRC).
-		pos += 12 + length;
-		
-		// Safety check to prevent infinite loops.
-		if length > 10_000_000
-		{
-			break;
-		}
-	}
+	/// Rows alternating left-to-right and right-to-left (default).
+	Serpentine,
 	
-	Ok((false, None))
+	/// Along a Hilbert curve, spreading the error to the curve's recent neighbors without a preferred direction.
+	Hilbert,
 }
 
-/// Parse minification info from marker text.
-fn parse_minification_info(marker_data: &[u8]) -> Option<MinificationInfo>
+/// Palette quantizer of median dithering and indexed output.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Quantizer
 {
-	// Convert to string, format: "MiniPNG by P. Andrian.\0quality=40,dithering=floyd,reduction=73.0,timestamp=2026-02-06T20:15:30Z"
-	let marker_str = std::str::from_utf8(marker_data).ok()?;
-	
-	// Skip the "MiniPNG by P. Andrian.\0" part.
-	let data_part = marker_str.strip_prefix(MARKER_STRING)?;
-	
-	let mut quality = None;
-	let mut dithering_mode = None;
-	let mut lossless = false;
-	let mut reduction_pct = 0.0;
-	let mut timestamp = None;
+	/// Median cut: repeatedly split the box of colors with the widest range (default).
+	Median,
 	
-	// Parse key=value pairs.
-	for pair in data_part.split(',')
+	/// Octree: merge the least used branches of a tree of the colors, faster on images with huge color counts.
+	Octree,
+}
+
+/// Parse a --quantizer name.
+pub fn parse_quantizer(quantizer: &str) -> Option<Quantizer>
+{
+	match quantizer.to_lowercase().as_str()
 	{
-		let parts: Vec<&str> = pair.split('=').collect();
-		if parts.len() == 2
-		{
-			match parts[0]
-			{
-				"quality" => quality = parts[1].parse::<u8>().ok(),
-				"dithering" => dithering_mode = parse_dithering_mode(parts[1]),
-				"lossless" => lossless = parts[1] == "true",
-				"reduction" => reduction_pct = parts[1].parse::<f64>().unwrap_or(0.0),
-				"timestamp" => timestamp = Some(parts[1].to_string()),
-				_ => {}
-			}
-		}
+		"median" => Some(Quantizer::Median),
+		"octree" => Some(Quantizer::Octree),
+		_ => None,
 	}
-	
-	Some(MinificationInfo
-	{
-		quality,
-		dithering_mode,
-		lossless,
-		reduction_pct,
-		timestamp,
-	})
 }
 
-// Helper function to parse dithering mode from string.
-fn parse_dithering_mode(mode_str: &str) -> Option<DitheringMode>
+/// Parse a --scan order name.
+pub fn parse_scan_order(order: &str) -> Option<ScanOrder>
 {
-	match mode_str
+	match order.to_lowercase().as_str()
 	{
-		"none" => Some(DitheringMode::None),
-		"ordered" => Some(DitheringMode::Ordered),
-		"floyd" => Some(DitheringMode::FloydSteinberg),
-		"median" => Some(DitheringMode::MedianCut),
-		"auto" => Some(DitheringMode::Auto),
+		"serpentine" => Some(ScanOrder::Serpentine),
+		"hilbert" => Some(ScanOrder::Hilbert),
 		_ => None,
 	}
 }
 
-/// Convert DitheringMode to its string representation.
-pub fn dithering_mode_to_string(mode: DitheringMode) -> &'static str
+/// Parse an --interlace mode name.
+pub fn parse_interlace_mode(mode: &str) -> Option<InterlaceMode>
 {
-	match mode
+	match mode.to_lowercase().as_str()
 	{
-		DitheringMode::None => "none",
-		DitheringMode::Ordered => "ordered",
-		DitheringMode::FloydSteinberg => "floyd",
-		DitheringMode::MedianCut => "median",
-		DitheringMode::Auto => "auto",
+		"none" => Some(InterlaceMode::None),
+		"adam7" => Some(InterlaceMode::Adam7),
+		"keep" => Some(InterlaceMode::Keep),
+		_ => None,
 	}
 }
 
-/// Applies lossless minification with aggressive settings for maximum minification
-/// while maintaining perfect image quality.
-fn apply_quality_lossless_minification(png_data: &[u8]) -> Result<Vec<u8>>
+/// Why a file was left untouched.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SkipReason
 {
-	// Use maximum lossless minification settings.
-	let mut options = OxiOptions::default();
-	options.strip = oxipng::StripChunks::Safe;
-	options.optimize_alpha = true;
-	options.interlace = None;
-	options.bit_depth_reduction = true;
-	options.color_type_reduction = true;
-	options.palette_reduction = true;
+	/// The file carries our marker from an earlier run.
+	AlreadyMinified,
 	
-	// Use Zopfli for maximum minification (slower but best results).
-	options.deflater = Deflater::Zopfli(Default::default());
+	/// The target file already exists and --if-exists skip is set.
+	TargetExists,
 	
-	// Apply oxipng optimization.
-	let optimized: Vec<u8> = optimize_from_memory(png_data, &options)
-		.map_err(|e| anyhow!("Failed to optimize PNG: {}", e))?;
+	/// The file does not start with the PNG signature.
+	NotPng,
 	
-	Ok(optimized)
+	/// The target file is read-only and --force-writable is not set.
+	ReadOnly,
+	
+	/// The image has more pixels than max_pixels allows.
+	TooLarge,
+	
+	/// The IHDR chunk holds a color type and bit depth combination the PNG specification doesn't define.
+	UnsupportedFormat,
 }
 
-/// Applies lossy minification with the specified quality level and dithering mode.
-/// Quality 40 (default) provides good visual quality with aggressive minification (~70-77% reduction).
-/// Quality 50-60 provides very good quality with strong minification (~57-73% reduction).
-/// Quality 70-80 provides excellent quality with moderate minification (~30-60% reduction).
-/// Returns (minified_data, effective_dithering_mode)
-fn apply_quality_lossy_minification(png_data: &[u8], quality: u8, dithering_mode: DitheringMode, smooth_radius: f32, denoise: bool) -> Result<(Vec<u8>, DitheringMode)>
+/// Content hashes of a processed file, mapping its source to its output for a build manifest (MinifyOptions::hashes).
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContentHashes
 {
-	// Validate it's a valid PNG and load it.
-	let img: image::DynamicImage = image::load_from_memory(png_data)
-		.map_err(|e| anyhow!("Failed to decode PNG: {}", e))?;
-	
-	// Determine effective dithering mode (resolve Auto).
-	let effective_dithering = match dithering_mode
-	{
-		DitheringMode::Auto => dithering::recommend_dithering_mode(&img),
-		_ => dithering_mode,
-	};
-	
-	// Apply color quantization with specified quality and dithering mode.
-	let quantized: Vec<u8> = apply_quantization(&img, quality, effective_dithering, smooth_radius, denoise)?;
+	/// SHA-256 of the source file as it was read.
+	pub source: [u8; 32],
 	
-	// Apply aggressive lossless minification to the quantized data.
-	let mut options = OxiOptions::default();
-	options.strip = oxipng::StripChunks::Safe;
-	options.optimize_alpha = true;
-	options.interlace = None;
-	options.deflater = Deflater::Zopfli(Default::default());
-	options.bit_depth_reduction = true;
-	options.color_type_reduction = true;
-	options.palette_reduction = true;
-	
-	let minified: Vec<u8> = optimize_from_memory(&quantized, &options)
-		.map_err(|e| anyhow!("Failed to optimize quantized PNG: {}", e))?;
+	/// Where the output is (the source path itself for in-place minification).
+	pub target: PathBuf,
 	
-	Ok((minified, effective_dithering))
+	/// SHA-256 of the bytes at the target afterward; the source hash if the file was left as it was.
+	pub output: [u8; 32],
 }
 
-/// Adds a tEXt chunk marker with minification info.
-fn add_minification_marker_with_info(png_data: &[u8], lossless: bool, quality: u8, dithering_mode: DitheringMode, reduction_pct: f64) -> Result<Vec<u8>>
+/// Results of processing a PNG file.
+#[derive(Debug)]
+pub struct ProcessingResult
 {
-	// Verify PNG signature.
-	if png_data.len() < 8 || &png_data[0..8] != PNG_SIGNATURE
-	{
-		return Err(anyhow!("Invalid PNG signature"));
-	}
+	/// The source file path.
+	pub path: PathBuf,
 	
-	// Find the position to insert our chunk (before IEND).
-	let mut iend_pos = None;
-	let mut pos = 8;
+	/// The original file size in bytes.
+	pub original_size: u64,
 	
-	while pos + 12 <= png_data.len()
-	{
-		let length: usize = u32::from_be_bytes([png_data[pos], png_data[pos + 1], png_data[pos + 2], png_data[pos + 3]]) as usize;
-		
-		let chunk_type = &png_data[pos + 4..pos + 8];
-		
-		if chunk_type == b"IEND"
-		{
-			iend_pos = Some(pos);
-			break;
-		}
-		
-		pos += 12 + length;
-		
-		// Safety check.
-		if length > 10_000_000
-		{
-			return Err(anyhow!("Invalid PNG chunk length"));
-		}
-	}
+	/// The new file size in bytes.
+	pub new_size: u64,
 	
-	let iend_pos = iend_pos.ok_or_else(|| anyhow!("IEND chunk not found"))?;
+	/// Set if the file was left untouched without processing, with the reason why.
+	pub skip_reason: Option<SkipReason>,
 	
-	// Create our marker chunk with minification info.
-	let timestamp = time_utils::get_iso8601_timestamp();
+	/// Whether the output was produced losslessly (for skipped files, by the earlier run).
+	pub lossless: bool,
 	
-	// Get dithering mode name.
-	let dithering_name = dithering_mode_to_string(dithering_mode);
+	/// Quality level used for lossy output.
+	pub quality: Option<u8>,
 	
-	let info_str = if lossless
-	{
-		format!("lossless=true,reduction={:.1},timestamp={}", reduction_pct, timestamp)
-	}
-	else
-	{
-		format!("quality={},dithering={},lossless=false,reduction={:.1},timestamp={}", quality, dithering_name, reduction_pct, timestamp)
-	};
+	/// Dithering mode actually used for lossy output (Auto is resolved to the chosen algorithm).
+	pub dithering_mode: Option<DitheringMode>,
 	
-	let marker_text: String = format!("{}{}", MARKER_STRING, info_str);
-	let marker_bytes = marker_text.as_bytes();
-	let marker_length: u32 = marker_bytes.len() as u32;
+	/// The output was written even though it isn't smaller (with always_write).
+	pub re_encoded: bool,
 	
-	// Calculate CRC for the chunk.
-	let mut crc_data = Vec::new();
-	crc_data.extend_from_slice(b"tEXt");
-	crc_data.extend_from_slice(marker_bytes);
-	let crc = crc_utils::hash(&crc_data);
+	/// The quality level was picked from the image analysis (AUTO_QUALITY).
+	pub auto_quality: bool,
 	
-	// Build new PNG with marker chunk inserted before IEND.
-	let mut result: Vec<u8> = Vec::with_capacity(png_data.len() + 12 + marker_bytes.len());
-	result.extend_from_slice(&png_data[..iend_pos]);
-	result.extend_from_slice(&marker_length.to_be_bytes());
-	result.extend_from_slice(b"tEXt");
-	result.extend_from_slice(marker_bytes);
-	result.extend_from_slice(&crc.to_be_bytes());
-	result.extend_from_slice(&png_data[iend_pos..]);
+	/// The source looked already quantized by another tool (lossy mode only).
+	/// Without safe, only indexed-color sources are detected, since counting the colors decodes the image again.
+	pub already_quantized: bool,
 	
-	Ok(result)
+	/// Class the image was sorted into for quality_per_type (lossy mode with a mapping only).
+	pub image_class: Option<ImageClass>,
+	
+	/// Lossy minification didn't reduce the size, and the unchanged image got a marker recording the attempt (mark_noop).
+	pub noop_marked: bool,
+	
+	/// Lossy minification didn't reduce the size, and the smaller lossless output was written instead (fallback_lossless).
+	pub lossless_fallback: bool,
+	
+	/// The mode was picked by comparing the lossy and lossless outputs (auto_mode); lossless tells which one won.
+	pub auto_mode: bool,
+	
+	/// Size of the WebP written next to the output (also_webp).
+	pub webp_size: Option<u64>,
+	
+	/// Bytes after the IEND chunk that were removed from the source (strip_trailing).
+	pub trailing_stripped: u64,
+	
+	/// Hashes of the source and the output (hashes option), for files that were read and have an output at the target.
+	pub hashes: Option<ContentHashes>,
 }
 
-/// Apply color quantization with selectable dithering mode.
-/// For lossy minification, this reduces the color palette and applies the specified dithering algorithm.
-fn apply_quantization(img: &image::DynamicImage, quality: u8, dithering_mode: DitheringMode, smooth_radius: f32, denoise: bool) -> Result<Vec<u8>>
+impl ProcessingResult
 {
-	// Extract dimensions and pixel data.
-	let (width, height) = img.dimensions();
-	let mut rgba = img.to_rgba8();
-	
-	// Apply darkening BEFORE quantization.
-	apply_darkening(&mut rgba);
-	
-	// Apply Gaussian blur if smooth_radius > 0.
-	if smooth_radius > 0.0
+	/// Result for a file left untouched without processing, for the given reason.
+	/// Everything else is left unset; callers add what they know, such as the mode of an earlier run or the hashes.
+	pub fn skipped(path: &std::path::Path, size: u64, reason: SkipReason) -> Self
 	{
-		// Apply Gaussian blur to smooth gradients before quantization.
-		// This reduces banding and makes "dithering none" mode work better.
-		rgba = image::imageops::blur(&image::DynamicImage::ImageRgba8(rgba), smooth_radius);
+		Self
+		{
+			path: path.to_path_buf(),
+			original_size: size,
+			new_size: size,
+			skip_reason: Some(reason),
+			lossless: false,
+			quality: None,
+			dithering_mode: None,
+			re_encoded: false,
+			auto_quality: false,
+			already_quantized: false,
+			image_class: None,
+			noop_marked: false,
+			lossless_fallback: false,
+			auto_mode: false,
+			webp_size: None,
+			trailing_stripped: 0,
+			hashes: None,
+		}
 	}
-	
-	// Determine downsampling factor based on quality.
-	// Higher quality = less downsampling.
-	let downsampling_factor = match quality
-	{
-		0..=40 => 32,
-		41..=55 => 16,
+}
+
+/// Information about previous minification.
+#[derive(Debug, Clone)]
+pub struct MinificationInfo
+{
+	pub quality: Option<u8>,
+	pub dithering_mode: Option<DitheringMode>,
+	pub lossless: bool,
+	pub reduction_pct: f64,
+	pub timestamp: Option<String>,
+	pub noop: bool,
+	pub marker_text: String,
+}
+
+/// Settings for the minification pipeline.
+/// Build with MinifyOptions::builder(), which validates the settings.
+#[derive(Debug, Clone)]
+pub struct MinifyOptions
+{
+	/// Use lossless compression only.
+	pub lossless: bool,
+	
+	/// Quality level for lossy compression (1-100).
+	pub quality: u8,
+	
+	/// Dithering mode for lossy compression (Auto is resolved per image).
+	pub dithering_mode: DitheringMode,
+	
+	/// Thresholds used when resolving Auto dithering.
+	pub thresholds: SelectionThresholds,
+	
+	/// Quality levels per image class (photo-like or flat, see dithering::classify_image) that replace quality
+	/// for images of that class. Other images keep quality.
+	pub quality_per_type: Option<QualityPerType>,
+	
+	/// Pre-quantization smoothing radius (0.0-5.0, 0 = off).
+	pub smooth_radius: f32,
+	
+	/// Apply post-processing denoising.
+	pub denoise: bool,
+	
+	/// Denoising strength (0.0-1.0): higher values filter noisier blocks and, above 0.75, use a 5x5 median filter.
+	pub denoise_strength: f32,
+	
+	/// Size of the blocks analyzed for denoising (3-64 pixels).
+	pub denoise_block: u32,
+	
+	/// Force grayscale output in lossy mode.
+	pub grayscale: bool,
+	
+	/// Collapse alpha to fully transparent or fully opaque at this threshold.
+	pub alpha_threshold: Option<u8>,
+	
+	/// Dither the alpha channel when applying alpha_threshold.
+	pub dither_alpha: bool,
+	
+	/// Quantize the alpha channel alongside RGB.
+	pub quantize_alpha: bool,
+	
+	/// Quantize and average in linear light.
+	pub linear: bool,
+	
+	/// Encoding gamma assumed for the conversions to and from linear light (SRGB_GAMMA uses the sRGB curve).
+	pub gamma: f32,
+	
+	/// Premultiply RGB by alpha before quantization and reverse it afterward.
+	/// Hidden colors of transparent pixels then can't bleed into visible edges through blur or diffused error.
+	pub premultiply: bool,
+	
+	/// Composite the image onto this opaque background color (RGB) before quantization.
+	/// The output has no transparency left, so oxipng can store it without an alpha channel.
+	pub flatten: Option<[u8; 3]>,
+	
+	/// Decode the output again and check it against the source.
+	pub verify: bool,
+	
+	/// Decode the output again and count the pixels that differ from the source (lossless mode only).
+	/// Any differing pixel makes the run fail with a verification error, so the original is kept.
+	pub diff_pixels: bool,
+	
+	/// Write an indexed-color (palette) PNG when the image fits in a palette.
+	pub indexed: bool,
+	
+	/// Keep the bit depth and color type of the image oxipng is given: no bit depth, color type or palette reduction.
+	/// Lossless output keeps the source's format; lossy output stays 8 bits per channel (cannot be combined with indexed).
+	pub keep_bit_depth: bool,
+	
+	/// Strip all ancillary chunks instead of only the safe ones.
+	pub strip_all: bool,
+	
+	/// Minimum size reduction (percent) required to use the output.
+	pub min_reduction: f64,
+	
+	/// What to do when a separate target file already exists.
+	pub if_exists: IfExists,
+	
+	/// Write read-only targets by clearing the read-only flag for the write (it is restored afterward).
+	pub force_writable: bool,
+	
+	/// When re-minifying a marked file, use the settings recorded in its marker.
+	pub reuse_settings: bool,
+	
+	/// Re-minify marked files whose marker timestamp is older than this time (seconds since the Unix epoch).
+	/// Marked files that are newer, or whose marker has no timestamp, are still skipped.
+	pub reminify_before: Option<u64>,
+	
+	/// Carry the pHYs chunk (physical pixel dimensions, DPI) over to the output.
+	pub keep_phys: bool,
+	
+	/// Carry textual metadata (tEXt, zTXt and iTXt chunks) over to the output.
+	pub keep_text: bool,
+	
+	/// Refine the median-cut palette with k-means iterations.
+	pub refine: bool,
+	
+	/// Pixel sampling step for building the median-cut palette (0 = pick from the image size).
+	pub sample_step: usize,
+	
+	/// Median cut palette size (2-256), overriding the size derived from the quality level.
+	pub median_colors: Option<usize>,
+	
+	/// Palette quantizer of median dithering and indexed output (the refine, sample_step and median_colors options apply to both).
+	pub quantizer: Quantizer,
+	
+	/// Downsampling factors for R, G and B (1-128), overriding the factor derived from the quality level.
+	pub channel_factors: Option<[u8; 3]>,
+	
+	/// Number of threads oxipng may use for each file (0 = share the caller's thread pool).
+	/// Callers processing several files at once can bound the total with jobs × threads.
+	pub optimizer_threads: usize,
+	
+	/// Compress with libdeflate instead of Zopfli: much faster, with slightly larger output (used for savings estimates).
+	pub fast_deflate: bool,
+	
+	/// Fixed RGB palette (up to 256 colors) to map every pixel onto instead of quantizing by the quality level.
+	/// The mapping is dithered with Floyd-Steinberg when that is the dithering mode, and each pixel keeps its alpha.
+	pub fixed_palette: Option<Vec<[u8; 3]>>,
+	
+	/// Return the re-encoded output even when it isn't smaller, e.g. to normalize files (lossless mode only).
+	pub always_write: bool,
+	
+	/// Don't create a separate target (e.g. below an output directory) for a file that couldn't be reduced,
+	/// instead of copying the source there unchanged.
+	pub skip_unchanged_output: bool,
+	
+	/// Also write a lossless WebP of the output pixels next to each PNG output (the target with a .webp extension).
+	pub also_webp: bool,
+	
+	/// Minify files that look already quantized by another tool (see is_already_quantized) losslessly,
+	/// instead of quantizing them again and compounding the quality loss.
+	pub safe: bool,
+	
+	/// Largest image (width × height, read from IHDR) to process; larger ones are left alone instead of being decoded,
+	/// since quantization and dithering hold several full-size buffers in memory.
+	pub max_pixels: Option<u64>,
+	
+	/// Interlacing of the output.
+	pub interlace: InterlaceMode,
+	
+	/// Pixel order of Floyd-Steinberg error diffusion on the quantization grid.
+	/// Fixed palette mapping and alpha dithering always scan serpentine rows.
+	pub scan: ScanOrder,
+	
+	/// When lossy minification doesn't reduce the size, add a marker to the unchanged image recording the attempt,
+	/// so later runs skip the file like an already-minified one instead of quantizing it again (lossy mode only).
+	pub mark_noop: bool,
+	
+	/// When lossy minification doesn't reduce the size, try lossless minification and use it if that is smaller (lossy mode only).
+	pub fallback_lossless: bool,
+	
+	/// Minify each file both lossy and losslessly, and keep the lossy output only when it is clearly smaller
+	/// and close enough to the source (see prefer_lossy), the lossless one otherwise (lossy mode only).
+	pub auto_mode: bool,
+	
+	/// Remove bytes after the IEND chunk (junk appended by faulty tools, or hidden data).
+	/// The output is written even if minification didn't reduce the image itself.
+	pub strip_trailing: bool,
+	
+	/// Hash the source and output of every file processed through the file API (see ProcessingResult::hashes).
+	pub hashes: bool,
+	
+	/// Make identical inputs produce byte-identical outputs: oxipng tries one fixed filter on a single thread
+	/// without fast evaluation, and the marker leaves out its timestamp.
+	pub deterministic: bool,
+	
+	/// Directory for the temporary file each output is written to before it replaces the target,
+	/// ideally on the same volume as the targets so the replacement is an atomic rename.
+	/// When None, outputs are written to the target directly.
+	pub temp_dir: Option<PathBuf>,
+	
+	/// Keyword of the marker chunk (software attribution) instead of the default "MiniPNG by P. Andrian.".
+	/// Must be 1-79 printable Latin-1 characters, as the PNG specification requires for tEXt keywords.
+	pub marker_text: Option<String>,
+}
+
+impl MinifyOptions
+{
+	/// Start building options from the defaults.
+	pub fn builder() -> MinifyOptionsBuilder
+	{
+		MinifyOptionsBuilder::new()
+	}
+	
+	/// Encoding gamma to convert to linear light with, or None to work on the encoded values.
+	pub(crate) fn linear_gamma(&self) -> Option<f32>
+	{
+		self.linear.then_some(self.gamma)
+	}
+}
+
+/// Builder for MinifyOptions.
+#[derive(Debug, Clone)]
+pub struct MinifyOptionsBuilder
+{
+	options: MinifyOptions,
+	
+	/// Whether a quality level was given explicitly (conflicts with lossless).
+	quality_set: bool,
+}
+
+impl MinifyOptionsBuilder
+{
+	/// Create a builder with the default settings (lossy, quality 40, Floyd-Steinberg).
+	#[allow(clippy::new_without_default)]
+	pub fn new() -> Self
+	{
+		MinifyOptionsBuilder
+		{
+			options: MinifyOptions
+			{
+				lossless: false,
+				quality: 40,
+				dithering_mode: DitheringMode::FloydSteinberg,
+				thresholds: SelectionThresholds::new(),
+				quality_per_type: None,
+				smooth_radius: 0.0,
+				denoise: false,
+				denoise_strength: DEFAULT_DENOISE_STRENGTH,
+				denoise_block: DEFAULT_DENOISE_BLOCK,
+				grayscale: false,
+				alpha_threshold: None,
+				dither_alpha: false,
+				quantize_alpha: false,
+				linear: false,
+				gamma: SRGB_GAMMA,
+				premultiply: false,
+				flatten: None,
+				verify: false,
+				diff_pixels: false,
+				indexed: false,
+				keep_bit_depth: false,
+				strip_all: false,
+				min_reduction: 0.0,
+				if_exists: IfExists::Overwrite,
+				force_writable: false,
+				reuse_settings: false,
+				reminify_before: None,
+				keep_phys: false,
+				keep_text: false,
+				refine: false,
+				sample_step: 0,
+				median_colors: None,
+				quantizer: Quantizer::Median,
+				channel_factors: None,
+				optimizer_threads: 0,
+				fast_deflate: false,
+				fixed_palette: None,
+				always_write: false,
+				skip_unchanged_output: false,
+				also_webp: false,
+				safe: false,
+				max_pixels: None,
+				interlace: InterlaceMode::None,
+				scan: ScanOrder::Serpentine,
+				mark_noop: false,
+				fallback_lossless: false,
+				auto_mode: false,
+				strip_trailing: false,
+				deterministic: false,
+				hashes: false,
+				temp_dir: None,
+				marker_text: None,
+			},
+			quality_set: false,
+		}
+	}
+	
+	/// Use lossless compression only.
+	pub fn lossless(mut self, lossless: bool) -> Self
+	{
+		self.options.lossless = lossless;
+		self
+	}
+	
+	/// Set the quality level for lossy compression (1-100).
+	pub fn quality(mut self, quality: u8) -> Self
+	{
+		self.options.quality = quality;
+		self.quality_set = true;
+		self
+	}
+	
+	/// Set the dithering mode.
+	pub fn dithering(mut self, dithering_mode: DitheringMode) -> Self
+	{
+		self.options.dithering_mode = dithering_mode;
+		self
+	}
+	
+	/// Set the thresholds used when resolving Auto dithering.
+	pub fn thresholds(mut self, thresholds: SelectionThresholds) -> Self
+	{
+		self.options.thresholds = thresholds;
+		self
+	}
+	
+	/// Set the quality levels used for photo-like and flat images instead of the quality level.
+	pub fn quality_per_type(mut self, quality_per_type: Option<QualityPerType>) -> Self
+	{
+		self.options.quality_per_type = quality_per_type;
+		self
+	}
+	
+	/// Set the pre-quantization smoothing radius (0.0-5.0).
+	pub fn smooth_radius(mut self, smooth_radius: f32) -> Self
+	{
+		self.options.smooth_radius = smooth_radius;
+		self
+	}
+	
+	/// Apply post-processing denoising.
+	pub fn denoise(mut self, denoise: bool) -> Self
+	{
+		self.options.denoise = denoise;
+		self
+	}
+	
+	/// Set the denoising strength (0.0-1.0).
+	pub fn denoise_strength(mut self, denoise_strength: f32) -> Self
+	{
+		self.options.denoise_strength = denoise_strength;
+		self
+	}
+	
+	/// Set the size of the blocks analyzed for denoising (3-64 pixels).
+	pub fn denoise_block(mut self, denoise_block: u32) -> Self
+	{
+		self.options.denoise_block = denoise_block;
+		self
+	}
+	
+	/// Force grayscale output.
+	pub fn grayscale(mut self, grayscale: bool) -> Self
+	{
+		self.options.grayscale = grayscale;
+		self
+	}
+	
+	/// Collapse alpha to on/off at the given threshold.
+	pub fn alpha_threshold(mut self, alpha_threshold: Option<u8>) -> Self
+	{
+		self.options.alpha_threshold = alpha_threshold;
+		self
+	}
+	
+	/// Dither alpha when applying the alpha threshold.
+	pub fn dither_alpha(mut self, dither_alpha: bool) -> Self
+	{
+		self.options.dither_alpha = dither_alpha;
+		self
+	}
+	
+	/// Quantize alpha alongside RGB.
+	pub fn quantize_alpha(mut self, quantize_alpha: bool) -> Self
+	{
+		self.options.quantize_alpha = quantize_alpha;
+		self
+	}
+	
+	/// Quantize and average in linear light.
+	pub fn linear(mut self, linear: bool) -> Self
+	{
+		self.options.linear = linear;
+		self
+	}
+	
+	/// Encoding gamma assumed in linear light mode (default SRGB_GAMMA, the sRGB curve).
+	pub fn gamma(mut self, gamma: f32) -> Self
+	{
+		self.options.gamma = gamma;
+		self
+	}
+	
+	/// Quantize in premultiplied alpha.
+	pub fn premultiply(mut self, premultiply: bool) -> Self
+	{
+		self.options.premultiply = premultiply;
+		self
+	}
+	
+	/// Composite the image onto an opaque background color before quantization.
+	pub fn flatten(mut self, flatten: Option<[u8; 3]>) -> Self
+	{
+		self.options.flatten = flatten;
+		self
+	}
+	
+	/// Check the output against the source before returning it.
+	pub fn verify(mut self, verify: bool) -> Self
+	{
+		self.options.verify = verify;
+		self
+	}
+	
+	/// Reject lossless output whose decoded pixels differ from the source in any way.
+	pub fn diff_pixels(mut self, diff_pixels: bool) -> Self
+	{
+		self.options.diff_pixels = diff_pixels;
+		self
+	}
+	
+	/// Write an indexed-color PNG when possible.
+	pub fn indexed(mut self, indexed: bool) -> Self
+	{
+		self.options.indexed = indexed;
+		self
+	}
+	
+	/// Keep the bit depth and color type instead of letting oxipng reduce them.
+	pub fn keep_bit_depth(mut self, keep_bit_depth: bool) -> Self
+	{
+		self.options.keep_bit_depth = keep_bit_depth;
+		self
+	}
+	
+	/// Strip all ancillary chunks.
+	pub fn strip_all(mut self, strip_all: bool) -> Self
+	{
+		self.options.strip_all = strip_all;
+		self
+	}
+	
+	/// Set the minimum size reduction (percent) required to use the output.
+	pub fn min_reduction(mut self, min_reduction: f64) -> Self
+	{
+		self.options.min_reduction = min_reduction;
+		self
+	}
+	
+	/// Set what to do when a separate target file already exists.
+	pub fn if_exists(mut self, if_exists: IfExists) -> Self
+	{
+		self.options.if_exists = if_exists;
+		self
+	}
+	
+	/// Write read-only targets instead of skipping them.
+	pub fn force_writable(mut self, force_writable: bool) -> Self
+	{
+		self.options.force_writable = force_writable;
+		self
+	}
+	
+	/// Re-minify marked files with the settings recorded in their marker.
+	pub fn reuse_settings(mut self, reuse_settings: bool) -> Self
+	{
+		self.options.reuse_settings = reuse_settings;
+		self
+	}
+	
+	/// Re-minify marked files minified before a time (seconds since the Unix epoch).
+	pub fn reminify_before(mut self, reminify_before: Option<u64>) -> Self
+	{
+		self.options.reminify_before = reminify_before;
+		self
+	}
+	
+	/// Carry the pHYs chunk (DPI) over to the output.
+	pub fn keep_phys(mut self, keep_phys: bool) -> Self
+	{
+		self.options.keep_phys = keep_phys;
+		self
+	}
+	
+	/// Carry textual metadata (tEXt, zTXt and iTXt) over to the output.
+	pub fn keep_text(mut self, keep_text: bool) -> Self
+	{
+		self.options.keep_text = keep_text;
+		self
+	}
+	
+	/// Refine the median-cut palette with k-means iterations.
+	pub fn refine(mut self, refine: bool) -> Self
+	{
+		self.options.refine = refine;
+		self
+	}
+	
+	/// Set the median-cut sampling step (0 = pick from the image size).
+	pub fn sample_step(mut self, sample_step: usize) -> Self
+	{
+		self.options.sample_step = sample_step;
+		self
+	}
+	
+	/// Set the median cut palette size instead of deriving it from the quality level.
+	pub fn median_colors(mut self, median_colors: Option<usize>) -> Self
+	{
+		self.options.median_colors = median_colors;
+		self
+	}
+	
+	/// Set the palette quantizer of median dithering and indexed output.
+	pub fn quantizer(mut self, quantizer: Quantizer) -> Self
+	{
+		self.options.quantizer = quantizer;
+		self
+	}
+	
+	/// Set per-channel downsampling factors (R, G, B) instead of deriving one from the quality level.
+	pub fn channel_factors(mut self, channel_factors: Option<[u8; 3]>) -> Self
+	{
+		self.options.channel_factors = channel_factors;
+		self
+	}
+	
+	/// Set the number of threads oxipng may use for each file (0 = share the caller's thread pool).
+	pub fn optimizer_threads(mut self, optimizer_threads: usize) -> Self
+	{
+		self.options.optimizer_threads = optimizer_threads;
+		self
+	}
+	
+	/// Compress with libdeflate instead of Zopfli (much faster, slightly larger output).
+	pub fn fast_deflate(mut self, fast_deflate: bool) -> Self
+	{
+		self.options.fast_deflate = fast_deflate;
+		self
+	}
+	
+	/// Map every pixel onto a fixed palette instead of quantizing by the quality level.
+	pub fn fixed_palette(mut self, fixed_palette: Option<Vec<[u8; 3]>>) -> Self
+	{
+		self.options.fixed_palette = fixed_palette;
+		self
+	}
+	
+	/// Return the re-encoded output even when it isn't smaller (lossless mode only).
+	pub fn always_write(mut self, always_write: bool) -> Self
+	{
+		self.options.always_write = always_write;
+		self
+	}
+	
+	/// Don't create a separate target for a file that couldn't be reduced.
+	pub fn skip_unchanged_output(mut self, skip_unchanged_output: bool) -> Self
+	{
+		self.options.skip_unchanged_output = skip_unchanged_output;
+		self
+	}
+	
+	/// Also write a WebP of the output next to each PNG output.
+	pub fn also_webp(mut self, also_webp: bool) -> Self
+	{
+		self.options.also_webp = also_webp;
+		self
+	}
+	
+	/// Minify files that look already quantized by another tool losslessly.
+	pub fn safe(mut self, safe: bool) -> Self
+	{
+		self.options.safe = safe;
+		self
+	}
+	
+	/// Leave images with more pixels than this alone.
+	pub fn max_pixels(mut self, max_pixels: Option<u64>) -> Self
+	{
+		self.options.max_pixels = max_pixels;
+		self
+	}
+	
+	/// Set the interlacing of the output.
+	pub fn interlace(mut self, interlace: InterlaceMode) -> Self
+	{
+		self.options.interlace = interlace;
+		self
+	}
+	
+	/// Set the pixel order of Floyd-Steinberg error diffusion.
+	pub fn scan(mut self, scan: ScanOrder) -> Self
+	{
+		self.options.scan = scan;
+		self
+	}
+	
+	/// Mark images that lossy minification couldn't reduce, so later runs skip them.
+	pub fn mark_noop(mut self, mark_noop: bool) -> Self
+	{
+		self.options.mark_noop = mark_noop;
+		self
+	}
+	
+	/// Try lossless minification for images that lossy minification couldn't reduce.
+	pub fn fallback_lossless(mut self, fallback_lossless: bool) -> Self
+	{
+		self.options.fallback_lossless = fallback_lossless;
+		self
+	}
+	
+	/// Pick lossy or lossless minification for each file by comparing both outputs.
+	pub fn auto_mode(mut self, auto_mode: bool) -> Self
+	{
+		self.options.auto_mode = auto_mode;
+		self
+	}
+	
+	/// Remove bytes after the IEND chunk.
+	pub fn strip_trailing(mut self, strip_trailing: bool) -> Self
+	{
+		self.options.strip_trailing = strip_trailing;
+		self
+	}
+	
+	/// Hash the source and output of every processed file.
+	pub fn hashes(mut self, hashes: bool) -> Self
+	{
+		self.options.hashes = hashes;
+		self
+	}
+	
+	/// Produce byte-identical outputs for identical inputs.
+	pub fn deterministic(mut self, deterministic: bool) -> Self
+	{
+		self.options.deterministic = deterministic;
+		self
+	}
+	
+	/// Write outputs through a temporary file in this directory instead of directly to the target.
+	pub fn temp_dir(mut self, temp_dir: Option<PathBuf>) -> Self
+	{
+		self.options.temp_dir = temp_dir;
+		self
+	}
+	
+	/// Set the keyword of the marker chunk (None for the default).
+	pub fn marker_text(mut self, marker_text: Option<String>) -> Self
+	{
+		self.options.marker_text = marker_text;
+		self
+	}
+	
+	/// Validate the settings and return the options.
+	pub fn build(self) -> Result<MinifyOptions>
+	{
+		let options: MinifyOptions = self.options;
+		
+		if options.quality > 100
+		{
+			return Err(MinifyError::InvalidOptions("Quality must be between 1 and 100 (or 0 for auto)".to_string()));
+		}
+		
+		if options.smooth_radius < 0.0 || options.smooth_radius > 5.0
+		{
+			return Err(MinifyError::InvalidOptions("Smooth radius must be between 0.0 and 5.0".to_string()));
+		}
+		
+		if !(0.0..=1.0).contains(&options.denoise_strength)
+		{
+			return Err(MinifyError::InvalidOptions("Denoise strength must be between 0.0 and 1.0".to_string()));
+		}
+		
+		if !(3..=64).contains(&options.denoise_block)
+		{
+			return Err(MinifyError::InvalidOptions("Denoise block size must be between 3 and 64".to_string()));
+		}
+		
+		if options.lossless && self.quality_set
+		{
+			return Err(MinifyError::InvalidOptions("A quality level cannot be combined with lossless mode".to_string()));
+		}
+		
+		if options.dither_alpha && options.alpha_threshold.is_none()
+		{
+			return Err(MinifyError::InvalidOptions("Alpha dithering requires an alpha threshold".to_string()));
+		}
+		
+		if !(options.gamma.is_finite() && options.gamma > 0.0)
+		{
+			return Err(MinifyError::InvalidOptions("The gamma must be a positive number".to_string()));
+		}
+		
+		if options.auto_mode && options.lossless
+		{
+			return Err(MinifyError::InvalidOptions("Auto mode picks between lossy and lossless output and cannot be combined with lossless mode".to_string()));
+		}
+		
+		if options.always_write && !options.lossless
+		{
+			return Err(MinifyError::InvalidOptions("Writing output that isn't smaller is only supported in lossless mode".to_string()));
+		}
+		
+		if options.diff_pixels && !options.lossless
+		{
+			return Err(MinifyError::InvalidOptions("Pixel diffs are only supported in lossless mode".to_string()));
+		}
+		
+		if let Some(marker_text) = &options.marker_text
+		{
+			validate_marker_text(marker_text)?;
+		}
+		
+		if !(0.0..=100.0).contains(&options.min_reduction)
+		{
+			return Err(MinifyError::InvalidOptions("Minimum reduction must be between 0 and 100 percent".to_string()));
+		}
+		
+		if let Some(qualities) = options.quality_per_type
+		{
+			for quality in [qualities.photo, qualities.flat]
+			{
+				if let Some(quality) = quality && !(1..=100).contains(&quality)
+				{
+					return Err(MinifyError::InvalidOptions("Quality levels per image class must be between 1 and 100".to_string()));
+				}
+			}
+		}
+		
+		if options.max_pixels == Some(0)
+		{
+			return Err(MinifyError::InvalidOptions("Maximum pixel count must be at least 1".to_string()));
+		}
+		
+		if let Some(colors) = options.median_colors && !(2..=256).contains(&colors)
+		{
+			return Err(MinifyError::InvalidOptions("Median palette size must be between 2 and 256".to_string()));
+		}
+		
+		if let Some(factors) = options.channel_factors
+		{
+			for factor in factors
+			{
+				if !(1..=128).contains(&factor)
+				{
+					return Err(MinifyError::InvalidOptions("Channel factors must be between 1 and 128".to_string()));
+				}
+			}
+		}
+		
+		if let Some(palette) = &options.fixed_palette
+		{
+			if palette.is_empty() || palette.len() > 256
+			{
+				return Err(MinifyError::InvalidOptions("Fixed palette must have between 1 and 256 colors".to_string()));
+			}
+			
+			if options.grayscale
+			{
+				return Err(MinifyError::InvalidOptions("A fixed palette cannot be combined with grayscale output".to_string()));
+			}
+		}
+		
+		if options.keep_bit_depth && options.indexed
+		{
+			return Err(MinifyError::InvalidOptions("Indexed output cannot be combined with keeping the bit depth".to_string()));
+		}
+		
+		if options.also_webp && !cfg!(feature = "webp")
+		{
+			return Err(MinifyError::InvalidOptions(WEBP_UNAVAILABLE.to_string()));
+		}
+		
+		Ok(options)
+	}
+}
+
+/// Override the lossless flag, quality and dithering mode with the values recorded in a marker.
+pub fn apply_recorded_settings(options: &MinifyOptions, info: &MinificationInfo) -> MinifyOptions
+{
+	let mut reused: MinifyOptions = options.clone();
+	reused.lossless = info.lossless;
+	
+	// The recorded quality replaces any per-class mapping, which could pick a different one.
+	if let Some(quality) = info.quality
+	{
+		reused.quality = quality;
+		reused.quality_per_type = None;
+	}
+	
+	if let Some(dithering_mode) = info.dithering_mode
+	{
+		reused.dithering_mode = dithering_mode;
+	}
+	
+	reused
+}
+
+/// Minifies PNG data in memory.
+///
+/// Runs the same pipeline as the command line tool's minify_png, without any file I/O or already-minified check.
+/// Returns the minified data (with the marker added) if it is smaller than the input by at least min_reduction percent, otherwise a copy of the input,
+/// together with the dithering mode and quality level actually used and, with quality_per_type, the image class.
+/// With always_write, the minified data is returned even if it isn't smaller.
+///
+/// If verify is true, the output is decoded again and checked against the source before it is returned.
+/// With strip_trailing, bytes after the IEND chunk are removed from the source before anything else.
+/// Data without the PNG signature is rejected with MinifyError::InvalidSignature.
+pub fn minify_bytes(source_data: &[u8], options: &MinifyOptions) -> Result<(Vec<u8>, DitheringMode, u8, Option<ImageClass>)>
+{
+	let (output_data, effective_dithering, effective_quality, image_class, _) = minify_bytes_with_pixels(source_data, options)?;
+	Ok((output_data, effective_dithering, effective_quality, image_class))
+}
+
+/// Minifies PNG data in memory like minify_bytes, and with also_webp also returns the quantized pixels a lossy output was encoded from,
+/// so a WebP of the output can be encoded without decoding the PNG again.
+/// The pixels are None for lossless output and when a copy of the input is returned.
+#[allow(clippy::type_complexity)]
+pub fn minify_bytes_with_pixels(source_data: &[u8], options: &MinifyOptions) -> Result<(Vec<u8>, DitheringMode, u8, Option<ImageClass>, Option<image::RgbaImage>)>
+{
+	if source_data.len() < PNG_SIGNATURE.len() || &source_data[..PNG_SIGNATURE.len()] != PNG_SIGNATURE
+	{
+		return Err(MinifyError::InvalidSignature);
+	}
+	
+	// Refuse headers the decoder could only misread, before anything decodes the image.
+	let (color_type, bit_depth): (u8, u8) = png_format(source_data)?;
+	if !supported_format(color_type, bit_depth)
+	{
+		return Err(MinifyError::Decode(format!("Unsupported color type {} with bit depth {}", color_type, bit_depth)).context("decoding source PNG"));
+	}
+	
+	// Drop the bytes after IEND first, so even the unchanged image is returned without them.
+	let source_data: &[u8] = if options.strip_trailing { strip_trailing_data(source_data)? } else { source_data };
+	
+	let original_size: u64 = source_data.len() as u64;
+	
+	// Apply minification based on mode - quality-first, not size-based.
+	let (mut minified_data, effective_dithering, effective_quality, image_class, pixels) = if options.lossless
+	{
+		// Apply lossless minification only.
+		(apply_quality_lossless_minification(source_data, options)?, options.dithering_mode, options.quality, None, None)
+	}
+	else
+	{
+		// Apply lossy minification with specified quality level and dithering mode.
+		apply_quality_lossy_minification(source_data, options)?
+	};
+	
+	// Restore the physical pixel dimensions (DPI) if re-encoding or stripping dropped them.
+	if options.keep_phys && find_chunk(&minified_data, b"pHYs")?.is_none() && let Some(phys_chunk) = find_chunk(source_data, b"pHYs")?
+	{
+		minified_data = insert_chunk_after_ihdr(&minified_data, &phys_chunk)?;
+	}
+	
+	// Restore textual metadata (copyright, author, ...) that re-encoding or stripping dropped.
+	if options.keep_text
+	{
+		let existing_chunks: Vec<Vec<u8>> = collect_text_chunks(&minified_data)?;
+		let mut missing_chunks: Vec<Vec<u8>> = Vec::new();
+		for chunk in collect_text_chunks(source_data)?
+		{
+			if !existing_chunks.contains(&chunk)
+			{
+				missing_chunks.push(chunk);
+			}
+		}
+		
+		if !missing_chunks.is_empty()
+		{
+			minified_data = insert_chunks_before_iend(&minified_data, &missing_chunks)?;
+		}
+	}
+	
+	// Get size from in-memory buffer (no disk I/O needed!).
+	let new_size: u64 = minified_data.len() as u64;
+	if new_size < original_size || options.always_write
+	{
+		// Calculate reduction percentage.
+		let reduction_pct = (1.0 - (new_size as f64 / original_size as f64)) * 100.0;
+		
+		// Add marker with minification info.
+		let marked_data = add_minification_marker_with_info(&minified_data, options.lossless, effective_quality, effective_dithering, reduction_pct, options.marker_text.as_deref(), !options.deterministic)?;
+		let marked_size: u64 = marked_data.len() as u64;
+		let marked_reduction_pct: f64 = (1.0 - (marked_size as f64 / original_size as f64)) * 100.0;
+		if (marked_size < original_size && marked_reduction_pct >= options.min_reduction) || options.always_write
+		{
+			if options.verify
+			{
+				verify_minified_data(source_data, &marked_data, options.lossless)?;
+			}
+			
+			if options.diff_pixels
+			{
+				check_pixel_diff(source_data, &marked_data)?;
+			}
+			
+			return Ok((marked_data, effective_dithering, effective_quality, image_class, pixels));
+		}
+	}
+	
+	// Minification didn't reduce size (enough) - keep original.
+	Ok((source_data.to_vec(), effective_dithering, effective_quality, image_class, None))
+}
+
+/// Check that minified data decodes and matches the source dimensions.
+/// In lossless mode, the decoded pixels must also be identical to the source.
+fn verify_minified_data(source_data: &[u8], minified_data: &[u8], lossless: bool) -> Result<()>
+{
+	let source_img = image::load_from_memory(source_data)
+		.map_err(|e| MinifyError::Verification(format!("Verification failed: source does not decode: {}", e)))?;
+	let minified_img = image::load_from_memory(minified_data)
+		.map_err(|e| MinifyError::Verification(format!("Verification failed: output does not decode: {}", e)))?;
+	
+	if source_img.dimensions() != minified_img.dimensions()
+	{
+		let (source_width, source_height): (u32, u32) = source_img.dimensions();
+		let (minified_width, minified_height): (u32, u32) = minified_img.dimensions();
+		return Err(MinifyError::Verification(format!("Verification failed: output is {}x{} but source is {}x{}", minified_width, minified_height, source_width, source_height)));
+	}
+	
+	// Compare as RGBA8 so color type and bit depth reductions don't count as differences.
+	if lossless && source_img.to_rgba8().as_raw() != minified_img.to_rgba8().as_raw()
+	{
+		return Err(MinifyError::Verification("Verification failed: lossless output pixels differ from the source".to_string()));
+	}
+	
+	Ok(())
+}
+
+/// Per-pixel difference between two decodings of an image, compared as RGBA8.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PixelDiff
+{
+	/// Pixels with at least one channel that differs.
+	pub differing_pixels: u64,
+	
+	/// Largest difference of a single channel over all pixels (0-255).
+	pub max_channel_delta: u8,
+}
+
+/// Decode the source and the output and count the pixels that differ.
+/// Fails if either doesn't decode or their dimensions differ.
+pub fn diff_pixels(source_data: &[u8], output_data: &[u8]) -> Result<PixelDiff>
+{
+	let source_img = image::load_from_memory(source_data)
+		.map_err(|e| MinifyError::Verification(format!("Pixel diff failed: source does not decode: {}", e)))?;
+	let output_img = image::load_from_memory(output_data)
+		.map_err(|e| MinifyError::Verification(format!("Pixel diff failed: output does not decode: {}", e)))?;
+	
+	if source_img.dimensions() != output_img.dimensions()
+	{
+		let (source_width, source_height): (u32, u32) = source_img.dimensions();
+		let (output_width, output_height): (u32, u32) = output_img.dimensions();
+		return Err(MinifyError::Verification(format!("Pixel diff failed: output is {}x{} but source is {}x{}", output_width, output_height, source_width, source_height)));
+	}
+	
+	let source_rgba: image::RgbaImage = source_img.to_rgba8();
+	let output_rgba: image::RgbaImage = output_img.to_rgba8();
+	let mut diff: PixelDiff = PixelDiff::default();
+	for (source_pixel, output_pixel) in source_rgba.pixels().zip(output_rgba.pixels())
+	{
+		let mut delta: u8 = 0;
+		for (channel, value) in source_pixel.0.iter().enumerate()
+		{
+			delta = delta.max(value.abs_diff(output_pixel.0[channel]));
+		}
+		if delta > 0
+		{
+			diff.differing_pixels += 1;
+			diff.max_channel_delta = diff.max_channel_delta.max(delta);
+		}
+	}
+	
+	Ok(diff)
+}
+
+/// Check that lossless output decodes to exactly the source pixels.
+/// Fails with the number of differing pixels and the largest channel delta otherwise.
+pub fn check_pixel_diff(source_data: &[u8], output_data: &[u8]) -> Result<PixelDiff>
+{
+	let diff: PixelDiff = diff_pixels(source_data, output_data)?;
+	if diff.differing_pixels > 0
+	{
+		return Err(MinifyError::Verification(format!("Pixel diff failed: {} pixel(s) differ from the source (max channel delta {})", diff.differing_pixels, diff.max_channel_delta)));
+	}
+	
+	Ok(diff)
+}
+
+/// Checks if a PNG file has already been minified by this tool.
+/// Returns (is_minified, minification_info).
+/// Malformed chunks are an error, including an ancillary chunk (such as a large iCCP or eXIf) over MAX_ANCILLARY_CHUNK_LENGTH,
+/// the same as when the marker is added. Earlier versions stopped looking for the marker at any chunk over 10 MB instead,
+/// so such files were reported as not minified; critical chunks, such as the IDAT of a very large image, have no size limit.
+pub fn is_already_minified(png_data: &[u8]) -> Result<(bool, Option<MinificationInfo>)>
+{
+	// Check for PNG signature.
+	if png_data.len() < 8 || &png_data[0..8] != PNG_SIGNATURE
+	{
+		return Ok((false, None));
+	}
+	
+	// Look for our custom tEXt chunk marker.
+	let mut pos = 8;
+	while pos + 12 <= png_data.len()
+	{
+		// Read chunk length (4 bytes, big-endian).
+		let length: usize = u32::from_be_bytes([png_data[pos], png_data[pos + 1], png_data[pos + 2], png_data[pos + 3]]) as usize;
+		
+		// Make sure the whole chunk is inside the buffer before trusting it.
+		check_chunk_bounds(png_data, pos, length)?;
+		
+		// Read chunk type (4 bytes).
+		let chunk_type = &png_data[pos + 4..pos + 8];
+		
+		// Check if this is our marker chunk.
+		if chunk_type == b"tEXt"
+		{
+			// Check if the chunk data contains our marker.
+			let chunk_data = &png_data[pos + 8..pos + 8 + length];
+			
+			// Look for our marker: the default keyword, or a custom one followed by the sentinel.
+			if is_marker_chunk(chunk_data)
+			{
+				// Parse the minification info from the marker.
+				let info: Option<MinificationInfo> = parse_minification_info(chunk_data);
+				return Ok((true, info));
+			}
+		}
+		
+		// The image ends at IEND; trailing data after it is not made of chunks.
+		if chunk_type == b"IEND"
+		{
+			break;
+		}
+		
+		// Move to next chunk (length + type + data + CRC).
+		pos += 12 + length;
+	}
+	
+	Ok((false, None))
+}
+
+/// Peak signal-to-noise ratio (dB) of a minified PNG against its source, over the RGBA channels of every pixel.
+/// Higher is closer to the source; identical pixels give infinity.
+pub fn psnr(source_png: &[u8], output_png: &[u8]) -> Result<f64>
+{
+	let decode = |png_data: &[u8]| -> Result<image::RgbaImage>
+	{
+		Ok(image::load_from_memory(png_data).map_err(|e| MinifyError::Decode(format!("Failed to decode PNG: {}", e)))?.into_rgba8())
+	};
+	let source: image::RgbaImage = decode(source_png)?;
+	let output: image::RgbaImage = decode(output_png)?;
+	if source.dimensions() != output.dimensions()
+	{
+		return Err(MinifyError::Malformed("Output dimensions differ from the source".to_string()));
+	}
+	
+	let output_values: &[u8] = output.as_raw();
+	let mut squared_error: f64 = 0.0;
+	for (index, &value) in source.as_raw().iter().enumerate()
+	{
+		squared_error += (value as f64 - output_values[index] as f64).powi(2);
+	}
+	if squared_error == 0.0
+	{
+		return Ok(f64::INFINITY);
+	}
+	
+	let mean_squared_error: f64 = squared_error / source.as_raw().len() as f64;
+	Ok(10.0 * (255.0 * 255.0 / mean_squared_error).log10())
+}
+
+/// Decide between the lossy and the lossless output of a PNG for auto_mode.
+/// The lossy output is kept only when it saves at least AUTO_MODE_MIN_SAVINGS of the lossless size
+/// and stays at or above AUTO_MODE_MIN_PSNR; otherwise the savings aren't worth the quality loss.
+pub fn prefer_lossy(source_png: &[u8], lossy_png: &[u8], lossless_size: usize) -> Result<bool>
+{
+	if lossy_png.len() as f64 > lossless_size as f64 * (1.0 - AUTO_MODE_MIN_SAVINGS)
+	{
+		return Ok(false);
+	}
+	
+	Ok(psnr(source_png, lossy_png)? >= AUTO_MODE_MIN_PSNR)
+}
+
+/// Checks if PNG data looks already quantized by another tool (pngquant, TinyPNG, ...), without a MiniPNG marker.
+/// That is the case for indexed-color (palette) PNGs, and for color images of at least QUANTIZED_MIN_PIXELS pixels
+/// with no more than QUANTIZED_MAX_COLORS distinct colors. Quantizing such images again compounds the quality loss.
+/// Counting the colors decodes the image; is_indexed_color is the check without decoding.
+pub fn is_already_quantized(png_data: &[u8]) -> Result<bool>
+{
+	if is_indexed_color(png_data)
+	{
+		return Ok(true);
+	}
+	
+	let rgba: image::RgbaImage = image::load_from_memory(png_data)
+		.map_err(|e| MinifyError::Decode(format!("Failed to decode PNG: {}", e)))?
+		.into_rgba8();
+	if (rgba.width() as u64 * rgba.height() as u64) < QUANTIZED_MIN_PIXELS
+	{
+		return Ok(false);
+	}
+	
+	// Gray images have at most 256 levels anyway, so only color images count.
+	let mut colors: std::collections::HashSet<[u8; 4]> = std::collections::HashSet::new();
+	let mut has_color: bool = false;
+	for pixel in rgba.pixels()
+	{
+		has_color = has_color || pixel[0] != pixel[1] || pixel[1] != pixel[2];
+		if colors.insert(pixel.0) && colors.len() > QUANTIZED_MAX_COLORS
+		{
+			return Ok(false);
+		}
+	}
+	
+	Ok(has_color)
+}
+
+/// Checks if PNG data is indexed-color (palette), from the IHDR chunk alone.
+pub fn is_indexed_color(png_data: &[u8]) -> bool
+{
+	// The IHDR chunk comes first; its color type is the 10th data byte.
+	const COLOR_TYPE_OFFSET: usize = 8 + 8 + 9;
+	png_data.len() > COLOR_TYPE_OFFSET && &png_data[12..16] == b"IHDR" && png_data[COLOR_TYPE_OFFSET] == 3
+}
+
+/// Checks if a marker was written before a cutoff (seconds since the Unix epoch).
+/// Markers without a readable timestamp are not considered older.
+pub fn minified_before(info: &MinificationInfo, cutoff: u64) -> bool
+{
+	match info.timestamp.as_deref().and_then(time_utils::parse_iso8601)
+	{
+		Some(minified_at) => minified_at < cutoff,
+		None => false,
+	}
+}
+
+/// Read the image dimensions (width, height) from the IHDR chunk, without decoding the image.
+pub fn png_dimensions(png_data: &[u8]) -> Result<(u32, u32)>
+{
+	if png_data.len() < 8 || &png_data[0..8] != PNG_SIGNATURE
+	{
+		return Err(MinifyError::InvalidSignature);
+	}
+	
+	match find_chunk(png_data, b"IHDR")?
+	{
+		// Whole chunk: length and type, then the width and height as the first 8 data bytes.
+		Some(ihdr) if ihdr.len() >= 16 =>
+		{
+			let width: u32 = u32::from_be_bytes([ihdr[8], ihdr[9], ihdr[10], ihdr[11]]);
+			let height: u32 = u32::from_be_bytes([ihdr[12], ihdr[13], ihdr[14], ihdr[15]]);
+			Ok((width, height))
+		},
+		_ => Err(MinifyError::Malformed("Malformed PNG: missing IHDR chunk".to_string())),
+	}
+}
+
+/// Checks if an image has more pixels than options.max_pixels allows (never, without a limit).
+pub fn exceeds_max_pixels(png_data: &[u8], options: &MinifyOptions) -> Result<bool>
+{
+	let max_pixels: u64 = match options.max_pixels
+	{
+		Some(max_pixels) => max_pixels,
+		None => return Ok(false),
+	};
+	
+	let (width, height): (u32, u32) = png_dimensions(png_data)?;
+	Ok(width as u64 * height as u64 > max_pixels)
+}
+
+/// Read the minification info from a PNG file without minifying it.
+/// Returns None if the file has no MiniPNG marker.
+/// Only built with the "cli" feature, so the core has no file system access.
+#[cfg(feature = "cli")]
+pub fn read_minification_info(path: &std::path::Path) -> Result<Option<MinificationInfo>>
+{
+	let png_data: Vec<u8> = std::fs::read(path)
+		.map_err(|e| MinifyError::io("Failed to read file", e))?;
+	
+	if png_data.len() < PNG_SIGNATURE.len() || &png_data[..PNG_SIGNATURE.len()] != PNG_SIGNATURE
+	{
+		return Err(MinifyError::InvalidSignature);
+	}
+	
+	let (_, info): (bool, Option<MinificationInfo>) = is_already_minified(&png_data)?;
+	Ok(info)
+}
+
+/// Split tEXt chunk data into its keyword and text at the null separator.
+fn split_text_chunk(chunk_data: &[u8]) -> Option<(&[u8], &[u8])>
+{
+	let separator: usize = chunk_data.iter().position(|&byte| byte == 0)?;
+	Some((&chunk_data[..separator], &chunk_data[separator + 1..]))
+}
+
+/// Checks if tEXt chunk data is a MiniPNG marker: the default keyword, or any keyword with the sentinel starting the text.
+fn is_marker_chunk(chunk_data: &[u8]) -> bool
+{
+	match split_text_chunk(chunk_data)
+	{
+		Some((keyword, text)) => keyword == MARKER_KEYWORD.as_bytes() || text.split(|&byte| byte == b',').next() == Some(MARKER_SENTINEL.as_bytes()),
+		None => false,
+	}
+}
+
+/// Check that a custom marker keyword is a valid tEXt keyword: 1-79 printable Latin-1 characters,
+/// without leading, trailing or consecutive spaces.
+pub fn validate_marker_text(marker_text: &str) -> Result<()>
+{
+	let invalid = |reason: &str| Err(MinifyError::InvalidOptions(format!("Invalid marker text '{}': {}", marker_text, reason)));
+	
+	let length: usize = marker_text.chars().count();
+	if !(1..=79).contains(&length)
+	{
+		return invalid("must be 1 to 79 characters long");
+	}
+	
+	for c in marker_text.chars()
+	{
+		if !matches!(c as u32, 32..=126 | 161..=255)
+		{
+			return invalid("only printable Latin-1 characters are allowed");
+		}
+	}
+	
+	if marker_text.starts_with(' ') || marker_text.ends_with(' ') || marker_text.contains("  ")
+	{
+		return invalid("leading, trailing and consecutive spaces are not allowed");
+	}
+	
+	Ok(())
+}
+
+/// Parse minification info from marker text.
+fn parse_minification_info(marker_data: &[u8]) -> Option<MinificationInfo>
+{
+	// Format: "MiniPNG by P. Andrian.\0quality=40,dithering=floyd,reduction=73.0,timestamp=2026-02-06T20:15:30Z",
+	// with a custom keyword "Keyword\0minipng=1,quality=40,...".
+	let (keyword, text): (&[u8], &[u8]) = split_text_chunk(marker_data)?;
+	
+	// The keyword is Latin-1, every byte is the code point of its character.
+	let mut marker_text: String = String::with_capacity(keyword.len());
+	for &byte in keyword
+	{
+		marker_text.push(byte as char);
+	}
+	let data_part = std::str::from_utf8(text).ok()?;
+	
+	let mut quality = None;
+	let mut dithering_mode = None;
+	let mut lossless = false;
+	let mut reduction_pct = 0.0;
+	let mut timestamp = None;
+	let mut noop: bool = false;
+	
+	// Parse key=value pairs.
+	for pair in data_part.split(',')
+	{
+		let parts: Vec<&str> = pair.split('=').collect();
+		if parts.len() == 2
+		{
+			match parts[0]
+			{
+				"quality" => quality = parts[1].parse::<u8>().ok(),
+				"dithering" => dithering_mode = parse_dithering_mode(parts[1]),
+				"lossless" => lossless = parts[1] == "true",
+				"reduction" => reduction_pct = parts[1].parse::<f64>().unwrap_or(0.0),
+				"timestamp" => timestamp = Some(parts[1].to_string()),
+				"noop" => noop = parts[1] == "true",
+				_ => {}
+			}
+		}
+	}
+	
+	Some(MinificationInfo
+	{
+		quality,
+		dithering_mode,
+		lossless,
+		reduction_pct,
+		timestamp,
+		noop,
+		marker_text,
+	})
+}
+
+/// Parse a dithering mode from its string representation (as written by dithering_mode_to_string).
+pub fn parse_dithering_mode(mode_str: &str) -> Option<DitheringMode>
+{
+	match mode_str
+	{
+		"none" => Some(DitheringMode::None),
+		"ordered" => Some(DitheringMode::Ordered),
+		"bluenoise" => Some(DitheringMode::BlueNoise),
+		"floyd" => Some(DitheringMode::FloydSteinberg),
+		"median" => Some(DitheringMode::MedianCut),
+		"auto" => Some(DitheringMode::Auto),
+		_ => None,
+	}
+}
+
+/// Convert DitheringMode to its string representation.
+pub fn dithering_mode_to_string(mode: DitheringMode) -> &'static str
+{
+	match mode
+	{
+		DitheringMode::None => "none",
+		DitheringMode::Ordered => "ordered",
+		DitheringMode::BlueNoise => "bluenoise",
+		DitheringMode::FloydSteinberg => "floyd",
+		DitheringMode::MedianCut => "median",
+		DitheringMode::Auto => "auto",
+	}
+}
+
+/// Select which ancillary chunks oxipng removes.
+/// Our marker is added after optimization, so it is kept either way.
+fn strip_level(strip_all: bool) -> oxipng::StripChunks
+{
+	if strip_all
+	{
+		oxipng::StripChunks::All
+	}
+	else
+	{
+		oxipng::StripChunks::Safe
+	}
+}
+
+/// Interlacing setting for oxipng: Some(false) de-interlaces and Some(true) applies Adam7.
+/// Keep resolves to the source's own interlacing, since the quantized image is always encoded without it.
+fn oxipng_interlace(mode: InterlaceMode, source_png: &[u8]) -> Result<Option<bool>>
+{
+	match mode
+	{
+		InterlaceMode::None => Ok(Some(false)),
+		InterlaceMode::Adam7 => Ok(Some(true)),
+		InterlaceMode::Keep => Ok(Some(is_interlaced(source_png)?)),
+	}
+}
+
+/// Read the color type and bit depth (color_type, bit_depth) from the IHDR chunk, without decoding the image.
+fn png_format(png_data: &[u8]) -> Result<(u8, u8)>
+{
+	match find_chunk(png_data, b"IHDR")?
+	{
+		// Whole chunk: the bit depth and color type are the 9th and 10th data bytes, after the length and type.
+		Some(ihdr) if ihdr.len() >= 18 => Ok((ihdr[17], ihdr[16])),
+		_ => Err(MinifyError::Malformed("Malformed PNG: missing IHDR chunk".to_string())),
+	}
+}
+
+/// Checks if the IHDR color type and bit depth are a combination the PNG specification allows,
+/// so decoding and quantization can handle the image faithfully (16-bit images are decoded to 8 bits per channel in lossy mode).
+pub fn is_supported_format(png_data: &[u8]) -> Result<bool>
+{
+	let (color_type, bit_depth): (u8, u8) = png_format(png_data)?;
+	Ok(supported_format(color_type, bit_depth))
+}
+
+/// Checks a color type and bit depth combination against the ones the PNG specification defines.
+fn supported_format(color_type: u8, bit_depth: u8) -> bool
+{
+	match color_type
+	{
+		0 => matches!(bit_depth, 1 | 2 | 4 | 8 | 16), // Grayscale.
+		3 => matches!(bit_depth, 1 | 2 | 4 | 8), // Indexed.
+		2 | 4 | 6 => matches!(bit_depth, 8 | 16), // RGB, grayscale with alpha, RGBA.
+		_ => false,
+	}
+}
+
+/// Checks if PNG data is Adam7-interlaced (interlace method byte of IHDR).
+pub fn is_interlaced(png_data: &[u8]) -> Result<bool>
+{
+	match find_chunk(png_data, b"IHDR")?
+	{
+		// Whole chunk: the interlace method is the 13th data byte, after the length and type.
+		Some(ihdr) if ihdr.len() >= 21 => Ok(ihdr[20] != 0),
+		_ => Err(MinifyError::Malformed("Malformed PNG: missing IHDR chunk".to_string())),
+	}
+}
+
+/// Select the deflate implementation: Zopfli for the smallest files, or libdeflate at its highest level for speed.
+fn deflater(fast: bool) -> Deflater
+{
+	if fast
+	{
+		Deflater::Libdeflater { compression: 12 }
+	}
+	else
+	{
+		Deflater::Zopfli(Default::default())
+	}
+}
+
+/// Pin the oxipng settings that can make outputs differ between runs of the same input:
+/// a single fixed filter instead of a search (fast evaluation picks among several on timing-dependent threads),
+/// and no timeout. Returns the thread count to run oxipng with.
+fn pin_oxipng(oxi_options: &mut OxiOptions, options: &MinifyOptions) -> usize
+{
+	if !options.deterministic
+	{
+		return options.optimizer_threads;
+	}
+	
+	oxi_options.filters.clear();
+	oxi_options.filters.insert(oxipng::FilterStrategy::MinSum);
+	oxi_options.fast_evaluation = false;
+	oxi_options.timeout = None;
+	1
+}
+
+/// Applies lossless minification with aggressive settings for maximum minification
+/// while maintaining perfect image quality.
+#[allow(clippy::field_reassign_with_default)]
+fn apply_quality_lossless_minification(png_data: &[u8], options: &MinifyOptions) -> Result<Vec<u8>>
+{
+	// Use maximum lossless minification settings.
+	let mut oxi_options = OxiOptions::default();
+	oxi_options.strip = strip_level(options.strip_all);
+	oxi_options.optimize_alpha = true;
+	oxi_options.interlace = oxipng_interlace(options.interlace, png_data)?;
+	oxi_options.bit_depth_reduction = !options.keep_bit_depth;
+	oxi_options.color_type_reduction = !options.keep_bit_depth;
+	oxi_options.palette_reduction = !options.keep_bit_depth;
+	
+	// Use Zopfli for maximum minification (slower but best results).
+	oxi_options.deflater = deflater(options.fast_deflate);
+	let threads: usize = pin_oxipng(&mut oxi_options, options);
+	
+	// Apply oxipng optimization.
+	let optimized: Vec<u8> = par_utils::run_with_threads(threads, || optimize_from_memory(png_data, &oxi_options))
+		.map_err(|e| MinifyError::Encode(format!("Failed to optimize PNG: {}", e)).context("optimizing with oxipng"))?;
+	
+	Ok(optimized)
+}
+
+/// Applies lossy minification with the specified quality level and dithering mode.
+/// Quality 40 (default) provides good visual quality with aggressive minification (~70-77% reduction).
+/// Quality 50-60 provides very good quality with strong minification (~57-73% reduction).
+/// Quality 70-80 provides excellent quality with moderate minification (~30-60% reduction).
+/// Returns (minified_data, effective_dithering_mode, effective_quality, image_class, quantized pixels with also_webp)
+#[allow(clippy::field_reassign_with_default, clippy::type_complexity)]
+fn apply_quality_lossy_minification(png_data: &[u8], options: &MinifyOptions) -> Result<(Vec<u8>, DitheringMode, u8, Option<ImageClass>, Option<image::RgbaImage>)>
+{
+	// Validate it's a valid PNG and load it.
+	// Each stage names itself in its errors, so a failure tells whether decoding, quantization or oxipng failed.
+	let img: image::DynamicImage = image::load_from_memory(png_data)
+		.map_err(|e| MinifyError::Decode(format!("Failed to decode PNG: {}", e)).context("decoding source PNG"))?;
+	
+	// Convert to RGBA once and share the buffer between analysis and quantization.
+	let (width, height): (u32, u32) = img.dimensions();
+	let source_color: image::ColorType = img.color();
+	let rgba: image::RgbaImage = img.into_rgba8();
+	
+	// Use the quality level mapped to the image class, if any.
+	let image_class: Option<ImageClass> = options.quality_per_type.map(|_| dithering::classify_image(&rgba, width, height, &options.thresholds));
+	let class_options: MinifyOptions;
+	let options: &MinifyOptions = if let (Some(qualities), Some(class)) = (options.quality_per_type, image_class) && let Some(quality) = qualities.quality_for(class)
+	{
+		class_options = MinifyOptions { quality, ..options.clone() };
+		&class_options
+	}
+	else
+	{
+		options
+	};
+	
+	// Resolve the auto quality level from the image analysis.
+	let auto_options: MinifyOptions;
+	let options: &MinifyOptions = if options.quality == AUTO_QUALITY
+	{
+		auto_options = MinifyOptions { quality: dithering::recommend_quality(&rgba, width, height), ..options.clone() };
+		&auto_options
+	}
+	else
+	{
+		options
+	};
+	
+	// Determine effective dithering mode (resolve Auto).
+	let effective_dithering = match options.dithering_mode
+	{
+		DitheringMode::Auto => dithering::recommend_dithering_mode(&rgba, width, height, &options.thresholds),
+		_ => options.dithering_mode,
+	};
+	
+	// Apply color quantization with specified quality and dithering mode.
+	// The quantized pixels are kept for the WebP, which shows the same image as the PNG.
+	let pixels: image::RgbaImage = quantize_pixels(rgba, width, height, effective_dithering, options)
+		.map_err(|e| e.context("quantizing"))?;
+	let kept_pixels: Option<image::RgbaImage> = if options.also_webp { Some(pixels.clone()) } else { None };
+	let quantized: Vec<u8> = encode_quantized_png(pixels, source_color, options)
+		.map_err(|e| e.context("quantizing"))?;
+	
+	// Apply aggressive lossless minification to the quantized data.
+	let mut oxi_options = OxiOptions::default();
+	oxi_options.strip = strip_level(options.strip_all);
+	oxi_options.optimize_alpha = true;
+	oxi_options.interlace = oxipng_interlace(options.interlace, png_data)?;
+	oxi_options.deflater = deflater(options.fast_deflate);
+	oxi_options.bit_depth_reduction = !options.keep_bit_depth;
+	oxi_options.color_type_reduction = !options.keep_bit_depth;
+	oxi_options.palette_reduction = !options.keep_bit_depth;
+	let threads: usize = pin_oxipng(&mut oxi_options, options);
+	
+	let minified: Vec<u8> = par_utils::run_with_threads(threads, || optimize_from_memory(&quantized, &oxi_options))
+		.map_err(|e| MinifyError::Encode(format!("Failed to optimize quantized PNG: {}", e)).context("optimizing with oxipng"))?;
+	
+	Ok((minified, effective_dithering, options.quality, image_class, kept_pixels))
+}
+
+/// Quantize a decoded image with the given dithering mode, as the lossy pipeline does before the final optimization.
+/// Meant for previews of the dithering modes: nothing is written and no marker is added.
+pub fn quantize_rgba(rgba: image::RgbaImage, dithering_mode: DitheringMode, options: &MinifyOptions) -> Result<image::RgbaImage>
+{
+	let (width, height): (u32, u32) = rgba.dimensions();
+	let auto_options: MinifyOptions;
+	let options: &MinifyOptions = if options.quality == AUTO_QUALITY
+	{
+		auto_options = MinifyOptions { quality: dithering::recommend_quality(&rgba, width, height), ..options.clone() };
+		&auto_options
+	}
+	else
+	{
+		options
+	};
+	
+	quantize_pixels(rgba, width, height, dithering_mode, options)
+}
+
+/// Encode pixels as a WebP (also_webp).
+/// The WebP encoder only writes lossless WebP, so the quality level doesn't apply: lossy outputs stay small because their pixels are already quantized.
+#[cfg(feature = "webp")]
+pub fn encode_webp(rgba: &image::RgbaImage) -> Result<Vec<u8>>
+{
+	let mut buffer: Vec<u8> = Vec::new();
+	image_webp::WebPEncoder::new(&mut buffer).encode(rgba.as_raw(), rgba.width(), rgba.height(), image_webp::ColorType::Rgba8)
+		.map_err(|e| MinifyError::Encode(format!("Failed to encode WebP: {}", e)).context("encoding WebP"))?;
+	Ok(buffer)
+}
+
+/// Without the "webp" feature there is no WebP encoder (MinifyOptionsBuilder::build rejects also_webp).
+#[cfg(not(feature = "webp"))]
+pub fn encode_webp(_rgba: &image::RgbaImage) -> Result<Vec<u8>>
+{
+	Err(MinifyError::InvalidOptions(WEBP_UNAVAILABLE.to_string()))
+}
+
+/// Encode the pixels of a PNG as a WebP, for outputs without quantized pixels at hand (lossless output or an unchanged image).
+pub fn webp_from_png(png_data: &[u8]) -> Result<Vec<u8>>
+{
+	let rgba: image::RgbaImage = image::load_from_memory(png_data)
+		.map_err(|e| MinifyError::Decode(format!("Failed to decode PNG: {}", e)).context("decoding output for WebP"))?
+		.into_rgba8();
+	encode_webp(&rgba)
+}
+
+/// Checks that a chunk (length + type + data + CRC) starting at pos lies entirely within the buffer,
+/// and that an ancillary chunk isn't larger than MAX_ANCILLARY_CHUNK_LENGTH.
+/// Every chunk walker uses this check, so they all reject the same malformed input.
+fn check_chunk_bounds(png_data: &[u8], pos: usize, length: usize) -> Result<()>
+{
+	let chunk_end: Option<usize> = pos.checked_add(12).and_then(|end| end.checked_add(length));
+	match chunk_end
+	{
+		Some(end) if end <= png_data.len() => {},
+		_ => return Err(MinifyError::Malformed("Malformed PNG: chunk extends past end of file".to_string())),
+	}
+	
+	// Bit 5 of the first type byte (a lowercase letter) marks an ancillary chunk.
+	let is_ancillary: bool = png_data[pos + 4] & 0x20 != 0;
+	if is_ancillary && length > MAX_ANCILLARY_CHUNK_LENGTH
+	{
+		return Err(MinifyError::Malformed(format!("Malformed PNG: ancillary chunk {} is larger than {} bytes", String::from_utf8_lossy(&png_data[pos + 4..pos + 8]), MAX_ANCILLARY_CHUNK_LENGTH)));
+	}
+	
+	Ok(())
+}
+
+/// List the chunks of PNG data in file order, each with its data length in bytes.
+/// Consecutive IDAT chunks are combined into one entry holding their total length.
+/// Bytes after IEND are listed as a last TRAILING_DATA entry.
+pub fn list_chunks(png_data: &[u8]) -> Result<Vec<(String, usize)>>
+{
+	if png_data.len() < 8 || &png_data[0..8] != PNG_SIGNATURE
+	{
+		return Err(MinifyError::InvalidSignature);
+	}
+	
+	let mut chunks: Vec<(String, usize)> = Vec::new();
+	let mut pos: usize = 8;
+	while pos + 12 <= png_data.len()
+	{
+		let length: usize = u32::from_be_bytes([png_data[pos], png_data[pos + 1], png_data[pos + 2], png_data[pos + 3]]) as usize;
+		check_chunk_bounds(png_data, pos, length)?;
+		
+		let chunk_type: String = String::from_utf8_lossy(&png_data[pos + 4..pos + 8]).into_owned();
+		match chunks.last_mut()
+		{
+			Some((last_type, total)) if chunk_type == "IDAT" && last_type == "IDAT" => *total += length,
+			_ => chunks.push((chunk_type, length)),
+		}
+		
+		// Move to next chunk (length + type + data + CRC).
+		pos += 12 + length;
+		
+		// The image ends at IEND; whatever follows is listed as trailing data.
+		if chunks.last().is_some_and(|(chunk_type, _)| chunk_type == "IEND")
+		{
+			if pos < png_data.len()
+			{
+				chunks.push((TRAILING_DATA.to_string(), png_data.len() - pos));
+			}
+			break;
+		}
+	}
+	
+	Ok(chunks)
+}
+
+/// Find the first chunk of the given type and return it whole (length, type, data and CRC).
+fn find_chunk(png_data: &[u8], wanted_type: &[u8; 4]) -> Result<Option<Vec<u8>>>
+{
+	if png_data.len() < 8 || &png_data[0..8] != PNG_SIGNATURE
+	{
+		return Ok(None);
+	}
+	
+	let mut pos: usize = 8;
+	while pos + 12 <= png_data.len()
+	{
+		let length: usize = u32::from_be_bytes([png_data[pos], png_data[pos + 1], png_data[pos + 2], png_data[pos + 3]]) as usize;
+		check_chunk_bounds(png_data, pos, length)?;
+		
+		if &png_data[pos + 4..pos + 8] == wanted_type
+		{
+			return Ok(Some(png_data[pos..pos + 12 + length].to_vec()));
+		}
+		
+		// The image ends at IEND; trailing data after it is not made of chunks.
+		if &png_data[pos + 4..pos + 8] == b"IEND"
+		{
+			break;
+		}
+		
+		// Move to next chunk (length + type + data + CRC).
+		pos += 12 + length;
+	}
+	
+	Ok(None)
+}
+
+/// Collect the textual metadata chunks (tEXt, zTXt and iTXt), each whole (length, type, data and CRC).
+/// Our own marker is left out, since a fresh one is added to every output.
+fn collect_text_chunks(png_data: &[u8]) -> Result<Vec<Vec<u8>>>
+{
+	let mut chunks: Vec<Vec<u8>> = Vec::new();
+	if png_data.len() < 8 || &png_data[0..8] != PNG_SIGNATURE
+	{
+		return Ok(chunks);
+	}
+	
+	let mut pos: usize = 8;
+	while pos + 12 <= png_data.len()
+	{
+		let length: usize = u32::from_be_bytes([png_data[pos], png_data[pos + 1], png_data[pos + 2], png_data[pos + 3]]) as usize;
+		check_chunk_bounds(png_data, pos, length)?;
+		
+		let chunk_type: &[u8] = &png_data[pos + 4..pos + 8];
+		let chunk_data: &[u8] = &png_data[pos + 8..pos + 8 + length];
+		let is_text: bool = chunk_type == b"tEXt" || chunk_type == b"zTXt" || chunk_type == b"iTXt";
+		if is_text && !is_marker_chunk(chunk_data)
+		{
+			chunks.push(png_data[pos..pos + 12 + length].to_vec());
+		}
+		
+		// The image ends at IEND; trailing data after it is not made of chunks.
+		if chunk_type == b"IEND"
+		{
+			break;
+		}
+		
+		// Move to next chunk (length + type + data + CRC).
+		pos += 12 + length;
+	}
+	
+	Ok(chunks)
+}
+
+/// Insert whole chunks right before IEND (where chunks that may follow IDAT can go).
+fn insert_chunks_before_iend(png_data: &[u8], chunks: &[Vec<u8>]) -> Result<Vec<u8>>
+{
+	if png_data.len() < 8 || &png_data[0..8] != PNG_SIGNATURE
+	{
+		return Err(MinifyError::InvalidSignature);
+	}
+	
+	let mut pos: usize = 8;
+	while pos + 12 <= png_data.len()
+	{
+		let length: usize = u32::from_be_bytes([png_data[pos], png_data[pos + 1], png_data[pos + 2], png_data[pos + 3]]) as usize;
+		check_chunk_bounds(png_data, pos, length)?;
+		
+		if &png_data[pos + 4..pos + 8] == b"IEND"
+		{
+			let mut extra_length: usize = 0;
+			for chunk in chunks
+			{
+				extra_length += chunk.len();
+			}
+			let mut result: Vec<u8> = Vec::with_capacity(png_data.len() + extra_length);
+			result.extend_from_slice(&png_data[..pos]);
+			for chunk in chunks
+			{
+				result.extend_from_slice(chunk);
+			}
+			result.extend_from_slice(&png_data[pos..]);
+			return Ok(result);
+		}
+		
+		// Move to next chunk (length + type + data + CRC).
+		pos += 12 + length;
+	}
+	
+	Err(MinifyError::Malformed("IEND chunk not found".to_string()))
+}
+
+/// Insert a whole chunk right after IHDR (where chunks that must precede PLTE and IDAT can go).
+fn insert_chunk_after_ihdr(png_data: &[u8], chunk: &[u8]) -> Result<Vec<u8>>
+{
+	if png_data.len() < 8 + 12 || &png_data[0..8] != PNG_SIGNATURE || &png_data[12..16] != b"IHDR"
+	{
+		return Err(MinifyError::Malformed("Invalid PNG: IHDR must be the first chunk".to_string()));
+	}
+	
+	let ihdr_length: usize = u32::from_be_bytes([png_data[8], png_data[9], png_data[10], png_data[11]]) as usize;
+	check_chunk_bounds(png_data, 8, ihdr_length)?;
+	let ihdr_end: usize = 8 + 12 + ihdr_length;
+	
+	let mut result: Vec<u8> = Vec::with_capacity(png_data.len() + chunk.len());
+	result.extend_from_slice(&png_data[..ihdr_end]);
+	result.extend_from_slice(chunk);
+	result.extend_from_slice(&png_data[ihdr_end..]);
+	
+	Ok(result)
+}
+
+/// Adds a marker to an image that lossy minification at the given settings couldn't reduce (see MinifyOptions::mark_noop).
+/// The image data is left as it is; the marker records the attempt with a reduction of 0 and noop=true.
+pub fn add_noop_marker(png_data: &[u8], quality: u8, dithering_mode: DitheringMode, marker_text: Option<&str>, timestamp: bool) -> Result<Vec<u8>>
+{
+	Ok(noop_marked(png_data, quality, dithering_mode, marker_text, timestamp)?.to_vec())
+}
+
+/// Prepare the marker of add_noop_marker without building the marked image, so it can be streamed with MarkedPng::write_to.
+/// Fails before anything is written if the data isn't a PNG with an IEND chunk.
+pub fn noop_marked<'a>(png_data: &'a [u8], quality: u8, dithering_mode: DitheringMode, marker_text: Option<&str>, timestamp: bool) -> Result<MarkedPng<'a>>
+{
+	MarkedPng::new(png_data, marker_chunk(false, quality, dithering_mode, 0.0, true, marker_text, timestamp))
+}
+
+/// PNG data with a marker chunk to insert before its IEND chunk.
+/// write_to streams the marked image (the bytes up to IEND, the marker chunk, then IEND and anything after it),
+/// so marking a large image doesn't hold a second copy of it in memory; to_vec builds the same bytes in memory.
+pub struct MarkedPng<'a>
+{
+	png_data: &'a [u8],
+	iend_pos: usize,
+	chunk: Vec<u8>,
+}
+
+impl<'a> MarkedPng<'a>
+{
+	/// Locate the IEND chunk the marker chunk goes before.
+	fn new(png_data: &'a [u8], chunk: Vec<u8>) -> Result<Self>
+	{
+		let iend_pos: usize = find_iend(png_data)?;
+		Ok(MarkedPng { png_data, iend_pos, chunk })
+	}
+	
+	/// Size of the marked image in bytes.
+	pub fn size(&self) -> u64
+	{
+		(self.png_data.len() + self.chunk.len()) as u64
+	}
+	
+	/// Write the marked image.
+	pub fn write_to<W>(&self, out: &mut W) -> std::io::Result<()> where W: std::io::Write + ?Sized
+	{
+		out.write_all(&self.png_data[..self.iend_pos])?;
+		out.write_all(&self.chunk)?;
+		out.write_all(&self.png_data[self.iend_pos..])
+	}
+	
+	/// Build the marked image in memory.
+	pub fn to_vec(&self) -> Vec<u8>
+	{
+		let mut result: Vec<u8> = Vec::with_capacity(self.size() as usize);
+		result.extend_from_slice(&self.png_data[..self.iend_pos]);
+		result.extend_from_slice(&self.chunk);
+		result.extend_from_slice(&self.png_data[self.iend_pos..]);
+		result
+	}
+}
+
+/// Adds a tEXt chunk marker with minification info.
+/// A custom marker text replaces the default keyword, and the info then starts with the sentinel.
+/// Without a timestamp, the marker only depends on the settings and the reduction (see MinifyOptions::deterministic).
+fn add_minification_marker_with_info(png_data: &[u8], lossless: bool, quality: u8, dithering_mode: DitheringMode, reduction_pct: f64, marker_text: Option<&str>, timestamp: bool) -> Result<Vec<u8>>
+{
+	Ok(MarkedPng::new(png_data, marker_chunk(lossless, quality, dithering_mode, reduction_pct, false, marker_text, timestamp))?.to_vec())
+}
+
+/// Offset of the IEND chunk, where the marker is inserted.
+fn find_iend(png_data: &[u8]) -> Result<usize>
+{
+	// Verify PNG signature.
+	if png_data.len() < 8 || &png_data[0..8] != PNG_SIGNATURE
+	{
+		return Err(MinifyError::InvalidSignature);
+	}
+	
+	let mut pos = 8;
+	while pos + 12 <= png_data.len()
+	{
+		let length: usize = u32::from_be_bytes([png_data[pos], png_data[pos + 1], png_data[pos + 2], png_data[pos + 3]]) as usize;
+		
+		check_chunk_bounds(png_data, pos, length)?;
+		
+		if &png_data[pos + 4..pos + 8] == b"IEND"
+		{
+			return Ok(pos);
+		}
+		
+		pos += 12 + length;
+	}
+	
+	Err(MinifyError::Malformed("IEND chunk not found".to_string()))
+}
+
+/// Number of bytes after the IEND chunk (appended by faulty tools, or hidden data), 0 if there is no IEND chunk.
+pub fn trailing_data_len(png_data: &[u8]) -> Result<usize>
+{
+	let iend_pos: usize = match find_iend(png_data)
+	{
+		Ok(iend_pos) => iend_pos,
+		Err(MinifyError::Malformed(_)) => return Ok(0),
+		Err(e) => return Err(e),
+	};
+	
+	let length: usize = u32::from_be_bytes([png_data[iend_pos], png_data[iend_pos + 1], png_data[iend_pos + 2], png_data[iend_pos + 3]]) as usize;
+	Ok(png_data.len() - (iend_pos + 12 + length))
+}
+
+/// PNG data without the bytes after its IEND chunk.
+pub fn strip_trailing_data(png_data: &[u8]) -> Result<&[u8]>
+{
+	let trailing: usize = trailing_data_len(png_data)?;
+	Ok(&png_data[..png_data.len() - trailing])
+}
+
+/// Build the complete marker chunk (length, type, data and CRC) with minification info.
+fn marker_chunk(lossless: bool, quality: u8, dithering_mode: DitheringMode, reduction_pct: f64, noop: bool, marker_text: Option<&str>, timestamp: bool) -> Vec<u8>
+{
+	// Create our marker chunk with minification info.
+	let timestamp: Option<String> = if timestamp { time_utils::get_iso8601_timestamp() } else { None };
+	
+	// Get dithering mode name.
+	let dithering_name = dithering_mode_to_string(dithering_mode);
+	
+	let mut info_str = if lossless
+	{
+		format!("lossless=true,reduction={:.1}", reduction_pct)
+	}
+	else
+	{
+		format!("quality={},dithering={},lossless=false,reduction={:.1}", quality, dithering_name, reduction_pct)
+	};
+	
+	if noop
+	{
+		info_str.push_str(",noop=true");
+	}
+	
+	// The timestamp is left out where there is no system clock, and in deterministic mode.
+	if let Some(timestamp) = timestamp
+	{
+		info_str.push_str(&format!(",timestamp={}", timestamp));
+	}
+	
+	// Keywords are Latin-1: validated marker texts only hold characters up to U+00FF, one byte each.
+	let mut marker_bytes: Vec<u8> = match marker_text
+	{
+		Some(marker_text) =>
+		{
+			let mut bytes: Vec<u8> = Vec::with_capacity(marker_text.len());
+			for c in marker_text.chars()
+			{
+				bytes.push(c as u8);
+			}
+			bytes.push(0);
+			bytes.extend_from_slice(MARKER_SENTINEL.as_bytes());
+			bytes.push(b',');
+			bytes
+		},
+		None =>
+		{
+			let mut bytes: Vec<u8> = MARKER_KEYWORD.as_bytes().to_vec();
+			bytes.push(0);
+			bytes
+		},
+	};
+	marker_bytes.extend_from_slice(info_str.as_bytes());
+	let marker_bytes: &[u8] = &marker_bytes;
+	let marker_length: u32 = marker_bytes.len() as u32;
+	
+	// Calculate CRC for the chunk.
+	let mut crc_data = Vec::new();
+	crc_data.extend_from_slice(b"tEXt");
+	crc_data.extend_from_slice(marker_bytes);
+	let crc = crc_utils::hash(&crc_data);
+	
+	let mut chunk: Vec<u8> = Vec::with_capacity(12 + marker_bytes.len());
+	chunk.extend_from_slice(&marker_length.to_be_bytes());
+	chunk.extend_from_slice(b"tEXt");
+	chunk.extend_from_slice(marker_bytes);
+	chunk.extend_from_slice(&crc.to_be_bytes());
+	chunk
+}
+
+/// Apply color quantization with selectable dithering mode.
+/// For lossy minification, this reduces the color palette and applies the specified dithering algorithm.
+/// Takes ownership of the decoded RGBA buffer since it is modified in place before quantization.
+/// The quantized pixels are returned as they are, encode_quantized_png turns them into a PNG.
+fn quantize_pixels(mut rgba: image::RgbaImage, width: u32, height: u32, dithering_mode: DitheringMode, options: &MinifyOptions) -> Result<image::RgbaImage>
+{
+	// Drop the transparency first, so every later step sees the colors as they will be shown.
+	if let Some(background) = options.flatten
+	{
+		flatten_onto(&mut rgba, background);
+	}
+	
+	// Apply darkening BEFORE quantization.
+	apply_darkening(&mut rgba);
+	
+	// Premultiply before smoothing, so the blur weighs colors by their visibility.
+	if options.premultiply
+	{
+		premultiply_alpha(&mut rgba);
+	}
+	
+	// Apply Gaussian blur if smooth_radius > 0.
+	if options.smooth_radius > 0.0
+	{
+		// Apply Gaussian blur to smooth gradients before quantization.
+		// This reduces banding and makes "dithering none" mode work better.
+		rgba = image::imageops::blur(&image::DynamicImage::ImageRgba8(rgba), options.smooth_radius);
+	}
+	
+	// Determine downsampling factor based on quality.
+	let downsampling_factor: u8 = quality_downsampling_factor(options.quality);
+	
+	// Per-channel factors (R, G, B) replace the quality-derived factor when given.
+	let factors: [u8; 3] = options.channel_factors.unwrap_or([downsampling_factor; 3]);
+	
+	// Map onto the fixed palette when one is given, otherwise apply the selected dithering algorithm.
+	let mut quantized_img = if let Some(palette) = &options.fixed_palette
+	{
+		median::map_to_palette(&rgba, palette, dithering_mode == DitheringMode::FloydSteinberg)
+	}
+	else
+	{
+		match dithering_mode
+		{
+			DitheringMode::None =>
+			{
+				// No dithering - simple quantization produces cleanest results for gradients.
+				// May show banding in some cases, but avoids adding noise.
+				apply_no_dithering(&rgba, width, height, factors, options.quantize_alpha, options.linear_gamma())
+			},
+			
+			DitheringMode::FloydSteinberg =>
+			{
+				// Floyd-Steinberg dithering - distributes quantization error to neighboring pixels.
+				// Creates smooth gradients instead of harsh banding, excellent for photos.
+				match options.scan
+				{
+					ScanOrder::Serpentine => apply_floyd_steinberg_dithering(&rgba, width, height, factors, options.quantize_alpha, options.linear_gamma()),
+					ScanOrder::Hilbert => apply_hilbert_dithering(&rgba, width, height, factors, options.quantize_alpha, options.linear_gamma()),
+				}
+			},
+			
+			DitheringMode::Ordered =>
+			{
+				// Ordered (Bayer) dithering - uses a fixed pattern matrix.
+				// Balanced approach: less noisy than Floyd-Steinberg, better than none for photos.
+				apply_ordered_dithering(&rgba, width, height, factors, options.quantize_alpha, options.linear_gamma())
+			},
+			
+			DitheringMode::BlueNoise =>
+			{
+				// Blue-noise dithering - uses a tiled blue-noise threshold texture.
+				// Balanced like ordered dithering, without its visible cross-hatch pattern.
+				apply_blue_noise_dithering(&rgba, width, height, factors, options.quantize_alpha, options.linear_gamma())
+			},
+			
+			DitheringMode::MedianCut =>
+			{
+				// Median cut color quantization - classic algorithm with excellent palette quality.
+				// Fast and produces high-quality results.
+				apply_median_quantization(&rgba, factors[1], options)
+			},
+			
+			DitheringMode::Auto =>
+			{
+				// This should have been resolved earlier, but handle it just in case.
+				return Err(MinifyError::InvalidOptions("Auto dithering mode should be resolved before quantization".to_string()));
+			},
+		}
+	};
+	
+	if options.premultiply
+	{
+		unpremultiply_alpha(&mut quantized_img);
+	}
+	
+	// Convert the RgbaImage back to DynamicImage.
+	let mut dynamic_img = image::DynamicImage::ImageRgba8(quantized_img);
+	
+	// Apply selective denoising if enabled (not with a fixed palette, since the filtered colors could fall outside it).
+	if options.denoise && options.fixed_palette.is_none()
+	{
+		dynamic_img = apply_selective_denoising(&dynamic_img, options.denoise_strength, options.denoise_block);
+	}
+	
+	// Collapse alpha to fully transparent or fully opaque if requested.
+	if let Some(threshold) = options.alpha_threshold
+	{
+		let mut binary_alpha_img: image::RgbaImage = dynamic_img.to_rgba8();
+		apply_binary_alpha(&mut binary_alpha_img, threshold, options.dither_alpha);
+		dynamic_img = image::DynamicImage::ImageRgba8(binary_alpha_img);
+	}
+	
+	// Forced grayscale changes the colors, so it is applied to the pixels and not only to the color type of the PNG.
+	if options.grayscale
+	{
+		dynamic_img = image::DynamicImage::ImageLumaA8(dynamic_img.to_luma_alpha8());
+	}
+	
+	Ok(dynamic_img.into_rgba8())
+}
+
+/// Encode quantized pixels as a PNG, palettized or grayscale when the options and the colors allow it.
+/// With keep_bit_depth the PNG keeps the color type and bit depth the source was decoded with (source_color) instead.
+fn encode_quantized_png(quantized: image::RgbaImage, source_color: image::ColorType, options: &MinifyOptions) -> Result<Vec<u8>>
+{
+	// Write a palettized PNG when requested and the image fits in a palette.
+	if options.indexed && let Some(buffer) = palette::encode_indexed_png(&quantized, options)?
+	{
+		return Ok(buffer);
+	}
+	
+	// Store gray images (including forced grayscale) with a grayscale color type instead of RGBA.
+	let (is_gray, is_opaque): (bool, bool) = analyze_grayscale(&quantized);
+	let mut dynamic_img = image::DynamicImage::ImageRgba8(quantized);
+	if options.keep_bit_depth
+	{
+		dynamic_img = convert_to_color(dynamic_img, source_color);
+	}
+	else if is_gray
+	{
+		dynamic_img = if is_opaque
+		{
+			image::DynamicImage::ImageLuma8(dynamic_img.to_luma8())
+		}
+		else
+		{
+			image::DynamicImage::ImageLumaA8(dynamic_img.to_luma_alpha8())
+		};
+	}
+	
+	// Encode the image back to PNG.
+	let mut buffer = Vec::new();
+	{
+		let mut cursor = Cursor::new(&mut buffer);
+		dynamic_img.write_to(&mut cursor, ImageFormat::Png)
+			.map_err(|e| MinifyError::Encode(format!("Failed to encode quantized image: {}", e)))?;
+	}
+	
+	Ok(buffer)
+}
+
+/// Convert an image to the given color type and bit depth, such as the source's with keep_bit_depth.
+/// 8-bit values are scaled to the full 16-bit range, so the pixels stay the same.
+fn convert_to_color(image: image::DynamicImage, color: image::ColorType) -> image::DynamicImage
+{
+	match color
+	{
+		image::ColorType::L8 => image::DynamicImage::ImageLuma8(image.to_luma8()),
+		image::ColorType::La8 => image::DynamicImage::ImageLumaA8(image.to_luma_alpha8()),
+		image::ColorType::Rgb8 => image::DynamicImage::ImageRgb8(image.to_rgb8()),
+		image::ColorType::L16 => image::DynamicImage::ImageLuma16(image.to_luma16()),
+		image::ColorType::La16 => image::DynamicImage::ImageLumaA16(image.to_luma_alpha16()),
+		image::ColorType::Rgb16 => image::DynamicImage::ImageRgb16(image.to_rgb16()),
+		image::ColorType::Rgba16 => image::DynamicImage::ImageRgba16(image.to_rgba16()),
+		_ => image,
+	}
+}
+
+/// Collapse the alpha channel to 0 or 255.
+/// Alpha below the threshold becomes 0, at or above becomes 255.
+/// With dithering, the thresholding error is diffused to neighbors (Floyd-Steinberg) for smoother edges.
+fn apply_binary_alpha(rgba: &mut image::RgbaImage, threshold: u8, dither: bool)
+{
+	let (width, height): (u32, u32) = rgba.dimensions();
+	
+	if !dither
+	{
+		for pixel in rgba.pixels_mut()
+		{
+			pixel[3] = if pixel[3] < threshold { 0 } else { 255 };
+		}
+		return;
+	}
+	
+	// Working buffer for alpha error diffusion (can go below 0 or above 255).
+	let mut working_alpha: Vec<i16> = vec![0; (width * height) as usize];
+	for (x, y, pixel) in rgba.enumerate_pixels()
+	{
+		working_alpha[(y * width + x) as usize] = pixel[3] as i16;
+	}
+	
+	for y in 0..height
+	{
+		for x in 0..width
+		{
+			let index: usize = (y * width + x) as usize;
+			let old_alpha: i16 = working_alpha[index];
+			let new_alpha: i16 = if old_alpha < threshold as i16 { 0 } else { 255 };
+			let error: i16 = old_alpha - new_alpha;
+			
+			// Distribute error using Floyd-Steinberg weights (7/16, 3/16, 5/16, 1/16).
+			if x + 1 < width
+			{
+				working_alpha[index + 1] += (error * 7) / 16;
+			}
+			
+			if y + 1 < height
+			{
+				if x > 0
+				{
+					working_alpha[index + width as usize - 1] += (error * 3) / 16;
+				}
+				
+				working_alpha[index + width as usize] += (error * 5) / 16;
+				
+				if x + 1 < width
+				{
+					working_alpha[index + width as usize + 1] += error / 16;
+				}
+			}
+			
+			rgba.get_pixel_mut(x, y)[3] = new_alpha as u8;
+		}
+	}
+}
+
+/// Check whether every pixel is gray (r == g == b) and whether the image is fully opaque.
+/// Returns (is_gray, is_opaque).
+fn analyze_grayscale(rgba: &image::RgbaImage) -> (bool, bool)
+{
+	let mut is_gray: bool = true;
+	let mut is_opaque: bool = true;
+	
+	for pixel in rgba.pixels()
+	{
+		if pixel[0] != pixel[1] || pixel[1] != pixel[2]
+		{
+			is_gray = false;
+		}
+		
+		if pixel[3] != 255
+		{
+			is_opaque = false;
+		}
+		
+		if !is_gray && !is_opaque
+		{
+			break;
+		}
+	}
+	
+	(is_gray, is_opaque)
+}
+
+/// Composite every pixel onto an opaque background color (rounded), leaving a fully opaque image.
+fn flatten_onto(rgba: &mut image::RgbaImage, background: [u8; 3])
+{
+	for pixel in rgba.pixels_mut()
+	{
+		let alpha: u32 = pixel[3] as u32;
+		for i in 0..3
+		{
+			pixel[i] = ((pixel[i] as u32 * alpha + background[i] as u32 * (255 - alpha) + 127) / 255) as u8;
+		}
+		pixel[3] = 255;
+	}
+}
+
+/// Multiply the RGB channels by alpha / 255 (rounded), so fully transparent pixels become black.
+fn premultiply_alpha(rgba: &mut image::RgbaImage)
+{
+	for pixel in rgba.pixels_mut()
+	{
+		let alpha: u16 = pixel[3] as u16;
+		for i in 0..3
+		{
+			pixel[i] = ((pixel[i] as u16 * alpha + 127) / 255) as u8;
+		}
+	}
+}
+
+/// Divide the RGB channels by alpha / 255 again (rounded and clamped), reversing premultiply_alpha.
+/// Fully transparent pixels have no color left to recover and stay black.
+fn unpremultiply_alpha(rgba: &mut image::RgbaImage)
+{
+	for pixel in rgba.pixels_mut()
+	{
+		let alpha: u16 = pixel[3] as u16;
+		for i in 0..3
+		{
+			pixel[i] = (pixel[i] as u16 * 255 + alpha / 2).checked_div(alpha).map_or(0, |value: u16| value.min(255) as u8);
+		}
+	}
+}
+
+/// Apply selective darkening to the image before quantization.
+/// This preserves compression patterns while making the image darker.
+fn apply_darkening(rgba: &mut image::RgbaImage)
+{
+	for pixel in rgba.pixels_mut()
+	{
+		// Selectively darken RGB channels based on brightness (skip alpha).
+		for i in 0..3
+		{
+			let value = pixel[i];
+			
+			pixel[i] = if value < 16
+			{
+				// Shadows: darken by 20%.
+				(value as f32 * 0.8) as u8
+			}
+			else if value < 32
+			{
+				// Lighter shadows: darken by 10%.
+				(value as f32 * 0.9) as u8
+			}
+			else
+			{
+				// Highlights: keep unchanged.
+				value
+			};
+		}
+	}
+}
+
+/// Apply simple quantization without dithering.
+/// Cleanest for gradients and UI elements, but may show banding.
+/// Uses parallel processing for improved performance.
+#[allow(clippy::type_complexity)]
+fn apply_no_dithering(rgba: &image::RgbaImage, width: u32, height: u32, factors: [u8; 3], quantize_alpha: bool, linear: Option<f32>) -> image::RgbaImage
+{
+	let mut quantized_img = image::RgbaImage::new(width, height);
+	let quantize = |value: i16, factor: u8| quantize_channel_in(value, factor, linear);
+	
+	// Process rows in parallel for better performance.
+	let rows: Vec<(u32, Vec<(u32, image::Rgba<u8>)>)> = par_utils::map_rows(height, |y|
+	{
+		let mut row_pixels = Vec::with_capacity(width as usize);
+		
+		for x in 0..width
+		{
+			let pixel = rgba.get_pixel(x, y);
+			let r = quantize(pixel[0] as i16, factors[0]);
+			let g = quantize(pixel[1] as i16, factors[1]);
+			let b = quantize(pixel[2] as i16, factors[2]);
+			let a = if quantize_alpha { quantize_alpha_channel(pixel[3] as i16, alpha_factor(factors)) } else { pixel[3] };
+			row_pixels.push((x, image::Rgba([r, g, b, a])));
+		}
+		
+		(y, row_pixels)
+	});
+	
+	// Write all rows to the output image.
+	for (y, row_pixels) in rows
+	{
+		for (x, pixel) in row_pixels
+		{
+			quantized_img.put_pixel(x, y, pixel);
+		}
+	}
+	
+	quantized_img
+}
+
+/// Apply Floyd-Steinberg dithering.
+/// This distributes quantization error to neighboring pixels for smoother gradients.
+#[allow(clippy::identity_op, clippy::needless_range_loop)]
+fn apply_floyd_steinberg_dithering(rgba: &image::RgbaImage, width: u32, height: u32, factors: [u8; 3], quantize_alpha: bool, linear: Option<f32>) -> image::RgbaImage
+{
+	// Create a working buffer with i16 to handle error diffusion (can be negative).
+	// In linear mode, RGB is stored as linear light so the error diffuses in physical intensity.
+	let initial_buffer = vec![vec![[0i16; 4]; width as usize]; height as usize];
+	let mut working_buffer: Vec<Vec<[i16; 4]>> = initial_buffer;
+	for (x, y, pixel) in rgba.enumerate_pixels()
+	{
+		working_buffer[y as usize][x as usize] = [encode_working_channel(pixel[0], linear), encode_working_channel(pixel[1], linear), encode_working_channel(pixel[2], linear), pixel[3] as i16];
+	}
+	
+	let quantize = |value: i16, factor: u8| quantize_channel_in(value, factor, linear);
+	
+	// Apply Floyd-Steinberg dithering with serpentine scanning and reduced error.
+	// Serpentine: alternating left-to-right and right-to-left scan eliminates "worms".
+	// Reduced error (7/8 factor): softer, smoother gradients with less visible noise.
+	const ERROR_REDUCTION: i16 = 7; // 7/8 = 0.875 error reduction factor.
+	const ERROR_DIVISOR: i16 = 8;
+	
+	for y in 0..height as usize
+	{
+		// Serpentine: even rows go left-to-right, odd rows go right-to-left.
+		let is_forward = y % 2 == 0;
+		let mut x_range: Vec<usize> = Vec::with_capacity(width as usize);
+		if is_forward
+		{
+			for x in 0..width as usize
+			{
+				x_range.push(x);
+			}
+		}
+		else
+		{
+			for x in (0..width as usize).rev()
+			{
+				x_range.push(x);
+			}
+		};
+		
+		for x in x_range
+		{
+			let old_pixel = working_buffer[y][x];
+			
+			// Quantize RGB channels (alpha only when requested).
+			let r = quantize(decode_working_channel(old_pixel[0], linear), factors[0]);
+			let g = quantize(decode_working_channel(old_pixel[1], linear), factors[1]);
+			let b = quantize(decode_working_channel(old_pixel[2], linear), factors[2]);
+			let a = if quantize_alpha { quantize_alpha_channel(old_pixel[3], alpha_factor(factors)) } else { old_pixel[3].clamp(0, 255) as u8 };
+			let new_pixel = [r, g, b, a];
+			
+			// Calculate quantization error for each channel (alpha error is zero unless alpha is quantized).
+			let alpha_error = if quantize_alpha { old_pixel[3] - new_pixel[3] as i16 } else { 0 };
+			let new_working = [encode_working_channel(r, linear), encode_working_channel(g, linear), encode_working_channel(b, linear), a as i16];
+			let error = [old_pixel[0] - new_working[0], old_pixel[1] - new_working[1], old_pixel[2] - new_working[2], alpha_error];
+			
+			// Reduce error to create softer gradients (7/8 of original error).
+			let error =
+			[
+				(error[0] * ERROR_REDUCTION) / ERROR_DIVISOR,
+				(error[1] * ERROR_REDUCTION) / ERROR_DIVISOR,
+				(error[2] * ERROR_REDUCTION) / ERROR_DIVISOR,
+				(error[3] * ERROR_REDUCTION) / ERROR_DIVISOR
+			];
+			
+			// Distribute error using Floyd-Steinberg dithering.
+			// Current pixel = [X], fractions show error distribution:
+			//
+			//  Left-to-right scan:        Right-to-left scan:
+			//  ┌────────┬────────┐        ┌────────┬────────┐
+			//  │   [X]  │   7/16 │        │   7/16 │   [X]  │
+			//  ├────────┼────────┤        ├────────┼────────┤
+			//  │   3/16 │   5/16 │ 1/16   │ 1/16   │   5/16 │ 3/16
+			//  └────────┴────────┘        └────────┴────────┘
+			//      (row n)                      (row n)
+			//  └─────────────────┘        └─────────────────┘
+			//      (row n+1)                    (row n+1)
+			//
+			
+			if is_forward
+			{
+				// Forward scan (left to right).
+				// Right pixel (x+1, y).
+				if x + 1 < width as usize
+				{
+					for c in 0..4
+					{
+						working_buffer[y][x + 1][c] += (error[c] * 7) / 16;
+					}
+				}
+				
+				// Bottom-left pixel (x-1, y+1).
+				if y + 1 < height as usize && x > 0
+				{
+					for c in 0..4
+					{
+						working_buffer[y + 1][x - 1][c] += (error[c] * 3) / 16;
+					}
+				}
+				
+				// Bottom pixel (x, y+1).
+				if y + 1 < height as usize
+				{
+					for c in 0..4
+					{
+						working_buffer[y + 1][x][c] += (error[c] * 5) / 16;
+					}
+				}
+				
+				// Bottom-right pixel (x+1, y+1).
+				if y + 1 < height as usize && x + 1 < width as usize
+				{
+					for c in 0..4
+					{
+						working_buffer[y + 1][x + 1][c] += (error[c] * 1) / 16;
+					}
+				}
+			}
+			else
+			{
+				// Reverse scan (right to left).
+				// Left pixel (x-1, y).
+				if x > 0
+				{
+					for c in 0..4
+					{
+						working_buffer[y][x - 1][c] += (error[c] * 7) / 16;
+					}
+				}
+				
+				// Bottom-right pixel (x+1, y+1).
+				if y + 1 < height as usize && x + 1 < width as usize
+				{
+					for c in 0..4
+					{
+						working_buffer[y + 1][x + 1][c] += (error[c] * 3) / 16;
+					}
+				}
+				
+				// Bottom pixel (x, y+1).
+				if y + 1 < height as usize
+				{
+					for c in 0..4
+					{
+						working_buffer[y + 1][x][c] += (error[c] * 5) / 16;
+					}
+				}
+				
+				// Bottom-left pixel (x-1, y+1).
+				if y + 1 < height as usize && x > 0
+				{
+					for c in 0..4
+					{
+						working_buffer[y + 1][x - 1][c] += (error[c] * 1) / 16;
+					}
+				}
+			}
+			
+			// Write quantized pixel back.
+			working_buffer[y][x] = new_working;
+		}
+	}
+	
+	// Convert working buffer back to image.
+	let mut quantized_img = image::RgbaImage::new(width, height);
+	for y in 0..height as usize
+	{
+		for x in 0..width as usize
+		{
+			let pixel = working_buffer[y][x];
+			let r = decode_working_channel(pixel[0], linear).clamp(0, 255) as u8;
+			let g = decode_working_channel(pixel[1], linear).clamp(0, 255) as u8;
+			let b = decode_working_channel(pixel[2], linear).clamp(0, 255) as u8;
+			let a = pixel[3].clamp(0, 255) as u8;
+			quantized_img.put_pixel(x as u32, y as u32, image::Rgba([r, g, b, a]));
+		}
+	}
+	
+	quantized_img
+}
+
+/// Number of recently visited pixels whose error is spread along the Hilbert curve.
+const HILBERT_HISTORY: usize = 16;
+
+/// Ratio between the weights of the newest and the oldest error in the history.
+const HILBERT_WEIGHT_RATIO: f32 = 16.0;
+
+/// Apply error diffusion along a Hilbert curve (after Riemersma's dithering).
+/// Each pixel receives a weighted share of the errors of the last HILBERT_HISTORY pixels on the curve, the most recent
+/// weighing most, so the error goes to spatial neighbors without the directional artifacts of row scans.
+/// Uses the same 7/8 error reduction and linear-light option as Floyd-Steinberg.
+fn apply_hilbert_dithering(rgba: &image::RgbaImage, width: u32, height: u32, factors: [u8; 3], quantize_alpha: bool, linear: Option<f32>) -> image::RgbaImage
+{
+	const ERROR_REDUCTION: f32 = 7.0 / 8.0;
+	
+	let quantize = |value: i16, factor: u8| quantize_channel_in(value, factor, linear);
+	
+	// Weights grow exponentially from the oldest error to the newest, and add up to one so the error is preserved.
+	let mut weights: [f32; HILBERT_HISTORY] = [0.0; HILBERT_HISTORY];
+	let mut weight_sum: f32 = 0.0;
+	for (index, weight) in weights.iter_mut().enumerate()
+	{
+		*weight = HILBERT_WEIGHT_RATIO.powf(index as f32 / (HILBERT_HISTORY - 1) as f32);
+		weight_sum += *weight;
+	}
+	for weight in &mut weights
+	{
+		*weight = *weight / weight_sum * ERROR_REDUCTION;
+	}
+	
+	// Errors of the last pixels on the curve, oldest first.
+	let mut history: std::collections::VecDeque<[f32; 4]> = std::collections::VecDeque::from(vec![[0.0; 4]; HILBERT_HISTORY]);
+	let mut quantized_img = image::RgbaImage::new(width, height);
+	for (x, y) in hilbert::hilbert_order(width, height)
+	{
+		let pixel = rgba.get_pixel(x, y);
+		let mut diffused: [f32; 4] = [0.0; 4];
+		for (error, weight) in history.iter().zip(weights.iter())
+		{
+			for c in 0..4
+			{
+				diffused[c] += error[c] * weight;
+			}
+		}
+		
+		let source: [i16; 4] = [encode_working_channel(pixel[0], linear), encode_working_channel(pixel[1], linear), encode_working_channel(pixel[2], linear), pixel[3] as i16];
+		let alpha_diffused: i16 = if quantize_alpha { diffused[3].round() as i16 } else { 0 };
+		let adjusted: [i16; 4] = [source[0] + diffused[0].round() as i16, source[1] + diffused[1].round() as i16, source[2] + diffused[2].round() as i16, source[3] + alpha_diffused];
+		
+		// Quantize RGB channels (alpha only when requested).
+		let r = quantize(decode_working_channel(adjusted[0], linear), factors[0]);
+		let g = quantize(decode_working_channel(adjusted[1], linear), factors[1]);
+		let b = quantize(decode_working_channel(adjusted[2], linear), factors[2]);
+		let a = if quantize_alpha { quantize_alpha_channel(adjusted[3], alpha_factor(factors)) } else { pixel[3] };
+		quantized_img.put_pixel(x, y, image::Rgba([r, g, b, a]));
+		
+		// Remember this pixel's error (alpha error is zero unless alpha is quantized).
+		let new_working: [i16; 4] = [encode_working_channel(r, linear), encode_working_channel(g, linear), encode_working_channel(b, linear), a as i16];
+		let alpha_error: f32 = if quantize_alpha { (adjusted[3] - new_working[3]) as f32 } else { 0.0 };
+		history.pop_front();
+		history.push_back([(adjusted[0] - new_working[0]) as f32, (adjusted[1] - new_working[1]) as f32, (adjusted[2] - new_working[2]) as f32, alpha_error]);
+	}
+	
+	quantized_img
+}
+
+/// Apply ordered (Bayer) dithering.
+/// Balanced approach: less noisy than Floyd-Steinberg, better than none for photos.
+/// Uses parallel processing for improved performance.
+#[allow(clippy::type_complexity)]
+fn apply_ordered_dithering(rgba: &image::RgbaImage, width: u32, height: u32, factors: [u8; 3], quantize_alpha: bool, linear: Option<f32>) -> image::RgbaImage
+{
+	// 4x4 Bayer matrix for ordered dithering.
+	// Centered around zero to avoid brightness bias.
+	const BAYER_MATRIX: [[i16; 4]; 4] =
+	[
+		[-8,  0, -6,  2],
+		[ 4, -4,  6, -2],
+		[-5,  3, -7,  1],
+		[ 7, -1,  5, -3],
+	];
+	
+	let mut result = image::RgbaImage::new(width, height);
+	let quantize = |value: i16, factor: u8| quantize_channel_in(value, factor, linear);
+	
+	// Process rows in parallel.
+	let rows: Vec<(u32, Vec<(u32, image::Rgba<u8>)>)> = par_utils::map_rows(height, |y|
+	{
+		let mut row_pixels = Vec::with_capacity(width as usize);
+		
+		for x in 0..width
+		{
+			let pixel = rgba.get_pixel(x, y);
+			
+			// Get threshold from Bayer matrix.
+			let threshold = BAYER_MATRIX[y as usize % 4][x as usize % 4];
+			
+			// Scale threshold based on each channel's downsampling factor.
+			let scale = |factor: u8| (threshold * factor as i16) / 32;
+			
+			let r = quantize(pixel[0] as i16 + scale(factors[0]), factors[0]);
+			let g = quantize(pixel[1] as i16 + scale(factors[1]), factors[1]);
+			let b = quantize(pixel[2] as i16 + scale(factors[2]), factors[2]);
+			let a = if quantize_alpha && pixel[3] < 255 { quantize_alpha_channel(pixel[3] as i16 + scale(alpha_factor(factors)), alpha_factor(factors)) } else { pixel[3] };
+			row_pixels.push((x, image::Rgba([r, g, b, a])));
+		}
+		
+		(y, row_pixels)
+	});
+	
+	// Write all rows to the output image.
+	for (y, row_pixels) in rows
+	{
+		for (x, pixel) in row_pixels
+		{
+			result.put_pixel(x, y, pixel);
+		}
+	}
+	
+	result
+}
+
+/// Apply blue-noise dithering.
+/// Works like ordered dithering, but the thresholds come from a tiled blue-noise texture instead of a Bayer matrix.
+/// Uses parallel processing for improved performance.
+#[allow(clippy::type_complexity)]
+fn apply_blue_noise_dithering(rgba: &image::RgbaImage, width: u32, height: u32, factors: [u8; 3], quantize_alpha: bool, linear: Option<f32>) -> image::RgbaImage
+{
+	let mut result = image::RgbaImage::new(width, height);
+	let quantize = |value: i16, factor: u8| quantize_channel_in(value, factor, linear);
+	
+	// Process rows in parallel.
+	let rows: Vec<(u32, Vec<(u32, image::Rgba<u8>)>)> = par_utils::map_rows(height, |y|
+	{
+		let mut row_pixels = Vec::with_capacity(width as usize);
+		
+		for x in 0..width
+		{
+			let pixel = rgba.get_pixel(x, y);
+			
+			// Get threshold from the blue-noise texture (-128 to 127).
+			let threshold = blue_noise::blue_noise_threshold(x, y);
+			
+			// Scale threshold based on each channel's downsampling factor (same amplitude as the Bayer matrix).
+			let scale = |factor: u8| (threshold * factor as i16) / 512;
+			
+			let r = quantize(pixel[0] as i16 + scale(factors[0]), factors[0]);
+			let g = quantize(pixel[1] as i16 + scale(factors[1]), factors[1]);
+			let b = quantize(pixel[2] as i16 + scale(factors[2]), factors[2]);
+			let a = if quantize_alpha && pixel[3] < 255 { quantize_alpha_channel(pixel[3] as i16 + scale(alpha_factor(factors)), alpha_factor(factors)) } else { pixel[3] };
+			row_pixels.push((x, image::Rgba([r, g, b, a])));
+		}
+		
+		(y, row_pixels)
+	});
+	
+	// Write all rows to the output image.
+	for (y, row_pixels) in rows
+	{
+		for (x, pixel) in row_pixels
+		{
+			result.put_pixel(x, y, pixel);
+		}
+	}
+	
+	result
+}
+
+/// Downsampling factor for the alpha channel: the finest of the color factors.
+fn alpha_factor(factors: [u8; 3]) -> u8
+{
+	factors[0].min(factors[1]).min(factors[2])
+}
+
+/// Number of steps between 0 and 255 on the quantization grid of a downsampling factor.
+/// The grid has steps + 1 evenly spaced levels that always include both 0 and 255,
+/// so the step is 255 / steps (close to the factor) rather than the factor itself.
+fn quantization_steps(factor: u8) -> i32
+{
+	let factor: i32 = factor.max(1) as i32;
+	((255 + factor / 2) / factor).max(1)
+}
+
+/// Value of a level on the quantization grid, rounded to the nearest integer.
+fn quantization_level(index: i32, steps: i32) -> u8
+{
+	((index * 255 + steps / 2) / steps) as u8
+}
+
+/// Quantize a single color channel to the nearest level of the grid.
+/// Each level's bucket is centered on it, so values round up as often as down and pure white (255) stays white.
+fn quantize_channel(value: i16, factor: u8) -> u8
+{
+	let clamped: i32 = value.clamp(0, 255) as i32;
+	let steps: i32 = quantization_steps(factor);
+	let index: i32 = (clamped * steps + 127) / 255;
+	quantization_level(index, steps)
+}
+
+/// Quantize a single color channel, in linear light decoded with the given gamma in linear mode.
+fn quantize_channel_in(value: i16, factor: u8, linear: Option<f32>) -> u8
+{
+	match linear
+	{
+		Some(gamma) => quantize_channel_linear(value, factor, gamma),
+		None => quantize_channel(value, factor),
+	}
+}
+
+/// Quantize a single color channel to the nearest level in linear light.
+/// Uses the same grid as quantize_channel but avoids the darkening bias of rounding gamma-encoded values.
+fn quantize_channel_linear(value: i16, factor: u8, gamma: f32) -> u8
+{
+	let clamped: i32 = value.clamp(0, 255) as i32;
+	let steps: i32 = quantization_steps(factor);
+	let lower_index: i32 = clamped * steps / 255;
+	let lower: u8 = quantization_level(lower_index, steps);
+	let upper: u8 = quantization_level((lower_index + 1).min(steps), steps);
+	
+	let value_linear: f32 = to_linear(clamped as u8, gamma);
+	let lower_distance: f32 = value_linear - to_linear(lower, gamma);
+	let upper_distance: f32 = to_linear(upper, gamma) - value_linear;
+	if lower_distance <= upper_distance
+	{
+		lower
+	}
+	else
+	{
+		upper
+	}
+}
+
+/// Scale for linear light values in the Floyd-Steinberg working buffer (12-bit precision).
+const LINEAR_WORKING_SCALE: f32 = 4095.0;
+
+/// Convert a channel value to the Floyd-Steinberg working representation.
+fn encode_working_channel(value: u8, linear: Option<f32>) -> i16
+{
+	match linear
+	{
+		Some(gamma) => (to_linear(value, gamma) * LINEAR_WORKING_SCALE).round() as i16,
+		None => value as i16,
+	}
+}
+
+/// Convert a Floyd-Steinberg working value back to a channel value (may be out of range when not linear).
+fn decode_working_channel(value: i16, linear: Option<f32>) -> i16
+{
+	match linear
+	{
+		Some(gamma) => from_linear(value as f32 / LINEAR_WORKING_SCALE, gamma) as i16,
+		None => value,
+	}
+}
+
+/// Quantize the alpha channel like a color channel.
+/// Fully opaque pixels stay fully opaque, even when diffused error pushes them past 255.
+fn quantize_alpha_channel(value: i16, factor: u8) -> u8
+{
+	if value >= 255
+	{
+		return 255;
+	}
+	
+	quantize_channel(value, factor)
+}
+
+/// Apply selective denoising to remove dithering artifacts in gradient areas.
+/// Detects smooth gradient regions and applies noise removal while preserving edges.
+/// The strength (0.0-1.0) sets the noise level a block needs to be filtered (30 at 0.0, 15 at the default 0.5, 0 at 1.0),
+/// and strengths above 0.75 widen the median filter from 3x3 to 5x5.
+fn apply_selective_denoising(img: &image::DynamicImage, strength: f32, block_size: u32) -> image::DynamicImage
+{
+	let rgba = img.to_rgba8();
+	let (width, height) = img.dimensions();
+	let mut result = rgba.clone();
+	
+	let noise_threshold: f64 = 30.0 * (1.0 - strength as f64);
+	let filter_radius: u32 = if strength > 0.75 { 2 } else { 1 };
+	
+	// Process image in blocks to detect gradient vs detail regions.
+	for block_y in (0..height).step_by(block_size as usize)
+	{
+		for block_x in (0..width).step_by(block_size as usize)
+		{
+			let block_end_x = (block_x + block_size).min(width);
+			let block_end_y = (block_y + block_size).min(height);
+			
+			// Analyze this block to determine if it's a gradient area.
+			let (is_gradient, noise_level) = analyze_block(&rgba, block_x, block_y, block_end_x, block_end_y);
+			
+			// If it's a gradient with noise, apply selective median filter.
+			if is_gradient && noise_level > noise_threshold
+			{
+				apply_median_filter_to_block(&rgba, &mut result, block_x, block_y, block_end_x, block_end_y, filter_radius);
+			}
+		}
+	}
+	
+	image::DynamicImage::ImageRgba8(result)
+}
+
+/// Analyze a block to determine if it's a gradient area with noise.
+/// Returns (is_gradient, noise_level).
+fn analyze_block(rgba: &image::RgbaImage, start_x: u32, start_y: u32, end_x: u32, end_y: u32) -> (bool, f64)
+{
+	let mut edge_count = 0;
+	let mut total_pixels = 0;
+	let mut variance_sum = 0.0;
+	
+	// Calculate edge density and local variance.
+	for y in start_y..end_y
+	{
+		for x in start_x..end_x
+		{
+			if x > 0 && y > 0 && x < end_x.saturating_sub(1) && y < end_y.saturating_sub(1)
+			{
+				// Simple edge detection - check if pixel differs significantly from neighbors.
+				let center = rgba.get_pixel(x, y);
+				let right = rgba.get_pixel(x + 1, y);
+				let bottom = rgba.get_pixel(x, y + 1);
+				
+				let diff_h = pixel_diff(center, right);
+				let diff_v = pixel_diff(center, bottom);
+				
+				if diff_h > 30.0 || diff_v > 30.0
+				{
+					edge_count += 1;
+				}
+				
+				// Calculate local variance (noise indicator).
+				let neighbors =
+				[
+					rgba.get_pixel(x.saturating_sub(1), y),
+					rgba.get_pixel(x + 1, y),
+					rgba.get_pixel(x, y.saturating_sub(1)),
+					rgba.get_pixel(x, y + 1),
+				];
+				
+				let mut neighbor_diffs = 0.0;
+				for neighbor in neighbors
+				{
+					neighbor_diffs += pixel_diff(center, neighbor);
+				}
+				variance_sum += neighbor_diffs / 4.0;
+				total_pixels += 1;
+			}
+		}
+	}
+	
+	let edge_density = if total_pixels > 0 { edge_count as f64 / total_pixels as f64 } else { 0.0 };
+	let avg_variance = if total_pixels > 0 { variance_sum / total_pixels as f64 } else { 0.0 };
+	
+	// Gradient area: low edge density (<15%) but with some variance (noise from dithering).
+	let is_gradient = edge_density < 0.15 && avg_variance > 5.0;
+	
+	(is_gradient, avg_variance)
+}
+
+/// Calculate color difference between two pixels.
+fn pixel_diff(p1: &image::Rgba<u8>, p2: &image::Rgba<u8>) -> f64
+{
+	let dr = (p1[0] as i16 - p2[0] as i16).abs() as f64;
+	let dg = (p1[1] as i16 - p2[1] as i16).abs() as f64;
+	let db = (p1[2] as i16 - p2[2] as i16).abs() as f64;
+	(dr + dg + db) / 3.0
+}
+
+/// Apply a median filter (3x3 for radius 1, 5x5 for radius 2) to a block to remove dithering noise.
+fn apply_median_filter_to_block(source: &image::RgbaImage, dest: &mut image::RgbaImage, start_x: u32, start_y: u32, end_x: u32, end_y: u32, radius: u32)
+{
+	let reach: i32 = radius as i32;
+	for y in start_y..end_y
+	{
+		for x in start_x..end_x
+		{
+			if x >= radius && y >= radius && x + radius < end_x && y + radius < end_y
+			{
+				// Collect the neighborhood.
+				let mut r_values = Vec::new();
+				let mut g_values = Vec::new();
+				let mut b_values = Vec::new();
+				
+				for dy in -reach..=reach
+				{
+					for dx in -reach..=reach
+					{
+						let px = (x as i32 + dx) as u32;
+						let py = (y as i32 + dy) as u32;
+						let pixel = source.get_pixel(px, py);
+						r_values.push(pixel[0]);
+						g_values.push(pixel[1]);
+						b_values.push(pixel[2]);
+					}
+				}
+				
+				// Sort and get median.
+				r_values.sort();
+				g_values.sort();
+				b_values.sort();
+				
+				let middle: usize = r_values.len() / 2;
+				let median_r = r_values[middle];
+				let median_g = g_values[middle];
+				let median_b = b_values[middle];
+				
+				let alpha = source.get_pixel(x, y)[3];
+				dest.put_pixel(x, y, image::Rgba([median_r, median_g, median_b, alpha]));
+			}
+		}
+	}
+}
+
+/// Downsampling factor for a quality level.
+/// Higher quality = less downsampling.
+fn quality_downsampling_factor(quality: u8) -> u8
+{
+	match quality
+	{
+		0..=40 => 32,
+		41..=55 => 16,
 		56..=70 => 12,
 		_ => 8,
-	};
+	}
+}
+
+/// Number of median cut colors for a downsampling factor, unless a palette size was given.
+fn median_color_count(downsampling_factor: u8, options: &MinifyOptions) -> usize
+{
+	// Lower factor = more colors allowed.
+	match options.median_colors
+	{
+		Some(colors) => colors,
+		None => match downsampling_factor
+		{
+			32 => 128, // Most aggressive.
+			16 => 256, // Balanced.
+			12 => 512, // High quality.
+			_ => 1024, // Maximum quality.
+		},
+	}
+}
+
+/// Apply median cut color quantization (or octree quantization, see MinifyOptions::quantizer).
+/// Uses the classic median cut algorithm for excellent palette quality.
+fn apply_median_quantization(rgba: &image::RgbaImage, downsampling_factor: u8, options: &MinifyOptions) -> image::RgbaImage
+{
+	let max_colors: usize = median_color_count(downsampling_factor, options);
+	quantize_with_palette(rgba, max_colors, options).0
+}
+
+/// Reduce an image to at most max_colors colors with the selected quantizer, also returning the palette, most used color first.
+pub(crate) fn quantize_with_palette(rgba: &image::RgbaImage, max_colors: usize, options: &MinifyOptions) -> (image::RgbaImage, Vec<[u8; 4]>)
+{
+	match options.quantizer
+	{
+		Quantizer::Median => median::quantize_image_with_median_palette(rgba, max_colors, options.linear_gamma(), options.refine, options.sample_step),
+		Quantizer::Octree => octree::quantize_image_with_octree_palette(rgba, max_colors, options.linear_gamma(), options.refine, options.sample_step),
+	}
+}
+
+/// Build the palette median cut quantization (or the selected quantizer) would choose for a PNG with these options, most used color first.
+/// The image goes through the same preparation (flattening, darkening, premultiplying and smoothing) as in a median cut minification.
+/// With premultiply, the palette holds premultiplied colors, as chosen before they are divided by alpha again.
+pub fn median_cut_palette(png_data: &[u8], options: &MinifyOptions) -> Result<Vec<[u8; 4]>>
+{
+	let mut rgba: image::RgbaImage = image::load_from_memory(png_data)
+		.map_err(|e| MinifyError::Decode(format!("Failed to decode PNG: {}", e)))?
+		.into_rgba8();
+	
+	if let Some(background) = options.flatten
+	{
+		flatten_onto(&mut rgba, background);
+	}
+	apply_darkening(&mut rgba);
+	if options.premultiply
+	{
+		premultiply_alpha(&mut rgba);
+	}
+	if options.smooth_radius > 0.0
+	{
+		rgba = image::imageops::blur(&image::DynamicImage::ImageRgba8(rgba), options.smooth_radius);
+	}
+	
+	// Median cut uses the green factor when per-channel factors are given, as in quantize_pixels.
+	let quality: u8 = if options.quality == AUTO_QUALITY { dithering::recommend_quality(&rgba, rgba.width(), rgba.height()) } else { options.quality };
+	let downsampling_factor: u8 = options.channel_factors.map_or(quality_downsampling_factor(quality), |factors| factors[1]);
+	let max_colors: usize = median_color_count(downsampling_factor, options);
+	
+	let (_, palette): (image::RgbaImage, Vec<[u8; 4]>) = quantize_with_palette(&rgba, max_colors, options);
+	Ok(palette)
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+	use crate::test_utils;
+	
+	#[test]
+	fn quantize_channel_rounds_to_nearest_level()
+	{
+		assert_eq!(quantize_channel(0, 32), 0);
+		assert_eq!(quantize_channel(15, 32), 0);
+		assert_eq!(quantize_channel(16, 32), 32);
+		assert_eq!(quantize_channel(100, 32), 96);
+		assert_eq!(quantize_channel(250, 32), 255);
+		assert_eq!(quantize_channel(-20, 32), 0);
+		assert_eq!(quantize_channel(300, 32), 255);
+		assert_eq!(quantize_channel(77, 1), 77);
+	}
+	
+	#[test]
+	fn quantize_channel_keeps_white()
+	{
+		// The old integer grid capped factor 8 at 248 and factor 48 at 240.
+		assert_eq!(quantize_channel(255, 8), 255);
+		assert_eq!(quantize_channel(255, 48), 255);
+		assert_eq!(quantize_channel_linear(255, 48, SRGB_GAMMA), 255);
+	}
+	
+	#[test]
+	fn quantized_ramp_covers_both_endpoints()
+	{
+		for factor in [2u8, 8, 24, 32, 48, 64, 100, 128]
+		{
+			let levels: Vec<u8> = (0..=255).map(|value: i16| quantize_channel(value, factor)).collect();
+			assert_eq!(levels[0], 0, "factor {}", factor);
+			assert_eq!(levels[255], 255, "factor {}", factor);
+			
+			// Centered buckets: no value moves by more than half a step.
+			let half_step: f64 = 255.0 / quantization_steps(factor) as f64 / 2.0;
+			for (value, level) in levels.iter().enumerate()
+			{
+				assert!((*level as f64 - value as f64).abs() <= half_step + 0.5, "factor {} moved {} to {}", factor, value, level);
+			}
+		}
+	}
+	
+	#[test]
+	fn no_dithering_quantizes_each_channel()
+	{
+		let rgba: image::RgbaImage = test_utils::solid(4, 4, [100, 150, 200, 255]);
+		let quantized: image::RgbaImage = apply_no_dithering(&rgba, 4, 4, [32, 32, 32], false, None);
+		
+		assert_eq!(test_utils::distinct_colors(&quantized), vec![[96, 159, 191, 255]]);
+	}
+	
+	#[test]
+	fn no_dithering_uses_per_channel_factors()
+	{
+		let rgba: image::RgbaImage = test_utils::solid(2, 2, [100, 100, 100, 255]);
+		let quantized: image::RgbaImage = apply_no_dithering(&rgba, 2, 2, [8, 16, 64], false, None);
+		
+		assert_eq!(test_utils::distinct_colors(&quantized), vec![[104, 96, 128, 255]]);
+	}
+	
+	#[test]
+	fn ordered_dithering_keeps_values_on_the_grid()
+	{
+		let rgba: image::RgbaImage = test_utils::solid(8, 8, [128, 128, 128, 255]);
+		let quantized: image::RgbaImage = apply_ordered_dithering(&rgba, 8, 8, [32, 32, 32], false, None);
+		
+		assert_eq!(test_utils::distinct_colors(&quantized), vec![[128, 128, 128, 255]]);
+	}
+	
+	#[test]
+	fn ordered_dithering_mixes_neighboring_levels()
+	{
+		// 112 lies halfway between the levels 96 and 128, so the Bayer pattern picks each for half the pixels.
+		let rgba: image::RgbaImage = test_utils::solid(4, 4, [112, 112, 112, 255]);
+		let quantized: image::RgbaImage = apply_ordered_dithering(&rgba, 4, 4, [32, 32, 32], false, None);
+		
+		let low: usize = quantized.pixels().filter(|pixel| pixel.0 == [96, 96, 96, 255]).count();
+		let high: usize = quantized.pixels().filter(|pixel| pixel.0 == [128, 128, 128, 255]).count();
+		assert_eq!((low, high), (8, 8));
+	}
 	
-	// Apply the selected dithering algorithm.
-	let quantized_img = match dithering_mode
+	#[test]
+	fn floyd_steinberg_keeps_values_on_the_grid()
 	{
-		DitheringMode::None =>
-		{
-			// No dithering - simple quantization produces cleanest results for gradients.
-			// May show banding in some cases, but avoids adding noise.
-			apply_no_dithering(&rgba, width, height, downsampling_factor)
-		},
+		let rgba: image::RgbaImage = test_utils::solid(8, 8, [64, 128, 191, 255]);
+		let quantized: image::RgbaImage = apply_floyd_steinberg_dithering(&rgba, 8, 8, [32, 32, 32], false, None);
+		
+		assert_eq!(test_utils::distinct_colors(&quantized), vec![[64, 128, 191, 255]]);
+	}
+	
+	#[test]
+	fn floyd_steinberg_preserves_average_brightness()
+	{
+		let rgba: image::RgbaImage = test_utils::solid(16, 16, [112, 112, 112, 255]);
+		let quantized: image::RgbaImage = apply_floyd_steinberg_dithering(&rgba, 16, 16, [32, 32, 32], false, None);
 		
-		DitheringMode::FloydSteinberg =>
+		for pixel in quantized.pixels()
 		{
-			// Floyd-Steinberg dithering - distributes quantization error to neighboring pixels.
-			// Creates smooth gradients instead of harsh banding, excellent for photos.
-			apply_floyd_steinberg_dithering(&rgba, width, height, downsampling_factor)
-		},
+			assert!(pixel[0] == 96 || pixel[0] == 128, "unexpected level {}", pixel[0]);
+		}
+		
+		let average: f64 = quantized.pixels().map(|pixel| pixel[0] as f64).sum::<f64>() / 256.0;
+		assert!((average - 112.0).abs() < 4.0, "average {} drifted from 112", average);
+	}
+	
+	#[test]
+	fn hilbert_dithering_keeps_values_on_the_grid()
+	{
+		let rgba: image::RgbaImage = test_utils::solid(8, 8, [64, 128, 191, 255]);
+		let quantized: image::RgbaImage = apply_hilbert_dithering(&rgba, 8, 8, [32, 32, 32], false, None);
+		
+		assert_eq!(test_utils::distinct_colors(&quantized), vec![[64, 128, 191, 255]]);
+	}
+	
+	#[test]
+	fn hilbert_dithering_preserves_average_brightness()
+	{
+		let rgba: image::RgbaImage = test_utils::solid(16, 16, [112, 112, 112, 255]);
+		let quantized: image::RgbaImage = apply_hilbert_dithering(&rgba, 16, 16, [32, 32, 32], false, None);
 		
-		DitheringMode::Ordered =>
+		for pixel in quantized.pixels()
 		{
-			// Ordered (Bayer) dithering - uses a fixed pattern matrix.
-			// Balanced approach: less noisy than Floyd-Steinberg, better than none for photos.
-			apply_ordered_dithering(&rgba, width, height, downsampling_factor)
-		},
+			assert!(pixel[0] == 96 || pixel[0] == 128, "unexpected level {}", pixel[0]);
+		}
+		
+		let average: f64 = quantized.pixels().map(|pixel| pixel[0] as f64).sum::<f64>() / 256.0;
+		assert!((average - 112.0).abs() < 4.0, "average {} drifted from 112", average);
+	}
+	
+	#[test]
+	fn dithering_keeps_alpha_unless_quantized()
+	{
+		let rgba: image::RgbaImage = test_utils::solid(4, 4, [100, 100, 100, 77]);
+		
+		let kept: image::RgbaImage = apply_no_dithering(&rgba, 4, 4, [32, 32, 32], false, None);
+		assert!(kept.pixels().all(|pixel| pixel[3] == 77));
+		
+		let quantized: image::RgbaImage = apply_no_dithering(&rgba, 4, 4, [32, 32, 32], true, None);
+		assert!(quantized.pixels().all(|pixel| pixel[3] == 64));
+	}
+	
+	#[test]
+	fn list_chunks_reports_structure()
+	{
+		let png_data: Vec<u8> = test_utils::encode_png(&test_utils::gradient(32, 8));
+		let chunks: Vec<(String, usize)> = list_chunks(&png_data).expect("Chunk walk failed");
+		let names: Vec<&str> = chunks.iter().map(|(name, _)| name.as_str()).collect();
+		
+		assert_eq!(names.first(), Some(&"IHDR"));
+		assert_eq!(names.last(), Some(&"IEND"));
+		assert_eq!(names.iter().filter(|name| **name == "IDAT").count(), 1);
+		assert_eq!(chunks[0].1, 13);
+		assert_eq!(chunks[chunks.len() - 1].1, 0);
+		
+		// The entries account for every byte after the signature (12 bytes of framing per chunk).
+		let idat_size: usize = chunks.iter().find(|(name, _)| name == "IDAT").map_or(0, |(_, size)| *size);
+		assert!(idat_size > 0 && idat_size < png_data.len());
+		assert!(chunks.iter().map(|(_, size)| size + 12).sum::<usize>() <= png_data.len() - 8);
+	}
+	
+	#[test]
+	fn max_pixels_is_checked_against_ihdr()
+	{
+		let png_data: Vec<u8> = test_utils::encode_png(&test_utils::solid(40, 25, [1, 2, 3, 255]));
+		assert_eq!(png_dimensions(&png_data).expect("Missing IHDR"), (40, 25));
 		
-		DitheringMode::MedianCut =>
+		let limit = |max_pixels: Option<u64>| -> bool
 		{
-			// Median cut color quantization - classic algorithm with excellent palette quality.
-			// Fast and produces high-quality results.
-			apply_median_quantization(&rgba, downsampling_factor)
-		},
+			let options: MinifyOptions = MinifyOptions::builder().max_pixels(max_pixels).build().expect("Valid options");
+			exceeds_max_pixels(&png_data, &options).expect("Missing IHDR")
+		};
+		assert!(!limit(None));
+		assert!(!limit(Some(1000)));
+		assert!(limit(Some(999)));
+	}
+	
+	#[test]
+	fn interlace_mode_sets_output_interlacing()
+	{
+		let source: Vec<u8> = test_utils::encode_png(&test_utils::noisy_ramps(128, 64));
+		assert!(!is_interlaced(&source).expect("Missing IHDR"));
 		
-		DitheringMode::Auto =>
+		let minify = |png_data: &[u8], lossless: bool, interlace: InterlaceMode| -> Vec<u8>
 		{
-			// This should have been resolved earlier, but handle it just in case.
-			return Err(anyhow!("Auto dithering mode should be resolved before quantization"));
-		},
-	};
+			let builder: MinifyOptionsBuilder = MinifyOptions::builder().lossless(lossless).dithering(DitheringMode::None).fast_deflate(true).interlace(interlace);
+			let (output, _, _, _): (Vec<u8>, DitheringMode, u8, Option<ImageClass>) = minify_bytes(png_data, &builder.build().expect("Valid options")).expect("Minification failed");
+			output
+		};
+		
+		let interlaced: Vec<u8> = minify(&source, true, InterlaceMode::Adam7);
+		assert!(is_interlaced(&interlaced).expect("Missing IHDR"));
+		assert_eq!(test_utils::decode_png(&interlaced), test_utils::decode_png(&source));
+		
+		// The lossy path re-encodes the quantized pixels, so keeping the interlacing has to carry it over explicitly.
+		assert!(is_interlaced(&minify(&interlaced, false, InterlaceMode::Keep)).expect("Missing IHDR"));
+		assert!(!is_interlaced(&minify(&interlaced, false, InterlaceMode::None)).expect("Missing IHDR"));
+		assert!(!is_interlaced(&minify(&source, false, InterlaceMode::Keep)).expect("Missing IHDR"));
+	}
 	
-	// Convert the RgbaImage back to DynamicImage.
-	let mut dynamic_img = image::DynamicImage::ImageRgba8(quantized_img);
+	#[test]
+	fn lossless_round_trip_keeps_pixels()
+	{
+		let rgba: image::RgbaImage = test_utils::gradient(64, 16);
+		let options: MinifyOptions = MinifyOptions::builder().lossless(true).fast_deflate(true).build().expect("Valid options");
+		
+		let (output, _, _, _): (Vec<u8>, DitheringMode, u8, Option<ImageClass>) = minify_bytes(&test_utils::encode_png(&rgba), &options).expect("Minification failed");
+		
+		assert_eq!(test_utils::decode_png(&output), rgba);
+	}
 	
-	// Apply selective denoising if enabled.
-	if denoise
+	#[test]
+	fn lossy_round_trip_keeps_dimensions_and_marks_output()
 	{
-		dynamic_img = apply_selective_denoising(&dynamic_img);
+		let rgba: image::RgbaImage = test_utils::noisy_ramps(128, 64);
+		let options: MinifyOptions = MinifyOptions::builder().quality(40).dithering(DitheringMode::None).fast_deflate(true).build().expect("Valid options");
+		
+		let (output, dithering_mode, quality, _): (Vec<u8>, DitheringMode, u8, Option<ImageClass>) = minify_bytes(&test_utils::encode_png(&rgba), &options).expect("Minification failed");
+		
+		assert_eq!(test_utils::decode_png(&output).dimensions(), (128, 64));
+		assert_eq!((dithering_mode, quality), (DitheringMode::None, 40));
+		assert!(is_already_minified(&output).expect("Marker check failed").0);
 	}
 	
-	// Encode the image back to PNG.
-	let mut buffer = Vec::new();
+	#[test]
+	fn quality_per_type_follows_the_image_class()
 	{
-		let mut cursor = Cursor::new(&mut buffer);
-		dynamic_img.write_to(&mut cursor, ImageFormat::Png)
-			.map_err(|e| anyhow!("Failed to encode quantized image: {}", e))?;
+		let qualities: QualityPerType = QualityPerType::parse("photo=70,flat=35").expect("Valid mapping");
+		let options: MinifyOptions = MinifyOptions::builder().quality(50).quality_per_type(Some(qualities)).dithering(DitheringMode::None).fast_deflate(true).build().expect("Valid options");
+		
+		let (_, _, gradient_quality, gradient_class): (Vec<u8>, DitheringMode, u8, Option<ImageClass>) = minify_bytes(&test_utils::encode_png(&test_utils::gradient(128, 64)), &options).expect("Minification failed");
+		let (_, _, photo_quality, photo_class): (Vec<u8>, DitheringMode, u8, Option<ImageClass>) = minify_bytes(&test_utils::encode_png(&test_utils::photo_like(128, 64)), &options).expect("Minification failed");
+		
+		assert_eq!((gradient_class, gradient_quality), (Some(ImageClass::Flat), 35));
+		assert_eq!((photo_class, photo_quality), (Some(ImageClass::Photo), 70));
+		
+		// Without a mapping, the image isn't classified and the quality level applies.
+		let plain: MinifyOptions = MinifyOptions::builder().quality(50).dithering(DitheringMode::None).fast_deflate(true).build().expect("Valid options");
+		let (_, _, quality, class): (Vec<u8>, DitheringMode, u8, Option<ImageClass>) = minify_bytes(&test_utils::encode_png(&test_utils::photo_like(128, 64)), &plain).expect("Minification failed");
+		assert_eq!((class, quality), (None, 50));
 	}
 	
-	Ok(buffer)
-}
-
-/// Apply selective darkening to the image before quantization.
-/// This preserves compression patterns while making the image darker.
-fn apply_darkening(rgba: &mut image::RgbaImage)
-{
-	for pixel in rgba.pixels_mut()
+	#[test]
+	fn quality_per_type_rejects_invalid_mappings()
 	{
-		// Selectively darken RGB channels based on brightness (skip alpha).
-		for i in 0..3
+		assert_eq!(QualityPerType::parse("flat=20").expect("Valid mapping"), QualityPerType { photo: None, flat: Some(20) });
+		for spec in ["", "photo", "photo=0", "photo=101", "icon=50"]
 		{
-			let value = pixel[i];
-			
-			pixel[i] = if value < 16
-			{
-				// Shadows: darken by 20%.
-				(value as f32 * 0.8) as u8
-			}
-			else if value < 32
-			{
-				// Lighter shadows: darken by 10%.
-				(value as f32 * 0.9) as u8
-			}
-			else
-			{
-				// Highlights: keep unchanged.
-				value
-			};
+			assert!(QualityPerType::parse(spec).is_err(), "{}", spec);
 		}
 	}
-}
-
-/// Apply simple quantization without dithering.
-/// Cleanest for gradients and UI elements, but may show banding.
-/// Uses parallel processing for improved performance.
-fn apply_no_dithering(rgba: &image::RgbaImage, width: u32, height: u32, factor: u8) -> image::RgbaImage
-{
-	use rayon::prelude::*;
 	
-	let mut quantized_img = image::RgbaImage::new(width, height);
+	#[test]
+	fn lossless_diff_pixels_reports_no_changes()
+	{
+		let rgba: image::RgbaImage = test_utils::blocks(128, 64, 8, &[[255, 0, 0, 255], [0, 0, 255, 255], [20, 200, 40, 255]]);
+		let source: Vec<u8> = test_utils::encode_png(&rgba);
+		let options: MinifyOptions = MinifyOptions::builder().lossless(true).diff_pixels(true).fast_deflate(true).build().expect("Valid options");
+		
+		let (output, _, _, _): (Vec<u8>, DitheringMode, u8, Option<ImageClass>) = minify_bytes(&source, &options).expect("Minification failed");
+		
+		assert!(is_already_minified(&output).expect("Marker check failed").0);
+		assert_eq!(diff_pixels(&source, &output).expect("Diff failed"), PixelDiff { differing_pixels: 0, max_channel_delta: 0 });
+		assert!(MinifyOptions::builder().quality(40).diff_pixels(true).build().is_err());
+	}
 	
-	// Process rows in parallel for better performance.
-	let rows: Vec<(u32, Vec<(u32, image::Rgba<u8>)>)> = (0..height).into_par_iter().map(|y|
+	#[test]
+	fn diff_pixels_rejects_a_corrupted_output()
 	{
-		let mut row_pixels = Vec::with_capacity(width as usize);
+		let rgba: image::RgbaImage = test_utils::gradient(64, 16);
+		let mut corrupted: image::RgbaImage = rgba.clone();
+		corrupted.get_pixel_mut(0, 0).0[0] = 7;
+		corrupted.get_pixel_mut(10, 3).0[3] = 250;
+		corrupted.get_pixel_mut(63, 15).0[2] = 251;
+		let (source, output): (Vec<u8>, Vec<u8>) = (test_utils::encode_png(&rgba), test_utils::encode_png(&corrupted));
 		
-		for x in 0..width
-		{
-			let pixel = rgba.get_pixel(x, y);
-			let r = quantize_channel(pixel[0] as i16, factor);
-			let g = quantize_channel(pixel[1] as i16, factor);
-			let b = quantize_channel(pixel[2] as i16, factor);
-			row_pixels.push((x, image::Rgba([r, g, b, pixel[3]])));
-		}
+		assert_eq!(diff_pixels(&source, &output).expect("Diff failed"), PixelDiff { differing_pixels: 3, max_channel_delta: 7 });
+		let error: String = check_pixel_diff(&source, &output).expect_err("Corrupted output should be rejected").to_string();
+		assert!(error.contains("3 pixel(s) differ") && error.contains("max channel delta 7"), "{}", error);
+		assert!(check_pixel_diff(&source, &source).is_ok());
+	}
+	
+	#[test]
+	fn custom_marker_text_is_written_and_read_back()
+	{
+		let source: Vec<u8> = test_utils::encode_png(&test_utils::noisy_ramps(128, 64));
+		let marker_text: &str = "ACME Imaging \u{a9} 2026";
+		let options: MinifyOptions = MinifyOptions::builder().quality(40).dithering(DitheringMode::None).fast_deflate(true).marker_text(Some(marker_text.to_string())).build().expect("Valid options");
 		
-		(y, row_pixels)
-	}).collect();
+		let (output, _, _, _): (Vec<u8>, DitheringMode, u8, Option<ImageClass>) = minify_bytes(&source, &options).expect("Minification failed");
+		
+		// The keyword is stored as Latin-1, followed by the null separator and the sentinel.
+		let chunk: Vec<u8> = find_chunk(&output, b"tEXt").expect("Chunk walk failed").expect("Missing marker chunk");
+		assert!(chunk[8..].starts_with(b"ACME Imaging \xa9 2026\0minipng=1,quality=40,"));
+		
+		let (minified, info): (bool, Option<MinificationInfo>) = is_already_minified(&output).expect("Marker check failed");
+		let info: MinificationInfo = info.expect("Marker info");
+		assert!(minified);
+		assert_eq!(info.marker_text, marker_text);
+		assert_eq!((info.quality, info.dithering_mode), (Some(40), Some(DitheringMode::None)));
+		
+		// The original marker is still written by default and read back.
+		let default_options: MinifyOptions = MinifyOptions::builder().quality(40).dithering(DitheringMode::None).fast_deflate(true).build().expect("Valid options");
+		let (output, _, _, _): (Vec<u8>, DitheringMode, u8, Option<ImageClass>) = minify_bytes(&source, &default_options).expect("Minification failed");
+		let chunk: Vec<u8> = find_chunk(&output, b"tEXt").expect("Chunk walk failed").expect("Missing marker chunk");
+		assert!(chunk[8..].starts_with(b"MiniPNG by P. Andrian.\0quality=40,"));
+		assert_eq!(is_already_minified(&output).expect("Marker check failed").1.expect("Marker info").marker_text, MARKER_KEYWORD);
+	}
 	
-	// Write all rows to the output image.
-	for (y, row_pixels) in rows
+	#[test]
+	fn invalid_marker_texts_are_rejected()
 	{
-		for (x, pixel) in row_pixels
+		for marker_text in ["", " leading", "trailing ", "double  space", "tab\there", "wide \u{100}", &"x".repeat(80)]
 		{
-			quantized_img.put_pixel(x, y, pixel);
+			assert!(validate_marker_text(marker_text).is_err(), "{:?}", marker_text);
 		}
+		assert!(validate_marker_text("Caf\u{e9} Studio").is_ok());
+		assert!(validate_marker_text(&"x".repeat(79)).is_ok());
 	}
 	
-	quantized_img
-}
-
-/// Apply Floyd-Steinberg dithering.
-/// This distributes quantization error to neighboring pixels for smoother gradients.
-fn apply_floyd_steinberg_dithering(rgba: &image::RgbaImage, width: u32, height: u32, factor: u8) -> image::RgbaImage
-{
-	// Create a working buffer with i16 to handle error diffusion (can be negative).
-	let initial_buffer = vec![vec![[0i16; 4]; width as usize]; height as usize];
-	let mut working_buffer: Vec<Vec<[i16; 4]>> = initial_buffer;
-	for (x, y, pixel) in rgba.enumerate_pixels()
+	#[test]
+	fn streamed_marker_matches_the_in_memory_marker()
 	{
-		working_buffer[y as usize][x as usize] = [pixel[0] as i16, pixel[1] as i16, pixel[2] as i16, pixel[3] as i16];
+		let rgba: image::RgbaImage = test_utils::noisy_ramps(64, 32);
+		let source: Vec<u8> = test_utils::encode_png(&rgba);
+		
+		let marked: MarkedPng = noop_marked(&source, 40, DitheringMode::FloydSteinberg, Some("Studio"), true).expect("Marking failed");
+		let mut streamed: Vec<u8> = Vec::new();
+		marked.write_to(&mut streamed).expect("Writing failed");
+		
+		assert_eq!(streamed, marked.to_vec());
+		assert_eq!(streamed.len() as u64, marked.size());
+		assert!(is_already_minified(&streamed).expect("Marker check failed").1.expect("Marker info").noop);
+		assert_eq!(test_utils::decode_png(&streamed), rgba);
+		
+		// Data without IEND is rejected before anything is written.
+		let truncated: &[u8] = &source[..source.len() - 12];
+		assert!(noop_marked(truncated, 40, DitheringMode::FloydSteinberg, None, true).is_err());
 	}
 	
-	// Apply Floyd-Steinberg dithering with serpentine scanning and reduced error.
-	// Serpentine: alternating left-to-right and right-to-left scan eliminates "worms".
-	// Reduced error (7/8 factor): softer, smoother gradients with less visible noise.
-	const ERROR_REDUCTION: i16 = 7; // 7/8 = 0.875 error reduction factor.
-	const ERROR_DIVISOR: i16 = 8;
-	
-	for y in 0..height as usize
+	#[test]
+	fn premultiply_reduces_fringing_on_soft_edges()
 	{
-		// Serpentine: even rows go left-to-right, odd rows go right-to-left.
-		let is_forward = y % 2 == 0;
-		let mut x_range: Vec<usize> = Vec::with_capacity(width as usize);
-		if is_forward
+		// A red shape with a soft edge over transparent pixels that hide a green color, as left behind by many editors.
+		let red: [u8; 3] = [200, 30, 30];
+		let rgba: image::RgbaImage = image::RgbaImage::from_fn(32, 32, |x, _|
 		{
-			for x in 0..width as usize
+			match x
 			{
-				x_range.push(x);
+				0..=13 => image::Rgba([0, 255, 0, 0]),
+				14..=17 => image::Rgba([red[0], red[1], red[2], (x as u8 - 13) * 50]),
+				_ => image::Rgba([red[0], red[1], red[2], 255]),
 			}
-		}
-		else
+		});
+		
+		// Alpha-weighted green tint of the visible edge pixels, which smoothing in straight alpha picks up from the hidden color.
+		let fringing = |premultiply: bool| -> f64
 		{
-			for x in (0..width as usize).rev()
+			let options: MinifyOptions = MinifyOptions::builder().quality(80).smooth_radius(1.0).premultiply(premultiply).fast_deflate(true).build().expect("Valid options");
+			let quantized: image::RgbaImage = quantize_pixels(rgba.clone(), 32, 32, DitheringMode::FloydSteinberg, &options).expect("Quantization failed");
+			let output: Vec<u8> = encode_quantized_png(quantized, image::ColorType::Rgba8, &options).expect("Encoding failed");
+			let (mut error, mut weight): (f64, f64) = (0.0, 0.0);
+			for pixel in test_utils::decode_png(&output).pixels().filter(|pixel| pixel[3] > 0 && pixel[3] < 255)
 			{
-				x_range.push(x);
+				let alpha: f64 = pixel[3] as f64 / 255.0;
+				error += alpha * (pixel[1] as f64 - red[1] as f64).abs();
+				weight += alpha;
 			}
+			error / weight
 		};
 		
-		for x in x_range
+		let (straight, premultiplied): (f64, f64) = (fringing(false), fringing(true));
+		assert!(premultiplied * 2.0 < straight, "premultiplied {:.1} vs straight {:.1}", premultiplied, straight);
+	}
+	
+	#[test]
+	fn flatten_composites_onto_the_background_and_drops_alpha()
+	{
+		let colors: [[u8; 4]; 4] = [[255, 0, 0, 128], [0, 0, 0, 64], [0, 0, 255, 0], [100, 150, 200, 255]];
+		let expected: [[u8; 3]; 4] = [[255, 127, 127], [191, 191, 191], [255, 255, 255], [100, 150, 200]];
+		let rgba: image::RgbaImage = test_utils::blocks(32, 32, 8, &colors);
+		
+		let mut flattened: image::RgbaImage = rgba.clone();
+		flatten_onto(&mut flattened, [255, 255, 255]);
+		for (pixel, source) in flattened.pixels().zip(rgba.pixels())
 		{
-			let old_pixel = working_buffer[y][x];
-			
-			// Quantize RGB channels (not alpha).
-			let r = quantize_channel(old_pixel[0], factor);
-			let g = quantize_channel(old_pixel[1], factor);
-			let b = quantize_channel(old_pixel[2], factor);
-			let a = old_pixel[3].clamp(0, 255) as u8;
-			let new_pixel = [r, g, b, a];
-			
-			// Calculate quantization error for each channel.
-			let error = [old_pixel[0] - new_pixel[0] as i16, old_pixel[1] - new_pixel[1] as i16, old_pixel[2] - new_pixel[2] as i16, 0];
-			
-			// Reduce error to create softer gradients (7/8 of original error).
-			let error =
-			[
-				(error[0] * ERROR_REDUCTION) / ERROR_DIVISOR,
-				(error[1] * ERROR_REDUCTION) / ERROR_DIVISOR,
-				(error[2] * ERROR_REDUCTION) / ERROR_DIVISOR,
-				0
-			];
-			
-			// Distribute error using Floyd-Steinberg dithering.
-			// Current pixel = [X], fractions show error distribution:
-			//
-			//  Left-to-right scan:        Right-to-left scan:
-			//  ┌────────┬────────┐        ┌────────┬────────┐
-			//  │   [X]  │   7/16 │        │   7/16 │   [X]  │
-			//  ├────────┼────────┤        ├────────┼────────┤
-			//  │   3/16 │   5/16 │ 1/16   │ 1/16   │   5/16 │ 3/16
-			//  └────────┴────────┘        └────────┴────────┘
-			//      (row n)                      (row n)
-			//  └─────────────────┘        └─────────────────┘
-			//      (row n+1)                    (row n+1)
-			//
-			
-			if is_forward
-			{
-				// Forward scan (left to right).
-				// Right pixel (x+1, y).
-				if x + 1 < width as usize
-				{
-					for c in 0..3
-					{
-						working_buffer[y][x + 1][c] += (error[c] * 7) / 16;
-					}
-				}
-				
-				// Bottom-left pixel (x-1, y+1).
-				if y + 1 < height as usize && x > 0
-				{
-					for c in 0..3
-					{
-						working_buffer[y + 1][x - 1][c] += (error[c] * 3) / 16;
-					}
-				}
-				
-				// Bottom pixel (x, y+1).
-				if y + 1 < height as usize
-				{
-					for c in 0..3
-					{
-						working_buffer[y + 1][x][c] += (error[c] * 5) / 16;
-					}
-				}
-				
-				// Bottom-right pixel (x+1, y+1).
-				if y + 1 < height as usize && x + 1 < width as usize
-				{
-					for c in 0..3
-					{
-						working_buffer[y + 1][x + 1][c] += (error[c] * 1) / 16;
-					}
-				}
-			}
-			else
-			{
-				// Reverse scan (right to left).
-				// Left pixel (x-1, y).
-				if x > 0
-				{
-					for c in 0..3
-					{
-						working_buffer[y][x - 1][c] += (error[c] * 7) / 16;
-					}
-				}
-				
-				// Bottom-right pixel (x+1, y+1).
-				if y + 1 < height as usize && x + 1 < width as usize
-				{
-					for c in 0..3
-					{
-						working_buffer[y + 1][x + 1][c] += (error[c] * 3) / 16;
-					}
-				}
-				
-				// Bottom pixel (x, y+1).
-				if y + 1 < height as usize
-				{
-					for c in 0..3
-					{
-						working_buffer[y + 1][x][c] += (error[c] * 5) / 16;
-					}
-				}
-				
-				// Bottom-left pixel (x-1, y+1).
-				if y + 1 < height as usize && x > 0
-				{
-					for c in 0..3
-					{
-						working_buffer[y + 1][x - 1][c] += (error[c] * 1) / 16;
-					}
-				}
-			}
+			let index: usize = colors.iter().position(|color| *color == source.0).expect("Unknown color");
+			assert_eq!(pixel.0, [expected[index][0], expected[index][1], expected[index][2], 255]);
+		}
+		
+		// The lossy output is stored without an alpha channel: no alpha color type and no tRNS chunk.
+		let options: MinifyOptions = MinifyOptions::builder().quality(80).dithering(DitheringMode::None).flatten(Some([255, 255, 255])).fast_deflate(true).build().expect("Valid options");
+		let (output, _, _, _, _): (Vec<u8>, DitheringMode, u8, Option<ImageClass>, Option<image::RgbaImage>) = apply_quality_lossy_minification(&test_utils::encode_png(&rgba), &options).expect("Minification failed");
+		let color_type: u8 = output[25];
+		assert!(color_type == 0 || color_type == 2 || color_type == 3, "color type {}", color_type);
+		assert!(find_chunk(&output, b"tRNS").expect("Chunk walk failed").is_none());
+		
+		for (pixel, source) in test_utils::decode_png(&output).pixels().zip(rgba.pixels())
+		{
+			let index: usize = colors.iter().position(|color| *color == source.0).expect("Unknown color");
+			assert_eq!(pixel[3], 255);
+			assert!((0..3).all(|i| pixel[i].abs_diff(expected[index][i]) <= 8), "{:?} vs {:?}", pixel, expected[index]);
+		}
+	}
+	
+	#[test]
+	fn deterministic_minification_is_byte_identical()
+	{
+		let source: Vec<u8> = test_utils::encode_png(&test_utils::noisy_ramps(96, 64));
+		for lossless in [false, true]
+		{
+			let options: MinifyOptions = MinifyOptions::builder().lossless(lossless).deterministic(true).fast_deflate(true).build().expect("Valid options");
+			let (first, _, _, _): (Vec<u8>, DitheringMode, u8, Option<ImageClass>) = minify_bytes(&source, &options).expect("Minification failed");
+			let (second, _, _, _): (Vec<u8>, DitheringMode, u8, Option<ImageClass>) = minify_bytes(&source, &options).expect("Minification failed");
 			
-			// Write quantized pixel back.
-			working_buffer[y][x] = [new_pixel[0] as i16, new_pixel[1] as i16, new_pixel[2] as i16, new_pixel[3] as i16];
+			let (marked, info): (bool, Option<MinificationInfo>) = is_already_minified(&first).expect("Marker check failed");
+			assert!(marked && info.expect("Missing marker info").timestamp.is_none());
+			assert!(first == second, "lossless={}: outputs differ", lossless);
 		}
 	}
 	
-	// Convert working buffer back to image.
-	let mut quantized_img = image::RgbaImage::new(width, height);
-	for y in 0..height as usize
+	#[test]
+	fn keep_bit_depth_leaves_rgba_images_unreduced()
 	{
-		for x in 0..width as usize
+		// An opaque image, which oxipng would otherwise store without the alpha channel.
+		let source: Vec<u8> = test_utils::encode_png(&test_utils::noisy_ramps(96, 64));
+		let ihdr_format = |png_data: &[u8]| -> (u8, u8) { (png_data[24], png_data[25]) };
+		assert_eq!(ihdr_format(&source), (8, 6));
+		
+		let reduced: MinifyOptions = MinifyOptions::builder().lossless(true).fast_deflate(true).build().expect("Valid options");
+		let (output, _, _, _): (Vec<u8>, DitheringMode, u8, Option<ImageClass>) = minify_bytes(&source, &reduced).expect("Minification failed");
+		assert_ne!(ihdr_format(&output), (8, 6));
+		
+		for lossless in [true, false]
 		{
-			let pixel = working_buffer[y][x];
-			let r = pixel[0].clamp(0, 255) as u8;
-			let g = pixel[1].clamp(0, 255) as u8;
-			let b = pixel[2].clamp(0, 255) as u8;
-			let a = pixel[3].clamp(0, 255) as u8;
-			quantized_img.put_pixel(x as u32, y as u32, image::Rgba([r, g, b, a]));
+			let kept: MinifyOptions = MinifyOptions::builder().lossless(lossless).fast_deflate(true).always_write(lossless).keep_bit_depth(true).build().expect("Valid options");
+			let (output, _, _, _): (Vec<u8>, DitheringMode, u8, Option<ImageClass>) = minify_bytes(&source, &kept).expect("Minification failed");
+			assert!(is_already_minified(&output).expect("Marker check failed").0, "lossless={}: source returned", lossless);
+			assert_eq!(ihdr_format(&output), (8, 6), "lossless={}", lossless);
+		}
+		
+		// Lossy output keeps 16-bit and grayscale sources in their format too.
+		let rgba: image::RgbaImage = test_utils::noisy_ramps(64, 48);
+		let kept: MinifyOptions = MinifyOptions::builder().quality(40).fast_deflate(true).keep_bit_depth(true).build().expect("Valid options");
+		for (image, format) in [(image::DynamicImage::ImageRgb16(image::DynamicImage::ImageRgba8(rgba.clone()).to_rgb16()), (16, 2)), (image::DynamicImage::ImageLuma8(image::DynamicImage::ImageRgba8(rgba).to_luma8()), (8, 0))]
+		{
+			let mut source: Vec<u8> = Vec::new();
+			image.write_to(&mut Cursor::new(&mut source), ImageFormat::Png).expect("Failed to encode test image");
+			let (output, _, _, _): (Vec<u8>, DitheringMode, u8, Option<ImageClass>) = minify_bytes(&source, &kept).expect("Minification failed");
+			assert!(is_already_minified(&output).expect("Marker check failed").0, "{:?}: source returned", format);
+			assert_eq!(ihdr_format(&output), format);
 		}
+		
+		assert!(MinifyOptions::builder().indexed(true).keep_bit_depth(true).build().is_err());
 	}
 	
-	quantized_img
-}
-
-/// Apply ordered (Bayer) dithering.
-/// Balanced approach: less noisy than Floyd-Steinberg, better than none for photos.
-/// Uses parallel processing for improved performance.
-fn apply_ordered_dithering(rgba: &image::RgbaImage, width: u32, height: u32, factor: u8) -> image::RgbaImage
-{
-	use rayon::prelude::*;
+	#[test]
+	fn gamma_sets_the_encoding_of_linear_light_averaging()
+	{
+		// A single palette color is the average of the black and white halves.
+		let rgba: image::RgbaImage = test_utils::blocks(16, 16, 8, &[[0, 0, 0, 255], [255, 255, 255, 255]]);
+		let average = |options: MinifyOptions| quantize_with_palette(&rgba, 1, &options).1[0][0];
+		
+		// Values that are linear already average to the naive midpoint, sRGB ones to a lighter gray.
+		let linear_values: u8 = average(MinifyOptions::builder().linear(true).gamma(1.0).build().expect("Valid options"));
+		let srgb_values: u8 = average(MinifyOptions::builder().linear(true).build().expect("Valid options"));
+		assert!(linear_values.abs_diff(127) <= 1, "{}", linear_values);
+		assert!(srgb_values > 180, "{}", srgb_values);
+		
+		for gamma in [0.0, -1.0, f32::NAN]
+		{
+			assert!(MinifyOptions::builder().linear(true).gamma(gamma).build().is_err());
+		}
+	}
 	
-	// 4x4 Bayer matrix for ordered dithering.
-	// Centered around zero to avoid brightness bias.
-	const BAYER_MATRIX: [[i16; 4]; 4] =
-	[
-		[-8,  0, -6,  2],
-		[ 4, -4,  6, -2],
-		[-5,  3, -7,  1],
-		[ 7, -1,  5, -3],
-	];
+	#[test]
+	fn tiny_images_survive_every_lossy_path()
+	{
+		let modes: [DitheringMode; 6] = [DitheringMode::None, DitheringMode::FloydSteinberg, DitheringMode::Ordered, DitheringMode::BlueNoise, DitheringMode::MedianCut, DitheringMode::Auto];
+		for (width, height) in [(1, 1), (2, 2), (1, 9), (9, 1)]
+		{
+			let rgba: image::RgbaImage = image::RgbaImage::from_fn(width, height, |x: u32, y: u32| image::Rgba([(x * 90 + 13) as u8, (y * 70 + 29) as u8, 200, (255 - x * 20 - y * 10) as u8]));
+			let source: Vec<u8> = test_utils::encode_png(&rgba);
+			for mode in modes
+			{
+				for (scan, quantizer, extras) in [(ScanOrder::Serpentine, Quantizer::Median, false), (ScanOrder::Hilbert, Quantizer::Octree, false), (ScanOrder::Serpentine, Quantizer::Median, true), (ScanOrder::Hilbert, Quantizer::Octree, true)]
+				{
+					// The extras exercise the alpha and post-processing steps along with the dithering itself.
+					let options: MinifyOptions = MinifyOptions::builder().quality(20).dithering(mode).scan(scan).quantizer(quantizer).quantize_alpha(extras).denoise(extras).alpha_threshold(if extras { Some(128) } else { None }).dither_alpha(extras).indexed(extras).fast_deflate(true).build().expect("Valid options");
+					let (output, _, _, _): (Vec<u8>, DitheringMode, u8, Option<ImageClass>) = minify_bytes(&source, &options).unwrap_or_else(|e| panic!("{:?} {:?} {:?} failed on {}x{}: {}", mode, scan, quantizer, width, height, e));
+					assert_eq!(test_utils::decode_png(&output).dimensions(), (width, height), "{:?} {:?} {:?}", mode, scan, quantizer);
+				}
+			}
+		}
+	}
 	
-	let mut result = image::RgbaImage::new(width, height);
+	#[test]
+	fn sharing_the_decoded_buffer_leaves_the_output_unchanged()
+	{
+		// The pipeline analyzes and quantizes one decoded buffer; decoding separately for each step must give the same pixels.
+		let rgba: image::RgbaImage = test_utils::photo_like(96, 64);
+		let options: MinifyOptions = MinifyOptions::builder().quality(40).dithering(DitheringMode::Auto).fast_deflate(true).build().expect("Valid options");
+		let (output, dithering_mode, _, _): (Vec<u8>, DitheringMode, u8, Option<ImageClass>) = minify_bytes(&test_utils::encode_png(&rgba), &options).expect("Minification failed");
+		
+		let analyzed: image::RgbaImage = test_utils::decode_png(&test_utils::encode_png(&rgba));
+		assert_eq!(dithering_mode, dithering::recommend_dithering_mode(&analyzed, 96, 64, &options.thresholds));
+		let quantized: image::RgbaImage = quantize_rgba(test_utils::decode_png(&test_utils::encode_png(&rgba)), dithering_mode, &options).expect("Quantization failed");
+		assert_eq!(test_utils::decode_png(&output), quantized);
+	}
 	
-	// Process rows in parallel.
-	let rows: Vec<(u32, Vec<(u32, image::Rgba<u8>)>)> = (0..height).into_par_iter().map(|y|
+	#[test]
+	fn gray_images_come_out_grayscale_typed()
 	{
-		let mut row_pixels = Vec::with_capacity(width as usize);
+		// The quantized PNG is checked before oxipng, which would otherwise reduce the color type itself.
+		let mut gray: image::RgbaImage = test_utils::photo_like(128, 64);
+		for pixel in gray.pixels_mut()
+		{
+			let value: u8 = ((pixel[0] as u32 + pixel[1] as u32 + pixel[2] as u32) / 3) as u8;
+			*pixel = image::Rgba([value, value, value, 255]);
+		}
+		let options: MinifyOptions = MinifyOptions::builder().quality(40).dithering(DitheringMode::None).fast_deflate(true).build().expect("Valid options");
+		let color_type = |rgba: &image::RgbaImage, options: &MinifyOptions| -> u8
+		{
+			let quantized: image::RgbaImage = quantize_pixels(rgba.clone(), rgba.width(), rgba.height(), DitheringMode::None, options).expect("Quantization failed");
+			png_format(&encode_quantized_png(quantized, image::ColorType::Rgba8, options).expect("Encoding failed")).expect("Invalid output").0
+		};
+		assert_eq!(color_type(&gray, &options), 0);
 		
-		for x in 0..width
+		// Colored images are only stored as grayscale when forced.
+		let colored: image::RgbaImage = test_utils::noisy_ramps(64, 64);
+		assert_eq!(color_type(&colored, &options), 6);
+		let forced: MinifyOptions = MinifyOptions { grayscale: true, ..options.clone() };
+		assert_eq!(color_type(&colored, &forced), 0);
+		
+		// Keeping the bit depth keeps the source's color type as well.
+		let kept: MinifyOptions = MinifyOptions { keep_bit_depth: true, ..options };
+		assert_eq!(color_type(&gray, &kept), 6);
+		let kept_forced: MinifyOptions = MinifyOptions { grayscale: true, ..kept };
+		assert_eq!(color_type(&colored, &kept_forced), 6);
+	}
+	
+	#[test]
+	fn alpha_threshold_makes_every_pixel_opaque_or_transparent()
+	{
+		let rgba: image::RgbaImage = image::RgbaImage::from_fn(16, 4, |x: u32, _| image::Rgba([200, 100, 50, (x * 17) as u8]));
+		
+		let mut thresholded: image::RgbaImage = rgba.clone();
+		apply_binary_alpha(&mut thresholded, 128, false);
+		for (x, _, pixel) in thresholded.enumerate_pixels()
 		{
-			let pixel = rgba.get_pixel(x, y);
-			
-			// Get threshold from Bayer matrix.
-			let threshold = BAYER_MATRIX[y as usize % 4][x as usize % 4];
-			
-			// Scale threshold based on downsampling factor.
-			let threshold_scaled = (threshold * factor as i16) / 32;
+			assert_eq!(pixel[3], if x * 17 < 128 { 0 } else { 255 });
+		}
+		
+		// Dithered, the share of opaque pixels follows the alpha instead of a hard edge.
+		let mut dithered: image::RgbaImage = rgba.clone();
+		apply_binary_alpha(&mut dithered, 128, true);
+		assert!(dithered.pixels().all(|pixel| pixel[3] == 0 || pixel[3] == 255));
+		let mut opaque: usize = 0;
+		for pixel in dithered.pixels()
+		{
+			if pixel[3] == 255
+			{
+				opaque += 1;
+			}
+		}
+		assert!((24..=40).contains(&opaque), "{} of 64 pixels opaque", opaque);
+		
+		// The whole pipeline applies it after quantization.
+		let mut photo: image::RgbaImage = test_utils::photo_like(128, 32);
+		for (x, _, pixel) in photo.enumerate_pixels_mut()
+		{
+			pixel[3] = (x * 2) as u8;
+		}
+		let options: MinifyOptions = MinifyOptions::builder().quality(40).dithering(DitheringMode::None).alpha_threshold(Some(100)).fast_deflate(true).build().expect("Valid options");
+		let (output, _, _, _): (Vec<u8>, DitheringMode, u8, Option<ImageClass>) = minify_bytes(&test_utils::encode_png(&photo), &options).expect("Minification failed");
+		for (x, _, pixel) in test_utils::decode_png(&output).enumerate_pixels()
+		{
+			assert_eq!(pixel[3], if x * 2 < 100 { 0 } else { 255 });
+		}
+	}
+	
+	#[test]
+	#[allow(clippy::type_complexity)]
+	fn quantized_alpha_snaps_to_the_grid_and_leaves_opaque_images_alone()
+	{
+		let fade: image::RgbaImage = image::RgbaImage::from_fn(64, 4, |x: u32, _| image::Rgba([128, 128, 128, (x * 4) as u8]));
+		let opaque: image::RgbaImage = test_utils::photo_like(16, 16);
+		let dithering: [fn(&image::RgbaImage, u32, u32, [u8; 3], bool, Option<f32>) -> image::RgbaImage; 5] = [apply_no_dithering, apply_ordered_dithering, apply_blue_noise_dithering, apply_floyd_steinberg_dithering, apply_hilbert_dithering];
+		for apply in dithering
+		{
+			let quantized: image::RgbaImage = apply(&fade, 64, 4, [32, 32, 32], true, None);
+			for pixel in quantized.pixels()
+			{
+				assert_eq!(quantize_channel(pixel[3] as i16, 32), pixel[3], "alpha {} is off the grid", pixel[3]);
+			}
 			
-			let r = quantize_channel(pixel[0] as i16 + threshold_scaled, factor);
-			let g = quantize_channel(pixel[1] as i16 + threshold_scaled, factor);
-			let b = quantize_channel(pixel[2] as i16 + threshold_scaled, factor);
-			row_pixels.push((x, image::Rgba([r, g, b, pixel[3]])));
+			let kept: image::RgbaImage = apply(&opaque, 16, 16, [32, 32, 32], true, None);
+			assert!(kept.pixels().all(|pixel| pixel[3] == 255));
 		}
+	}
+	
+	#[test]
+	fn verification_catches_a_truncated_output()
+	{
+		let source: Vec<u8> = test_utils::encode_png(&test_utils::gradient(64, 16));
+		let truncated: &[u8] = &source[..source.len() / 2];
 		
-		(y, row_pixels)
-	}).collect();
+		let error: MinifyError = verify_minified_data(&source, truncated, true).expect_err("Truncated output should be rejected");
+		assert!(matches!(error, MinifyError::Verification(_)), "{}", error);
+		assert!(verify_minified_data(&source, &source, true).is_ok());
+	}
 	
-	// Write all rows to the output image.
-	for (y, row_pixels) in rows
+	#[test]
+	fn chunks_running_past_the_end_are_reported_as_malformed()
 	{
-		for (x, pixel) in row_pixels
+		let source: Vec<u8> = test_utils::encode_png(&test_utils::gradient(64, 16));
+		let idat_pos: usize = source.windows(4).position(|window: &[u8]| window == b"IDAT").expect("Missing IDAT") - 4;
+		
+		// Truncated in the middle of the IDAT chunk.
+		let truncated: Vec<u8> = source[..idat_pos + 20].to_vec();
+		
+		// A length field larger than the remaining bytes.
+		let mut overlong: Vec<u8> = source.clone();
+		let remaining: u32 = (source.len() - idat_pos) as u32;
+		overlong[idat_pos..idat_pos + 4].copy_from_slice(&remaining.to_be_bytes());
+		
+		for data in [&truncated, &overlong]
 		{
-			result.put_pixel(x, y, pixel);
+			let error: String = is_already_minified(data).expect_err("Malformed PNG should be rejected").to_string();
+			assert!(error.contains("chunk extends past end of file"), "{}", error);
+			let error: String = add_minification_marker_with_info(data, true, 100, DitheringMode::None, 0.0, None, false).expect_err("Malformed PNG should be rejected").to_string();
+			assert!(error.contains("chunk extends past end of file"), "{}", error);
 		}
 	}
 	
-	result
-}
-
-/// Quantize a single color channel with rounding.
-fn quantize_channel(value: i16, factor: u8) -> u8
-{
-	let clamped = value.clamp(0, 255);
-	let factor = factor as i16;
-	let quantized = ((clamped + factor / 2) / factor) * factor;
-	quantized.clamp(0, 255) as u8
-}
-
-/// Apply selective denoising to remove dithering artifacts in gradient areas.
-/// Detects smooth gradient regions and applies noise removal while preserving edges.
-fn apply_selective_denoising(img: &image::DynamicImage) -> image::DynamicImage
-{
-	let rgba = img.to_rgba8();
-	let (width, height) = img.dimensions();
-	let mut result = rgba.clone();
+	#[test]
+	fn strip_all_removes_gama_but_keeps_the_marker()
+	{
+		// gAMA with gamma 1/2.2.
+		let mut gama_chunk: Vec<u8> = Vec::new();
+		gama_chunk.extend_from_slice(&4u32.to_be_bytes());
+		gama_chunk.extend_from_slice(b"gAMA");
+		gama_chunk.extend_from_slice(&45455u32.to_be_bytes());
+		let crc: u32 = crc_utils::hash(&gama_chunk[4..]);
+		gama_chunk.extend_from_slice(&crc.to_be_bytes());
+		let source: Vec<u8> = insert_chunk_after_ihdr(&test_utils::encode_png(&test_utils::photo_like(64, 32)), &gama_chunk).expect("Failed to add gAMA");
+		
+		assert!(find_chunk(&source, b"gAMA").expect("Chunk walk failed").is_some());
+		
+		let options: MinifyOptions = MinifyOptions::builder().lossless(true).strip_all(true).fast_deflate(true).build().expect("Valid options");
+		let (output, _, _, _): (Vec<u8>, DitheringMode, u8, Option<ImageClass>) = minify_bytes(&source, &options).expect("Minification failed");
+		
+		assert!(find_chunk(&output, b"gAMA").expect("Chunk walk failed").is_none());
+		assert!(is_already_minified(&output).expect("Marker check failed").0);
+	}
 	
-	// Process image in blocks to detect gradient vs detail regions.
-	const BLOCK_SIZE: u32 = 8;
+	#[test]
+	fn small_reductions_are_kept_as_is_below_min_reduction()
+	{
+		// An optimized PNG without the marker, padded with a private chunk worth about 0.5% more than the marker.
+		let options: MinifyOptions = MinifyOptions::builder().lossless(true).fast_deflate(true).build().expect("Valid options");
+		let (optimized, _, _, _): (Vec<u8>, DitheringMode, u8, Option<ImageClass>) = minify_bytes(&test_utils::encode_png(&test_utils::photo_like(64, 64)), &options).expect("Minification failed");
+		let marker: Vec<u8> = find_chunk(&optimized, b"tEXt").expect("Chunk walk failed").expect("Missing marker chunk");
+		let marker_pos: usize = optimized.windows(marker.len()).position(|window: &[u8]| window == marker.as_slice()).expect("Missing marker chunk");
+		let mut unmarked: Vec<u8> = optimized[..marker_pos].to_vec();
+		unmarked.extend_from_slice(&optimized[marker_pos + marker.len()..]);
+		
+		let padding_length: usize = marker.len() - 12 + unmarked.len() / 200;
+		let mut padding_chunk: Vec<u8> = Vec::new();
+		padding_chunk.extend_from_slice(&(padding_length as u32).to_be_bytes());
+		padding_chunk.extend_from_slice(b"prVt");
+		padding_chunk.resize(8 + padding_length, 0x55);
+		let crc: u32 = crc_utils::hash(&padding_chunk[4..]);
+		padding_chunk.extend_from_slice(&crc.to_be_bytes());
+		let source: Vec<u8> = insert_chunk_after_ihdr(&unmarked, &padding_chunk).expect("Failed to add padding");
+		
+		let (output, _, _, _): (Vec<u8>, DitheringMode, u8, Option<ImageClass>) = minify_bytes(&source, &options).expect("Minification failed");
+		let reduction_pct: f64 = (source.len() - output.len()) as f64 * 100.0 / source.len() as f64;
+		assert!(reduction_pct > 0.0 && reduction_pct < 1.0, "reduction {:.2}%", reduction_pct);
+		
+		let options: MinifyOptions = MinifyOptions::builder().lossless(true).fast_deflate(true).min_reduction(5.0).build().expect("Valid options");
+		let (output, _, _, _): (Vec<u8>, DitheringMode, u8, Option<ImageClass>) = minify_bytes(&source, &options).expect("Minification failed");
+		assert_eq!(output, source);
+	}
 	
-	for block_y in (0..height).step_by(BLOCK_SIZE as usize)
+	#[test]
+	fn builder_rejects_out_of_range_and_conflicting_settings()
 	{
-		for block_x in (0..width).step_by(BLOCK_SIZE as usize)
+		let invalid: [(MinifyOptionsBuilder, &str); 4] =
+		[
+			(MinifyOptions::builder().quality(101), "Quality must be between 1 and 100"),
+			(MinifyOptions::builder().smooth_radius(5.5), "Smooth radius must be between 0.0 and 5.0"),
+			(MinifyOptions::builder().smooth_radius(-0.5), "Smooth radius must be between 0.0 and 5.0"),
+			(MinifyOptions::builder().lossless(true).quality(80), "cannot be combined with lossless mode"),
+		];
+		for (builder, message) in invalid
 		{
-			let block_end_x = (block_x + BLOCK_SIZE).min(width);
-			let block_end_y = (block_y + BLOCK_SIZE).min(height);
-			
-			// Analyze this block to determine if it's a gradient area.
-			let (is_gradient, noise_level) = analyze_block(&rgba, block_x, block_y, block_end_x, block_end_y);
+			let error: MinifyError = builder.build().expect_err("Invalid options should be rejected");
+			assert!(matches!(error, MinifyError::InvalidOptions(_)) && error.to_string().contains(message), "{}", error);
+		}
+		
+		assert!(MinifyOptions::builder().quality(100).smooth_radius(5.0).build().is_ok());
+		assert!(MinifyOptions::builder().quality(1).smooth_radius(0.0).build().is_ok());
+		assert!(MinifyOptions::builder().lossless(true).build().is_ok());
+	}
+	
+	#[test]
+	fn keep_phys_retains_300_dpi_after_lossy_minification()
+	{
+		// 300 DPI is 11811 pixels per meter (unit 1) on both axes.
+		let mut phys_chunk: Vec<u8> = Vec::new();
+		phys_chunk.extend_from_slice(&9u32.to_be_bytes());
+		phys_chunk.extend_from_slice(b"pHYs");
+		phys_chunk.extend_from_slice(&11811u32.to_be_bytes());
+		phys_chunk.extend_from_slice(&11811u32.to_be_bytes());
+		phys_chunk.push(1);
+		let crc: u32 = crc_utils::hash(&phys_chunk[4..]);
+		phys_chunk.extend_from_slice(&crc.to_be_bytes());
+		let source: Vec<u8> = insert_chunk_after_ihdr(&test_utils::encode_png(&test_utils::photo_like(128, 64)), &phys_chunk).expect("Failed to add pHYs");
+		
+		let options: MinifyOptions = MinifyOptions::builder().quality(40).keep_phys(true).fast_deflate(true).build().expect("Valid options");
+		let (output, _, _, _): (Vec<u8>, DitheringMode, u8, Option<ImageClass>) = minify_bytes(&source, &options).expect("Minification failed");
+		
+		assert!(output.len() < source.len());
+		assert_eq!(find_chunk(&output, b"pHYs").expect("Chunk walk failed"), Some(phys_chunk));
+	}
+	
+	#[test]
+	fn keep_text_carries_a_copyright_over_in_both_modes()
+	{
+		let text: &[u8] = b"Copyright\0(c) 2024 Example Publisher";
+		let mut text_chunk: Vec<u8> = Vec::new();
+		text_chunk.extend_from_slice(&(text.len() as u32).to_be_bytes());
+		text_chunk.extend_from_slice(b"tEXt");
+		text_chunk.extend_from_slice(text);
+		let crc: u32 = crc_utils::hash(&text_chunk[4..]);
+		text_chunk.extend_from_slice(&crc.to_be_bytes());
+		let source: Vec<u8> = insert_chunk_after_ihdr(&test_utils::encode_png(&test_utils::photo_like(96, 64)), &text_chunk).expect("Failed to add tEXt");
+		
+		for lossless in [true, false]
+		{
+			let builder: MinifyOptionsBuilder = if lossless { MinifyOptions::builder().lossless(true) } else { MinifyOptions::builder().quality(40) };
+			let options: MinifyOptions = builder.keep_text(true).fast_deflate(true).build().expect("Valid options");
+			let (output, _, _, _): (Vec<u8>, DitheringMode, u8, Option<ImageClass>) = minify_bytes(&source, &options).expect("Minification failed");
 			
-			// If it's a gradient with noise, apply selective median filter.
-			if is_gradient && noise_level > 15.0
+			// The copyright is kept exactly once, and the marker is still added.
+			let text_chunks: Vec<Vec<u8>> = collect_text_chunks(&output).expect("Chunk walk failed");
+			assert_eq!(text_chunks, vec![text_chunk.clone()], "lossless: {}", lossless);
+			assert!(is_already_minified(&output).expect("Marker check failed").0);
+		}
+	}
+	
+	#[test]
+	fn asymmetric_factors_quantize_green_on_a_finer_grid()
+	{
+		// Every channel ramps through all 256 levels.
+		let rgba: image::RgbaImage = image::RgbaImage::from_fn(256, 4, |x: u32, _| image::Rgba([x as u8, x as u8, x as u8, 255]));
+		let options: MinifyOptions = MinifyOptions::builder().quality(40).dithering(DitheringMode::None).channel_factors(Some([32, 8, 32])).build().expect("Valid options");
+		let quantized: image::RgbaImage = quantize_rgba(rgba, DitheringMode::None, &options).expect("Quantization failed");
+		
+		let mut levels: [std::collections::BTreeSet<u8>; 3] = Default::default();
+		for pixel in quantized.pixels()
+		{
+			for channel in 0..3
 			{
-				apply_median_filter_to_block(&rgba, &mut result, block_x, block_y, block_end_x, block_end_y);
+				levels[channel].insert(pixel[channel]);
 			}
 		}
+		
+		assert!(levels[1].len() > 3 * levels[0].len(), "green {} vs red {} levels", levels[1].len(), levels[0].len());
+		assert_eq!(levels[0], levels[2]);
 	}
 	
-	image::DynamicImage::ImageRgba8(result)
-}
-
-/// Analyze a block to determine if it's a gradient area with noise.
-/// Returns (is_gradient, noise_level).
-fn analyze_block(rgba: &image::RgbaImage, start_x: u32, start_y: u32, end_x: u32, end_y: u32) -> (bool, f64)
-{
-	let mut edge_count = 0;
-	let mut total_pixels = 0;
-	let mut variance_sum = 0.0;
+	#[cfg(feature = "cli")]
+	#[test]
+	fn optimizer_thread_budget_reaches_oxipng()
+	{
+		let options: MinifyOptions = MinifyOptions::builder().optimizer_threads(3).build().expect("Valid options");
+		let threads: usize = pin_oxipng(&mut OxiOptions::default(), &options);
+		assert_eq!(threads, 3);
+		assert_eq!(par_utils::run_with_threads(threads, rayon::current_num_threads), 3);
+		
+		// Deterministic runs optimize on a single thread whatever the budget.
+		let options: MinifyOptions = MinifyOptions::builder().optimizer_threads(3).deterministic(true).build().expect("Valid options");
+		assert_eq!(pin_oxipng(&mut OxiOptions::default(), &options), 1);
+	}
 	
-	// Calculate edge density and local variance.
-	for y in start_y..end_y
+	#[test]
+	fn fixed_palette_output_uses_only_the_palette_colors()
 	{
-		for x in start_x..end_x
+		let palette: Vec<[u8; 3]> = vec![[0, 0, 0], [255, 255, 255], [200, 40, 40], [40, 90, 200]];
+		let source: Vec<u8> = test_utils::encode_png(&test_utils::photo_like(96, 64));
+		
+		for dithering_mode in [DitheringMode::None, DitheringMode::FloydSteinberg]
 		{
-			if x > 0 && y > 0 && x < end_x - 1 && y < end_y - 1
+			let options: MinifyOptions = MinifyOptions::builder().quality(40).dithering(dithering_mode).fixed_palette(Some(palette.clone())).fast_deflate(true).build().expect("Valid options");
+			let (output, _, _, _): (Vec<u8>, DitheringMode, u8, Option<ImageClass>) = minify_bytes(&source, &options).expect("Minification failed");
+			
+			let colors: Vec<[u8; 4]> = test_utils::distinct_colors(&test_utils::decode_png(&output));
+			assert!(colors.len() > 1 && colors.len() <= 4, "{:?}: {:?}", dithering_mode, colors);
+			for color in colors
 			{
-				// Simple edge detection - check if pixel differs significantly from neighbors.
-				let center = rgba.get_pixel(x, y);
-				let right = rgba.get_pixel(x + 1, y);
-				let bottom = rgba.get_pixel(x, y + 1);
-				
-				let diff_h = pixel_diff(center, right);
-				let diff_v = pixel_diff(center, bottom);
-				
-				if diff_h > 30.0 || diff_v > 30.0
-				{
-					edge_count += 1;
-				}
-				
-				// Calculate local variance (noise indicator).
-				let neighbors =
-				[
-					rgba.get_pixel(x.saturating_sub(1), y),
-					rgba.get_pixel(x + 1, y),
-					rgba.get_pixel(x, y.saturating_sub(1)),
-					rgba.get_pixel(x, y + 1),
-				];
-				
-				let mut neighbor_diffs = 0.0;
-				for neighbor in neighbors
-				{
-					neighbor_diffs += pixel_diff(center, neighbor);
-				}
-				variance_sum += neighbor_diffs / 4.0;
-				total_pixels += 1;
+				assert!(palette.contains(&[color[0], color[1], color[2]]) && color[3] == 255, "{:?}: {:?}", dithering_mode, color);
 			}
 		}
 	}
 	
-	let edge_density = if total_pixels > 0 { edge_count as f64 / total_pixels as f64 } else { 0.0 };
-	let avg_variance = if total_pixels > 0 { variance_sum / total_pixels as f64 } else { 0.0 };
-	
-	// Gradient area: low edge density (<15%) but with some variance (noise from dithering).
-	let is_gradient = edge_density < 0.15 && avg_variance > 5.0;
+	#[test]
+	fn marker_is_found_past_an_idat_over_the_ancillary_limit()
+	{
+		let chunk = |chunk_type: &[u8; 4], data: &[u8]| -> Vec<u8>
+		{
+			let mut chunk: Vec<u8> = Vec::new();
+			chunk.extend_from_slice(&(data.len() as u32).to_be_bytes());
+			chunk.extend_from_slice(chunk_type);
+			chunk.extend_from_slice(data);
+			let crc: u32 = crc_utils::hash(&chunk[4..]);
+			chunk.extend_from_slice(&crc.to_be_bytes());
+			chunk
+		};
+		
+		// The chunk walkers don't decode the image, so the IDAT only needs the right size.
+		let source: Vec<u8> = test_utils::encode_png(&test_utils::gradient(16, 16));
+		let ihdr: Vec<u8> = find_chunk(&source, b"IHDR").expect("Chunk walk failed").expect("Missing IHDR");
+		let mut png: Vec<u8> = PNG_SIGNATURE.to_vec();
+		png.extend_from_slice(&ihdr);
+		png.extend_from_slice(&chunk(b"IDAT", &vec![0u8; MAX_ANCILLARY_CHUNK_LENGTH + 1]));
+		png.extend_from_slice(&chunk(b"IEND", &[]));
+		
+		assert!(!is_already_minified(&png).expect("Marker check failed").0);
+		let marked: Vec<u8> = add_minification_marker_with_info(&png, true, 100, DitheringMode::None, 0.0, None, false).expect("Failed to add the marker");
+		assert!(is_already_minified(&marked).expect("Marker check failed").0);
+		
+		// An ancillary chunk over the limit is rejected the same way by both.
+		let oversized: Vec<u8> = insert_chunk_after_ihdr(&marked, &chunk(b"iCCP", &vec![0u8; MAX_ANCILLARY_CHUNK_LENGTH + 1])).expect("Failed to add iCCP");
+		let error: String = is_already_minified(&oversized).expect_err("Oversized iCCP should be rejected").to_string();
+		assert!(error.contains("larger than"), "{}", error);
+		let error: String = add_minification_marker_with_info(&oversized, true, 100, DitheringMode::None, 0.0, None, false).expect_err("Oversized iCCP should be rejected").to_string();
+		assert!(error.contains("larger than"), "{}", error);
+	}
 	
-	(is_gradient, avg_variance)
-}
-
-/// Calculate color difference between two pixels.
-fn pixel_diff(p1: &image::Rgba<u8>, p2: &image::Rgba<u8>) -> f64
-{
-	let dr = (p1[0] as i16 - p2[0] as i16).abs() as f64;
-	let dg = (p1[1] as i16 - p2[1] as i16).abs() as f64;
-	let db = (p1[2] as i16 - p2[2] as i16).abs() as f64;
-	(dr + dg + db) / 3.0
-}
-
-/// Apply 3x3 median filter to a block to remove dithering noise.
-fn apply_median_filter_to_block(source: &image::RgbaImage, dest: &mut image::RgbaImage, start_x: u32, start_y: u32, end_x: u32, end_y: u32)
-{
-	for y in start_y..end_y
+	#[test]
+	fn stronger_denoising_leaves_less_noise_on_a_noisy_gradient()
 	{
-		for x in start_x..end_x
+		// Mean color difference between horizontal neighbors, which the noise dominates.
+		let roughness = |img: &image::DynamicImage| -> f64
 		{
-			if x > 0 && y > 0 && x < end_x - 1 && y < end_y - 1
+			let rgba: image::RgbaImage = img.to_rgba8();
+			let mut total: f64 = 0.0;
+			let mut count: u32 = 0;
+			for y in 0..rgba.height()
 			{
-				// Collect 3x3 neighborhood.
-				let mut r_values = Vec::new();
-				let mut g_values = Vec::new();
-				let mut b_values = Vec::new();
-				
-				for dy in -1i32..=1
+				for x in 1..rgba.width()
 				{
-					for dx in -1i32..=1
-					{
-						let px = (x as i32 + dx) as u32;
-						let py = (y as i32 + dy) as u32;
-						let pixel = source.get_pixel(px, py);
-						r_values.push(pixel[0]);
-						g_values.push(pixel[1]);
-						b_values.push(pixel[2]);
-					}
+					total += pixel_diff(rgba.get_pixel(x - 1, y), rgba.get_pixel(x, y));
+					count += 1;
 				}
-				
-				// Sort and get median.
-				r_values.sort();
-				g_values.sort();
-				b_values.sort();
-				
-				let median_r = r_values[4]; // Middle value of 9 elements.
-				let median_g = g_values[4];
-				let median_b = b_values[4];
-				
-				let alpha = source.get_pixel(x, y)[3];
-				dest.put_pixel(x, y, image::Rgba([median_r, median_g, median_b, alpha]));
 			}
+			total / count as f64
+		};
+		
+		// A gray gradient with dithering-like noise of up to 20 levels on every pixel, taken from the unrelated colors of photo_like.
+		let mut noisy: image::RgbaImage = test_utils::gradient(64, 64);
+		let noise: image::RgbaImage = test_utils::photo_like(64, 64);
+		for (pixel, noise_pixel) in noisy.pixels_mut().zip(noise.pixels())
+		{
+			let level: u8 = (pixel[0] as u32 * 235 / 255 + noise_pixel[0] as u32 % 21) as u8;
+			*pixel = image::Rgba([level, level, level, 255]);
 		}
+		let source: image::DynamicImage = image::DynamicImage::ImageRgba8(noisy);
+		let default: f64 = roughness(&apply_selective_denoising(&source, DEFAULT_DENOISE_STRENGTH, DEFAULT_DENOISE_BLOCK));
+		let strong: f64 = roughness(&apply_selective_denoising(&source, 1.0, DEFAULT_DENOISE_BLOCK));
+		assert!(strong < default, "strength 1.0 left {:.2}, the default {:.2}", strong, default);
+		assert!(default <= roughness(&source));
 	}
-}
-
-/// Apply median cut color quantization.
-/// Uses the classic median cut algorithm for excellent palette quality.
-fn apply_median_quantization(rgba: &image::RgbaImage, downsampling_factor: u8) -> image::RgbaImage
-{
-	// Calculate max colors based on downsampling factor.
-	// Lower factor = more colors allowed.
-	let max_colors = match downsampling_factor
+	
+	#[cfg(feature = "cli")]
+	#[test]
+	fn minification_info_is_read_from_minified_files_only()
 	{
-		32 => 128, // Most aggressive.
-		16 => 256, // Balanced.
-		12 => 512, // High quality.
-		_ => 1024, // Maximum quality.
-	};
+		let dir: PathBuf = std::env::temp_dir().join(format!("minipng-lib-test-{}-info", std::process::id()));
+		std::fs::create_dir_all(&dir).expect("Failed to create scratch directory");
+		
+		let source: Vec<u8> = test_utils::encode_png(&test_utils::noisy_ramps(96, 64));
+		let options: MinifyOptions = MinifyOptions::builder().quality(60).fast_deflate(true).build().expect("Valid options");
+		let (minified, _, _, _): (Vec<u8>, DitheringMode, u8, Option<ImageClass>) = minify_bytes(&source, &options).expect("Minification failed");
+		let plain_path: PathBuf = dir.join("plain.png");
+		let minified_path: PathBuf = dir.join("minified.png");
+		std::fs::write(&plain_path, &source).expect("Failed to write test image");
+		std::fs::write(&minified_path, &minified).expect("Failed to write test image");
+		
+		assert!(read_minification_info(&plain_path).expect("Failed to read info").is_none());
+		let info: MinificationInfo = read_minification_info(&minified_path).expect("Failed to read info").expect("Missing marker info");
+		assert!(!info.lossless);
+		assert_eq!(info.quality, Some(60));
+		assert!(info.reduction_pct > 0.0);
+		assert!(info.timestamp.is_some());
+		
+		let _ = std::fs::remove_dir_all(&dir);
+	}
 	
-	// Use the median module to perform quantization.
-	median::quantize_image_with_median(rgba, max_colors)
+	#[test]
+	fn auto_quality_is_higher_for_a_detailed_image_than_a_flat_gradient()
+	{
+		let options: MinifyOptions = MinifyOptions::builder().quality(AUTO_QUALITY).fast_deflate(true).build().expect("Valid options");
+		let auto_quality = |rgba: &image::RgbaImage| -> u8
+		{
+			let (_, _, quality, _): (Vec<u8>, DitheringMode, u8, Option<ImageClass>) = minify_bytes(&test_utils::encode_png(rgba), &options).expect("Minification failed");
+			quality
+		};
+		
+		let flat: u8 = auto_quality(&test_utils::gradient(128, 96));
+		let detailed: u8 = auto_quality(&test_utils::photo_like(128, 96));
+		assert!(detailed > flat, "detailed {} vs flat {}", detailed, flat);
+		assert!((30..=75).contains(&flat) && (30..=75).contains(&detailed));
+	}
 }
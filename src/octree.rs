@@ -0,0 +1,204 @@
+use image::RgbaImage;
+
+use crate::median::{self, Color};
+
+// Octree color quantization.
+// Every sampled color is filed into a tree that splits the RGB cube into eight octants per level, one bit of each channel at a time,
+// so the leaves at the deepest level are the distinct colors. The tree is then pruned from the bottom up,
+// merging the least used nodes into their parent, until no more than the requested number of leaves remain.
+// Each leaf becomes the average of the colors it holds. Building the tree is linear in the number of sampled colors,
+// which makes octree quantization faster than median cut on images with huge color counts.
+// Sampling, k-means refinement and the nearest-color mapping are shared with median cut.
+
+/// Depth of the tree: one level per bit of a channel.
+const MAX_DEPTH: usize = 8;
+
+/// Marks a missing child (the root, at index 0, is never a child).
+const NO_CHILD: usize = 0;
+
+/// Node of the octree: an inner node with up to eight children, or a leaf holding its colors.
+struct Node
+{
+	children: [usize; 8],
+	pixel_count: u64,
+	colors: Vec<(Color, u32)>,
+	leaf: bool,
+}
+
+impl Node
+{
+	fn new(leaf: bool) -> Self
+	{
+		Self { children: [NO_CHILD; 8], pixel_count: 0, colors: Vec::new(), leaf }
+	}
+}
+
+/// Octant of a color at a level: the bit of each channel at that depth.
+fn octant(color: &Color, level: usize) -> usize
+{
+	let shift: usize = 7 - level;
+	(((color.r >> shift) & 1) << 2 | ((color.g >> shift) & 1) << 1 | ((color.b >> shift) & 1)) as usize
+}
+
+/// Build the palette of at most max_colors colors for the sampled colors.
+fn build_palette(samples: &[(Color, u32)], max_colors: usize, linear: Option<f32>) -> Vec<Color>
+{
+	let mut nodes: Vec<Node> = vec![Node::new(false)];
+	let mut levels: Vec<Vec<usize>> = vec![Vec::new(); MAX_DEPTH];
+	levels[0].push(0);
+	let mut leaf_count: usize = 0;
+	
+	for &(color, count) in samples
+	{
+		let mut index: usize = 0;
+		nodes[index].pixel_count += count as u64;
+		for level in 0..MAX_DEPTH
+		{
+			let slot: usize = octant(&color, level);
+			if nodes[index].children[slot] == NO_CHILD
+			{
+				let leaf: bool = level + 1 == MAX_DEPTH;
+				nodes.push(Node::new(leaf));
+				let child: usize = nodes.len() - 1;
+				nodes[index].children[slot] = child;
+				if leaf
+				{
+					leaf_count += 1;
+				}
+				else
+				{
+					levels[level + 1].push(child);
+				}
+			}
+			
+			index = nodes[index].children[slot];
+			nodes[index].pixel_count += count as u64;
+		}
+		nodes[index].colors.push((color, count));
+	}
+	
+	// Merge the least used nodes of the deepest level first, moving up once a level is fully merged.
+	let max_colors: usize = max_colors.max(1);
+	for level in (0..MAX_DEPTH).rev()
+	{
+		if leaf_count <= max_colors
+		{
+			break;
+		}
+		
+		let mut reducible: Vec<usize> = std::mem::take(&mut levels[level]);
+		reducible.sort_by_key(|&index| (nodes[index].pixel_count, index));
+		for index in reducible
+		{
+			if leaf_count <= max_colors
+			{
+				break;
+			}
+			
+			let mut children: Vec<usize> = Vec::with_capacity(8);
+			for child in nodes[index].children
+			{
+				if child != NO_CHILD
+				{
+					children.push(child);
+				}
+			}
+			let mut colors: Vec<(Color, u32)> = Vec::new();
+			for &child in &children
+			{
+				colors.append(&mut nodes[child].colors);
+			}
+			
+			let node: &mut Node = &mut nodes[index];
+			node.children = [NO_CHILD; 8];
+			node.colors = colors;
+			node.leaf = true;
+			leaf_count = leaf_count + 1 - children.len();
+		}
+	}
+	
+	// Walk the tree in order, so the palette doesn't depend on the order of the samples.
+	let mut palette: Vec<Color> = Vec::with_capacity(leaf_count);
+	let mut stack: Vec<usize> = vec![0];
+	while let Some(index) = stack.pop()
+	{
+		let node: &mut Node = &mut nodes[index];
+		if node.leaf
+		{
+			palette.push(median::average_color(std::mem::take(&mut node.colors), linear));
+			continue;
+		}
+		
+		for &child in node.children.iter().rev()
+		{
+			if child != NO_CHILD
+			{
+				stack.push(child);
+			}
+		}
+	}
+	
+	palette
+}
+
+/// Quantize an image using octree quantization, with the same parameters as median::quantize_image_with_median.
+/// Every sample_step-th pixel is sampled in both axes to build the palette (0 picks a step from the image size).
+/// With refine, the octree palette is improved with a few k-means (Lloyd) iterations before mapping.
+pub fn quantize_image_with_octree(rgba: &RgbaImage, max_colors: usize, linear: Option<f32>, refine: bool, sample_step: usize) -> RgbaImage
+{
+	quantize_image_with_octree_palette(rgba, max_colors, linear, refine, sample_step).0
+}
+
+/// Quantize an image using octree quantization like quantize_image_with_octree, also returning the palette.
+/// The palette holds the distinct RGBA palette colors, most used first.
+pub fn quantize_image_with_octree_palette(rgba: &RgbaImage, max_colors: usize, linear: Option<f32>, refine: bool, sample_step: usize) -> (RgbaImage, Vec<[u8; 4]>)
+{
+	let (width, height): (u32, u32) = rgba.dimensions();
+	if width == 0 || height == 0
+	{
+		return (rgba.clone(), Vec::new());
+	}
+	
+	let samples: Vec<(Color, u32)> = median::sample_colors(rgba, sample_step);
+	let mut palette: Vec<Color> = build_palette(&samples, max_colors, linear);
+	if refine
+	{
+		median::refine_palette(&mut palette, &samples, linear);
+	}
+	
+	median::map_to_nearest(rgba, &palette)
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+	use crate::test_utils;
+	
+	#[test]
+	fn both_quantizers_stay_within_max_colors_on_a_high_color_image()
+	{
+		let rgba: RgbaImage = test_utils::photo_like(128, 128);
+		assert!(test_utils::distinct_colors(&rgba).len() > 10_000);
+		
+		for max_colors in [2, 16, 255]
+		{
+			let octree: RgbaImage = quantize_image_with_octree(&rgba, max_colors, None, false, 1);
+			let median_cut: RgbaImage = median::quantize_image_with_median(&rgba, max_colors, None, false, 1);
+			
+			assert_eq!(octree.dimensions(), rgba.dimensions());
+			assert!(test_utils::distinct_colors(&octree).len() <= max_colors, "octree with {} colors", max_colors);
+			assert!(test_utils::distinct_colors(&median_cut).len() <= max_colors, "median cut with {} colors", max_colors);
+		}
+	}
+	
+	#[test]
+	fn keeps_colors_that_fit_the_palette()
+	{
+		let rgba: RgbaImage = test_utils::blocks(16, 16, 4, &[[255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 255], [255, 255, 255, 255]]);
+		let (quantized, palette): (RgbaImage, Vec<[u8; 4]>) = quantize_image_with_octree_palette(&rgba, 4, None, false, 1);
+		
+		assert_eq!(quantized, rgba);
+		assert_eq!(palette.len(), 4);
+	}
+}
@@ -0,0 +1,421 @@
+use image::RgbaImage;
+use std::collections::{HashMap, HashSet};
+
+use crate::error::{MinifyError, Result};
+use crate::minify::{self, MinifyOptions, PNG_SIGNATURE};
+use crate::utils::crc_utils;
+
+// Indexed-color (palette) PNG encoding.
+// The image crate can't write palettized PNGs, so this module writes IHDR, PLTE, tRNS and IDAT itself.
+// The IDAT data uses stored (uncompressed) deflate blocks, the oxipng pass recompresses it afterward.
+
+/// Largest number of entries a PNG palette can hold.
+const MAX_PALETTE_SIZE: usize = 256;
+
+/// Largest payload of a single stored deflate block.
+const MAX_STORED_BLOCK: usize = 65535;
+
+/// Color every fully transparent pixel is stored as, so they all share one palette entry and one tRNS entry.
+const TRANSPARENT: [u8; 4] = [0, 0, 0, 0];
+
+/// Encode an image as an indexed-color PNG.
+/// Images with more than 256 colors are first reduced with median cut, as long as their alpha is binary (fully transparent or fully opaque).
+/// The median cut (or octree, see MinifyOptions::quantizer) uses the linear, refine and sample_step options.
+/// Fully transparent pixels are stored as one color whatever their RGB values, so an image with single-color transparency
+/// gets a single tRNS entry instead of one per hidden color (which could also push it past 256 colors and out of the palette).
+/// Returns None when the image can't be stored with a palette (too many colors with semi-transparent pixels).
+pub fn encode_indexed_png(rgba: &RgbaImage, options: &MinifyOptions) -> Result<Option<Vec<u8>>>
+{
+	let mut image: RgbaImage = rgba.clone();
+	for pixel in image.pixels_mut()
+	{
+		if pixel[3] == 0
+		{
+			pixel.0 = TRANSPARENT;
+		}
+	}
+	
+	let mut palette: Option<Vec<[u8; 4]>> = collect_palette(&image);
+	
+	if palette.is_none()
+	{
+		// Median cut treats every color as opaque, so only binary alpha can be restored afterward.
+		if !has_binary_alpha(&image)
+		{
+			return Ok(None);
+		}
+		
+		// Keep one entry free for the shared fully transparent color.
+		let (mut reduced, _): (RgbaImage, Vec<[u8; 4]>) = minify::quantize_with_palette(&image, MAX_PALETTE_SIZE - 1, options);
+		for (reduced_pixel, source_pixel) in reduced.pixels_mut().zip(image.pixels())
+		{
+			if source_pixel[3] == 0
+			{
+				reduced_pixel.0 = TRANSPARENT;
+			}
+		}
+		
+		image = reduced;
+		palette = collect_palette(&image);
+	}
+	
+	let mut palette: Vec<[u8; 4]> = match palette
+	{
+		Some(palette) => palette,
+		None => return Ok(None),
+	};
+	
+	// Put translucent entries first so the tRNS chunk can stop at the last of them.
+	palette.sort_by_key(|color| (color[3] == 255, *color));
+	
+	let mut indices: HashMap<[u8; 4], u8> = HashMap::new();
+	for (index, color) in palette.iter().enumerate()
+	{
+		indices.insert(*color, index as u8);
+	}
+	
+	let bit_depth: u8 = match palette.len()
+	{
+		0..=2 => 1,
+		3..=4 => 2,
+		5..=16 => 4,
+		_ => 8,
+	};
+	
+	// Pack each row behind a filter type byte of 0 (none).
+	let (width, height): (u32, u32) = image.dimensions();
+	let pixels_per_byte: usize = 8 / bit_depth as usize;
+	let row_bytes: usize = (width as usize).div_ceil(pixels_per_byte);
+	let mut raw: Vec<u8> = Vec::with_capacity((row_bytes + 1) * height as usize);
+	for y in 0..height
+	{
+		raw.push(0);
+		let row_start: usize = raw.len();
+		raw.resize(row_start + row_bytes, 0);
+		
+		for x in 0..width
+		{
+			let index: u8 = indices[&image.get_pixel(x, y).0];
+			let byte_pos: usize = row_start + x as usize / pixels_per_byte;
+			let shift: usize = 8 - bit_depth as usize * (x as usize % pixels_per_byte + 1);
+			raw[byte_pos] |= index << shift;
+		}
+	}
+	
+	let mut ihdr: Vec<u8> = Vec::with_capacity(13);
+	ihdr.extend_from_slice(&width.to_be_bytes());
+	ihdr.extend_from_slice(&height.to_be_bytes());
+	ihdr.push(bit_depth);
+	ihdr.push(3); // Color type 3 (indexed).
+	ihdr.push(0); // Compression method.
+	ihdr.push(0); // Filter method.
+	ihdr.push(0); // No interlacing.
+	
+	let mut plte: Vec<u8> = Vec::with_capacity(palette.len() * 3);
+	let mut trns: Vec<u8> = Vec::new();
+	for color in &palette
+	{
+		plte.extend_from_slice(&color[..3]);
+		if color[3] < 255
+		{
+			trns.push(color[3]);
+		}
+	}
+	
+	let mut png: Vec<u8> = Vec::new();
+	png.extend_from_slice(PNG_SIGNATURE);
+	write_chunk(&mut png, b"IHDR", &ihdr);
+	write_chunk(&mut png, b"PLTE", &plte);
+	if !trns.is_empty()
+	{
+		write_chunk(&mut png, b"tRNS", &trns);
+	}
+	write_chunk(&mut png, b"IDAT", &zlib_stored(&raw));
+	write_chunk(&mut png, b"IEND", &[]);
+	
+	Ok(Some(png))
+}
+
+/// Read a fixed palette of RGB colors.
+/// PNG data contributes the distinct colors of its visible (not fully transparent) pixels, in the order they first appear.
+/// Anything else is read as text with one RRGGBB hex color per line, optionally prefixed with #; blank lines are ignored.
+/// RRGGBBAA colors (as written by format_palette) are accepted too, their alpha is ignored.
+pub fn parse_palette(data: &[u8]) -> Result<Vec<[u8; 3]>>
+{
+	let mut palette: Vec<[u8; 3]> = Vec::new();
+	
+	if data.starts_with(PNG_SIGNATURE)
+	{
+		let image: RgbaImage = image::load_from_memory(data)
+			.map_err(|e| MinifyError::Decode(format!("Failed to decode palette image: {}", e)))?
+			.to_rgba8();
+		
+		let mut seen: HashSet<[u8; 3]> = HashSet::new();
+		for pixel in image.pixels()
+		{
+			let rgb: [u8; 3] = [pixel[0], pixel[1], pixel[2]];
+			if pixel[3] > 0 && seen.insert(rgb)
+			{
+				palette.push(rgb);
+			}
+		}
+	}
+	else
+	{
+		let text: String = String::from_utf8_lossy(data).into_owned();
+		for (line_index, line) in text.lines().enumerate()
+		{
+			let line: &str = line.trim();
+			if line.is_empty()
+			{
+				continue;
+			}
+			
+			let rgb: [u8; 3] = parse_hex_color(line)
+				.ok_or_else(|| MinifyError::InvalidOptions(format!("Invalid palette color '{}' on line {} (expected RRGGBB)", line, line_index + 1)))?;
+			if !palette.contains(&rgb)
+			{
+				palette.push(rgb);
+			}
+		}
+	}
+	
+	if palette.is_empty()
+	{
+		return Err(MinifyError::InvalidOptions("Palette has no colors".to_string()));
+	}
+	
+	if palette.len() > MAX_PALETTE_SIZE
+	{
+		return Err(MinifyError::InvalidOptions(format!("Palette has {} colors, at most {} are supported", palette.len(), MAX_PALETTE_SIZE)));
+	}
+	
+	Ok(palette)
+}
+
+/// Write a palette as text, one #RRGGBB color per line (#RRGGBBAA for translucent colors).
+pub fn format_palette(palette: &[[u8; 4]]) -> String
+{
+	let mut text: String = String::with_capacity(palette.len() * 10);
+	for color in palette
+	{
+		if color[3] == 255
+		{
+			text.push_str(&format!("#{:02X}{:02X}{:02X}\n", color[0], color[1], color[2]));
+		}
+		else
+		{
+			text.push_str(&format!("#{:02X}{:02X}{:02X}{:02X}\n", color[0], color[1], color[2], color[3]));
+		}
+	}
+	
+	text
+}
+
+/// Parse an RRGGBB (or RRGGBBAA, ignoring alpha) hex color, optionally prefixed with #.
+pub fn parse_hex_color(text: &str) -> Option<[u8; 3]>
+{
+	let hex: &str = text.strip_prefix('#').unwrap_or(text);
+	if hex.len() != 6 && hex.len() != 8
+	{
+		return None;
+	}
+	for c in hex.chars()
+	{
+		if !c.is_ascii_hexdigit()
+		{
+			return None;
+		}
+	}
+	
+	let channel = |start: usize| -> Option<u8> { u8::from_str_radix(&hex[start..start + 2], 16).ok() };
+	Some([channel(0)?, channel(2)?, channel(4)?])
+}
+
+/// Collect the distinct RGBA colors of an image.
+/// Returns None as soon as there are more than a palette can hold.
+fn collect_palette(rgba: &RgbaImage) -> Option<Vec<[u8; 4]>>
+{
+	let mut seen: HashSet<[u8; 4]> = HashSet::new();
+	let mut palette: Vec<[u8; 4]> = Vec::new();
+	for pixel in rgba.pixels()
+	{
+		if seen.insert(pixel.0)
+		{
+			if palette.len() == MAX_PALETTE_SIZE
+			{
+				return None;
+			}
+			palette.push(pixel.0);
+		}
+	}
+	
+	Some(palette)
+}
+
+/// Checks if every pixel is either fully transparent or fully opaque.
+fn has_binary_alpha(rgba: &RgbaImage) -> bool
+{
+	for pixel in rgba.pixels()
+	{
+		if pixel[3] != 0 && pixel[3] != 255
+		{
+			return false;
+		}
+	}
+	
+	true
+}
+
+/// Append a PNG chunk (length, type, data and CRC).
+fn write_chunk(png: &mut Vec<u8>, chunk_type: &[u8; 4], data: &[u8])
+{
+	png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+	
+	let crc_start: usize = png.len();
+	png.extend_from_slice(chunk_type);
+	png.extend_from_slice(data);
+	let crc: u32 = crc_utils::hash(&png[crc_start..]);
+	
+	png.extend_from_slice(&crc.to_be_bytes());
+}
+
+/// Wrap data in a zlib stream made of stored (uncompressed) deflate blocks.
+fn zlib_stored(data: &[u8]) -> Vec<u8>
+{
+	let block_count: usize = data.len().div_ceil(MAX_STORED_BLOCK).max(1);
+	let mut out: Vec<u8> = Vec::with_capacity(data.len() + block_count * 5 + 6);
+	
+	// Zlib header: deflate with a 32K window, no preset dictionary.
+	out.push(0x78);
+	out.push(0x01);
+	
+	for block_index in 0..block_count
+	{
+		let start: usize = block_index * MAX_STORED_BLOCK;
+		let end: usize = (start + MAX_STORED_BLOCK).min(data.len());
+		let length: u16 = (end - start) as u16;
+		
+		let is_final: bool = block_index + 1 == block_count;
+		out.push(if is_final { 1 } else { 0 });
+		out.extend_from_slice(&length.to_le_bytes());
+		out.extend_from_slice(&(!length).to_le_bytes());
+		out.extend_from_slice(&data[start..end]);
+	}
+	
+	out.extend_from_slice(&adler32(data).to_be_bytes());
+	out
+}
+
+/// Adler-32 checksum used by the zlib trailer.
+fn adler32(data: &[u8]) -> u32
+{
+	const MODULUS: u32 = 65521;
+	
+	let mut a: u32 = 1;
+	let mut b: u32 = 0;
+	for &byte in data
+	{
+		a = (a + byte as u32) % MODULUS;
+		b = (b + a) % MODULUS;
+	}
+	
+	(b << 16) | a
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+	use crate::minify::{self, DitheringMode};
+	use crate::test_utils;
+	
+	/// Data of the first chunk of a type, if any.
+	fn chunk_data(png_data: &[u8], wanted_type: &[u8; 4]) -> Option<Vec<u8>>
+	{
+		let mut pos: usize = PNG_SIGNATURE.len();
+		while pos + 12 <= png_data.len()
+		{
+			let length: usize = u32::from_be_bytes([png_data[pos], png_data[pos + 1], png_data[pos + 2], png_data[pos + 3]]) as usize;
+			if &png_data[pos + 4..pos + 8] == wanted_type
+			{
+				return Some(png_data[pos + 8..pos + 8 + length].to_vec());
+			}
+			pos += 12 + length;
+		}
+		
+		None
+	}
+	
+	/// Opaque color blocks with a transparent block whose hidden colors all differ, as left behind by editors.
+	fn image_with_one_transparent_color() -> RgbaImage
+	{
+		let mut rgba: RgbaImage = test_utils::blocks(64, 64, 16, &[[200, 40, 40, 255], [40, 200, 40, 255], [40, 40, 200, 255], [0, 0, 0, 0]]);
+		let hidden: RgbaImage = test_utils::photo_like(64, 64);
+		for (pixel, hidden_pixel) in rgba.pixels_mut().zip(hidden.pixels())
+		{
+			if pixel[3] == 0
+			{
+				pixel.0 = [hidden_pixel[0], hidden_pixel[1], hidden_pixel[2], 0];
+			}
+		}
+		
+		rgba
+	}
+	
+	#[test]
+	fn single_transparent_color_gets_one_trns_entry()
+	{
+		let rgba: RgbaImage = image_with_one_transparent_color();
+		let options: MinifyOptions = MinifyOptions::builder().build().expect("Valid options");
+		
+		let png: Vec<u8> = encode_indexed_png(&rgba, &options).expect("Encoding failed").expect("Image fits in a palette");
+		let ihdr: Vec<u8> = chunk_data(&png, b"IHDR").expect("Missing IHDR");
+		assert_eq!(ihdr[9], 3);
+		assert_eq!(chunk_data(&png, b"PLTE").expect("Missing PLTE").len(), 4 * 3);
+		assert_eq!(chunk_data(&png, b"tRNS").expect("Missing tRNS"), vec![0]);
+		
+		// Only the hidden colors changed.
+		let decoded: RgbaImage = test_utils::decode_png(&png);
+		for (decoded_pixel, source_pixel) in decoded.pixels().zip(rgba.pixels())
+		{
+			assert!(decoded_pixel == source_pixel || (decoded_pixel[3] == 0 && source_pixel[3] == 0));
+		}
+	}
+	
+	#[test]
+	fn indexed_output_keeps_trns_instead_of_an_alpha_channel()
+	{
+		let rgba: RgbaImage = image_with_one_transparent_color();
+		let options: MinifyOptions = MinifyOptions::builder().indexed(true).dithering(DitheringMode::None).fast_deflate(true).build().expect("Valid options");
+		
+		let (output, _, _, _): (Vec<u8>, DitheringMode, u8, Option<crate::dithering::ImageClass>) = minify::minify_bytes(&test_utils::encode_png(&rgba), &options).expect("Minification failed");
+		
+		assert!(minify::is_already_minified(&output).expect("Marker check failed").0);
+		
+		// Color types 4 and 6 carry a full alpha channel.
+		let color_type: u8 = chunk_data(&output, b"IHDR").expect("Missing IHDR")[9];
+		assert!(color_type != 4 && color_type != 6, "color type {}", color_type);
+		assert!(chunk_data(&output, b"tRNS").is_some());
+		assert_eq!(test_utils::decode_png(&output).pixels().filter(|pixel| pixel[3] == 0).count(), 16 * 16 * 4);
+	}
+	
+	#[test]
+	fn indexed_output_is_palettized_and_round_trips_the_pixels()
+	{
+		let colors: [[u8; 4]; 5] = [[200, 30, 40, 255], [20, 160, 90, 255], [10, 20, 250, 128], [255, 255, 255, 255], [0, 0, 0, 0]];
+		let rgba: RgbaImage = test_utils::blocks(40, 24, 4, &colors);
+		let options: MinifyOptions = MinifyOptions::builder().indexed(true).build().expect("Valid options");
+		
+		let indexed: Vec<u8> = encode_indexed_png(&rgba, &options).expect("Encoding failed").expect("Image fits in a palette");
+		assert_eq!(chunk_data(&indexed, b"IHDR").expect("Missing IHDR")[9], 3);
+		assert_eq!(test_utils::decode_png(&indexed), rgba);
+		
+		// The oxipng pass keeps the palette.
+		let source: Vec<u8> = test_utils::encode_png(&test_utils::photo_like(128, 64));
+		let options: MinifyOptions = MinifyOptions::builder().quality(20).indexed(true).fast_deflate(true).build().expect("Valid options");
+		let (output, _, _, _): (Vec<u8>, DitheringMode, u8, Option<crate::dithering::ImageClass>) = minify::minify_bytes(&source, &options).expect("Minification failed");
+		assert_eq!(chunk_data(&output, b"IHDR").expect("Missing IHDR")[9], 3);
+		assert_eq!(test_utils::decode_png(&output).dimensions(), (128, 64));
+	}
+}
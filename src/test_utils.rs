@@ -0,0 +1,86 @@
+use image::{ImageFormat, RgbaImage};
+use std::io::Cursor;
+
+// Synthetic images for the unit tests: gradients, solid blocks and in-memory PNG encoding.
+
+/// Horizontal gray gradient from black (left) to white (right), fully opaque.
+pub(crate) fn gradient(width: u32, height: u32) -> RgbaImage
+{
+	RgbaImage::from_fn(width, height, |x, _|
+	{
+		let level: u8 = (x * 255 / (width - 1).max(1)) as u8;
+		image::Rgba([level, level, level, 255])
+	})
+}
+
+/// Color ramps with fine noise on top, which quantization shrinks noticeably (unlike smooth ramps that already compress well).
+pub(crate) fn noisy_ramps(width: u32, height: u32) -> RgbaImage
+{
+	RgbaImage::from_fn(width, height, |x, y|
+	{
+		// Integer hash of the position, so the noise has no pattern the deflate pass could pick up.
+		let mut hash: u32 = x.wrapping_mul(0x9E37_79B1) ^ y.wrapping_mul(0x85EB_CA77);
+		hash ^= hash >> 15;
+		hash = hash.wrapping_mul(0x2C1B_3C6D);
+		hash ^= hash >> 12;
+		let noise: u32 = hash % 13;
+		image::Rgba([(x + noise) as u8, (y * 3 + noise) as u8, (x + y + noise) as u8, 255])
+	})
+}
+
+/// Photo-like image: every pixel gets an unrelated color, so neighbors differ strongly and the colors are spread widely.
+pub(crate) fn photo_like(width: u32, height: u32) -> RgbaImage
+{
+	RgbaImage::from_fn(width, height, |x, y|
+	{
+		let mut hash: u32 = x.wrapping_mul(0x9E37_79B1) ^ y.wrapping_mul(0x85EB_CA77);
+		hash ^= hash >> 15;
+		hash = hash.wrapping_mul(0x2C1B_3C6D);
+		hash ^= hash >> 12;
+		image::Rgba([hash as u8, (hash >> 8) as u8, (hash >> 16) as u8, 255])
+	})
+}
+
+/// Image filled with one color.
+pub(crate) fn solid(width: u32, height: u32, color: [u8; 4]) -> RgbaImage
+{
+	RgbaImage::from_pixel(width, height, image::Rgba(color))
+}
+
+/// Image made of solid square blocks of block_size pixels, cycling through the colors row by row.
+pub(crate) fn blocks(width: u32, height: u32, block_size: u32, colors: &[[u8; 4]]) -> RgbaImage
+{
+	let blocks_per_row: u32 = width.div_ceil(block_size);
+	RgbaImage::from_fn(width, height, |x, y|
+	{
+		let block_index: u32 = (y / block_size) * blocks_per_row + x / block_size;
+		image::Rgba(colors[block_index as usize % colors.len()])
+	})
+}
+
+/// Encode an image as an RGBA PNG in memory.
+pub(crate) fn encode_png(rgba: &RgbaImage) -> Vec<u8>
+{
+	let mut buffer: Vec<u8> = Vec::new();
+	image::DynamicImage::ImageRgba8(rgba.clone()).write_to(&mut Cursor::new(&mut buffer), ImageFormat::Png).expect("Encoding a test image failed");
+	buffer
+}
+
+/// Decode PNG data into RGBA pixels.
+pub(crate) fn decode_png(png_data: &[u8]) -> RgbaImage
+{
+	image::load_from_memory(png_data).expect("Decoding a test image failed").to_rgba8()
+}
+
+/// Distinct RGBA colors of an image, sorted.
+pub(crate) fn distinct_colors(rgba: &RgbaImage) -> Vec<[u8; 4]>
+{
+	let mut colors: Vec<[u8; 4]> = Vec::new();
+	for pixel in rgba.pixels()
+	{
+		colors.push(pixel.0);
+	}
+	colors.sort();
+	colors.dedup();
+	colors
+}
@@ -1,7 +1,94 @@
+use std::collections::HashSet;
 use std::path::{Path, PathBuf};
 use std::env;
+use std::time::Duration;
 use anyhow::{anyhow, Result};
 
+use crate::config::{self, Config};
+use crate::dithering::{QualityPerType, SelectionThresholds};
+use crate::minify::{self, IfExists, InterlaceMode, Quantizer, ScanOrder};
+use crate::utils::file_utils::{self, SortOrder};
+use crate::utils::report_utils::{self, ReportFormat};
+use crate::utils::time_utils;
+
+/// Named quality levels for --preset, from smallest files to best quality.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QualityPreset
+{
+	/// Smallest files (quality 25).
+	Tiny,
+	
+	/// Small files, the default level (quality 40).
+	Small,
+	
+	/// Small files with very good quality (quality 50).
+	Balanced,
+	
+	/// Medium files with excellent quality (quality 60).
+	High,
+	
+	/// Larger files with near-perfect quality (quality 80).
+	Max,
+}
+
+impl QualityPreset
+{
+	/// Quality level the preset stands for, in the middle of the matching range of the settings description.
+	pub fn quality(self) -> u8
+	{
+		match self
+		{
+			QualityPreset::Tiny => 25,
+			QualityPreset::Small => 40,
+			QualityPreset::Balanced => 50,
+			QualityPreset::High => 60,
+			QualityPreset::Max => 80,
+		}
+	}
+	
+	/// Preset name as given on the command line.
+	pub fn name(self) -> &'static str
+	{
+		match self
+		{
+			QualityPreset::Tiny => "tiny",
+			QualityPreset::Small => "small",
+			QualityPreset::Balanced => "balanced",
+			QualityPreset::High => "high",
+			QualityPreset::Max => "max",
+		}
+	}
+}
+
+/// Parse a --preset name.
+pub fn parse_preset(name: &str) -> Option<QualityPreset>
+{
+	match name.to_lowercase().as_str()
+	{
+		"tiny" => Some(QualityPreset::Tiny),
+		"small" => Some(QualityPreset::Small),
+		"balanced" => Some(QualityPreset::Balanced),
+		"high" => Some(QualityPreset::High),
+		"max" => Some(QualityPreset::Max),
+		_ => None,
+	}
+}
+
+/// Short flags that take a value. In a bundle of short flags they must come last.
+const VALUE_SHORT_FLAGS: &[char] = &['D', 'o', 'x', 'j', 'q', 'd', 'm', 's', 'r'];
+
+/// Environment variable with the default quality level.
+const ENV_QUALITY: &str = "MINIPNG_QUALITY";
+
+/// Environment variable with the default dithering mode.
+const ENV_DITHERING: &str = "MINIPNG_DITHERING";
+
+/// Environment variable with the default number of parallel jobs.
+const ENV_JOBS: &str = "MINIPNG_JOBS";
+
+/// Dithering mode names accepted by --dithering (without a ":N" palette size).
+const DITHERING_MODES: &[&str] = &["auto", "none", "ordered", "bluenoise", "blue-noise", "floyd", "floyd-steinberg", "mediancut", "median"];
+
 #[derive(Debug)]
 pub struct Args
 {
@@ -12,10 +99,94 @@ pub struct Args
 	/// Directory to scan for PNG files. If not provided, current directory is used.
 	pub dir: Option<PathBuf>,
 	
+	/// Ignore .minipngignore files during directory scanning.
+	pub no_ignore: bool,
+	
+	/// Follow symbolic links during directory scanning (symlink cycles are detected).
+	pub follow_symlinks: bool,
+	
+	/// Only scan the top level of the directory, without descending into subdirectories.
+	pub no_recurse: bool,
+	
+	/// Read PNG data from stdin and write the result to stdout (given as a "-" argument).
+	pub stdin: bool,
+	
 	/// Process files in-place (always overwrites original files).
 	/// This is the default behavior and the flag is kept for backward compatibility.
 	pub inplace: bool,
 	
+	/// Write the output here instead of in place: a file name for a single input (or any path ending in .png),
+	/// otherwise a directory mirroring the inputs' paths.
+	pub output: Option<PathBuf>,
+	
+	/// What to do when writing to a separate target file that already exists.
+	pub if_exists: IfExists,
+	
+	/// Write read-only targets by clearing the read-only flag for the write and restoring it afterward.
+	/// Without it, read-only targets are skipped.
+	pub force_writable: bool,
+	
+	/// Patterns of files to exclude from directory scanning (e.g. "*.min.png", "build/**").
+	pub excludes: Vec<String>,
+	
+	/// Explicit configuration file. If not provided, .minipng.toml is discovered by walking up from the current directory.
+	pub config: Option<PathBuf>,
+	
+	/// Ignore configuration files entirely.
+	pub no_config: bool,
+	
+	/// Write a CSV report with one row per processed file.
+	pub csv: Option<PathBuf>,
+	
+	/// Write a JSON manifest mapping each processed file to its output, with sizes and SHA-256 hashes.
+	pub manifest: Option<PathBuf>,
+	
+	/// What the end-of-run summary emits: human-readable text, JSON or CSV on stdout, or nothing.
+	/// With anything but human, the per-file output goes to stderr.
+	pub report: ReportFormat,
+	
+	/// Write a before/after comparison image of the single input file here instead of minifying it.
+	pub compare: Option<PathBuf>,
+	
+	/// Write the median cut palette of the single input file here (one hex color per line) instead of minifying it.
+	pub dump_palette: Option<PathBuf>,
+	
+	/// Write a montage of the single input file quantized with each dithering mode here instead of minifying it.
+	pub preview: Option<PathBuf>,
+	
+	/// Only list the files that would be processed (source and target), then exit without decoding anything.
+	pub list_only: bool,
+	
+	/// Estimate the savings with a fast compressor instead of minifying, without writing anything.
+	pub estimate: bool,
+	
+	/// Print the settings recorded in each listed file's marker, then exit without changing anything.
+	pub info: bool,
+	
+	/// Process the directory as it is walked, keeping only running totals instead of every file and result.
+	pub stream: bool,
+	
+	/// Skip the files listed in the .minipng-progress file of an interrupted run, and record each completed file there.
+	pub resume: bool,
+	
+	/// Read and write the .minipng-cache.json analysis cache in the scanned directory.
+	pub cache: bool,
+	
+	/// Directory to copy each file into before it is overwritten.
+	pub backup_dir: Option<PathBuf>,
+	
+	/// Directory for the temporary files outputs are written to before they replace their targets.
+	pub temp_dir: Option<PathBuf>,
+	
+	/// Keyword of the MiniPNG marker chunk (software attribution) instead of the default.
+	pub marker_text: Option<String>,
+	
+	/// File to append the printed lines to, timestamped and without colors.
+	pub log_file: Option<PathBuf>,
+	
+	/// Backup directory to restore the files from (copies every backup back, then exits).
+	pub restore: Option<PathBuf>,
+	
 	// 2. Operation Mode Parameters.
 	/// Use lossless compression only.
 	pub lossless: bool,
@@ -23,11 +194,95 @@ pub struct Args
 	/// Force re-minification of already-minified files without prompting.
 	pub force: bool,
 	
+	/// When re-minifying an already-minified file, use the mode, quality and dithering recorded in its marker.
+	pub reuse_settings: bool,
+	
+	/// Re-minify already-minified files whose marker is older than this time (seconds since the Unix epoch).
+	pub reminify_before: Option<u64>,
+	
 	/// Skip already-minified files without prompting (default for batch operations).
 	pub skip: bool,
 	
+	/// Process files one at a time and ask before re-minifying each already-minified file.
+	pub interactive: bool,
+	
+	/// Number of files to process in parallel (0 = use all cores).
+	pub jobs: usize,
+	
+	/// Number of threads oxipng uses for each file, independent of --jobs (0 = split the jobs between the files).
+	pub threads_per_file: usize,
+	
+	/// Decode the output again and check it against the source before writing.
+	/// In lossless mode, the pixels must be identical.
+	pub verify: bool,
+	
+	/// Count the pixels lossless output changed and reject outputs that changed any.
+	pub diff_pixels: bool,
+	
+	/// Strip all ancillary chunks instead of only the ones that are safe to remove.
+	/// The minification marker is still added afterward.
+	pub strip_all: bool,
+	
+	/// Minimum size reduction (percent) required to rewrite a file.
+	/// Smaller reductions are treated as "no reduction" and the original is kept.
+	pub min_reduction: f64,
+	
+	/// Write the re-encoded output even when it isn't smaller (lossless mode only), e.g. for a normalization pass.
+	pub always_write: bool,
+	
+	/// Don't write files that couldn't be reduced to the output, leaving the output tree sparse.
+	pub skip_unchanged_output: bool,
+	
+	/// Also write a lossless WebP next to each PNG output.
+	pub also_webp: bool,
+	
+	/// Minify files that look already quantized by another tool losslessly instead of quantizing them again.
+	pub safe: bool,
+	
+	/// Skip images with more pixels than this (width × height from IHDR), to bound memory use.
+	pub max_pixels: Option<u64>,
+	
+	/// Interlacing of the output: none (de-interlace), adam7 or keep the source's.
+	pub interlace: InterlaceMode,
+	
+	/// Pixel order of Floyd-Steinberg error diffusion: serpentine rows or a Hilbert curve.
+	pub scan: ScanOrder,
+	
+	/// Mark files that lossy minification couldn't reduce, so later runs skip them.
+	pub mark_noop: bool,
+	
+	/// Minify files losslessly when lossy output isn't smaller and lossless output is.
+	pub fallback_lossless: bool,
+	
+	/// Minify each file both lossy and losslessly, keeping the lossy output only when it is worth its quality loss.
+	pub auto_mode: bool,
+	
+	/// Remove bytes after the IEND chunk.
+	pub strip_trailing: bool,
+	
+	/// Produce byte-identical outputs for identical inputs.
+	pub deterministic: bool,
+	
+	/// Print per-file processing times.
+	pub verbose: bool,
+	
+	/// Exit with a distinct code when no file got smaller.
+	pub fail_on_no_savings: bool,
+	
+	/// Disable colored output (also disabled when NO_COLOR is set or stdout is not a terminal).
+	pub no_color: bool,
+	
+	/// Number of files to list in the "top savings" summary (0 = hide the list).
+	pub top: usize,
+	
+	/// Order to process the files in (name, size) or to list them in the summary (savings).
+	pub sort: Option<SortOrder>,
+	
+	/// Stop starting new files once the run has taken this long; files in progress are finished.
+	pub time_budget: Option<Duration>,
+	
 	// 3. Image Quality Parameters.
-	/// Quality level for lossy compression (1-100). Higher = better quality, larger file.
+	/// Quality level for lossy compression (1-100, or AUTO_QUALITY to pick it per image). Higher = better quality, larger file.
 	/// Default is 40 which provides good quality with aggressive compression (~700-930KB for 3MB file).
 	/// Common values: 40 (aggressive - default), 50 (balanced), 60 (high quality), 70 (excellent quality).
 	pub quality: u8,
@@ -36,10 +291,28 @@ pub struct Args
 	/// auto = automatic selection based on image analysis (default).
 	/// none = no dithering (cleanest for gradients, may show banding).
 	/// ordered = Bayer dithering (balanced pattern).
+	/// bluenoise = Blue-noise dithering (balanced, without a visible pattern).
 	/// floyd = Floyd-Steinberg error diffusion (best for photos, can be noisy).
 	/// median = Median cut color quantization (excellent palette quality).
+	/// median:N sets the median cut palette size (see median_colors).
 	pub dithering: String,
 	
+	/// Median cut palette size given as "median:N" (2-256), overriding the size derived from the quality level.
+	pub median_colors: Option<usize>,
+	
+	/// Downsampling factors for R, G and B (1-128), overriding the factor derived from the quality level.
+	/// A single value applies to all three channels.
+	pub factors: Option<[u8; 3]>,
+	
+	/// Quality levels for photo-like and flat images ("photo=70,flat=35"); other images use quality.
+	pub quality_per_type: Option<QualityPerType>,
+	
+	/// Named quality level (tiny, small, balanced, high, max), overridden by an explicit quality.
+	pub preset: Option<QualityPreset>,
+	
+	/// Palette file (a PNG or a list of RRGGBB colors) to map every pixel onto instead of quantizing by quality.
+	pub palette_from: Option<PathBuf>,
+	
 	// 4. Advanced Image Processing Parameters.
 	/// Pre-quantization smoothing radius (0.0-5.0, 0 = off).
 	/// Applies Gaussian blur before color reduction to smooth gradients.
@@ -52,6 +325,69 @@ pub struct Args
 	/// Use when you see grainy dots in smooth areas after processing.
 	pub denoise: bool,
 	
+	/// Denoising strength (0.0-1.0, default 0.5). Higher values filter noisier blocks and, above 0.75, use a wider median filter.
+	pub denoise_strength: f32,
+	
+	/// Size of the blocks analyzed for denoising (3-64 pixels, default 8).
+	pub denoise_block: u32,
+	
+	/// Force grayscale output in lossy mode, even for images with color.
+	/// Images whose pixels are all gray are always stored as grayscale.
+	pub grayscale: bool,
+	
+	/// Collapse alpha to fully transparent (below threshold) or fully opaque (at or above) in lossy mode.
+	/// Useful for sprites targeting engines that only support on/off transparency.
+	pub alpha_threshold: Option<u8>,
+	
+	/// Quantize and average in linear light instead of gamma-encoded sRGB.
+	/// Avoids darkening soft gradients such as skies at a small speed cost.
+	pub linear: bool,
+	
+	/// Encoding gamma assumed by linear light mode instead of sRGB, e.g. 1.0 for pre-linearized images.
+	pub gamma: Option<f32>,
+	
+	/// Quantize in premultiplied alpha, so hidden colors of transparent pixels don't fringe soft edges.
+	pub premultiply: bool,
+	
+	/// Composite images onto this opaque background color, so the output has no alpha channel.
+	pub flatten: Option<[u8; 3]>,
+	
+	/// Quantize the alpha channel alongside RGB so soft shadows and glows benefit from color reduction.
+	/// Fully opaque pixels are never affected.
+	pub quantize_alpha: bool,
+	
+	/// Diffuse the alpha thresholding error (Floyd-Steinberg) for smoother edges. Requires --alpha-threshold.
+	pub dither_alpha: bool,
+	
+	/// Keep the pHYs chunk (physical pixel dimensions, DPI), which lossy re-encoding would otherwise drop.
+	pub keep_phys: bool,
+	
+	/// Keep textual metadata (tEXt, zTXt and iTXt chunks such as copyright and author), which lossy re-encoding would otherwise drop.
+	pub keep_text: bool,
+	
+	/// Write an indexed-color (palette) PNG with up to 256 colors in lossy mode.
+	/// Images with more colors are reduced with median cut first, unless they have semi-transparent pixels.
+	pub indexed: bool,
+	
+	/// Keep the bit depth and color type instead of letting oxipng reduce them.
+	pub keep_bit_depth: bool,
+	
+	/// Refine the median-cut palette with a few k-means iterations (slower, closer colors).
+	pub refine: bool,
+	
+	/// Pixel sampling step for building the median-cut palette (0 = pick from the image size).
+	pub sample_step: usize,
+	
+	/// Palette quantizer of median dithering and indexed output: median cut or octree.
+	pub quantizer: Quantizer,
+	
+	/// Overrides for the automatic dithering selection thresholds (hidden option).
+	/// Given as comma-separated key=value pairs, e.g. "photo_edge=0.3,low_variance=150".
+	pub thresholds: SelectionThresholds,
+	
+	/// Options given on the command line as typed (e.g. "-q" or "--quality"), so the environment and config files don't override them.
+	pub explicit: HashSet<String>,
+	
 	// 5. Program Metadata.
 	/// Program version info.
 	pub version: String,
@@ -71,15 +407,93 @@ impl Args
 		Args
 		{
 			files: Vec::new(),
+			stdin: false,
+			no_ignore: false,
+			follow_symlinks: false,
+			no_recurse: false,
 			dir: None,
 			inplace: true,
+			output: None,
+			if_exists: IfExists::Overwrite,
+			force_writable: false,
+			excludes: Vec::new(),
+			config: None,
+			no_config: false,
+			cache: false,
+			backup_dir: None,
+			temp_dir: None,
+			marker_text: None,
+			log_file: None,
+			restore: None,
+			compare: None,
+			dump_palette: None,
+			preview: None,
+			list_only: false,
+			estimate: false,
+			info: false,
+			stream: false,
+			resume: false,
+			csv: None,
+			manifest: None,
+			report: ReportFormat::Human,
 			lossless: false,
 			quality: 40,
 			force: false,
+			reuse_settings: false,
+			reminify_before: None,
 			skip: false,
+			interactive: false,
+			jobs: 0,
+			threads_per_file: 0,
+			verify: false,
+			diff_pixels: false,
+			strip_all: false,
+			min_reduction: 0.0,
+			max_pixels: None,
+			interlace: InterlaceMode::None,
+			scan: ScanOrder::Serpentine,
+			mark_noop: false,
+			fallback_lossless: false,
+			auto_mode: false,
+			strip_trailing: false,
+			deterministic: false,
+			always_write: false,
+			skip_unchanged_output: false,
+			also_webp: false,
+			safe: false,
+			verbose: false,
+			fail_on_no_savings: false,
+			top: 10,
+			sort: None,
+			time_budget: None,
+			no_color: false,
 			dithering: "floyd".to_string(),
+			median_colors: None,
+			factors: None,
+			quality_per_type: None,
+			preset: None,
+			palette_from: None,
 			smooth: 0.0,
 			denoise: false,
+			denoise_strength: minify::DEFAULT_DENOISE_STRENGTH,
+			denoise_block: minify::DEFAULT_DENOISE_BLOCK,
+			grayscale: false,
+			alpha_threshold: None,
+			dither_alpha: false,
+			quantize_alpha: false,
+			linear: false,
+			gamma: None,
+			premultiply: false,
+			flatten: None,
+			indexed: false,
+			keep_bit_depth: false,
+			keep_phys: false,
+			keep_text: false,
+			refine: false,
+			sample_step: 0,
+			quantizer: Quantizer::Median,
+			thresholds: SelectionThresholds::new(),
+			explicit: HashSet::new(),
 			version: env!("CARGO_PKG_VERSION").to_string(),
 			author: env!("CARGO_PKG_AUTHORS").to_string(),
 			about: env!("CARGO_PKG_DESCRIPTION").to_string(),
@@ -89,8 +503,6 @@ impl Args
 	/// Parse command line arguments and return an Args struct.
 	pub fn parse() -> Result<Self>
 	{
-		let mut args: Args = Args::new();
-		
 		// Get all command line arguments.
 		let mut cli_args: Vec<String> = Vec::new();
 		for arg in env::args()
@@ -104,12 +516,33 @@ impl Args
 			cli_args.remove(0);
 		}
 		
+		Args::parse_from(cli_args)
+	}
+	
+	/// Parse the given arguments (without the program name) and return an Args struct.
+	pub fn parse_from(cli_args: Vec<String>) -> Result<Self>
+	{
+		Self::parse_with_env(cli_args, env_value)
+	}
+	
+	/// Parse the given arguments, reading the MINIPNG_* defaults through env_var (see apply_env).
+	#[allow(clippy::println_empty_string)]
+	fn parse_with_env<E>(mut cli_args: Vec<String>, env_var: E) -> Result<Self> where E: Fn(&str) -> Option<String>
+	{
+		let mut args: Args = Args::new();
+		
 		// Process arguments.
 		let mut i: usize = 0;
 		while i < cli_args.len()
 		{
 			let arg: &String = &cli_args[i];
 			
+			// Remember the options given (values are consumed by their option, and bundles are recorded once expanded).
+			if arg.len() > 1 && arg.starts_with('-') && arg != "--" && !is_short_flag_bundle(arg)
+			{
+				args.explicit.insert(arg.clone());
+			}
+			
 			match arg.as_str()
 			{
 				// 1. Input/Output Parameters.
@@ -125,177 +558,1434 @@ impl Args
 						return Err(anyhow!("Missing value for {} argument", arg));
 					}
 				}
+				"-" =>
+				{
+					args.stdin = true;
+				}
 				"--inplace" | "-i" =>
 				{
 					args.inplace = true;
 				}
-				
-				// 2. Operation Mode Parameters.
-				"--lossless" | "-L" =>
+				"--output" | "-o" =>
 				{
-					args.lossless = true;
+					if i + 1 < cli_args.len()
+					{
+						i += 1;
+						args.output = Some(PathBuf::from(&cli_args[i]));
+						args.inplace = false;
+					}
+					else
+					{
+						return Err(anyhow!("Missing value for {} argument", arg));
+					}
 				}
-				"--force" | "-F" =>
+				"--if-exists" =>
 				{
-					args.force = true;
+					if i + 1 < cli_args.len()
+					{
+						i += 1;
+						args.if_exists = match minify::parse_if_exists(&cli_args[i])
+						{
+							Some(policy) => policy,
+							None => return Err(anyhow!("Invalid if-exists policy: {}. Valid options are: skip, overwrite, error", cli_args[i])),
+						};
+					}
+					else
+					{
+						return Err(anyhow!("Missing value for {} argument", arg));
+					}
 				}
-				"--skip" | "-S" =>
+				"--force-writable" =>
 				{
-					args.skip = true;
+					args.force_writable = true;
 				}
-				
-				// 3. Image Quality Parameters.
-				"--quality" | "-q" =>
+				"--also-webp" =>
+				{
+					args.also_webp = true;
+				}
+				"--exclude" | "-x" =>
 				{
 					if i + 1 < cli_args.len()
 					{
 						i += 1;
-						let value: u8 = cli_args[i].parse::<u8>().map_err(|_| anyhow!("Invalid quality value: must be an integer between 1 and 100"))?;
-						args.quality = value;
+						args.excludes.push(cli_args[i].to_string());
 					}
 					else
 					{
 						return Err(anyhow!("Missing value for {} argument", arg));
 					}
 				}
-				"--dithering" | "-d" | "-m" => // Keep -m for backward compatibility.
+				"--config" =>
 				{
 					if i + 1 < cli_args.len()
 					{
 						i += 1;
-						args.dithering = cli_args[i].to_string();
+						args.config = Some(PathBuf::from(&cli_args[i]));
 					}
 					else
 					{
 						return Err(anyhow!("Missing value for {} argument", arg));
 					}
 				}
-				
-				// 4. Advanced Image Processing Parameters.
-				"--smooth" | "-s" | "-r" => // Keep -r for backward compatibility.
+				"--report" =>
 				{
 					if i + 1 < cli_args.len()
 					{
 						i += 1;
-						let value: f32 = cli_args[i].parse::<f32>().map_err(|_| anyhow!("Invalid smooth value: must be a number between 0.0 and 5.0"))?;
-						args.smooth = value;
+						args.report = match report_utils::parse_report_format(&cli_args[i])
+						{
+							Some(format) => format,
+							None => return Err(anyhow!("Invalid report format: {}. Valid options are: human, json, csv, none", cli_args[i])),
+						};
 					}
 					else
 					{
 						return Err(anyhow!("Missing value for {} argument", arg));
 					}
 				}
-				"--denoise" | "-N" =>
+				"--csv" =>
 				{
-					args.denoise = true;
+					if i + 1 < cli_args.len()
+					{
+						i += 1;
+						args.csv = Some(PathBuf::from(&cli_args[i]));
+					}
+					else
+					{
+						return Err(anyhow!("Missing value for {} argument", arg));
+					}
 				}
-				
-				// 5. Program Information.
-				"--help" | "-h" =>
+				"--manifest" =>
 				{
-					println!("{} - {}", args.about, args.version);
-					println!("By {}", args.author);
-					println!("\nUSAGE:");
-					println!("    minipng [OPTIONS] [FILES...]");
-					println!("\nOPTIONS:");
-					// Input/Output Parameters.
-					println!("  INPUT/OUTPUT:");
-					println!("    -D, --dir <DIR>              Directory to scan for PNG files");
-					println!("    -i, --inplace                Process files in-place (default)");
-					println!("");
-					// Operation Mode Parameters.
-					println!("  OPERATION MODE:");
-					println!("    -L, --lossless               Use lossless compression only");
-					println!("    -F, --force                  Force re-minification of already-minified files");
-					println!("    -S, --skip                   Skip already-minified files");
-					println!("");
-					// Image Quality Parameters.
-					println!("  IMAGE QUALITY:");
-					println!("    -q, --quality <QUALITY>      Quality level (1-100, default: 40)");
-					println!("    -d, --dithering <MODE>       Dithering mode (auto, none, ordered, floyd, median)");
-					println!("");
-					// Advanced Image Processing Parameters.
-					println!("  ADVANCED PROCESSING:");
-					println!("    -s, --smooth <RADIUS>        Pre-quantization smoothing radius (0.0-5.0)");
-					println!("    -N, --denoise                Apply post-processing denoising");
-					println!("");
-					// General Options.
-					println!("  GENERAL:");
-					println!("    -h, --help                   Show help information");
-					println!("    -V, --version                Display version information");
-					
-					std::process::exit(0);
+					if i + 1 < cli_args.len()
+					{
+						i += 1;
+						args.manifest = Some(PathBuf::from(&cli_args[i]));
+					}
+					else
+					{
+						return Err(anyhow!("Missing value for {} argument", arg));
+					}
 				}
-				"--version" | "-V" =>
+				"--no-ignore" =>
 				{
-					println!("{} {}", env!("CARGO_PKG_NAME"), args.version);
-					std::process::exit(0);
+					args.no_ignore = true;
 				}
-				
-				// Anything else is treated as a file path if it doesn't start with "-".
-				_ =>
+				"--follow-symlinks" =>
+				{
+					args.follow_symlinks = true;
+				}
+				"--no-recurse" | "-R" =>
+				{
+					args.no_recurse = true;
+				}
+				"--no-config" =>
+				{
+					args.no_config = true;
+				}
+				"--cache" =>
+				{
+					args.cache = true;
+				}
+				"--no-cache" =>
+				{
+					args.cache = false;
+				}
+				"--backup-dir" =>
 				{
-					if !arg.starts_with('-')
+					if i + 1 < cli_args.len()
 					{
-						args.files.push(PathBuf::from(arg));
+						i += 1;
+						args.backup_dir = Some(PathBuf::from(&cli_args[i]));
 					}
 					else
 					{
-						return Err(anyhow!("Unknown option: {}", arg));
+						return Err(anyhow!("Missing value for {} argument", arg));
 					}
 				}
-			}
-			
-			i += 1;
-		}
-		
-		Ok(args)
-	}
-	
-	/// Check if an argument was explicitly provided on the command line.
-	pub fn is_explicitly_set(arg_name: &str) -> bool
-	{
-		let args: Vec<String> = env::args().collect();
-		
-		// Check if the argument is present in the command line.
-		for a in &args
-		{
-			if a == arg_name
-			{
-				return true;
-			}
-		}
-		
-		false
-	}
-	
-	/// Validate parameter values and relationships.
-	/// Returns Ok(()) if all parameters are valid, otherwise returns an error.
-	pub fn validate(&self) -> Result<()>
-	{
-		// Validate quality parameter.
-		if self.quality == 0 || self.quality > 100
-		{
-			return Err(anyhow!("Quality must be between 1 and 100"));
-		}
-		
-		// Validate smooth parameter.
-		if self.smooth < 0.0 || self.smooth > 5.0
-		{
+				"--temp-dir" =>
+				{
+					if i + 1 < cli_args.len()
+					{
+						i += 1;
+						args.temp_dir = Some(PathBuf::from(&cli_args[i]));
+					}
+					else
+					{
+						return Err(anyhow!("Missing value for {} argument", arg));
+					}
+				}
+				"--log-file" =>
+				{
+					if i + 1 < cli_args.len()
+					{
+						i += 1;
+						args.log_file = Some(PathBuf::from(&cli_args[i]));
+					}
+					else
+					{
+						return Err(anyhow!("Missing value for {} argument", arg));
+					}
+				}
+				"--marker-text" =>
+				{
+					if i + 1 < cli_args.len()
+					{
+						i += 1;
+						minify::validate_marker_text(&cli_args[i])?;
+						args.marker_text = Some(cli_args[i].clone());
+					}
+					else
+					{
+						return Err(anyhow!("Missing value for {} argument", arg));
+					}
+				}
+				"--restore" =>
+				{
+					if i + 1 < cli_args.len()
+					{
+						i += 1;
+						args.restore = Some(PathBuf::from(&cli_args[i]));
+					}
+					else
+					{
+						return Err(anyhow!("Missing value for {} argument", arg));
+					}
+				}
+				"--preview" =>
+				{
+					if i + 1 < cli_args.len()
+					{
+						i += 1;
+						args.preview = Some(PathBuf::from(&cli_args[i]));
+					}
+					else
+					{
+						return Err(anyhow!("Missing value for {} argument", arg));
+					}
+				}
+				"--compare" =>
+				{
+					if i + 1 < cli_args.len()
+					{
+						i += 1;
+						args.compare = Some(PathBuf::from(&cli_args[i]));
+					}
+					else
+					{
+						return Err(anyhow!("Missing value for {} argument", arg));
+					}
+				}
+				"--dump-palette" =>
+				{
+					if i + 1 < cli_args.len()
+					{
+						i += 1;
+						args.dump_palette = Some(PathBuf::from(&cli_args[i]));
+					}
+					else
+					{
+						return Err(anyhow!("Missing value for {} argument", arg));
+					}
+				}
+				"--list-only" | "-l" =>
+				{
+					args.list_only = true;
+				}
+				"--estimate" =>
+				{
+					args.estimate = true;
+				}
+				"--stream" =>
+				{
+					args.stream = true;
+				}
+				"--resume" =>
+				{
+					args.resume = true;
+				}
+				"--info" =>
+				{
+					args.info = true;
+				}
+				
+				// 2. Operation Mode Parameters.
+				"--lossless" | "-L" =>
+				{
+					args.lossless = true;
+				}
+				"--force" | "-F" =>
+				{
+					args.force = true;
+				}
+				"--reuse-settings" =>
+				{
+					args.reuse_settings = true;
+				}
+				"--reminify-before" =>
+				{
+					if i + 1 < cli_args.len()
+					{
+						i += 1;
+						let cutoff: u64 = time_utils::parse_iso8601(&cli_args[i])
+							.ok_or_else(|| anyhow!("Invalid date for --reminify-before: {} (expected YYYY-MM-DD or YYYY-MM-DDTHH:MM:SSZ)", cli_args[i]))?;
+						args.reminify_before = Some(cutoff);
+					}
+					else
+					{
+						return Err(anyhow!("Missing value for {} argument", arg));
+					}
+				}
+				"--skip" | "-S" =>
+				{
+					args.skip = true;
+				}
+				"--interactive" =>
+				{
+					args.interactive = true;
+				}
+				"--jobs" | "-j" =>
+				{
+					if i + 1 < cli_args.len()
+					{
+						i += 1;
+						let value: usize = cli_args[i].parse::<usize>().map_err(|_| anyhow!("Invalid jobs value: must be a non-negative integer"))?;
+						args.jobs = value;
+					}
+					else
+					{
+						return Err(anyhow!("Missing value for {} argument", arg));
+					}
+				}
+				"--threads-per-file" =>
+				{
+					if i + 1 < cli_args.len()
+					{
+						i += 1;
+						args.threads_per_file = cli_args[i].parse::<usize>().map_err(|_| anyhow!("Invalid threads per file value: must be a non-negative integer"))?;
+					}
+					else
+					{
+						return Err(anyhow!("Missing value for {} argument", arg));
+					}
+				}
+				"--verify" =>
+				{
+					args.verify = true;
+				}
+				"--diff-pixels" =>
+				{
+					args.diff_pixels = true;
+				}
+				"--strip-all" =>
+				{
+					args.strip_all = true;
+				}
+				"--strip" =>
+				{
+					if i + 1 < cli_args.len()
+					{
+						i += 1;
+						args.strip_all = match cli_args[i].to_lowercase().as_str()
+						{
+							"safe" => false,
+							"all" => true,
+							_ => return Err(anyhow!("Invalid strip level: {}. Valid options are: safe, all", cli_args[i])),
+						};
+					}
+					else
+					{
+						return Err(anyhow!("Missing value for {} argument", arg));
+					}
+				}
+				"--verbose" | "-v" =>
+				{
+					args.verbose = true;
+				}
+				"--fail-on-no-savings" =>
+				{
+					args.fail_on_no_savings = true;
+				}
+				"--no-color" =>
+				{
+					args.no_color = true;
+				}
+				"--always-write" =>
+				{
+					args.always_write = true;
+				}
+				"--skip-unchanged-output" =>
+				{
+					args.skip_unchanged_output = true;
+				}
+				"--safe" =>
+				{
+					args.safe = true;
+				}
+				"--mark-noop" =>
+				{
+					args.mark_noop = true;
+				}
+				"--fallback-lossless" =>
+				{
+					args.fallback_lossless = true;
+				}
+				"--auto-mode" =>
+				{
+					args.auto_mode = true;
+				}
+				"--strip-trailing" =>
+				{
+					args.strip_trailing = true;
+				}
+				"--deterministic" =>
+				{
+					args.deterministic = true;
+				}
+				"--scan" =>
+				{
+					if i + 1 < cli_args.len()
+					{
+						i += 1;
+						args.scan = match minify::parse_scan_order(&cli_args[i])
+						{
+							Some(order) => order,
+							None => return Err(anyhow!("Invalid scan order: {}. Valid options are: serpentine, hilbert", cli_args[i])),
+						};
+					}
+					else
+					{
+						return Err(anyhow!("Missing value for {} argument", arg));
+					}
+				}
+				"--interlace" =>
+				{
+					if i + 1 < cli_args.len()
+					{
+						i += 1;
+						args.interlace = match minify::parse_interlace_mode(&cli_args[i])
+						{
+							Some(mode) => mode,
+							None => return Err(anyhow!("Invalid interlace mode: {}. Valid options are: none, adam7, keep", cli_args[i])),
+						};
+					}
+					else
+					{
+						return Err(anyhow!("Missing value for {} argument", arg));
+					}
+				}
+				"--max-pixels" =>
+				{
+					if i + 1 < cli_args.len()
+					{
+						i += 1;
+						let value: u64 = cli_args[i].parse::<u64>().ok().filter(|value| *value > 0).ok_or_else(|| anyhow!("Invalid max-pixels value: must be a positive integer"))?;
+						args.max_pixels = Some(value);
+					}
+					else
+					{
+						return Err(anyhow!("Missing value for {} argument", arg));
+					}
+				}
+				"--min-reduction" =>
+				{
+					if i + 1 < cli_args.len()
+					{
+						i += 1;
+						let value: f64 = cli_args[i].trim_end_matches('%').parse::<f64>().map_err(|_| anyhow!("Invalid min-reduction value: must be a percentage between 0 and 100"))?;
+						args.min_reduction = value;
+					}
+					else
+					{
+						return Err(anyhow!("Missing value for {} argument", arg));
+					}
+				}
+				"--sort" =>
+				{
+					if i + 1 < cli_args.len()
+					{
+						i += 1;
+						args.sort = Some(file_utils::parse_sort_order(&cli_args[i]).ok_or_else(|| anyhow!("Invalid sort order: {}. Valid options are: name, size, savings", cli_args[i]))?);
+					}
+					else
+					{
+						return Err(anyhow!("Missing value for {} argument", arg));
+					}
+				}
+				"--time-budget" =>
+				{
+					if i + 1 < cli_args.len()
+					{
+						i += 1;
+						args.time_budget = Some(parse_time_budget(&cli_args[i])?);
+					}
+					else
+					{
+						return Err(anyhow!("Missing value for {} argument", arg));
+					}
+				}
+				"--top" =>
+				{
+					if i + 1 < cli_args.len()
+					{
+						i += 1;
+						let value: usize = cli_args[i].parse::<usize>().map_err(|_| anyhow!("Invalid top value: must be a non-negative integer"))?;
+						args.top = value;
+					}
+					else
+					{
+						return Err(anyhow!("Missing value for {} argument", arg));
+					}
+				}
+				
+				// 3. Image Quality Parameters.
+				"--quality" | "-q" =>
+				{
+					if i + 1 < cli_args.len()
+					{
+						i += 1;
+						args.quality = parse_quality(&cli_args[i]).ok_or_else(|| anyhow!("Invalid quality value: must be an integer between 1 and 100, or auto"))?;
+					}
+					else
+					{
+						return Err(anyhow!("Missing value for {} argument", arg));
+					}
+				}
+				"--palette-from" =>
+				{
+					if i + 1 < cli_args.len()
+					{
+						i += 1;
+						args.palette_from = Some(PathBuf::from(&cli_args[i]));
+					}
+					else
+					{
+						return Err(anyhow!("Missing value for {} argument", arg));
+					}
+				}
+				"--factors" =>
+				{
+					if i + 1 < cli_args.len()
+					{
+						i += 1;
+						args.factors = Some(parse_factors(&cli_args[i])?);
+					}
+					else
+					{
+						return Err(anyhow!("Missing value for {} argument", arg));
+					}
+				}
+				"--quality-per-type" =>
+				{
+					if i + 1 < cli_args.len()
+					{
+						i += 1;
+						args.quality_per_type = Some(QualityPerType::parse(&cli_args[i])?);
+					}
+					else
+					{
+						return Err(anyhow!("Missing value for {} argument", arg));
+					}
+				}
+				"--preset" =>
+				{
+					if i + 1 < cli_args.len()
+					{
+						i += 1;
+						args.preset = Some(parse_preset(&cli_args[i]).ok_or_else(|| anyhow!("Invalid preset: {}. Valid options are: tiny, small, balanced, high, max", cli_args[i]))?);
+					}
+					else
+					{
+						return Err(anyhow!("Missing value for {} argument", arg));
+					}
+				}
+				"--dithering" | "-d" | "-m" => // Keep -m for backward compatibility.
+				{
+					if i + 1 < cli_args.len()
+					{
+						i += 1;
+						args.dithering = cli_args[i].to_string();
+					}
+					else
+					{
+						return Err(anyhow!("Missing value for {} argument", arg));
+					}
+				}
+				
+				// 4. Advanced Image Processing Parameters.
+				"--smooth" | "-s" | "-r" => // Keep -r for backward compatibility.
+				{
+					if i + 1 < cli_args.len()
+					{
+						i += 1;
+						let value: f32 = cli_args[i].parse::<f32>().map_err(|_| anyhow!("Invalid smooth value: must be a number between 0.0 and 5.0"))?;
+						args.smooth = value;
+					}
+					else
+					{
+						return Err(anyhow!("Missing value for {} argument", arg));
+					}
+				}
+				"--denoise" | "-N" =>
+				{
+					args.denoise = true;
+				}
+				"--denoise-strength" =>
+				{
+					if i + 1 < cli_args.len()
+					{
+						i += 1;
+						args.denoise_strength = cli_args[i].parse::<f32>().map_err(|_| anyhow!("Invalid denoise strength: must be a number between 0.0 and 1.0"))?;
+					}
+					else
+					{
+						return Err(anyhow!("Missing value for {} argument", arg));
+					}
+				}
+				"--denoise-block" =>
+				{
+					if i + 1 < cli_args.len()
+					{
+						i += 1;
+						args.denoise_block = cli_args[i].parse::<u32>().map_err(|_| anyhow!("Invalid denoise block size: must be an integer between 3 and 64"))?;
+					}
+					else
+					{
+						return Err(anyhow!("Missing value for {} argument", arg));
+					}
+				}
+				"--grayscale" | "-G" =>
+				{
+					args.grayscale = true;
+				}
+				"--alpha-threshold" =>
+				{
+					if i + 1 < cli_args.len()
+					{
+						i += 1;
+						let value: u8 = cli_args[i].parse::<u8>().map_err(|_| anyhow!("Invalid alpha threshold: must be an integer between 0 and 255"))?;
+						args.alpha_threshold = Some(value);
+					}
+					else
+					{
+						return Err(anyhow!("Missing value for {} argument", arg));
+					}
+				}
+				"--dither-alpha" =>
+				{
+					args.dither_alpha = true;
+				}
+				"--linear" =>
+				{
+					args.linear = true;
+				}
+				"--gamma" =>
+				{
+					if i + 1 < cli_args.len()
+					{
+						i += 1;
+						let value: f32 = cli_args[i].parse::<f32>().map_err(|_| anyhow!("Invalid gamma: {}. Must be a positive number", cli_args[i]))?;
+						args.gamma = Some(value);
+					}
+					else
+					{
+						return Err(anyhow!("Missing value for {} argument", arg));
+					}
+				}
+				"--premultiply" =>
+				{
+					args.premultiply = true;
+				}
+				"--flatten" =>
+				{
+					if i + 1 < cli_args.len()
+					{
+						i += 1;
+						args.flatten = Some(parse_flatten_color(&cli_args[i])?);
+					}
+					else
+					{
+						return Err(anyhow!("Missing value for {} argument", arg));
+					}
+				}
+				"--quantize-alpha" =>
+				{
+					args.quantize_alpha = true;
+				}
+				"--indexed" =>
+				{
+					args.indexed = true;
+				}
+				"--keep-bit-depth" =>
+				{
+					args.keep_bit_depth = true;
+				}
+				"--keep-phys" =>
+				{
+					args.keep_phys = true;
+				}
+				"--keep-text" =>
+				{
+					args.keep_text = true;
+				}
+				"--refine" =>
+				{
+					args.refine = true;
+				}
+				"--quantizer" =>
+				{
+					if i + 1 < cli_args.len()
+					{
+						i += 1;
+						args.quantizer = match minify::parse_quantizer(&cli_args[i])
+						{
+							Some(quantizer) => quantizer,
+							None => return Err(anyhow!("Invalid quantizer: {}. Valid options are: median, octree", cli_args[i])),
+						};
+					}
+					else
+					{
+						return Err(anyhow!("Missing value for {} argument", arg));
+					}
+				}
+				"--sample-step" =>
+				{
+					if i + 1 < cli_args.len()
+					{
+						i += 1;
+						let value: usize = cli_args[i].parse::<usize>().map_err(|_| anyhow!("Invalid sample-step value: must be a non-negative integer"))?;
+						args.sample_step = value;
+					}
+					else
+					{
+						return Err(anyhow!("Missing value for {} argument", arg));
+					}
+				}
+				"--thresholds" => // Hidden option for tuning automatic dithering selection.
+				{
+					if i + 1 < cli_args.len()
+					{
+						i += 1;
+						args.thresholds = SelectionThresholds::parse(&cli_args[i])?;
+					}
+					else
+					{
+						return Err(anyhow!("Missing value for {} argument", arg));
+					}
+				}
+				
+				// 5. Program Information.
+				"--help" | "-h" =>
+				{
+					println!("{} - {}", args.about, args.version);
+					println!("By {}", args.author);
+					println!("\nUSAGE:");
+					println!("    minipng [OPTIONS] [FILES...] [@LIST_FILE...] [-- FILES...]");
+					println!("    minipng [OPTIONS] - < input.png > output.png");
+					println!("\nOPTIONS:");
+					// Input/Output Parameters.
+					println!("  INPUT/OUTPUT:");
+					println!("    -D, --dir <DIR>              Directory to scan for PNG files");
+					println!("    -i, --inplace                Process files in-place (default)");
+					println!("    -o, --output <PATH>          Output file (one input or a .png name) or directory");
+					println!("        --if-exists <POLICY>     When a separate target exists: skip, overwrite, error (default: overwrite)");
+					println!("        --skip-unchanged-output  Don't copy files that couldn't be reduced to the output (-o)");
+					println!("        --also-webp              Also write a WebP of each output next to it (always lossless)");
+					println!("        --force-writable         Write read-only targets (restoring read-only afterward)");
+					println!("    -x, --exclude <PATTERN>      Exclude matching files from directory scanning (repeatable)");
+					println!("        --no-ignore              Don't skip paths listed in .minipngignore");
+					println!("        --follow-symlinks        Follow symbolic links when scanning directories");
+					println!("    -R, --no-recurse             Only scan the top level of the directory, not its subdirectories");
+					println!("        --config <PATH>          Load defaults from a specific config file");
+					println!("        --no-config              Ignore .minipng.toml config files");
+					println!("        --csv <PATH>             Write a CSV report of processed files");
+					println!("        --manifest <PATH>        Write a JSON manifest of sources, outputs and their SHA-256 hashes");
+					println!("        --report <FORMAT>        Summary on stdout: human, json, csv, none (default: human)");
+					println!("        --log-file <PATH>        Append the printed lines and summary to PATH, with timestamps");
+					println!("        --cache                  Keep a .minipng-cache.json analysis cache in the scanned directory");
+					println!("        --no-cache               Don't use the analysis cache (default)");
+					println!("        --backup-dir <DIR>       Copy each file into DIR before overwriting it");
+					println!("        --resume                 Skip files completed by an interrupted run (.minipng-progress)");
+					println!("        --temp-dir <DIR>         Write outputs to a temporary file in DIR, then move it over the target");
+					println!("        --marker-text <TEXT>     Software attribution in the marker chunk (printable Latin-1, 1-79 characters)");
+					println!("        --restore <DIR>          Copy the backups in DIR back to their original paths and exit");
+					println!("        --compare <OUT>          Write the input next to its minified version to OUT (one file)");
+					println!("        --preview <OUT>          Write the input quantized with each dithering mode to OUT (one file)");
+					println!("        --dump-palette <OUT>     Write the input's median cut palette to OUT as hex colors (one file)");
+					println!("    -l, --list-only              List the files that would be processed and exit");
+					println!("        --estimate               Estimate the savings quickly without writing any file");
+					println!("        --info                   Show how the listed files were minified and their chunks, then exit");
+					println!("        --stream                 Process huge directories in constant memory (summary has totals only)");
+					println!("");
+					// Operation Mode Parameters.
+					println!("  OPERATION MODE:");
+					println!("    -L, --lossless               Use lossless compression only");
+					println!("    -F, --force                  Force re-minification of already-minified files");
+					println!("        --reuse-settings         Re-minify with the settings recorded in each file's marker");
+					println!("        --reminify-before <DATE> Re-minify files minified before an ISO 8601 UTC date");
+					println!("    -S, --skip                   Skip already-minified files");
+					println!("        --interactive            Ask before re-minifying each already-minified file");
+					println!("    -j, --jobs <N>               Number of threads to use (0 = all cores)");
+					println!("        --threads-per-file <N>   Optimizer threads for each file, on top of --jobs (0 = auto)");
+					println!("        --verify                 Check that the output decodes correctly before writing");
+					println!("        --diff-pixels            Count changed pixels of lossless output and keep the original if any changed");
+					println!("        --strip <LEVEL>          Ancillary chunks to strip (safe, all; default: safe)");
+					println!("        --strip-all              Same as --strip all");
+					println!("        --min-reduction <PCT>    Only rewrite files that shrink by at least PCT percent (default: 0)");
+					println!("        --always-write           Write the lossless output even if it isn't smaller");
+					println!("        --safe                   Minify files already quantized by another tool losslessly");
+					println!("        --mark-noop              Mark files lossy mode couldn't reduce, so later runs skip them");
+					println!("        --fallback-lossless      Minify losslessly when lossy output isn't smaller");
+					println!("        --auto-mode              Per file, keep lossy output only if much smaller than lossless");
+					println!("        --strip-trailing         Remove junk bytes after the end of the image (IEND)");
+					println!("        --deterministic          Produce byte-identical output for identical input");
+					println!("        --max-pixels <N>         Skip images with more than N pixels (width × height)");
+					println!("        --interlace <MODE>       Output interlacing: none, adam7, keep (default: none)");
+					println!("    -v, --verbose                Print per-file processing times");
+					println!("        --fail-on-no-savings     Exit with code 3 when no file got smaller");
+					println!("        --no-color               Disable colored output");
+					println!("        --top <N>                Files to list by bytes saved in the summary (default: 10, 0 = off)");
+					println!("        --sort <ORDER>           Process by name or size (largest first), or list all files by savings");
+					println!("        --time-budget <SECONDS>  Stop starting new files after this many seconds");
+					println!("");
+					// Image Quality Parameters.
+					println!("  IMAGE QUALITY:");
+					println!("    -q, --quality <QUALITY>      Quality level (1-100 or auto, default: 40)");
+					println!("        --quality-per-type <MAP> Quality per image class, e.g. photo=70,flat=35 (others use --quality)");
+					println!("        --preset <NAME>          Quality by name: tiny (25), small (40), balanced (50), high (60), max (80)");
+					println!("    -d, --dithering <MODE>       Dithering mode (auto, none, ordered, bluenoise, floyd, median[:N])");
+					println!("        --scan <ORDER>           Floyd-Steinberg pixel order: serpentine, hilbert (default: serpentine)");
+					println!("        --factors <R,G,B>        Per-channel downsampling factors (1-128), one value for all");
+					println!("        --palette-from <FILE>    Map colors onto a fixed palette (PNG or RRGGBB list)");
+					println!("");
+					// Advanced Image Processing Parameters.
+					println!("  ADVANCED PROCESSING:");
+					println!("    -s, --smooth <RADIUS>        Pre-quantization smoothing radius (0.0-5.0)");
+					println!("    -N, --denoise                Apply post-processing denoising");
+					println!("        --denoise-strength <S>   Denoising strength (0.0-1.0, default 0.5)");
+					println!("        --denoise-block <N>      Denoising analysis block size (3-64, default 8)");
+					println!("    -G, --grayscale              Force grayscale output (lossy mode)");
+					println!("        --linear                 Quantize in linear light (gamma-correct, lossy mode)");
+					println!("        --gamma <VALUE>          Encoding gamma assumed by --linear (default: 2.2, the sRGB curve)");
+					println!("        --premultiply            Quantize in premultiplied alpha to avoid edge fringing (lossy mode)");
+					println!("        --flatten <#RRGGBB>      Composite onto an opaque background color, dropping alpha (lossy mode)");
+					println!("        --quantize-alpha         Quantize alpha alongside RGB (lossy mode)");
+					println!("        --alpha-threshold <N>    Collapse alpha to on/off at threshold N (0-255)");
+					println!("        --dither-alpha           Dither alpha when applying --alpha-threshold");
+					println!("        --indexed                Write a palette PNG (up to 256 colors, lossy mode)");
+					println!("        --keep-bit-depth         Keep the bit depth and color type (no reduction by the optimizer)");
+					println!("        --keep-phys              Keep the pHYs chunk (DPI / pixel aspect ratio)");
+					println!("        --keep-text              Keep text metadata (tEXt, zTXt, iTXt: copyright, author, ...)");
+					println!("        --refine                 Refine the median-cut palette with k-means (slower)");
+					println!("        --sample-step <N>        Median cut samples every Nth pixel (default: 0 = by image size)");
+					println!("        --quantizer <NAME>       Palette quantizer for median and --indexed: median, octree (default: median)");
+					println!("");
+					// General Options.
+					println!("  GENERAL:");
+					println!("    -h, --help                   Show help information");
+					println!("    -V, --version                Display version information (with --verbose: build details)");
+					
+					std::process::exit(0);
+				}
+				"--version" | "-V" =>
+				{
+					// With --verbose anywhere on the command line, print the build metadata as well.
+					let mut detailed: bool = false;
+					for other in &cli_args
+					{
+						if other == "--verbose" || other == "-v"
+						{
+							detailed = true;
+						}
+					}
+					println!("{}", version_text(&args.version, detailed));
+					std::process::exit(0);
+				}
+				
+				// Everything after a bare "--" is a file path, even if it starts with "-".
+				"--" =>
+				{
+					for file in &cli_args[i + 1..]
+					{
+						args.files.push(PathBuf::from(file));
+					}
+					break;
+				}
+				
+				// Anything else is treated as a file path if it doesn't start with "-".
+				// Arguments starting with "@" name a response file containing one path per line.
+				_ =>
+				{
+					if let Some(list_path) = arg.strip_prefix('@')
+					{
+						let listed_files: Vec<PathBuf> = read_response_file(Path::new(list_path))?;
+						for path in listed_files
+						{
+							args.files.push(path);
+						}
+					}
+					else if !arg.starts_with('-')
+					{
+						args.files.push(PathBuf::from(arg));
+					}
+					else if is_short_flag_bundle(arg)
+					{
+						// Expand bundled short flags (e.g. -FiN) in place and parse them individually.
+						let flags: Vec<String> = expand_short_flag_bundle(arg)?;
+						cli_args.splice(i..i + 1, flags);
+						continue;
+					}
+					else
+					{
+						return Err(anyhow!("Unknown option: {}", arg));
+					}
+				}
+			}
+			
+			i += 1;
+		}
+		
+		args.apply_env(env_var)?;
+		args.split_dithering_suffix()?;
+		
+		Ok(args)
+	}
+	
+	/// Set the quality level from --preset, unless a quality was given on the command line.
+	/// Runs after the config file is merged, so a preset also takes precedence over MINIPNG_QUALITY and the config file.
+	pub fn apply_preset(&mut self, quality_given: bool)
+	{
+		if let Some(preset) = self.preset && !quality_given
+		{
+			self.quality = preset.quality();
+		}
+	}
+	
+	/// Apply MINIPNG_QUALITY, MINIPNG_DITHERING and MINIPNG_JOBS for options not given on the command line.
+	/// Precedence: command line > environment > config file > built-in defaults.
+	fn apply_env<E>(&mut self, env_var: E) -> Result<()> where E: Fn(&str) -> Option<String>
+	{
+		if let Some(value) = env_var(ENV_QUALITY) && !self.is_explicitly_set("--quality") && !self.is_explicitly_set("-q")
+		{
+			self.quality = parse_quality(&value)
+				.ok_or_else(|| anyhow!("Invalid {} value '{}': quality must be between 1 and 100, or auto", ENV_QUALITY, value))?;
+		}
+		
+		if let Some(value) = env_var(ENV_DITHERING) && !self.is_explicitly_set("--dithering") && !self.is_explicitly_set("-d") && !self.is_explicitly_set("-m")
+		{
+			let mode: &str = value.split(':').next().unwrap_or_default();
+			if !DITHERING_MODES.contains(&mode.to_lowercase().as_str())
+			{
+				return Err(anyhow!("Invalid {} value '{}': use auto, none, ordered, bluenoise, floyd, or median", ENV_DITHERING, value));
+			}
+			self.dithering = value;
+		}
+		
+		if let Some(value) = env_var(ENV_JOBS) && !self.is_explicitly_set("--jobs") && !self.is_explicitly_set("-j")
+		{
+			self.jobs = value.parse::<usize>().map_err(|_| anyhow!("Invalid {} value '{}': must be a non-negative integer", ENV_JOBS, value))?;
+		}
+		
+		Ok(())
+	}
+	
+	/// Move an inline palette size ("median:N") from the dithering mode to median_colors.
+	pub fn split_dithering_suffix(&mut self) -> Result<()>
+	{
+		let (mode, size): (String, String) = match self.dithering.split_once(':')
+		{
+			Some((mode, size)) => (mode.to_string(), size.to_string()),
+			None => return Ok(()),
+		};
+		
+		if !matches!(mode.to_lowercase().as_str(), "median" | "mediancut")
+		{
+			return Err(anyhow!("A palette size (:N) can only be given for median dithering"));
+		}
+		
+		let colors: usize = size.trim().parse::<usize>().map_err(|_| anyhow!("Invalid median palette size: must be an integer between 2 and 256"))?;
+		self.dithering = mode;
+		self.median_colors = Some(colors);
+		
+		Ok(())
+	}
+	
+	/// Load project defaults from a config file, if any, and merge them into these arguments.
+	/// Uses the --config path when given, otherwise discovers .minipng.toml walking up from the current directory.
+	/// Returns the path of the config file that was applied.
+	pub fn load_config(&mut self) -> Result<Option<PathBuf>>
+	{
+		if self.no_config
+		{
+			if self.config.is_some()
+			{
+				return Err(anyhow!("Cannot use --config and --no-config together"));
+			}
+			return Ok(None);
+		}
+		
+		let config_path: Option<PathBuf> = match &self.config
+		{
+			Some(path) => Some(path.clone()),
+			None =>
+			{
+				let current_dir: PathBuf = env::current_dir().map_err(|e| anyhow!("Failed to get current directory: {}", e))?;
+				config::discover_config_file(&current_dir)
+			},
+		};
+		
+		if let Some(path) = &config_path
+		{
+			let config: Config = config::load_config(path)?;
+			self.apply_config(&config);
+			self.split_dithering_suffix()?;
+		}
+		
+		Ok(config_path)
+	}
+	
+	/// Apply config file values for every option that was not explicitly set on the command line.
+	/// Precedence: command line > config file > built-in defaults.
+	pub fn apply_config(&mut self, config: &Config)
+	{
+		if let Some(quality) = config.quality && !self.is_explicitly_set("--quality") && !self.is_explicitly_set("-q") && env_value(ENV_QUALITY).is_none()
+		{
+			self.quality = quality;
+		}
+		
+		if let Some(dithering) = &config.dithering && !self.is_explicitly_set("--dithering") && !self.is_explicitly_set("-d") && !self.is_explicitly_set("-m") && env_value(ENV_DITHERING).is_none()
+		{
+			self.dithering = dithering.clone();
+		}
+		
+		if let Some(smooth) = config.smooth && !self.is_explicitly_set("--smooth") && !self.is_explicitly_set("-s") && !self.is_explicitly_set("-r")
+		{
+			self.smooth = smooth;
+		}
+		
+		if let Some(denoise) = config.denoise && !self.is_explicitly_set("--denoise") && !self.is_explicitly_set("-N")
+		{
+			self.denoise = denoise;
+		}
+		
+		if let Some(lossless) = config.lossless && !self.is_explicitly_set("--lossless") && !self.is_explicitly_set("-L")
+		{
+			self.lossless = lossless;
+		}
+		
+		if let Some(excludes) = &config.excludes && !self.is_explicitly_set("--exclude") && !self.is_explicitly_set("-x")
+		{
+			self.excludes = excludes.clone();
+		}
+		
+		if let Some(jobs) = config.jobs && !self.is_explicitly_set("--jobs") && !self.is_explicitly_set("-j") && env_value(ENV_JOBS).is_none()
+		{
+			self.jobs = jobs;
+		}
+	}
+	
+	/// Check if an option was explicitly provided on the command line, as typed (e.g. "-q" or "--quality").
+	/// Short flags given in a bundle such as -FiN count as given, while option values and file names never do.
+	pub fn is_explicitly_set(&self, arg_name: &str) -> bool
+	{
+		self.explicit.contains(arg_name)
+	}
+	
+	/// List the lossy-only options that were explicitly given together with --lossless.
+	/// Options holding their defaults (or values from a config file) are not listed.
+	pub fn lossless_conflicts(&self) -> Vec<&'static str>
+	{
+		let mut conflicts: Vec<&'static str> = Vec::new();
+		if !self.lossless
+		{
+			return conflicts;
+		}
+		
+		if self.is_explicitly_set("--quality") || self.is_explicitly_set("-q")
+		{
+			conflicts.push("--quality");
+		}
+		
+		if self.is_explicitly_set("--dithering") || self.is_explicitly_set("-d")
+		{
+			conflicts.push("--dithering");
+		}
+		
+		if self.is_explicitly_set("--smooth") || self.is_explicitly_set("-s") || self.is_explicitly_set("-r")
+		{
+			conflicts.push("--smooth");
+		}
+		
+		if self.is_explicitly_set("--factors")
+		{
+			conflicts.push("--factors");
+		}
+		
+		if self.is_explicitly_set("--quality-per-type")
+		{
+			conflicts.push("--quality-per-type");
+		}
+		
+		if self.is_explicitly_set("--preset")
+		{
+			conflicts.push("--preset");
+		}
+		
+		if self.is_explicitly_set("--palette-from")
+		{
+			conflicts.push("--palette-from");
+		}
+		
+		if self.is_explicitly_set("--denoise") || self.is_explicitly_set("-N")
+		{
+			conflicts.push("--denoise");
+		}
+		
+		if self.is_explicitly_set("--denoise-strength")
+		{
+			conflicts.push("--denoise-strength");
+		}
+		
+		if self.is_explicitly_set("--denoise-block")
+		{
+			conflicts.push("--denoise-block");
+		}
+		
+		if self.is_explicitly_set("--safe")
+		{
+			conflicts.push("--safe");
+		}
+		
+		if self.is_explicitly_set("--scan")
+		{
+			conflicts.push("--scan");
+		}
+		
+		if self.is_explicitly_set("--mark-noop")
+		{
+			conflicts.push("--mark-noop");
+		}
+		
+		if self.is_explicitly_set("--fallback-lossless")
+		{
+			conflicts.push("--fallback-lossless");
+		}
+		
+		if self.is_explicitly_set("--auto-mode")
+		{
+			conflicts.push("--auto-mode");
+		}
+		
+		if self.is_explicitly_set("--flatten")
+		{
+			conflicts.push("--flatten");
+		}
+		
+		if self.is_explicitly_set("--quantizer")
+		{
+			conflicts.push("--quantizer");
+		}
+		
+		conflicts
+	}
+	
+	/// Validate parameter values and relationships.
+	/// Returns Ok(()) if all parameters are valid, otherwise returns an error.
+	/// Suspicious but harmless combinations only print a warning to stderr.
+	pub fn validate(&self) -> Result<()>
+	{
+		// Validate quality parameter.
+		if self.quality > 100
+		{
+			return Err(anyhow!("Quality must be between 1 and 100, or auto"));
+		}
+		
+		// Validate the denoising parameters.
+		if !(0.0..=1.0).contains(&self.denoise_strength)
+		{
+			return Err(anyhow!("Denoise strength must be between 0.0 and 1.0"));
+		}
+		
+		if !(3..=64).contains(&self.denoise_block)
+		{
+			return Err(anyhow!("Denoise block size must be between 3 and 64"));
+		}
+		
+		// Validate smooth parameter.
+		if self.smooth < 0.0 || self.smooth > 5.0
+		{
 			return Err(anyhow!("Smooth radius must be between 0.0 and 5.0"));
 		}
 		
+		// Validate min-reduction parameter.
+		if !(0.0..=100.0).contains(&self.min_reduction)
+		{
+			return Err(anyhow!("Minimum reduction must be between 0 and 100 percent"));
+		}
+		
+		// Validate that pixel diffs audit lossless output.
+		if self.diff_pixels && !self.lossless
+		{
+			return Err(anyhow!("--diff-pixels requires --lossless (lossy output changes pixels by design)"));
+		}
+		
+		// Validate that there is a separate output to leave sparse.
+		if self.skip_unchanged_output && self.output.is_none()
+		{
+			return Err(anyhow!("--skip-unchanged-output requires --output (in-place runs never copy unchanged files)"));
+		}
+		
+		// Validate that only lossless output may replace a smaller file.
+		if self.always_write && !self.lossless
+		{
+			return Err(anyhow!("--always-write requires --lossless (lossy output never replaces a smaller file)"));
+		}
+		
+		// Validate that the gamma is usable and that linear light mode uses it.
+		if let Some(gamma) = self.gamma
+		{
+			if !(gamma.is_finite() && gamma > 0.0)
+			{
+				return Err(anyhow!("Invalid gamma: {}. Must be a positive number", gamma));
+			}
+			
+			if !self.linear
+			{
+				return Err(anyhow!("--gamma requires --linear (it sets the gamma of the linear light conversions)"));
+			}
+		}
+		
+		// Validate that alpha dithering has a threshold to work with.
+		if self.dither_alpha && self.alpha_threshold.is_none()
+		{
+			return Err(anyhow!("--dither-alpha requires --alpha-threshold"));
+		}
+		
+		// Validate that there is a file to mark.
+		if self.mark_noop && self.stdin
+		{
+			return Err(anyhow!("--mark-noop cannot be combined with reading from stdin (-)"));
+		}
+		
+		// Validate that the fallback has a file to report it for.
+		if self.fallback_lossless && self.stdin
+		{
+			return Err(anyhow!("--fallback-lossless cannot be combined with reading from stdin (-)"));
+		}
+		
+		// Validate that the chosen mode has a file to report it for.
+		if self.auto_mode && self.stdin
+		{
+			return Err(anyhow!("--auto-mode cannot be combined with reading from stdin (-)"));
+		}
+		
+		// Validate that the manifest has files to list.
+		if self.manifest.is_some() && self.stdin
+		{
+			return Err(anyhow!("--manifest cannot be combined with reading from stdin (-)"));
+		}
+		
+		// Validate that stdin mode is not mixed with file discovery.
+		if self.stdin && (!self.files.is_empty() || self.dir.is_some())
+		{
+			return Err(anyhow!("Reading from stdin (-) cannot be combined with files or --dir"));
+		}
+		
+		// Validate that there is an output file to put the WebP next to.
+		if self.also_webp && self.stdin
+		{
+			return Err(anyhow!("--also-webp cannot be combined with reading from stdin (-)"));
+		}
+		
+		// Validate that an output path is only used when files are written.
+		if self.output.is_some()
+		{
+			if self.is_explicitly_set("--inplace") || self.is_explicitly_set("-i")
+			{
+				return Err(anyhow!("Cannot use --inplace and --output together"));
+			}
+			
+			if self.stdin || self.compare.is_some() || self.dump_palette.is_some() || self.preview.is_some() || self.info || self.estimate
+			{
+				return Err(anyhow!("--output cannot be combined with stdin (-), --compare, --dump-palette, --preview, --info or --estimate"));
+			}
+		}
+		
+		// Validate that a comparison has exactly one input and doesn't overwrite it.
+		if let Some(compare) = &self.compare
+		{
+			if self.files.len() != 1 || self.dir.is_some() || self.stdin
+			{
+				return Err(anyhow!("--compare needs exactly one input file"));
+			}
+			
+			if compare == &self.files[0]
+			{
+				return Err(anyhow!("--compare output must not be the input file"));
+			}
+		}
+		
+		// Validate that a palette dump has exactly one input and isn't mixed with a comparison.
+		if self.dump_palette.is_some()
+		{
+			if self.files.len() != 1 || self.dir.is_some() || self.stdin
+			{
+				return Err(anyhow!("--dump-palette needs exactly one input file"));
+			}
+			
+			if self.compare.is_some() || self.lossless || self.palette_from.is_some()
+			{
+				return Err(anyhow!("--dump-palette cannot be combined with --compare, --lossless or --palette-from"));
+			}
+		}
+		
+		// Validate that a preview has exactly one input, doesn't overwrite it and isn't mixed with the other single-file modes.
+		if let Some(preview) = &self.preview
+		{
+			if self.files.len() != 1 || self.dir.is_some() || self.stdin
+			{
+				return Err(anyhow!("--preview needs exactly one input file"));
+			}
+			
+			if preview == &self.files[0]
+			{
+				return Err(anyhow!("--preview output must not be the input file"));
+			}
+			
+			if self.compare.is_some() || self.dump_palette.is_some() || self.lossless || self.palette_from.is_some()
+			{
+				return Err(anyhow!("--preview cannot be combined with --compare, --dump-palette, --lossless or --palette-from"));
+			}
+		}
+		
+		// Validate that a report summarizes a minification run.
+		if self.report != ReportFormat::Human && (self.stdin || self.estimate || self.list_only || self.info || self.compare.is_some() || self.preview.is_some() || self.dump_palette.is_some() || self.restore.is_some())
+		{
+			return Err(anyhow!("--report cannot be combined with stdin (-), --estimate, --list-only, --info, --compare, --preview, --dump-palette or --restore"));
+		}
+		
+		// Validate that the temporary file directory exists.
+		if let Some(dir) = &self.temp_dir && !dir.is_dir()
+		{
+			return Err(anyhow!("--temp-dir '{}' is not a directory", dir.display()));
+		}
+		
+		// Validate that a restore runs on its own.
+		if self.restore.is_some() && (!self.files.is_empty() || self.dir.is_some() || self.stdin || self.compare.is_some() || self.backup_dir.is_some())
+		{
+			return Err(anyhow!("--restore cannot be combined with files, --dir, stdin (-), --compare or --backup-dir"));
+		}
+		
+		// Validate that --info has files to inspect.
+		if self.info && (self.files.is_empty() || self.dir.is_some() || self.stdin)
+		{
+			return Err(anyhow!("--info needs one or more files (and no --dir or stdin)"));
+		}
+		
+		// Validate that an estimate isn't mixed with other modes that don't process files.
+		if self.estimate && (self.compare.is_some() || self.stdin)
+		{
+			return Err(anyhow!("--estimate cannot be combined with --compare or reading from stdin (-)"));
+		}
+		
+		// The cache lives in the scanned directory, so listed files alone have nowhere to keep it.
+		if self.cache && ((!self.files.is_empty() && self.dir.is_none()) || self.stdin)
+		{
+			return Err(anyhow!("--cache needs a scanned directory (--dir or the current directory), not only listed files or stdin (-)"));
+		}
+		
+		// Streaming walks a single directory and keeps no per-file results.
+		if self.stream
+		{
+			if !self.files.is_empty() || self.stdin
+			{
+				return Err(anyhow!("--stream processes a directory (--dir or the current directory), not listed files or stdin (-)"));
+			}
+			
+			if self.interactive || self.output.is_some() || self.csv.is_some() || self.manifest.is_some() || self.estimate || self.list_only || self.compare.is_some() || self.dump_palette.is_some() || self.restore.is_some() || self.time_budget.is_some() || self.resume
+			{
+				return Err(anyhow!("--stream cannot be combined with --interactive, --output, --csv, --manifest, --estimate, --list-only, --compare, --dump-palette, --restore, --time-budget or --resume"));
+			}
+			
+			// The per-file results aren't kept, so there is nothing to list in a machine-readable report.
+			if self.report == ReportFormat::Json || self.report == ReportFormat::Csv
+			{
+				return Err(anyhow!("--stream only supports --report human or none"));
+			}
+			
+			// Files are processed as the walk finds them and the results aren't kept.
+			if self.sort.is_some()
+			{
+				return Err(anyhow!("--stream cannot be combined with --sort"));
+			}
+		}
+		
+		// The budget is checked as the batch workers pick up files; interactive runs wait for answers anyway.
+		if self.time_budget.is_some() && self.interactive
+		{
+			return Err(anyhow!("--time-budget cannot be combined with --interactive"));
+		}
+		
 		// Validate that force and skip are not both set.
 		if self.force && self.skip
 		{
 			return Err(anyhow!("Cannot use --force and --skip together"));
 		}
 		
+		// Forcing already re-minifies every file, whatever its date.
+		if self.force && self.reminify_before.is_some()
+		{
+			return Err(anyhow!("Cannot use --force and --reminify-before together"));
+		}
+		
+		// Interactive mode decides per file, so it can't be combined with a blanket answer.
+		if self.interactive && (self.force || self.skip)
+		{
+			return Err(anyhow!("Cannot use --interactive with --force or --skip"));
+		}
+		
+		if self.keep_bit_depth && self.indexed
+		{
+			return Err(anyhow!("Cannot use --keep-bit-depth with --indexed"));
+		}
+		
+		// Validate the median cut palette size.
+		if let Some(colors) = self.median_colors && !(2..=256).contains(&colors)
+		{
+			return Err(anyhow!("Median palette size must be between 2 and 256"));
+		}
+		
+		// Validate the per-channel factors.
+		if let Some(factors) = self.factors
+		{
+			for factor in factors
+			{
+				if !(1..=128).contains(&factor)
+				{
+					return Err(anyhow!("Channel factors must be between 1 and 128"));
+				}
+			}
+		}
+		
 		// Validate dithering mode.
-		match self.dithering.to_lowercase().as_str()
+		if !DITHERING_MODES.contains(&self.dithering.to_lowercase().as_str())
+		{
+			return Err(anyhow!("Invalid dithering mode. Use: auto, none, ordered, bluenoise, floyd, or median"));
+		}
+		
+		// Warn when the files processed at once times the optimizer threads for each of them oversubscribe the CPU.
+		let cores: usize = std::thread::available_parallelism().map(|cores| cores.get()).unwrap_or(1);
+		let concurrent_files: usize = if self.jobs > 0 { self.jobs } else { cores };
+		if self.threads_per_file > 0 && concurrent_files.saturating_mul(self.threads_per_file) > cores * 2
+		{
+			eprintln!("Warning: {} jobs × {} threads per file = {} threads on {} cores, consider lowering --jobs or --threads-per-file.", concurrent_files, self.threads_per_file, concurrent_files.saturating_mul(self.threads_per_file), cores);
+		}
+		
+		// Warn about lossy-only options that --lossless ignores (not an error, to keep scripts working).
+		let conflicts: Vec<&'static str> = self.lossless_conflicts();
+		if !conflicts.is_empty()
 		{
-			"auto" | "none" | "ordered" | "floyd" | "floyd-steinberg" | "mediancut" | "median" => {},
-			_ => return Err(anyhow!("Invalid dithering mode. Use: auto, none, ordered, floyd, or median")),
+			eprintln!("Warning: --lossless ignores {}.", conflicts.join(", "));
 		}
 		
 		// All validations passed.
@@ -303,17 +1993,151 @@ impl Args
 	}
 }
 
+/// Parse a quality level: 1-100, or "auto" (or 0) to pick it from each image.
+fn parse_quality(value: &str) -> Option<u8>
+{
+	if value.trim().eq_ignore_ascii_case("auto")
+	{
+		return Some(minify::AUTO_QUALITY);
+	}
+	
+	value.trim().parse::<u8>().ok().filter(|quality| *quality <= 100)
+}
+
+/// Version line printed by --version, followed by the commit, build date and optimizer crate versions when detailed.
+fn version_text(version: &str, detailed: bool) -> String
+{
+	let mut text: String = format!("{} {}", env!("CARGO_PKG_NAME"), version);
+	if detailed
+	{
+		let build_epoch: u64 = env!("MINIPNG_BUILD_EPOCH").parse::<u64>().unwrap_or(0);
+		text.push_str(&format!("\ncommit: {}", env!("MINIPNG_GIT_HASH")));
+		text.push_str(&format!("\nbuilt: {}", time_utils::format_iso8601(build_epoch)));
+		text.push_str(&format!("\noxipng: {}", env!("MINIPNG_OXIPNG_VERSION")));
+		text.push_str(&format!("\nimage: {}", env!("MINIPNG_IMAGE_VERSION")));
+	}
+	
+	text
+}
+
+/// Parse per-channel factors given as "R,G,B" or as a single value for all channels.
+fn parse_factors(spec: &str) -> Result<[u8; 3]>
+{
+	let mut values: Vec<u8> = Vec::new();
+	for part in spec.split(',')
+	{
+		let value: u32 = part.trim().parse::<u32>().map_err(|_| anyhow!("Invalid factors value '{}': expected R,G,B or a single integer", spec))?;
+		if !(1..=128).contains(&value)
+		{
+			return Err(anyhow!("Channel factors must be between 1 and 128"));
+		}
+		values.push(value as u8);
+	}
+	
+	match values.as_slice()
+	{
+		[all] => Ok([*all; 3]),
+		[r, g, b] => Ok([*r, *g, *b]),
+		_ => Err(anyhow!("Invalid factors value '{}': expected R,G,B or a single integer", spec)),
+	}
+}
+
+/// Parse the --time-budget seconds (fractions allowed).
+fn parse_time_budget(spec: &str) -> Result<Duration>
+{
+	match spec.parse::<f64>()
+	{
+		Ok(seconds) if seconds > 0.0 && seconds.is_finite() => Ok(Duration::from_secs_f64(seconds)),
+		_ => Err(anyhow!("Invalid time budget: {}. Expected a number of seconds greater than 0", spec)),
+	}
+}
+
+/// Parse the --flatten background color, given as RRGGBB with an optional #.
+fn parse_flatten_color(spec: &str) -> Result<[u8; 3]>
+{
+	match minipng::palette::parse_hex_color(spec)
+	{
+		Some(color) if spec.trim_start_matches('#').len() == 6 => Ok(color),
+		_ => Err(anyhow!("Invalid flatten color '{}': expected #RRGGBB", spec)),
+	}
+}
+
+/// Read a non-empty environment variable.
+fn env_value(name: &str) -> Option<String>
+{
+	match env::var(name)
+	{
+		Ok(value) if !value.trim().is_empty() => Some(value.trim().to_string()),
+		_ => None,
+	}
+}
+
+/// Checks if an argument is a bundle of several short flags (e.g. -FiN).
+fn is_short_flag_bundle(arg: &str) -> bool
+{
+	arg.len() > 2 && arg.starts_with('-') && !arg.starts_with("--")
+}
+
+/// Expand a bundle of short flags (e.g. -FiN) into individual flags (-F, -i, -N).
+/// A flag that takes a value is only allowed as the last flag of the bundle.
+#[allow(clippy::reserve_after_initialization)]
+fn expand_short_flag_bundle(arg: &str) -> Result<Vec<String>>
+{
+	let flags: Vec<char> = arg[1..].chars().collect();
+	let mut expanded: Vec<String> = Vec::new();
+	expanded.reserve(flags.len());
+	
+	for (index, &flag) in flags.iter().enumerate()
+	{
+		if VALUE_SHORT_FLAGS.contains(&flag) && index + 1 < flags.len()
+		{
+			return Err(anyhow!("Option -{} takes a value and must be last in {}", flag, arg));
+		}
+		
+		expanded.push(format!("-{}", flag));
+	}
+	
+	Ok(expanded)
+}
+
+/// Read a response file containing one file path per line.
+/// Blank lines and lines starting with '#' are ignored.
+fn read_response_file(path: &Path) -> Result<Vec<PathBuf>>
+{
+	let content: String = std::fs::read_to_string(path)
+		.map_err(|e| anyhow!("Failed to read response file {}: {}", path.display(), e))?;
+	
+	let mut files: Vec<PathBuf> = Vec::new();
+	for line in content.lines()
+	{
+		let line: &str = line.trim();
+		if line.is_empty() || line.starts_with('#')
+		{
+			continue;
+		}
+		
+		files.push(PathBuf::from(line));
+	}
+	
+	Ok(files)
+}
+
 /// Enumeration representing the mode of operation.
 pub enum Mode
 {
 	Directory(Option<PathBuf>),
 	Files(Vec<PathBuf>),
+	
+	/// Directory scan combined with explicitly listed files (both sources contribute).
+	Combined(PathBuf, Vec<PathBuf>),
 }
 
 /// Determines the mode of operation based on the provided arguments.
-pub fn determine_mode(args: &Args, is_png_file: fn(&Path) -> bool) -> Result<Mode>
+/// When both --dir and specific files are given, the directory scan and the files are combined.
+/// Listed files are only checked for existence; their content is checked when they are processed.
+pub fn determine_mode(args: &Args) -> Result<Mode>
 {
-	// If specific files are provided, they take precedence.
+	// If specific files are provided, validate them first.
 	if !args.files.is_empty()
 	{
 		// Validate each file.
@@ -321,13 +2145,13 @@ pub fn determine_mode(args: &Args, is_png_file: fn(&Path) -> bool) -> Result<Mod
 		
 		for path in &args.files
 		{
-			if path.is_file() && is_png_file(path)
+			if path.is_file()
 			{
 				png_files.push(path.clone());
 			}
 			else
 			{
-				return Err(anyhow!("Input '{}' is not a PNG file.", path.display()));
+				return Err(anyhow!("Input '{}' is not a file.", path.display()));
 			}
 		}
 		
@@ -336,6 +2160,12 @@ pub fn determine_mode(args: &Args, is_png_file: fn(&Path) -> bool) -> Result<Mod
 			return Err(anyhow!("No valid PNG files provided."));
 		}
 		
+		// Combine with a directory scan when --dir is also given.
+		if let Some(dir) = &args.dir
+		{
+			return Ok(Mode::Combined(dir.clone(), png_files));
+		}
+		
 		Ok(Mode::Files(png_files))
 	}
 	else // If no files are specified, use directory mode. Use the specified directory or default to current.
@@ -343,3 +2173,208 @@ pub fn determine_mode(args: &Args, is_png_file: fn(&Path) -> bool) -> Result<Mod
 		Ok(Mode::Directory(args.dir.clone()))
 	}
 }
+
+#[cfg(test)]
+pub(crate) mod tests
+{
+	use super::*;
+	
+	/// Parse a command line given without the program name.
+	pub(crate) fn parse(cli_args: &[&str]) -> Result<Args>
+	{
+		let mut owned: Vec<String> = Vec::new();
+		for arg in cli_args
+		{
+			owned.push(arg.to_string());
+		}
+		Args::parse_from(owned)
+	}
+	
+	#[test]
+	fn presets_resolve_to_their_documented_quality()
+	{
+		let expected: [(&str, u8); 5] = [("tiny", 25), ("small", 40), ("balanced", 50), ("high", 60), ("max", 80)];
+		for (name, quality) in expected
+		{
+			let mut args: Args = Args::new();
+			args.preset = parse_preset(name);
+			args.apply_preset(false);
+			assert_eq!(args.quality, quality, "{}", name);
+			assert_eq!(args.preset.map(QualityPreset::name), Some(name));
+		}
+		assert_eq!(parse_preset("HIGH"), Some(QualityPreset::High));
+		assert_eq!(parse_preset("huge"), None);
+	}
+	
+	#[test]
+	fn explicit_quality_overrides_preset()
+	{
+		let mut args: Args = Args::new();
+		args.quality = 55;
+		args.preset = Some(QualityPreset::Tiny);
+		args.apply_preset(true);
+		assert_eq!(args.quality, 55);
+	}
+	
+	#[test]
+	fn bundled_short_flags_expand_to_the_individual_flags()
+	{
+		let args: Args = parse(&["-Fi"]).expect("Valid arguments");
+		assert!(args.force && args.inplace);
+		
+		let args: Args = parse(&["-iN"]).expect("Valid arguments");
+		assert!(args.inplace && args.denoise);
+		
+		// A value flag may end a bundle and takes the next argument.
+		let args: Args = parse(&["-Fq", "60"]).expect("Valid arguments");
+		assert!(args.force);
+		assert_eq!(args.quality, 60);
+		
+		let error: anyhow::Error = parse(&["-qF", "60"]).expect_err("A value flag must be last in a bundle");
+		assert!(error.to_string().contains("must be last"), "{}", error);
+	}
+	
+	#[test]
+	fn only_options_given_count_as_explicitly_set()
+	{
+		let args: Args = parse(&["-Fq", "60", "--exclude", "-iq*", "--", "-quick.png"]).expect("Valid arguments");
+		assert!(args.is_explicitly_set("-F"));
+		assert!(args.is_explicitly_set("-q"));
+		assert!(args.is_explicitly_set("--exclude"));
+		
+		// Neither an option value nor a file name shaped like a bundle marks its letters as given.
+		assert!(!args.is_explicitly_set("-i"));
+		assert!(!args.is_explicitly_set("--quality"));
+		assert_eq!(args.excludes, ["-iq*"]);
+		assert_eq!(args.files, [PathBuf::from("-quick.png")]);
+	}
+	
+	#[test]
+	fn command_line_overrides_config_file_overrides_defaults()
+	{
+		let config: Config = config::parse_config("quality = 70\ndithering = \"ordered\"\njobs = 3\n").expect("Valid config");
+		let mut args: Args = parse(&["-q", "50", "--jobs", "2"]).expect("Valid arguments");
+		args.apply_config(&config);
+		
+		// Given on the command line.
+		assert_eq!(args.quality, 50);
+		assert_eq!(args.jobs, 2);
+		
+		// Only in the config file.
+		assert_eq!(args.dithering, "ordered");
+		
+		// In neither.
+		let defaults: Args = Args::new();
+		assert_eq!(args.smooth, defaults.smooth);
+		assert_eq!(args.denoise, defaults.denoise);
+	}
+	
+	#[test]
+	fn response_file_queues_only_the_listed_paths()
+	{
+		let dir: PathBuf = crate::utils::file_utils::tests::scratch_dir("response-file");
+		let list: PathBuf = dir.join("files.txt");
+		std::fs::write(&list, "# Generated list\nicons/a.png\n\n   \n  sprites/b.png  \n# icons/skipped.png\n").expect("Failed to write response file");
+		
+		// Listed files and files from the response file are combined, in order.
+		let args: Args = parse(&["first.png", &format!("@{}", list.display()), "last.png"]).expect("Valid arguments");
+		assert_eq!(args.files, [PathBuf::from("first.png"), PathBuf::from("icons/a.png"), PathBuf::from("sprites/b.png"), PathBuf::from("last.png")]);
+		
+		assert!(parse(&[&format!("@{}", dir.join("missing.txt").display())]).is_err());
+		
+		let _ = std::fs::remove_dir_all(&dir);
+	}
+	
+	#[test]
+	fn arguments_after_a_double_dash_are_files()
+	{
+		let args: Args = parse(&["-F", "--", "-weird.png", "--lossless"]).expect("Valid arguments");
+		assert_eq!(args.files, [PathBuf::from("-weird.png"), PathBuf::from("--lossless")]);
+		assert!(args.force);
+		assert!(!args.lossless);
+		
+		assert!(parse(&["-weird.png"]).is_err());
+	}
+	
+	#[test]
+	fn lossless_warning_lists_only_explicitly_given_lossy_options()
+	{
+		let defaults: Args = parse(&["--lossless"]).expect("Failed to parse arguments");
+		assert!(defaults.lossless_conflicts().is_empty());
+		
+		let explicit: Args = parse(&["--lossless", "-q", "80", "--dithering", "none", "-N"]).expect("Failed to parse arguments");
+		assert_eq!(explicit.lossless_conflicts(), vec!["--quality", "--dithering", "--denoise"]);
+		
+		let lossy: Args = parse(&["--quality", "80", "--smooth", "1.0"]).expect("Failed to parse arguments");
+		assert!(lossy.lossless_conflicts().is_empty());
+	}
+	
+	#[test]
+	fn median_dithering_takes_an_optional_palette_size()
+	{
+		let sized: Args = parse(&["--dithering", "median:64"]).expect("Failed to parse arguments");
+		assert_eq!((sized.dithering.as_str(), sized.median_colors), ("median", Some(64)));
+		assert!(sized.validate().is_ok());
+		
+		let bare: Args = parse(&["--dithering", "median"]).expect("Failed to parse arguments");
+		assert_eq!((bare.dithering.as_str(), bare.median_colors), ("median", None));
+		assert!(bare.validate().is_ok());
+		
+		let invalid: Args = parse(&["--dithering", "median:0"]).expect("Failed to parse arguments");
+		let error: String = invalid.validate().expect_err("A palette of 0 colors should be rejected").to_string();
+		assert!(error.contains("between 2 and 256"), "{}", error);
+	}
+	
+	#[test]
+	fn command_line_overrides_environment_overrides_defaults()
+	{
+		let env_var = |name: &str| -> Option<String>
+		{
+			match name
+			{
+				ENV_QUALITY => Some("55".to_string()),
+				ENV_DITHERING => Some("ordered".to_string()),
+				ENV_JOBS => Some("3".to_string()),
+				_ => None,
+			}
+		};
+		
+		let from_env: Args = Args::parse_with_env(Vec::new(), env_var).expect("Failed to parse arguments");
+		assert_eq!((from_env.quality, from_env.dithering.as_str(), from_env.jobs), (55, "ordered", 3));
+		
+		let cli_args: Vec<String> = vec!["-q".to_string(), "70".to_string(), "--dithering".to_string(), "none".to_string(), "-j".to_string(), "2".to_string()];
+		let from_cli: Args = Args::parse_with_env(cli_args, env_var).expect("Failed to parse arguments");
+		assert_eq!((from_cli.quality, from_cli.dithering.as_str(), from_cli.jobs), (70, "none", 2));
+		
+		let defaults: Args = Args::parse_with_env(Vec::new(), |_: &str| None).expect("Failed to parse arguments");
+		let built_in: Args = Args::new();
+		assert_eq!((defaults.quality, defaults.dithering.as_str(), defaults.jobs), (built_in.quality, built_in.dithering.as_str(), built_in.jobs));
+		
+		let error: String = Args::parse_with_env(Vec::new(), |name: &str| if name == ENV_QUALITY { Some("999".to_string()) } else { None }).expect_err("An out of range quality should be rejected").to_string();
+		assert!(error.contains("MINIPNG_QUALITY"), "{}", error);
+	}
+	
+	#[test]
+	fn a_single_factor_applies_to_every_channel()
+	{
+		assert_eq!(parse(&["--factors", "16"]).expect("Failed to parse arguments").factors, Some([16, 16, 16]));
+		assert_eq!(parse(&["--factors", "32,8,32"]).expect("Failed to parse arguments").factors, Some([32, 8, 32]));
+		assert!(parse(&["--factors", "32,8"]).is_err());
+	}
+	
+	#[test]
+	fn version_text_holds_the_package_version_and_details_only_when_verbose()
+	{
+		let short: String = version_text(env!("CARGO_PKG_VERSION"), false);
+		assert!(!short.is_empty());
+		assert!(short.contains(env!("CARGO_PKG_VERSION")));
+		assert_eq!(short.lines().count(), 1);
+		
+		let detailed: String = version_text(env!("CARGO_PKG_VERSION"), true);
+		assert!(detailed.starts_with(&short));
+		for label in ["commit: ", "built: ", "oxipng: ", "image: "]
+		{
+			assert!(detailed.contains(label), "{}", detailed);
+		}
+	}
+}
@@ -0,0 +1,182 @@
+use anyhow::{anyhow, Result};
+use std::fs;
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::utils::file_utils::canonical_or_original;
+
+/// Name of the index file in the backup directory, listing where each backup came from.
+pub const BACKUP_INDEX_NAME: &str = ".minipng-backup-index";
+
+/// Copies of files taken before they are overwritten, so an in-place run can be undone with restore.
+/// Backups mirror the file's path relative to the scan root under the backup directory, and each one is
+/// listed in the index file (backup path, tab, original path) as soon as it is taken.
+/// A file that already has a backup (from an earlier run) keeps it, so the backup is always the oldest original.
+#[derive(Debug)]
+pub struct BackupStore
+{
+	/// Directory the backups are written to.
+	dir: PathBuf,
+	
+	/// Directory the backup paths are relative to.
+	root: PathBuf,
+	
+	/// Serializes appends to the index file.
+	index_lock: Mutex<()>,
+}
+
+impl BackupStore
+{
+	/// Create the backup directory if needed.
+	pub fn open(dir: &Path, root: &Path) -> Result<Self>
+	{
+		fs::create_dir_all(dir)
+			.map_err(|e| anyhow!("Failed to create backup directory {}: {}", dir.display(), e))?;
+		
+		Ok(Self { dir: canonical_or_original(dir), root: canonical_or_original(root), index_lock: Mutex::new(()) })
+	}
+	
+	/// Checks if a path lies inside the backup directory (backups must not be processed themselves).
+	pub fn contains(&self, path: &Path) -> bool
+	{
+		canonical_or_original(path).starts_with(&self.dir)
+	}
+	
+	/// Copy a file into the backup directory before it is overwritten.
+	/// Does nothing if the file doesn't exist or already has a backup.
+	pub fn back_up(&self, path: &Path) -> Result<()>
+	{
+		if !path.is_file()
+		{
+			return Ok(());
+		}
+		
+		let original: PathBuf = canonical_or_original(path);
+		let relative: PathBuf = mirrored_path(&original, &self.root);
+		let backup_path: PathBuf = self.dir.join(&relative);
+		
+		let _guard: std::sync::MutexGuard<()> = self.index_lock.lock().expect("Backup index mutex poisoned");
+		if backup_path.exists()
+		{
+			return Ok(());
+		}
+		
+		if let Some(parent) = backup_path.parent()
+		{
+			fs::create_dir_all(parent)
+				.map_err(|e| anyhow!("Failed to create backup directory {}: {}", parent.display(), e))?;
+		}
+		fs::copy(&original, &backup_path)
+			.map_err(|e| anyhow!("Failed to back up {}: {}", original.display(), e))?;
+		
+		let index_path: PathBuf = self.dir.join(BACKUP_INDEX_NAME);
+		let mut index: fs::File = fs::OpenOptions::new().create(true).append(true).open(&index_path)
+			.map_err(|e| anyhow!("Failed to open backup index {}: {}", index_path.display(), e))?;
+		writeln!(index, "{}\t{}", relative.to_string_lossy().replace('\\', "/"), original.display())
+			.map_err(|e| anyhow!("Failed to write backup index {}: {}", index_path.display(), e))?;
+		
+		Ok(())
+	}
+}
+
+/// Copy every backup listed in a backup directory's index back to its original location.
+/// Returns the restored paths and the files that failed (with the error message).
+#[allow(clippy::type_complexity)]
+pub fn restore(dir: &Path) -> Result<(Vec<PathBuf>, Vec<(String, String)>)>
+{
+	let index_path: PathBuf = dir.join(BACKUP_INDEX_NAME);
+	let index: String = fs::read_to_string(&index_path)
+		.map_err(|e| anyhow!("Failed to read backup index {}: {}", index_path.display(), e))?;
+	
+	let mut restored: Vec<PathBuf> = Vec::new();
+	let mut errors: Vec<(String, String)> = Vec::new();
+	for line in index.lines()
+	{
+		let (relative, original): (&str, &str) = match line.split_once('\t')
+		{
+			Some(entry) => entry,
+			None => continue,
+		};
+		
+		let backup_path: PathBuf = dir.join(relative);
+		let original_path: PathBuf = PathBuf::from(original);
+		match fs::copy(&backup_path, &original_path)
+		{
+			Ok(_) => restored.push(original_path),
+			Err(e) => errors.push((original.to_string(), format!("Failed to restore from {}: {}", backup_path.display(), e))),
+		}
+	}
+	
+	Ok((restored, errors))
+}
+
+/// Path of a file's backup relative to the backup directory.
+/// Files inside the root keep their relative path; others use their absolute path without the drive or root.
+fn mirrored_path(path: &Path, root: &Path) -> PathBuf
+{
+	if let Ok(relative) = path.strip_prefix(root)
+	{
+		return relative.to_path_buf();
+	}
+	
+	let mut mirrored: PathBuf = PathBuf::new();
+	for component in path.components()
+	{
+		match component
+		{
+			Component::Prefix(prefix) => mirrored.push(prefix.as_os_str().to_string_lossy().replace(':', "")),
+			Component::Normal(name) => mirrored.push(name),
+			_ => {},
+		}
+	}
+	
+	mirrored
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+	use crate::minify::MinifyOptions;
+	
+	use crate::utils::file_utils::process_file;
+	use crate::utils::file_utils::tests::{photo, scratch_dir};
+	
+	#[test]
+	fn restoring_after_an_in_place_run_gives_back_the_original_bytes()
+	{
+		let dir: PathBuf = scratch_dir("backup-restore");
+		let root: PathBuf = dir.join("images");
+		let backup_dir: PathBuf = dir.join("backups");
+		fs::create_dir_all(root.join("nested")).expect("Failed to create test directory");
+		
+		let paths: [PathBuf; 2] = [root.join("a.png"), root.join("nested").join("b.png")];
+		let mut originals: Vec<Vec<u8>> = Vec::new();
+		for path in &paths
+		{
+			let rgba: image::RgbaImage = photo(48, 48);
+			rgba.save(path).expect("Failed to write test image");
+			originals.push(fs::read(path).expect("Failed to read test image"));
+		}
+		
+		let options: MinifyOptions = MinifyOptions::builder().quality(40).fast_deflate(true).build().expect("Valid options");
+		let store: BackupStore = BackupStore::open(&backup_dir, &root).expect("Failed to open backup store");
+		for (path, original) in paths.iter().zip(&originals)
+		{
+			process_file(path, path, &options, false, None, Some(&store), None).expect("Processing failed");
+			assert_ne!(&fs::read(path).expect("Failed to read minified image"), original);
+		}
+		assert!(backup_dir.join("nested").join("b.png").is_file());
+		
+		let (restored, errors): (Vec<PathBuf>, Vec<(String, String)>) = restore(&backup_dir).expect("Restore failed");
+		assert!(errors.is_empty(), "{:?}", errors);
+		assert_eq!(restored.len(), 2);
+		for (path, original) in paths.iter().zip(&originals)
+		{
+			assert_eq!(&fs::read(path).expect("Failed to read restored image"), original);
+		}
+		
+		let _ = fs::remove_dir_all(&dir);
+	}
+}
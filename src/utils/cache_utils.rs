@@ -0,0 +1,359 @@
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
+
+use crate::minify::{self, DitheringMode};
+use crate::utils::file_utils::canonical_or_original;
+
+/// Name of the analysis cache file written to the scan root.
+pub const CACHE_FILE_NAME: &str = ".minipng-cache.json";
+
+/// Version of the cache file format. Files with another version are ignored.
+const CACHE_VERSION: u32 = 1;
+
+/// What was recorded about a file the last time it was processed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct CacheEntry
+{
+	/// File size when the entry was recorded.
+	size: u64,
+	
+	/// Modification time (nanoseconds since the Unix epoch) when the entry was recorded.
+	mtime_ns: u64,
+	
+	/// Dithering mode chosen for the file.
+	dithering_mode: DitheringMode,
+	
+	/// Size the file was minified to.
+	new_size: u64,
+}
+
+/// Per-file analysis results kept across runs in a sidecar file.
+/// Entries are keyed by path and only match while the file's size and modification time are unchanged,
+/// so a forced re-run can reuse the chosen dithering mode instead of analyzing the image again.
+#[derive(Debug)]
+pub struct AnalysisCache
+{
+	/// Directory the cache file lives in (paths are stored relative to it).
+	root: PathBuf,
+	
+	/// Recorded entries by cache key.
+	entries: Mutex<HashMap<String, CacheEntry>>,
+}
+
+impl AnalysisCache
+{
+	/// Load the cache file from the scan root, starting empty if there is none.
+	/// A cache file that can't be read or parsed is ignored with a warning.
+	pub fn load(root: &Path) -> Self
+	{
+		let root: PathBuf = canonical_or_original(root);
+		let path: PathBuf = root.join(CACHE_FILE_NAME);
+		
+		let mut entries: HashMap<String, CacheEntry> = HashMap::new();
+		if path.is_file()
+		{
+			match fs::read_to_string(&path)
+			{
+				Ok(text) =>
+				{
+					match parse_cache(&text)
+					{
+						Some(parsed) => entries = parsed,
+						None => eprintln!("Warning: ignoring unreadable cache file {}", path.display()),
+					}
+				},
+				Err(e) => eprintln!("Warning: failed to read cache file {}: {}", path.display(), e),
+			}
+		}
+		
+		Self { root, entries: Mutex::new(entries) }
+	}
+	
+	/// Path of the cache file.
+	pub fn file_path(&self) -> PathBuf
+	{
+		self.root.join(CACHE_FILE_NAME)
+	}
+	
+	/// Look up the dithering mode recorded for a file, if the file hasn't changed since.
+	pub fn lookup(&self, path: &Path) -> Option<DitheringMode>
+	{
+		let (size, mtime_ns): (u64, u64) = file_stamp(path)?;
+		let entries: std::sync::MutexGuard<HashMap<String, CacheEntry>> = self.entries.lock().expect("Cache mutex poisoned");
+		let entry: &CacheEntry = entries.get(&self.key(path))?;
+		
+		if entry.size == size && entry.mtime_ns == mtime_ns
+		{
+			Some(entry.dithering_mode)
+		}
+		else
+		{
+			None
+		}
+	}
+	
+	/// Record the dithering mode and size for a file in its current state on disk.
+	pub fn record(&self, path: &Path, dithering_mode: DitheringMode, new_size: u64)
+	{
+		let (size, mtime_ns): (u64, u64) = match file_stamp(path)
+		{
+			Some(stamp) => stamp,
+			None => return,
+		};
+		
+		let entry: CacheEntry = CacheEntry { size, mtime_ns, dithering_mode, new_size };
+		self.entries.lock().expect("Cache mutex poisoned").insert(self.key(path), entry);
+	}
+	
+	/// Write the cache file to the scan root.
+	pub fn save(&self) -> Result<()>
+	{
+		let entries: std::sync::MutexGuard<HashMap<String, CacheEntry>> = self.entries.lock().expect("Cache mutex poisoned");
+		
+		// Sort by path so the file doesn't change between runs for no reason.
+		let mut keys: Vec<&String> = entries.keys().collect();
+		keys.sort();
+		
+		let mut json: String = String::new();
+		json.push_str("{\n");
+		json.push_str(&format!("  \"version\": {},\n", CACHE_VERSION));
+		json.push_str("  \"files\": [\n");
+		for (index, key) in keys.iter().enumerate()
+		{
+			let entry: &CacheEntry = &entries[*key];
+			let separator: &str = if index + 1 < keys.len() { "," } else { "" };
+			json.push_str(&format!("    {{\"path\": \"{}\", \"size\": {}, \"mtime_ns\": {}, \"dithering\": \"{}\", \"new_size\": {}}}{}\n", escape_json(key), entry.size, entry.mtime_ns, minify::dithering_mode_to_string(entry.dithering_mode), entry.new_size, separator));
+		}
+		json.push_str("  ]\n");
+		json.push_str("}\n");
+		
+		let path: PathBuf = self.file_path();
+		fs::write(&path, json).map_err(|e| anyhow!("Failed to write cache file {}: {}", path.display(), e))
+	}
+	
+	/// Cache key for a file: its path relative to the cache root, or the absolute path for files outside it.
+	fn key(&self, path: &Path) -> String
+	{
+		let absolute: PathBuf = canonical_or_original(path);
+		let relative: &Path = absolute.strip_prefix(&self.root).unwrap_or(&absolute);
+		relative.to_string_lossy().replace('\\', "/")
+	}
+}
+
+/// Size and modification time (nanoseconds since the Unix epoch) of a file.
+fn file_stamp(path: &Path) -> Option<(u64, u64)>
+{
+	let metadata: fs::Metadata = fs::metadata(path).ok()?;
+	let modified: std::time::Duration = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+	Some((metadata.len(), modified.as_nanos() as u64))
+}
+
+/// Parse the cache file written by save.
+/// Returns None if the file isn't a cache file of the current version.
+fn parse_cache(text: &str) -> Option<HashMap<String, CacheEntry>>
+{
+	let mut version: Option<u32> = None;
+	let mut entries: HashMap<String, CacheEntry> = HashMap::new();
+	
+	for line in text.lines()
+	{
+		let line: &str = line.trim().trim_end_matches(',');
+		if let Some(value) = line.strip_prefix("\"version\":")
+		{
+			version = value.trim().parse::<u32>().ok();
+		}
+		else if line.starts_with('{') && line.ends_with('}') && line.len() > 1
+		{
+			let fields: HashMap<String, String> = parse_flat_object(line)?;
+			let path: String = fields.get("path")?.clone();
+			let entry: CacheEntry = CacheEntry
+			{
+				size: fields.get("size")?.parse::<u64>().ok()?,
+				mtime_ns: fields.get("mtime_ns")?.parse::<u64>().ok()?,
+				dithering_mode: minify::parse_dithering_mode(fields.get("dithering")?)?,
+				new_size: fields.get("new_size")?.parse::<u64>().ok()?,
+			};
+			entries.insert(path, entry);
+		}
+	}
+	
+	if version != Some(CACHE_VERSION)
+	{
+		return None;
+	}
+	
+	Some(entries)
+}
+
+/// Parse a single-line JSON object whose values are strings or numbers.
+fn parse_flat_object(line: &str) -> Option<HashMap<String, String>>
+{
+	let chars: Vec<char> = line.chars().collect();
+	let mut fields: HashMap<String, String> = HashMap::new();
+	let mut pos: usize = 1;
+	
+	loop
+	{
+		skip_whitespace(&chars, &mut pos);
+		if chars.get(pos) == Some(&'}')
+		{
+			return Some(fields);
+		}
+		
+		let key: String = parse_json_string(&chars, &mut pos)?;
+		skip_whitespace(&chars, &mut pos);
+		if chars.get(pos) != Some(&':')
+		{
+			return None;
+		}
+		pos += 1;
+		skip_whitespace(&chars, &mut pos);
+		
+		let value: String = if chars.get(pos) == Some(&'"')
+		{
+			parse_json_string(&chars, &mut pos)?
+		}
+		else
+		{
+			let start: usize = pos;
+			while pos < chars.len() && chars[pos] != ',' && chars[pos] != '}'
+			{
+				pos += 1;
+			}
+			chars[start..pos].iter().collect::<String>().trim().to_string()
+		};
+		fields.insert(key, value);
+		
+		skip_whitespace(&chars, &mut pos);
+		match chars.get(pos)
+		{
+			Some(',') => pos += 1,
+			Some('}') => return Some(fields),
+			_ => return None,
+		}
+	}
+}
+
+/// Advance past spaces and tabs.
+fn skip_whitespace(chars: &[char], pos: &mut usize)
+{
+	while *pos < chars.len() && chars[*pos].is_whitespace()
+	{
+		*pos += 1;
+	}
+}
+
+/// Parse a JSON string starting at the opening quote, leaving pos after the closing quote.
+fn parse_json_string(chars: &[char], pos: &mut usize) -> Option<String>
+{
+	if chars.get(*pos) != Some(&'"')
+	{
+		return None;
+	}
+	*pos += 1;
+	
+	let mut value: String = String::new();
+	while *pos < chars.len()
+	{
+		let c: char = chars[*pos];
+		*pos += 1;
+		match c
+		{
+			'"' => return Some(value),
+			'\\' =>
+			{
+				let escaped: char = *chars.get(*pos)?;
+				*pos += 1;
+				match escaped
+				{
+					'n' => value.push('\n'),
+					'r' => value.push('\r'),
+					't' => value.push('\t'),
+					'u' =>
+					{
+						let hex: String = chars.get(*pos..*pos + 4)?.iter().collect();
+						*pos += 4;
+						value.push(char::from_u32(u32::from_str_radix(&hex, 16).ok()?)?);
+					},
+					other => value.push(other),
+				}
+			},
+			other => value.push(other),
+		}
+	}
+	
+	None
+}
+
+/// Escape a string for use inside JSON quotes.
+pub fn escape_json(text: &str) -> String
+{
+	let mut escaped: String = String::with_capacity(text.len());
+	for c in text.chars()
+	{
+		match c
+		{
+			'"' => escaped.push_str("\\\""),
+			'\\' => escaped.push_str("\\\\"),
+			'\n' => escaped.push_str("\\n"),
+			'\r' => escaped.push_str("\\r"),
+			'\t' => escaped.push_str("\\t"),
+			c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+			c => escaped.push(c),
+		}
+	}
+	
+	escaped
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+	use crate::minify::{MinifyOptions, ProcessingResult};
+	use crate::utils::file_utils::process_file;
+	use crate::utils::file_utils::tests::scratch_dir;
+	
+	/// Write a small noisy image that the auto analysis has to look at.
+	fn write_image(path: &Path, size: u32)
+	{
+		let rgba: image::RgbaImage = image::RgbaImage::from_fn(size, size, |x: u32, y: u32| image::Rgba([(x * 7 + y * 3) as u8, (x * y) as u8, (x ^ y) as u8 * 4, 255]));
+		rgba.save(path).expect("Failed to write test image");
+	}
+	
+	#[test]
+	fn second_run_reuses_the_recorded_dithering_mode()
+	{
+		let dir: PathBuf = scratch_dir("cache");
+		let source: PathBuf = dir.join("a.png");
+		let target: PathBuf = dir.join("out.png");
+		write_image(&source, 32);
+		let options: MinifyOptions = MinifyOptions::builder().dithering(DitheringMode::Auto).fast_deflate(true).build().expect("Valid options");
+		
+		// The first run analyzes the image and records the chosen mode.
+		let cache: AnalysisCache = AnalysisCache::load(&dir);
+		let (result, _): (ProcessingResult, Option<minify::MinificationInfo>) = process_file(&source, &target, &options, false, Some(&cache), None, None).expect("Processing failed");
+		let chosen: DitheringMode = result.dithering_mode.expect("Missing dithering mode");
+		assert_eq!(cache.lookup(&source), Some(chosen));
+		cache.save().expect("Failed to save cache");
+		
+		// The second run loads the cache file and uses the recorded mode instead of analyzing again.
+		// A different mode is recorded so the result shows where it came from.
+		let cache: AnalysisCache = AnalysisCache::load(&dir);
+		assert_eq!(cache.lookup(&source), Some(chosen));
+		let recorded: DitheringMode = if chosen == DitheringMode::Ordered { DitheringMode::None } else { DitheringMode::Ordered };
+		cache.record(&source, recorded, result.new_size);
+		let (result, _): (ProcessingResult, Option<minify::MinificationInfo>) = process_file(&source, &target, &options, true, Some(&cache), None, None).expect("Processing failed");
+		assert_eq!(result.dithering_mode, Some(recorded));
+		
+		// A changed file misses the cache.
+		write_image(&source, 40);
+		assert_eq!(cache.lookup(&source), None);
+		
+		let _ = fs::remove_dir_all(&dir);
+	}
+}
@@ -0,0 +1,60 @@
+// sRGB <-> linear light conversion.
+// Averaging or quantizing gamma-encoded values biases results toward darker colors,
+// so gamma-correct processing converts to linear light first and back afterward.
+// Values are assumed sRGB unless another encoding gamma is given, e.g. for scientific or pre-linearized images.
+
+/// Encoding gamma assumed by default, which converts with the exact sRGB curve rather than a pure power.
+pub const SRGB_GAMMA: f32 = 2.2;
+
+/// Convert an sRGB channel value (0-255) to linear light (0.0-1.0).
+pub fn srgb_to_linear(value: u8) -> f32
+{
+	let normalized: f32 = value as f32 / 255.0;
+	if normalized <= 0.04045
+	{
+		normalized / 12.92
+	}
+	else
+	{
+		((normalized + 0.055) / 1.055).powf(2.4)
+	}
+}
+
+/// Convert linear light (0.0-1.0) back to an sRGB channel value (0-255).
+pub fn linear_to_srgb(value: f32) -> u8
+{
+	let clamped: f32 = value.clamp(0.0, 1.0);
+	let encoded: f32 = if clamped <= 0.0031308
+	{
+		clamped * 12.92
+	}
+	else
+	{
+		1.055 * clamped.powf(1.0 / 2.4) - 0.055
+	};
+	
+	(encoded * 255.0).round().clamp(0.0, 255.0) as u8
+}
+
+/// Convert a channel value (0-255) encoded with the given gamma to linear light (0.0-1.0).
+/// SRGB_GAMMA uses the sRGB curve, other values a pure power (1.0 means the values are linear already).
+pub fn to_linear(value: u8, gamma: f32) -> f32
+{
+	if gamma == SRGB_GAMMA
+	{
+		return srgb_to_linear(value);
+	}
+	
+	(value as f32 / 255.0).powf(gamma)
+}
+
+/// Convert linear light (0.0-1.0) back to a channel value (0-255) encoded with the given gamma.
+pub fn from_linear(value: f32, gamma: f32) -> u8
+{
+	if gamma == SRGB_GAMMA
+	{
+		return linear_to_srgb(value);
+	}
+	
+	(value.clamp(0.0, 1.0).powf(1.0 / gamma) * 255.0).round().clamp(0.0, 255.0) as u8
+}
@@ -1,7 +1,18 @@
 use anyhow::{anyhow, Result};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{BufWriter, Write};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
 
-use crate::minify::{minify_png, ProcessingResult, MinificationInfo};
+use minipng::error::MinifyError;
+use minipng::utils::hash_utils::{self, Sha256};
+use crate::utils::backup_utils::BackupStore;
+use crate::utils::cache_utils::AnalysisCache;
+use crate::utils::ignore_utils::IgnoreRules;
+use crate::utils::progress_utils::ProgressLog;
+use crate::minify::{apply_recorded_settings, encode_webp, exceeds_max_pixels, is_already_minified, is_already_quantized, is_indexed_color, is_supported_format, minified_before, minify_bytes, minify_bytes_with_pixels, noop_marked, prefer_lossy, trailing_data_len, webp_from_png, ContentHashes, DitheringMode, MarkedPng, AUTO_QUALITY, IfExists, MinifyOptions, ProcessingResult, MinificationInfo, SkipReason, PNG_SIGNATURE};
 
 /// Represents a PNG file to process.
 #[derive(Clone)]
@@ -11,37 +22,147 @@ pub struct PngFile
 	pub target_path: PathBuf,
 }
 
+/// Order of the files to process (--sort).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortOrder
+{
+	/// Case-insensitive path order (directory scans already come in this order).
+	Name,
+	
+	/// Largest files first, to surface the big wins early.
+	Size,
+	
+	/// Processing in discovery order, with the summary listing every file by bytes saved.
+	Savings,
+}
+
+/// Parse a --sort order name.
+pub fn parse_sort_order(order: &str) -> Option<SortOrder>
+{
+	match order.to_lowercase().as_str()
+	{
+		"name" => Some(SortOrder::Name),
+		"size" => Some(SortOrder::Size),
+		"savings" => Some(SortOrder::Savings),
+		_ => None,
+	}
+}
+
+/// Sort the files to process by name or by descending size.
+/// Savings only orders the summary, so the files keep their discovery order.
+pub fn sort_png_files(files: &mut [PngFile], order: SortOrder)
+{
+	match order
+	{
+		SortOrder::Name => files.sort_by(|a: &PngFile, b: &PngFile| compare_paths_case_insensitive(&a.source_path, &b.source_path)),
+		SortOrder::Size =>
+		{
+			// Read each size once; files that can't be read count as empty. The sort is stable, so equal sizes keep their order.
+			files.sort_by_cached_key(|file: &PngFile| std::cmp::Reverse(fs::metadata(&file.source_path).map_or(0, |metadata| metadata.len())));
+		},
+		SortOrder::Savings => {},
+	}
+}
+
+/// Time limit of a batch run (--time-budget): files are only started while the budget lasts.
+/// Files already being processed when it runs out are finished; the others are counted as remaining.
+pub struct TimeBudget
+{
+	deadline: Instant,
+	remaining: AtomicUsize,
+}
+
+impl TimeBudget
+{
+	/// Budget for a run that started at start.
+	pub fn new(start: Instant, budget: Duration) -> Self
+	{
+		TimeBudget { deadline: start + budget, remaining: AtomicUsize::new(0) }
+	}
+	
+	/// Checks if another file may be started. Once the budget is spent, the file is counted as remaining instead.
+	pub fn try_start(&self) -> bool
+	{
+		if Instant::now() < self.deadline
+		{
+			return true;
+		}
+		
+		self.remaining.fetch_add(1, Ordering::Relaxed);
+		false
+	}
+	
+	/// Number of files that were not started because the budget was spent.
+	pub fn remaining(&self) -> usize
+	{
+		self.remaining.load(Ordering::Relaxed)
+	}
+}
+
 /// Recursively find all files in a directory that match a predicate.
-pub fn find_files_recursive<F>(directory: &Path, file_predicate: F) -> Result<Vec<PathBuf>> where F: Fn(&Path) -> bool + Copy
+/// Subdirectories are only descended if they match the directory predicate.
+/// Symbolic links are skipped unless follow_symlinks is true; directories reached twice (symlink cycles) are scanned once.
+pub fn find_files_recursive<F, D>(directory: &Path, file_predicate: F, dir_predicate: D, follow_symlinks: bool) -> Result<Vec<PathBuf>> where F: Fn(&Path) -> bool + Copy, D: Fn(&Path) -> bool + Copy
 {
 	let mut result: Vec<PathBuf> = Vec::new();
-	collect_files_recursive(directory, &mut result, file_predicate)?;
+	let mut visited_dirs: HashSet<PathBuf> = HashSet::new();
+	collect_files_recursive(directory, &mut |path: PathBuf| result.push(path), file_predicate, dir_predicate, follow_symlinks, &mut visited_dirs)?;
 	
 	if result.is_empty()
 	{
 		return Err(anyhow!("No matching files found in the directory or subdirectories."));
 	}
 	
+	// Sort case-insensitively so the order doesn't depend on the filesystem (exact path breaks ties).
+	result.sort_by(|a: &PathBuf, b: &PathBuf| compare_paths_case_insensitive(a, b));
+	
 	Ok(result)
 }
 
-/// Internal helper function to collect files recursively.
-fn collect_files_recursive<F>(dir: &Path, files: &mut Vec<PathBuf>, file_predicate: F) -> Result<()> where F: Fn(&Path) -> bool + Copy
+/// Compare paths case-insensitively, falling back to the exact path for a stable order.
+fn compare_paths_case_insensitive(a: &Path, b: &Path) -> std::cmp::Ordering
+{
+	let a_lower: String = a.to_string_lossy().to_lowercase();
+	let b_lower: String = b.to_string_lossy().to_lowercase();
+	a_lower.cmp(&b_lower).then_with(|| a.cmp(b))
+}
+
+/// Internal helper function to collect files recursively, passing each matching file to visit as it is found.
+fn collect_files_recursive<V, F, D>(dir: &Path, visit: &mut V, file_predicate: F, dir_predicate: D, follow_symlinks: bool, visited_dirs: &mut HashSet<PathBuf>) -> Result<()> where V: FnMut(PathBuf), F: Fn(&Path) -> bool + Copy, D: Fn(&Path) -> bool + Copy
 {
 	if !dir.is_dir()
 	{
 		return Err(anyhow!("Not a directory: {}", dir.display()));
 	}
 	
-	for entry in std::fs::read_dir(dir)?
+	// Scan each real directory only once so symlink cycles can't recurse forever.
+	if !visited_dirs.insert(canonical_or_original(dir))
+	{
+		return Ok(());
+	}
+	
+	for entry in fs::read_dir(dir)?
 	{
-		let entry_result: std::fs::DirEntry = entry?;
+		let entry_result: fs::DirEntry = entry?;
 		let path: PathBuf = entry_result.path();
 		
+		// Skip symbolic links unless asked to follow them.
+		let is_symlink: bool = entry_result.file_type().map(|file_type| file_type.is_symlink()).unwrap_or(false);
+		if is_symlink && !follow_symlinks
+		{
+			continue;
+		}
+		
 		if path.is_dir()
 		{
+			// Skip directories the caller doesn't want descended.
+			if !dir_predicate(&path)
+			{
+				continue;
+			}
+			
 			// Recursively process subdirectories.
-			if let Err(e) = collect_files_recursive(&path, files, file_predicate)
+			if let Err(e) = collect_files_recursive(&path, visit, file_predicate, dir_predicate, follow_symlinks, visited_dirs)
 			{
 				// Log error but continue with other directories.
 				eprintln!("Error processing directory {}: {}", path.display(), e);
@@ -49,19 +170,24 @@ fn collect_files_recursive<F>(dir: &Path, files: &mut Vec<PathBuf>, file_predica
 		}
 		else if file_predicate(&path)
 		{
-			// Add matching file to the list.
-			files.push(path);
+			// Hand the matching file to the caller.
+			visit(path);
 		}
 	}
 	
 	Ok(())
 }
 
-/// Find all PNG files in a directory and its subdirectories.
-pub fn find_png_files_in_dir(dir: Option<&Path>, _inplace: bool) -> Result<Vec<PngFile>>
+/// Find all PNG files in a directory and, if recurse is true, its subdirectories.
+/// Files matching any of the exclude patterns (relative to the scanned directory) are skipped.
+/// Unless disabled, paths matched by a .minipngignore file in the scanned directory are skipped too.
+/// Symbolic links are only followed if follow_symlinks is true.
+#[allow(clippy::reserve_after_initialization)]
+pub fn find_png_files_in_dir(dir: Option<&Path>, _inplace: bool, excludes: &[String], use_ignore_file: bool, follow_symlinks: bool, recurse: bool) -> Result<Vec<PngFile>>
 {
 	let directory: &Path = dir.unwrap_or_else(|| Path::new("."));
-	let png_files: Vec<PathBuf> = find_files_recursive(directory, is_png_file)?;
+	let ignore_rules: IgnoreRules = if use_ignore_file { IgnoreRules::load(directory)? } else { IgnoreRules::new() };
+	let png_files: Vec<PathBuf> = find_files_recursive(directory, |path: &Path| is_png_file(path) && !is_excluded(path, directory, excludes) && !ignore_rules.is_ignored(path, directory, false), |path: &Path| recurse && !ignore_rules.is_ignored(path, directory, true), follow_symlinks)?;
 	
 	// Convert to PngFile structures.
 	let mut result: Vec<PngFile> = Vec::new();
@@ -79,7 +205,19 @@ pub fn find_png_files_in_dir(dir: Option<&Path>, _inplace: bool) -> Result<Vec<P
 	Ok(result)
 }
 
+/// Call visit for each PNG file in a directory (and, if recurse is true, its subdirectories) as soon as it is found,
+/// without collecting or sorting them, so huge trees can be processed in constant memory.
+/// Applies the same exclude patterns, .minipngignore rules and symbolic link handling as find_png_files_in_dir.
+pub fn for_each_png_file_in_dir<V>(directory: &Path, excludes: &[String], use_ignore_file: bool, follow_symlinks: bool, recurse: bool, mut visit: V) -> Result<()> where V: FnMut(PngFile)
+{
+	let ignore_rules: IgnoreRules = if use_ignore_file { IgnoreRules::load(directory)? } else { IgnoreRules::new() };
+	let mut visited_dirs: HashSet<PathBuf> = HashSet::new();
+	let mut visit_path = |path: PathBuf| visit(PngFile { source_path: path.clone(), target_path: path });
+	collect_files_recursive(directory, &mut visit_path, |path: &Path| is_png_file(path) && !is_excluded(path, directory, excludes) && !ignore_rules.is_ignored(path, directory, false), |path: &Path| recurse && !ignore_rules.is_ignored(path, directory, true), follow_symlinks, &mut visited_dirs)
+}
+
 /// Prepare a list of specific PNG files for processing.
+#[allow(clippy::reserve_after_initialization)]
 pub fn prepare_specific_png_files(files: &[PathBuf], _inplace: bool) -> Vec<PngFile>
 {
 	let mut result: Vec<PngFile> = Vec::new();
@@ -97,6 +235,66 @@ pub fn prepare_specific_png_files(files: &[PathBuf], _inplace: bool) -> Vec<PngF
 	result
 }
 
+/// Point the targets at an output path instead of the sources.
+/// The output is a file name when it ends in .png, or for a single input file given on the command line unless the
+/// output is an existing directory; it is an error to combine a file name with several inputs.
+/// Otherwise it is a directory: each target keeps its path relative to root below it (files outside root keep only
+/// their name), and sources already inside the output directory (from an earlier run) are dropped.
+/// It is an error for two files outside root with the same name to collapse onto one target.
+pub fn redirect_targets(files: &mut Vec<PngFile>, output: &Path, root: &Path, single_input: bool) -> Result<()>
+{
+	let names_file: bool = output.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("png"));
+	if names_file || (single_input && files.len() == 1 && !output.is_dir())
+	{
+		if files.len() != 1
+		{
+			return Err(anyhow!("Output {} names a single file but {} input files were found (use a directory to write several files)", output.display(), files.len()));
+		}
+		
+		files[0].target_path = output.to_path_buf();
+		return Ok(());
+	}
+	
+	let output_dir: PathBuf = canonical_or_original(output);
+	let root: PathBuf = canonical_or_original(root);
+	files.retain(|file| !canonical_or_original(&file.source_path).starts_with(&output_dir));
+	let mut sources_by_target: HashMap<PathBuf, PathBuf> = HashMap::new();
+	for file in files.iter_mut()
+	{
+		let source: PathBuf = canonical_or_original(&file.source_path);
+		let relative: PathBuf = match source.strip_prefix(&root)
+		{
+			Ok(relative) => relative.to_path_buf(),
+			Err(_) => PathBuf::from(source.file_name().unwrap_or_default()),
+		};
+		file.target_path = output.join(relative);
+		
+		if let Some(other) = sources_by_target.insert(file.target_path.clone(), file.source_path.clone())
+		{
+			return Err(anyhow!("{} and {} would both be written to {} (files outside {} keep only their name)", other.display(), file.source_path.display(), file.target_path.display(), root.display()));
+		}
+	}
+	
+	Ok(())
+}
+
+/// Remove files that resolve to the same canonical path, keeping the first occurrence.
+/// Returns the number of duplicates removed.
+pub fn dedupe_png_files(files: &mut Vec<PngFile>) -> usize
+{
+	let original_count: usize = files.len();
+	let mut seen: HashSet<PathBuf> = HashSet::new();
+	files.retain(|file| seen.insert(canonical_or_original(&file.source_path)));
+	
+	original_count - files.len()
+}
+
+/// Canonicalize a path, falling back to the path itself if it cannot be resolved.
+pub(crate) fn canonical_or_original(path: &Path) -> PathBuf
+{
+	fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
 /// Checks if a file is a PNG file by its extension.
 pub fn is_png_file(path: &Path) -> bool
 {
@@ -108,9 +306,1416 @@ pub fn is_png_file(path: &Path) -> bool
 	false
 }
 
-/// Process a single PNG file.
-pub fn process_file(source_path: &Path, target_path: &Path, lossless: bool, quality: u8, dithering_mode: crate::minify::DitheringMode, smooth_radius: f32, denoise: bool, force: bool) -> Result<(ProcessingResult, Option<MinificationInfo>)>
+/// Checks if a path matches any of the exclude patterns.
+/// Patterns containing '/' are matched against the path relative to the scan root, others against the file name.
+/// Supported wildcards: '*' (any characters except '/'), '**' (any characters) and '?' (a single character).
+pub fn is_excluded(path: &Path, root: &Path, excludes: &[String]) -> bool
 {
-	// Apply the minification pipeline.
-	minify_png(source_path, target_path, lossless, quality, dithering_mode, smooth_radius, denoise, force)
+	if excludes.is_empty()
+	{
+		return false;
+	}
+	
+	let relative: &Path = path.strip_prefix(root).unwrap_or(path);
+	let relative_str: String = relative.to_string_lossy().replace('\\', "/");
+	let file_name: String = match path.file_name()
+	{
+		Some(name) => name.to_string_lossy().to_string(),
+		None => relative_str.clone(),
+	};
+	
+	for pattern in excludes
+	{
+		let pattern: String = pattern.replace('\\', "/");
+		let candidate: &str = if pattern.contains('/') { &relative_str } else { &file_name };
+		if wildcard_match(pattern.trim_start_matches("./").as_bytes(), candidate.as_bytes())
+		{
+			return true;
+		}
+	}
+	
+	false
+}
+
+/// Match text against a wildcard pattern supporting '*', '**' and '?'.
+pub fn wildcard_match(pattern: &[u8], text: &[u8]) -> bool
+{
+	if pattern.is_empty()
+	{
+		return text.is_empty();
+	}
+	
+	match pattern[0]
+	{
+		b'*' =>
+		{
+			// A double star crosses directory separators, a single star does not.
+			let crosses_dirs: bool = pattern.len() > 1 && pattern[1] == b'*';
+			let rest: &[u8] = if crosses_dirs { &pattern[2..] } else { &pattern[1..] };
+			
+			for split in 0..=text.len()
+			{
+				if wildcard_match(rest, &text[split..])
+				{
+					return true;
+				}
+				
+				if split < text.len() && text[split] == b'/' && !crosses_dirs
+				{
+					break;
+				}
+			}
+			
+			false
+		},
+		b'?' => !text.is_empty() && text[0] != b'/' && wildcard_match(&pattern[1..], &text[1..]),
+		c => !text.is_empty() && text[0] == c && wildcard_match(&pattern[1..], &text[1..]),
+	}
+}
+
+/// Minifies a PNG file using a combination of techniques.
+///
+/// If options.lossless is true, only lossless minification is applied.
+/// Otherwise, applies lossy minification with the specified quality level and dithering mode.
+/// 
+/// If force is true, skips the marker check and re-minifies even if already minified.
+/// With options.reuse_settings, a forced re-minification uses the mode, quality and dithering recorded in the marker.
+/// With options.reminify_before, marked files minified before the cutoff are re-minified as if force were set.
+/// If the target is a different, existing file, options.if_exists decides whether it is skipped, overwritten or reported as an error.
+/// With a backup store, an existing target is copied into it before it is overwritten.
+/// 
+/// Returns (ProcessingResult, Option<MinificationInfo>) - the second value is Some if file was already minified.
+pub fn minify_png(source_path: &Path, target_path: &Path, options: &MinifyOptions, force: bool, backup: Option<&BackupStore>) -> Result<(ProcessingResult, Option<MinificationInfo>)>
+{
+	// Get the original file size.
+	let original_size: u64 = fs::metadata(source_path)
+		.map_err(|e| anyhow!("Failed to get file metadata: {}", e))?
+		.len();
+	
+	// Apply the --if-exists policy when writing somewhere other than the source.
+	if source_path != target_path && target_path.exists()
+	{
+		match options.if_exists
+		{
+			IfExists::Overwrite => {},
+			IfExists::Skip =>
+			{
+				return Ok((ProcessingResult { lossless: options.lossless, ..ProcessingResult::skipped(source_path, original_size, SkipReason::TargetExists) }, None));
+			},
+			IfExists::Error =>
+			{
+				return Err(anyhow!("Target file already exists: {}", target_path.display()));
+			},
+		}
+	}
+	
+	// Leave read-only targets alone unless asked to write them anyway.
+	if !options.force_writable && is_read_only(target_path)
+	{
+		return Ok((ProcessingResult { lossless: options.lossless, ..ProcessingResult::skipped(source_path, original_size, SkipReason::ReadOnly) }, None));
+	}
+	
+	// Read the source file into memory.
+	let mut source_data: Vec<u8> = fs::read(source_path)
+		.map_err(|e| MinifyError::io("Failed to read source file", e))?;
+	let source_hash: Option<[u8; 32]> = if options.hashes { Some(hash_utils::sha256(&source_data)) } else { None };
+	
+	// Check if this file has already been minified by this tool (unless force is true).
+	let (is_minified, prev_info) = if force
+	{
+		(false, None)
+	}
+	else
+	{
+		is_already_minified(&source_data)?
+	};
+	
+	// Files minified before the cutoff are re-minified like forced ones.
+	let outdated: bool = match (options.reminify_before, &prev_info)
+	{
+		(Some(cutoff), Some(info)) => is_minified && minified_before(info, cutoff),
+		_ => false,
+	};
+	let (is_minified, prev_info) = if outdated { (false, None) } else { (is_minified, prev_info) };
+	
+	// Re-minify with the settings recorded in the marker instead of the current ones if requested.
+	let reused_options: Option<MinifyOptions> = if (force || outdated) && options.reuse_settings
+	{
+		match is_already_minified(&source_data)?
+		{
+			(true, Some(info)) => Some(apply_recorded_settings(options, &info)),
+			_ => None,
+		}
+	}
+	else
+	{
+		None
+	};
+	let options: &MinifyOptions = reused_options.as_ref().unwrap_or(options);
+	
+	// Leave images above the pixel limit alone before anything decodes them.
+	if !is_minified && exceeds_max_pixels(&source_data, options)?
+	{
+		return Ok((ProcessingResult { lossless: options.lossless, hashes: unchanged_hashes(source_hash, source_path, target_path), ..ProcessingResult::skipped(source_path, original_size, SkipReason::TooLarge) }, None));
+	}
+	
+	// Leave images with a color type and bit depth the decoder could only misread alone too.
+	if !is_minified && !is_supported_format(&source_data)?
+	{
+		return Ok((ProcessingResult { lossless: options.lossless, hashes: unchanged_hashes(source_hash, source_path, target_path), ..ProcessingResult::skipped(source_path, original_size, SkipReason::UnsupportedFormat) }, None));
+	}
+	
+	// Files that look already quantized by another tool are reported, and minified losslessly with safe.
+	// Counting the colors decodes the source a second time, so without safe only the header is checked (for the summary warning).
+	let already_quantized: bool = if options.lossless || is_minified
+	{
+		false
+	}
+	else if options.safe
+	{
+		is_already_quantized(&source_data).map_err(|e| e.context("decoding source PNG"))?
+	}
+	else
+	{
+		is_indexed_color(&source_data)
+	};
+	let safe_options: Option<MinifyOptions> = if already_quantized && options.safe
+	{
+		Some(MinifyOptions { lossless: true, ..options.clone() })
+	}
+	else
+	{
+		None
+	};
+	let options: &MinifyOptions = safe_options.as_ref().unwrap_or(options);
+	
+	if is_minified
+	{
+		// File already minified - return info about previous minification.
+		let (prev_lossless, prev_quality, prev_dithering): (bool, Option<u8>, Option<DitheringMode>) = match prev_info
+		{
+			Some(ref info) => (info.lossless, info.quality, info.dithering_mode),
+			None => (false, None, None),
+		};
+		
+		return Ok((ProcessingResult { lossless: prev_lossless, quality: prev_quality, dithering_mode: prev_dithering, hashes: unchanged_hashes(source_hash, source_path, target_path), ..ProcessingResult::skipped(source_path, original_size, SkipReason::AlreadyMinified) }, prev_info));
+	}
+	
+	// Drop the bytes after IEND up front, so they are gone from every output, even the unchanged image.
+	let trailing_stripped: u64 = if options.strip_trailing { trailing_data_len(&source_data)? as u64 } else { 0 };
+	source_data.truncate(source_data.len() - trailing_stripped as usize);
+	
+	// Minify in memory.
+	let (mut output_data, effective_dithering, effective_quality, image_class, mut pixels) = minify_bytes_with_pixels(&source_data, options)?;
+	
+	// Lossy output that isn't smaller (tiny or already optimized files) may still be beaten by a lossless pass.
+	// With auto mode, the lossless pass always runs, and the lossy output has to be worth its quality loss.
+	let lossless_options: MinifyOptions;
+	let mut lossless_fallback: bool = false;
+	let auto_mode: bool = options.auto_mode && !options.lossless;
+	let options: &MinifyOptions = if auto_mode
+	{
+		lossless_options = MinifyOptions { lossless: true, ..options.clone() };
+		let (lossless_data, _, _, _) = minify_bytes(&source_data, &lossless_options)?;
+		if prefer_lossy(&source_data, &output_data, lossless_data.len()).map_err(|e| e.context("comparing lossy and lossless output"))?
+		{
+			options
+		}
+		else
+		{
+			output_data = lossless_data;
+			pixels = None;
+			&lossless_options
+		}
+	}
+	else if options.fallback_lossless && !options.lossless && output_data.len() as u64 >= original_size
+	{
+		lossless_options = MinifyOptions { lossless: true, ..options.clone() };
+		let (lossless_data, _, _, _) = minify_bytes(&source_data, &lossless_options)?;
+		if (lossless_data.len() as u64) < original_size
+		{
+			output_data = lossless_data;
+			pixels = None;
+			lossless_fallback = true;
+			&lossless_options
+		}
+		else
+		{
+			options
+		}
+	}
+	else
+	{
+		options
+	};
+	
+	// Settings reported back to the caller.
+	let result_quality: Option<u8> = if options.lossless { None } else { Some(effective_quality) };
+	let class_quality: Option<u8> = options.quality_per_type.zip(image_class).and_then(|(qualities, class)| qualities.quality_for(class));
+	let auto_quality: bool = !options.lossless && options.quality == AUTO_QUALITY && class_quality.is_none();
+	let result_dithering: Option<DitheringMode> = if options.lossless { None } else { Some(effective_dithering) };
+	
+	// Only save the result if it's smaller than the original (or always, if asked to).
+	let new_size: u64 = output_data.len() as u64;
+	if new_size < original_size || options.always_write
+	{
+		// Keep a copy of the file being overwritten if requested.
+		if let Some(backup) = backup
+		{
+			backup.back_up(target_path)?;
+		}
+		
+		// Write directly to target (single disk write!).
+		create_target_dir(source_path, target_path)?;
+		write_output(target_path, &output_data, options.temp_dir.as_deref())
+			.map_err(|e| anyhow!("writing output: {}", e))?;
+		let hashes: Option<ContentHashes> = source_hash.map(|source: [u8; 32]| ContentHashes { source, target: target_path.to_path_buf(), output: hash_utils::sha256(&output_data) });
+		let webp_size: Option<u64> = write_webp(target_path, &output_data, pixels.as_ref(), options)?;
+		
+		Ok((ProcessingResult
+		{
+			path: source_path.to_path_buf(),
+			original_size,
+			new_size,
+			skip_reason: None,
+			lossless: options.lossless,
+			quality: result_quality,
+			dithering_mode: result_dithering,
+			re_encoded: new_size >= original_size,
+			auto_quality,
+			already_quantized,
+			noop_marked: false,
+			lossless_fallback,
+			auto_mode,
+			webp_size,
+			trailing_stripped,
+			hashes,
+			image_class,
+		}, None))
+	}
+	else if options.mark_noop && !options.lossless && !(options.skip_unchanged_output && source_path != target_path)
+	{
+		// Minification didn't reduce size - keep the image, with a marker so later runs skip it.
+		// The marked image is streamed to the file, so a large source isn't copied in memory to add the small chunk.
+		let marked: MarkedPng = noop_marked(&source_data, effective_quality, effective_dithering, options.marker_text.as_deref(), !options.deterministic)?;
+		if let Some(backup) = backup
+		{
+			backup.back_up(target_path)?;
+		}
+		
+		create_target_dir(source_path, target_path)?;
+		write_output_with(target_path, options.temp_dir.as_deref(), |out: &mut dyn Write| marked.write_to(out))
+			.map_err(|e| anyhow!("writing output: {}", e))?;
+		
+		// The marked image is streamed through the hasher too, which can't fail.
+		let mut hashes: Option<ContentHashes> = None;
+		if let Some(source) = source_hash
+		{
+			let mut hasher: Sha256 = Sha256::new();
+			let _ = marked.write_to(&mut hasher);
+			hashes = Some(ContentHashes { source, target: target_path.to_path_buf(), output: hasher.finish() });
+		}
+		
+		// The marker doesn't change the pixels, so the WebP shows the source.
+		let webp_size: Option<u64> = write_webp(target_path, &source_data, None, options)?;
+		
+		Ok((ProcessingResult
+		{
+			path: source_path.to_path_buf(),
+			original_size,
+			new_size: marked.size(),
+			skip_reason: None,
+			lossless: false,
+			quality: result_quality,
+			dithering_mode: result_dithering,
+			re_encoded: false,
+			auto_quality,
+			already_quantized,
+			noop_marked: true,
+			lossless_fallback: false,
+			auto_mode: false,
+			webp_size,
+			trailing_stripped: 0,
+			hashes,
+			image_class,
+		}, None))
+	}
+	else
+	{
+		// Minification didn't reduce size - keep original, copied to a separate target unless the output is left sparse.
+		let copied: bool = source_path != target_path && !options.skip_unchanged_output;
+		if copied
+		{
+			if let Some(backup) = backup
+			{
+				backup.back_up(target_path)?;
+			}
+			
+			create_target_dir(source_path, target_path)?;
+			write_target(target_path, || fs::copy(source_path, target_path).map(|_| ()))
+				.map_err(|e| anyhow!("writing output: Failed to copy source to target: {}", e))?;
+		}
+		
+		// A WebP goes next to every PNG output, including the unchanged source minified in place.
+		let webp_size: Option<u64> = if copied || source_path == target_path { write_webp(target_path, &source_data, None, options)? } else { None };
+		
+		Ok((ProcessingResult
+		{
+			path: source_path.to_path_buf(),
+			original_size,
+			new_size: original_size,
+			skip_reason: None,
+			lossless: options.lossless,
+			quality: result_quality,
+			dithering_mode: result_dithering,
+			re_encoded: false,
+			auto_quality,
+			already_quantized,
+			noop_marked: false,
+			lossless_fallback: false,
+			auto_mode: false,
+			webp_size,
+			trailing_stripped: 0,
+			hashes: if copied { source_hash.map(|source: [u8; 32]| ContentHashes { source, target: target_path.to_path_buf(), output: source }) } else { unchanged_hashes(source_hash, source_path, target_path) },
+			image_class,
+		}, None))
+	}
+}
+
+/// Write a lossless WebP of a PNG output next to its target (also_webp), returning its size.
+/// The quantized pixels of a lossy output are encoded directly; without them, the PNG is decoded for its pixels.
+fn write_webp(target_path: &Path, png_data: &[u8], pixels: Option<&image::RgbaImage>, options: &MinifyOptions) -> Result<Option<u64>>
+{
+	if !options.also_webp
+	{
+		return Ok(None);
+	}
+	
+	let webp_data: Vec<u8> = match pixels
+	{
+		Some(pixels) => encode_webp(pixels)?,
+		None => webp_from_png(png_data)?,
+	};
+	write_output(&target_path.with_extension("webp"), &webp_data, options.temp_dir.as_deref())
+		.map_err(|e| anyhow!("writing WebP: {}", e))?;
+	Ok(Some(webp_data.len() as u64))
+}
+
+/// Hashes of a file left as it was, which only has an output when it is minified in place.
+fn unchanged_hashes(source_hash: Option<[u8; 32]>, source_path: &Path, target_path: &Path) -> Option<ContentHashes>
+{
+	if source_path != target_path
+	{
+		return None;
+	}
+	
+	source_hash.map(|source: [u8; 32]| ContentHashes { source, target: target_path.to_path_buf(), output: source })
+}
+
+/// Checks if a file exists and is marked read-only.
+fn is_read_only(path: &Path) -> bool
+{
+	match fs::metadata(path)
+	{
+		Ok(metadata) => metadata.permissions().readonly(),
+		Err(_) => false,
+	}
+}
+
+/// Create the directory of a separate target (e.g. below an output directory) if it doesn't exist yet.
+fn create_target_dir(source_path: &Path, target_path: &Path) -> Result<()>
+{
+	if source_path == target_path
+	{
+		return Ok(());
+	}
+	
+	if let Some(parent) = target_path.parent() && !parent.as_os_str().is_empty() && !parent.is_dir()
+	{
+		fs::create_dir_all(parent).map_err(|e| MinifyError::io("Failed to create target directory", e))?;
+	}
+	
+	Ok(())
+}
+
+/// Run a write to the target file.
+/// A read-only target is made writable for the write and gets its original permissions back afterward, even if the write fails.
+fn write_target<F>(target_path: &Path, write: F) -> std::io::Result<()> where F: FnOnce() -> std::io::Result<()>
+{
+	let original_permissions: Option<fs::Permissions> = match fs::metadata(target_path)
+	{
+		Ok(metadata) if metadata.permissions().readonly() => Some(metadata.permissions()),
+		_ => None,
+	};
+	
+	let permissions: fs::Permissions = match original_permissions
+	{
+		Some(permissions) => permissions,
+		None => return write(),
+	};
+	
+	fs::set_permissions(target_path, writable_permissions(&permissions))?;
+	let result: std::io::Result<()> = write();
+	let restored: std::io::Result<()> = fs::set_permissions(target_path, permissions);
+	
+	result?;
+	restored
+}
+
+/// Permissions with the owner write bit added.
+#[cfg(unix)]
+fn writable_permissions(permissions: &fs::Permissions) -> fs::Permissions
+{
+	use std::os::unix::fs::PermissionsExt;
+	
+	fs::Permissions::from_mode(permissions.mode() | 0o200)
+}
+
+/// Permissions with the read-only attribute cleared.
+#[cfg(not(unix))]
+#[allow(clippy::permissions_set_readonly_false)]
+fn writable_permissions(permissions: &fs::Permissions) -> fs::Permissions
+{
+	let mut writable: fs::Permissions = permissions.clone();
+	writable.set_readonly(false);
+	writable
+}
+
+/// Write output data to the target file.
+/// With a temporary file directory, the data is written to a temporary file there first and then moved over the target,
+/// so the target is either the old or the new file, never a partly written one (when the directory is on the target's volume).
+fn write_output(target_path: &Path, data: &[u8], temp_dir: Option<&Path>) -> Result<()>
+{
+	write_output_with(target_path, temp_dir, |out: &mut dyn Write| out.write_all(data))
+}
+
+/// Write output to the target file like write_output, streaming it through a buffered writer.
+fn write_output_with<F>(target_path: &Path, temp_dir: Option<&Path>, write: F) -> Result<()> where F: FnOnce(&mut dyn Write) -> std::io::Result<()>
+{
+	// Create (or truncate) a file and write the output through a buffer.
+	let write_file = |path: &Path| -> std::io::Result<()>
+	{
+		let mut out: BufWriter<fs::File> = BufWriter::new(fs::File::create(path)?);
+		write(&mut out)?;
+		out.flush()
+	};
+	
+	let temp_dir: &Path = match temp_dir
+	{
+		Some(temp_dir) => temp_dir,
+		None =>
+		{
+			write_target(target_path, || write_file(target_path))
+				.map_err(|e| MinifyError::io("Failed to write to target file", e))?;
+			return Ok(());
+		}
+	};
+	
+	let temp_file: TempFile = TempFile::new(temp_dir)?;
+	write_file(temp_file.path())
+		.map_err(|e| MinifyError::io(&format!("Failed to write temporary file {}", temp_file.path().display()), e))?;
+	write_target(target_path, || temp_file.persist(target_path))
+		.map_err(|e| MinifyError::io("Failed to move temporary file over target file", e))?;
+	
+	Ok(())
+}
+
+/// Counter that makes the names of the temporary files of this process unique.
+static TEMP_FILE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Temporary file an output is written to before it replaces its target (--temp-dir).
+/// The file is deleted when dropped, so a failed write leaves nothing behind.
+pub struct TempFile
+{
+	path: PathBuf,
+}
+
+impl TempFile
+{
+	/// Create a new, empty temporary file in a directory.
+	/// Fails with an error naming the directory if it doesn't exist, isn't writable or is full.
+	pub fn new(dir: &Path) -> Result<TempFile, MinifyError>
+	{
+		loop
+		{
+			let name: String = format!(".minipng-{}-{}.tmp", std::process::id(), TEMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed));
+			let path: PathBuf = dir.join(name);
+			match fs::OpenOptions::new().write(true).create_new(true).open(&path)
+			{
+				Ok(_) => return Ok(TempFile { path }),
+				
+				// Left behind by an earlier process with the same id, try the next name.
+				Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+				
+				Err(e) => return Err(MinifyError::io(&format!("Failed to create a temporary file in {} (check that it exists, is writable and has free space, or pick another directory with --temp-dir)", dir.display()), e)),
+			}
+		}
+	}
+	
+	/// Path of the temporary file.
+	pub fn path(&self) -> &Path
+	{
+		&self.path
+	}
+	
+	/// Move the temporary file over the target, keeping the target's permissions.
+	/// Renaming only works within one volume, otherwise the data is copied into the target.
+	pub fn persist(self, target_path: &Path) -> std::io::Result<()>
+	{
+		if let Ok(metadata) = fs::metadata(target_path)
+		{
+			fs::set_permissions(&self.path, metadata.permissions())?;
+		}
+		
+		if fs::rename(&self.path, target_path).is_err()
+		{
+			fs::copy(&self.path, target_path)?;
+		}
+		
+		Ok(())
+	}
+}
+
+impl Drop for TempFile
+{
+	fn drop(&mut self)
+	{
+		// Already gone after a successful rename.
+		let _ = fs::remove_file(&self.path);
+	}
+}
+
+/// Checks if a file starts with the PNG signature.
+pub fn has_png_signature(path: &Path) -> Result<bool>
+{
+	use std::io::Read;
+	
+	let mut file: fs::File = fs::File::open(path)
+		.map_err(|e| anyhow!("Failed to open {}: {}", path.display(), e))?;
+	
+	let mut header: [u8; 8] = [0; 8];
+	let mut read: usize = 0;
+	while read < header.len()
+	{
+		let count: usize = file.read(&mut header[read..])
+			.map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e))?;
+		if count == 0
+		{
+			break;
+		}
+		read += count;
+	}
+	
+	Ok(read == header.len() && &header == PNG_SIGNATURE)
+}
+
+/// Process a single PNG file.
+/// Files that don't start with the PNG signature are reported as skipped rather than failing the run.
+/// With a cache, Auto dithering reuses the mode recorded for an unchanged file instead of analyzing it again,
+/// and the mode chosen for each minified file is recorded.
+/// With a backup store, files are backed up before they are overwritten.
+/// With a progress log, each file is recorded as completed once it was handled without an error (--resume).
+pub fn process_file(source_path: &Path, target_path: &Path, options: &MinifyOptions, force: bool, cache: Option<&AnalysisCache>, backup: Option<&BackupStore>, progress: Option<&ProgressLog>) -> Result<(ProcessingResult, Option<MinificationInfo>)>
+{
+	let (result, prev_info): (ProcessingResult, Option<MinificationInfo>) = process_png(source_path, target_path, options, force, cache, backup)?;
+	if let Some(progress) = progress
+	{
+		progress.record(source_path)?;
+	}
+	
+	Ok((result, prev_info))
+}
+
+/// Process a single PNG file, without recording its progress (see process_file).
+fn process_png(source_path: &Path, target_path: &Path, options: &MinifyOptions, force: bool, cache: Option<&AnalysisCache>, backup: Option<&BackupStore>) -> Result<(ProcessingResult, Option<MinificationInfo>)>
+{
+	if !has_png_signature(source_path)?
+	{
+		let size: u64 = fs::metadata(source_path)
+			.map_err(|e| anyhow!("Failed to get file metadata: {}", e))?
+			.len();
+		
+		return Ok((ProcessingResult { lossless: options.lossless, ..ProcessingResult::skipped(source_path, size, SkipReason::NotPng) }, None));
+	}
+	
+	// Reuse the dithering mode picked for this file last time if it hasn't changed since.
+	let cached_options: Option<MinifyOptions> = match cache
+	{
+		Some(cache) if !options.lossless && options.dithering_mode == DitheringMode::Auto =>
+		{
+			cache.lookup(source_path).map(|dithering_mode| MinifyOptions { dithering_mode, ..options.clone() })
+		},
+		_ => None,
+	};
+	let options: &MinifyOptions = cached_options.as_ref().unwrap_or(options);
+	
+	// Apply the minification pipeline.
+	let (result, prev_info) = minify_png(source_path, target_path, options, force, backup)?;
+	
+	// Record the chosen mode against the file as it is now, so an in-place rewrite still matches next time.
+	if let Some(cache) = cache && result.skip_reason.is_none() && let Some(dithering_mode) = result.dithering_mode
+	{
+		cache.record(source_path, dithering_mode, result.new_size);
+	}
+	
+	Ok((result, prev_info))
+}
+
+#[cfg(test)]
+pub(crate) mod tests
+{
+	use super::*;
+	
+	/// Empty scratch directory in the system temp directory, unique to this process and test.
+	pub(crate) fn scratch_dir(name: &str) -> PathBuf
+	{
+		let dir: PathBuf = std::env::temp_dir().join(format!("minipng-test-{}-{}", std::process::id(), name));
+		let _ = fs::remove_dir_all(&dir);
+		fs::create_dir_all(&dir).expect("Failed to create scratch directory");
+		dir
+	}
+	
+	/// Noisy, photo-like test image that lossy minification makes clearly smaller.
+	pub(crate) fn photo(width: u32, height: u32) -> image::RgbaImage
+	{
+		image::RgbaImage::from_fn(width, height, |x: u32, y: u32| image::Rgba([(x * 2 + (x * y) % 13) as u8, (y * 3 + (x ^ y) % 7) as u8, ((x + y) * 2) as u8, 255]))
+	}
+	
+	/// Names of the found files relative to the scanned directory, with forward slashes.
+	fn relative_names(files: &[PngFile], dir: &Path) -> Vec<String>
+	{
+		let mut names: Vec<String> = Vec::with_capacity(files.len());
+		for file in files
+		{
+			names.push(file.source_path.strip_prefix(dir).expect("Found file outside the directory").to_string_lossy().replace('\\', "/"));
+		}
+		names
+	}
+	
+	#[test]
+	fn no_recurse_lists_only_top_level_files()
+	{
+		let dir: PathBuf = scratch_dir("no-recurse");
+		fs::create_dir_all(dir.join("nested")).expect("Failed to create subdirectory");
+		for path in ["a.png", "b.PNG", "notes.txt", "nested/c.png"]
+		{
+			fs::write(dir.join(path), PNG_SIGNATURE).expect("Failed to write test file");
+		}
+		
+		let recursive: Vec<PngFile> = find_png_files_in_dir(Some(&dir), true, &[], false, false, true).expect("Scan failed");
+		let top_level: Vec<PngFile> = find_png_files_in_dir(Some(&dir), true, &[], false, false, false).expect("Scan failed");
+		
+		assert_eq!(relative_names(&recursive, &dir), vec!["a.png", "b.PNG", "nested/c.png"]);
+		assert_eq!(relative_names(&top_level, &dir), vec!["a.png", "b.PNG"]);
+		
+		let _ = fs::remove_dir_all(&dir);
+	}
+	
+	#[test]
+	fn images_above_max_pixels_are_skipped()
+	{
+		let dir: PathBuf = scratch_dir("max-pixels");
+		let large: PathBuf = dir.join("large.png");
+		let small: PathBuf = dir.join("small.png");
+		image::RgbaImage::from_pixel(64, 64, image::Rgba([10, 20, 30, 255])).save(&large).expect("Failed to write test image");
+		image::RgbaImage::from_pixel(16, 16, image::Rgba([10, 20, 30, 255])).save(&small).expect("Failed to write test image");
+		
+		let options: MinifyOptions = MinifyOptions::builder().lossless(true).fast_deflate(true).max_pixels(Some(1024)).build().expect("Valid options");
+		let (large_result, _): (ProcessingResult, Option<MinificationInfo>) = minify_png(&large, &large, &options, false, None).expect("Processing failed");
+		let (small_result, _): (ProcessingResult, Option<MinificationInfo>) = minify_png(&small, &small, &options, false, None).expect("Processing failed");
+		
+		assert_eq!(large_result.skip_reason, Some(SkipReason::TooLarge));
+		assert_eq!(large_result.new_size, large_result.original_size);
+		assert_eq!(small_result.skip_reason, None);
+		
+		let _ = fs::remove_dir_all(&dir);
+	}
+	
+	#[test]
+	fn second_run_skips_noop_marked_file()
+	{
+		let dir: PathBuf = scratch_dir("mark-noop");
+		let path: PathBuf = dir.join("tiny.png");
+		image::RgbaImage::from_fn(16, 16, |x, y| image::Rgba([(x * 16) as u8, (y * 16) as u8, 128, 255])).save(&path).expect("Failed to write test image");
+		let source_data: Vec<u8> = fs::read(&path).expect("Failed to read test image");
+		
+		// Asking for a 100% reduction makes sure the lossy output never counts as smaller.
+		let options: MinifyOptions = MinifyOptions::builder().quality(40).fast_deflate(true).min_reduction(100.0).mark_noop(true).build().expect("Valid options");
+		let (first, _): (ProcessingResult, Option<MinificationInfo>) = minify_png(&path, &path, &options, false, None).expect("Processing failed");
+		assert!(first.noop_marked);
+		assert_eq!(first.skip_reason, None);
+		
+		// The pixels are untouched, only the marker was added.
+		let marked_data: Vec<u8> = fs::read(&path).expect("Failed to read test image");
+		assert_eq!(first.new_size, marked_data.len() as u64);
+		let decode = |data: &[u8]| image::load_from_memory(data).expect("Failed to decode test image").to_rgba8();
+		assert_eq!(decode(&marked_data), decode(&source_data));
+		
+		let (second, info): (ProcessingResult, Option<MinificationInfo>) = minify_png(&path, &path, &options, false, None).expect("Processing failed");
+		assert_eq!(second.skip_reason, Some(SkipReason::AlreadyMinified));
+		assert!(info.expect("Marker info").noop);
+		assert_eq!(fs::read(&path).expect("Failed to read test image"), marked_data);
+		
+		let _ = fs::remove_dir_all(&dir);
+	}
+	
+	#[test]
+	fn temp_dir_writes_go_through_the_custom_directory()
+	{
+		let dir: PathBuf = scratch_dir("temp-dir");
+		let temp_dir: PathBuf = dir.join("temp");
+		fs::create_dir_all(&temp_dir).expect("Failed to create temp directory");
+		
+		// The temporary file is created in the given directory and removed when dropped.
+		let temp_file: TempFile = TempFile::new(&temp_dir).expect("Temp file creation failed");
+		assert_eq!(temp_file.path().parent(), Some(temp_dir.as_path()));
+		assert!(temp_file.path().is_file());
+		let temp_path: PathBuf = temp_file.path().to_path_buf();
+		drop(temp_file);
+		assert!(!temp_path.exists());
+		
+		// A minified output is moved from the directory over the target.
+		let path: PathBuf = dir.join("noisy.png");
+		crate::test_utils::noisy_ramps(64, 64).save(&path).expect("Failed to write test image");
+		let original_size: u64 = fs::metadata(&path).expect("Failed to read metadata").len();
+		
+		let options: MinifyOptions = MinifyOptions::builder().quality(40).fast_deflate(true).temp_dir(Some(temp_dir.clone())).build().expect("Valid options");
+		let (result, _): (ProcessingResult, Option<MinificationInfo>) = minify_png(&path, &path, &options, false, None).expect("Processing failed");
+		assert!(result.new_size < original_size);
+		assert_eq!(fs::metadata(&path).expect("Failed to read metadata").len(), result.new_size);
+		assert!(is_already_minified(&fs::read(&path).expect("Failed to read output")).expect("Marker check failed").0);
+		assert_eq!(fs::read_dir(&temp_dir).expect("Failed to list temp directory").count(), 0);
+		
+		let _ = fs::remove_dir_all(&dir);
+	}
+	
+	#[test]
+	fn missing_temp_dir_reports_the_directory()
+	{
+		let dir: PathBuf = scratch_dir("temp-dir-missing");
+		let missing: PathBuf = dir.join("missing");
+		
+		let error: String = TempFile::new(&missing).err().expect("Temp file creation should fail").to_string();
+		assert!(error.contains(&missing.display().to_string()), "{}", error);
+		assert!(error.contains("temporary file"), "{}", error);
+		
+		let _ = fs::remove_dir_all(&dir);
+	}
+	
+	#[test]
+	fn lossless_fallback_is_written_when_lossy_output_is_not_smaller()
+	{
+		let dir: PathBuf = scratch_dir("fallback-lossless");
+		let path: PathBuf = dir.join("smooth.png");
+		
+		// A small smooth image: quantizing and dithering it doesn't beat the original, but a lossless pass does.
+		image::RgbaImage::from_fn(48, 48, |x, y| image::Rgba([(x * 5) as u8, (y * 5) as u8, ((x + y) * 2) as u8, 255])).save(&path).expect("Failed to write test image");
+		let original_size: u64 = fs::metadata(&path).expect("Failed to read metadata").len();
+		
+		let lossy_only: MinifyOptions = MinifyOptions::builder().quality(40).fast_deflate(true).build().expect("Valid options");
+		let target: PathBuf = dir.join("lossy.png");
+		let (result, _): (ProcessingResult, Option<MinificationInfo>) = minify_png(&path, &target, &lossy_only, false, None).expect("Processing failed");
+		assert_eq!(result.new_size, original_size);
+		
+		let options: MinifyOptions = MinifyOptions::builder().quality(40).fast_deflate(true).fallback_lossless(true).build().expect("Valid options");
+		let (result, _): (ProcessingResult, Option<MinificationInfo>) = minify_png(&path, &path, &options, false, None).expect("Processing failed");
+		assert!(result.lossless_fallback);
+		assert!(result.lossless);
+		assert_eq!(result.quality, None);
+		assert!(result.new_size < original_size);
+		
+		let (minified, info): (bool, Option<MinificationInfo>) = is_already_minified(&fs::read(&path).expect("Failed to read output")).expect("Marker check failed");
+		assert!(minified);
+		assert!(info.expect("Marker info").lossless);
+		
+		let _ = fs::remove_dir_all(&dir);
+	}
+	
+	#[test]
+	fn sort_by_size_puts_the_largest_files_first()
+	{
+		let dir: PathBuf = scratch_dir("sort-size");
+		for (name, size) in [("a.png", 30), ("b.png", 500), ("c.png", 120), ("d.png", 4000), ("e.png", 120)]
+		{
+			fs::write(dir.join(name), [PNG_SIGNATURE.as_slice(), &vec![0; size]].concat()).expect("Failed to write test file");
+		}
+		
+		let mut files: Vec<PngFile> = find_png_files_in_dir(Some(&dir), true, &[], false, false, true).expect("Scan failed");
+		sort_png_files(&mut files, SortOrder::Size);
+		let sizes: Vec<u64> = files.iter().map(|file| fs::metadata(&file.source_path).expect("Failed to read metadata").len()).collect();
+		assert_eq!(sizes, vec![4008, 508, 128, 128, 38]);
+		assert_eq!(relative_names(&files, &dir), vec!["d.png", "b.png", "c.png", "e.png", "a.png"]);
+		
+		sort_png_files(&mut files, SortOrder::Name);
+		assert_eq!(relative_names(&files, &dir), vec!["a.png", "b.png", "c.png", "d.png", "e.png"]);
+		
+		let _ = fs::remove_dir_all(&dir);
+	}
+	
+	#[test]
+	fn strip_trailing_removes_bytes_after_iend()
+	{
+		let dir: PathBuf = scratch_dir("strip-trailing");
+		let path: PathBuf = dir.join("junk.png");
+		let rgba: image::RgbaImage = image::RgbaImage::from_fn(32, 32, |x, y| image::Rgba([(x * 8) as u8, (y * 8) as u8, 60, 255]));
+		rgba.save(&path).expect("Failed to write test image");
+		let mut source_data: Vec<u8> = fs::read(&path).expect("Failed to read test image");
+		source_data.extend((0..1024).map(|i: u32| (i * 37) as u8));
+		fs::write(&path, &source_data).expect("Failed to write test image");
+		
+		// The chunk walk stops at IEND and reports the rest as trailing data.
+		let chunks: Vec<(String, usize)> = crate::minify::list_chunks(&source_data).expect("Chunk walk failed");
+		assert_eq!(chunks.last(), Some(&(crate::minify::TRAILING_DATA.to_string(), 1024)));
+		assert_eq!(trailing_data_len(&source_data).expect("Chunk walk failed"), 1024);
+		
+		let options: MinifyOptions = MinifyOptions::builder().lossless(true).fast_deflate(true).strip_trailing(true).build().expect("Valid options");
+		let (result, _): (ProcessingResult, Option<MinificationInfo>) = minify_png(&path, &path, &options, false, None).expect("Processing failed");
+		assert_eq!(result.trailing_stripped, 1024);
+		
+		let output: Vec<u8> = fs::read(&path).expect("Failed to read output");
+		assert_eq!(trailing_data_len(&output).expect("Chunk walk failed"), 0);
+		assert_eq!(image::load_from_memory(&output).expect("Output does not decode").to_rgba8(), rgba);
+		
+		let _ = fs::remove_dir_all(&dir);
+	}
+	
+	#[test]
+	fn manifest_hashes_are_stable_when_rerun_on_unchanged_files()
+	{
+		let dir: PathBuf = scratch_dir("manifest");
+		let (noisy, smooth): (PathBuf, PathBuf) = (dir.join("noisy.png"), dir.join("smooth.png"));
+		crate::test_utils::noisy_ramps(64, 64).save(&noisy).expect("Failed to write test image");
+		image::RgbaImage::from_fn(48, 48, |x, y| image::Rgba([(x * 5) as u8, (y * 5) as u8, ((x + y) * 2) as u8, 255])).save(&smooth).expect("Failed to write test image");
+		let noisy_source: Vec<u8> = fs::read(&noisy).expect("Failed to read test image");
+		
+		let options: MinifyOptions = MinifyOptions::builder().quality(40).fast_deflate(true).hashes(true).build().expect("Valid options");
+		let run = || -> Vec<ProcessingResult>
+		{
+			let mut results: Vec<ProcessingResult> = Vec::new();
+			for path in [&noisy, &smooth]
+			{
+				results.push(minify_png(path, path, &options, false, None).expect("Processing failed").0);
+			}
+			results
+		};
+		
+		// The first run minifies the noisy image and leaves the smooth one, which it couldn't reduce, as it was.
+		let first: Vec<ProcessingResult> = run();
+		let mut first_hashes: Vec<ContentHashes> = Vec::new();
+		for result in &first
+		{
+			first_hashes.push(result.hashes.clone().expect("Missing hashes"));
+		}
+		assert_eq!(first_hashes[0].source, hash_utils::sha256(&noisy_source));
+		assert_eq!(first_hashes[0].output, hash_utils::sha256(&fs::read(&noisy).expect("Failed to read output")));
+		assert_ne!(first_hashes[0].source, first_hashes[0].output);
+		assert_eq!(first_hashes[1].source, first_hashes[1].output);
+		
+		// Running again over the unchanged files reports the same outputs.
+		let second: Vec<ProcessingResult> = run();
+		for (first_hashes, result) in first_hashes.iter().zip(&second)
+		{
+			let hashes: &ContentHashes = result.hashes.as_ref().expect("Missing hashes");
+			assert_eq!(hashes.output, first_hashes.output);
+			assert_eq!(hashes.source, hashes.output);
+			assert_eq!(hashes.target, first_hashes.target);
+		}
+		
+		let manifest: String = crate::utils::report_utils::format_manifest(&second);
+		assert_eq!(manifest.matches("\"target_sha256\"").count(), 2);
+		assert!(manifest.contains(&hash_utils::to_hex(&first_hashes[0].output)));
+		
+		let _ = fs::remove_dir_all(&dir);
+	}
+	
+	#[test]
+	fn time_budget_stops_starting_files_once_spent()
+	{
+		let dir: PathBuf = scratch_dir("time-budget");
+		for name in ["a.png", "b.png", "c.png", "d.png"]
+		{
+			image::RgbaImage::from_fn(16, 16, |x, y| image::Rgba([(x * 16) as u8, (y * 16) as u8, 128, 255])).save(dir.join(name)).expect("Failed to write test image");
+		}
+		let files: Vec<PngFile> = find_png_files_in_dir(Some(&dir), true, &[], false, false, true).expect("Scan failed");
+		
+		// A budget that lasts starts every file; one that ran out before the batch starts none and counts them all.
+		let options: MinifyOptions = MinifyOptions::builder().lossless(true).fast_deflate(true).build().expect("Valid options");
+		let started_long_ago: Instant = Instant::now().checked_sub(Duration::from_secs(60)).expect("Clock too close to its origin");
+		for (budget, expected_processed) in [(TimeBudget::new(Instant::now(), Duration::from_secs(3600)), 4), (TimeBudget::new(started_long_ago, Duration::from_secs(1)), 0)]
+		{
+			let mut processed: usize = 0;
+			for file in &files
+			{
+				if !budget.try_start()
+				{
+					continue;
+				}
+				
+				process_file(&file.source_path, &file.target_path, &options, false, None, None, None).expect("Processing failed");
+				processed += 1;
+			}
+			
+			assert_eq!(processed, expected_processed);
+			assert_eq!(budget.remaining(), 4 - expected_processed);
+		}
+		
+		let _ = fs::remove_dir_all(&dir);
+	}
+	
+	#[test]
+	fn errors_name_the_failing_stage()
+	{
+		let dir: PathBuf = scratch_dir("error-stage");
+		let source: PathBuf = dir.join("source.png");
+		image::RgbaImage::from_fn(64, 64, |x, y| image::Rgba([(x * 4) as u8, (y * 4) as u8, ((x ^ y) * 4) as u8, 255])).save(&source).expect("Failed to write test image");
+		let source_data: Vec<u8> = fs::read(&source).expect("Failed to read test image");
+		let options: MinifyOptions = MinifyOptions::builder().quality(40).fast_deflate(true).build().expect("Valid options");
+		
+		// A damaged IDAT chunk keeps the chunk structure intact, so the failure comes from decoding the pixels.
+		let corrupt: PathBuf = dir.join("corrupt.png");
+		let idat: usize = source_data.windows(4).position(|window: &[u8]| window == b"IDAT").expect("Missing IDAT chunk");
+		let mut corrupt_data: Vec<u8> = source_data.clone();
+		corrupt_data[idat + 4..idat + 40].fill(0xA5);
+		fs::write(&corrupt, &corrupt_data).expect("Failed to write test image");
+		let error: String = minify_png(&corrupt, &corrupt, &options, false, None).expect_err("Corrupt image should fail").to_string();
+		assert!(error.contains("decoding"), "{}", error);
+		
+		// A directory in place of the target can't be written.
+		let target: PathBuf = dir.join("target.png");
+		fs::create_dir(&target).expect("Failed to create directory");
+		let error: String = minify_png(&source, &target, &options, false, None).expect_err("Writing over a directory should fail").to_string();
+		assert!(error.contains("writing"), "{}", error);
+		
+		let _ = fs::remove_dir_all(&dir);
+	}
+	
+	#[test]
+	fn sixteen_bit_gray_alpha_is_minified_and_invalid_headers_are_skipped()
+	{
+		let dir: PathBuf = scratch_dir("png-format");
+		let path: PathBuf = dir.join("gray16.png");
+		let gray: image::ImageBuffer<image::LumaA<u16>, Vec<u16>> = image::ImageBuffer::from_fn(64, 64, |x, y| image::LumaA([(x * 1024 + y * 7) as u16, (65535 - y * 1000) as u16]));
+		image::DynamicImage::ImageLumaA16(gray.clone()).save(&path).expect("Failed to write test image");
+		let source_data: Vec<u8> = fs::read(&path).expect("Failed to read test image");
+		assert_eq!((source_data[24], source_data[25]), (16, 4));
+		
+		// Lossy mode decodes the 16-bit samples to 8 bits and keeps the image gray with its alpha.
+		let options: MinifyOptions = MinifyOptions::builder().quality(100).dithering(DitheringMode::None).fast_deflate(true).build().expect("Valid options");
+		let (result, _): (ProcessingResult, Option<MinificationInfo>) = minify_png(&path, &path, &options, false, None).expect("Processing failed");
+		assert_eq!(result.skip_reason, None);
+		let output: image::RgbaImage = image::open(&path).expect("Output does not decode").to_rgba8();
+		for (pixel, source) in output.pixels().zip(gray.pixels())
+		{
+			assert!(pixel[0] == pixel[1] && pixel[1] == pixel[2], "{:?}", pixel);
+			assert!(pixel[0].abs_diff((source[0] >> 8) as u8) <= 8 && pixel[3].abs_diff((source[1] >> 8) as u8) <= 8, "{:?} vs {:?}", pixel, source);
+		}
+		
+		// Grayscale with alpha has no 4-bit form, so the file is skipped before anything decodes it.
+		let mut invalid_data: Vec<u8> = source_data.clone();
+		invalid_data[24] = 4;
+		fs::write(&path, &invalid_data).expect("Failed to write test image");
+		let (result, _): (ProcessingResult, Option<MinificationInfo>) = minify_png(&path, &path, &options, false, None).expect("Processing failed");
+		assert_eq!(result.skip_reason, Some(SkipReason::UnsupportedFormat));
+		assert_eq!(fs::read(&path).expect("Failed to read test image"), invalid_data);
+		
+		let _ = fs::remove_dir_all(&dir);
+	}
+	
+	#[test]
+	fn skip_unchanged_output_leaves_no_target_for_unreducible_files()
+	{
+		let dir: PathBuf = scratch_dir("skip-unchanged-output");
+		let path: PathBuf = dir.join("tiny.png");
+		image::RgbaImage::from_fn(16, 16, |x, y| image::Rgba([(x * 16) as u8, (y * 16) as u8, 128, 255])).save(&path).expect("Failed to write test image");
+		let target: PathBuf = dir.join("out").join("tiny.png");
+		
+		// Asking for a 100% reduction makes sure the output never counts as smaller.
+		let copying: MinifyOptions = MinifyOptions::builder().quality(40).fast_deflate(true).min_reduction(100.0).build().expect("Valid options");
+		let (result, _): (ProcessingResult, Option<MinificationInfo>) = minify_png(&path, &target, &copying, false, None).expect("Processing failed");
+		assert_eq!(result.new_size, result.original_size);
+		assert_eq!(fs::read(&target).expect("Failed to read target"), fs::read(&path).expect("Failed to read source"));
+		fs::remove_file(&target).expect("Failed to remove target");
+		
+		// Marking unreduced files doesn't write them either.
+		let sparse: MinifyOptions = MinifyOptions::builder().quality(40).fast_deflate(true).min_reduction(100.0).mark_noop(true).skip_unchanged_output(true).build().expect("Valid options");
+		let (result, _): (ProcessingResult, Option<MinificationInfo>) = minify_png(&path, &target, &sparse, false, None).expect("Processing failed");
+		assert_eq!(result.new_size, result.original_size);
+		assert!(!result.noop_marked);
+		assert!(!target.exists());
+		
+		let _ = fs::remove_dir_all(&dir);
+	}
+	
+	#[test]
+	fn auto_mode_keeps_lossless_output_for_flat_images_and_lossy_output_for_photos()
+	{
+		let dir: PathBuf = scratch_dir("auto-mode");
+		let flat_path: PathBuf = dir.join("flat.png");
+		let photo_path: PathBuf = dir.join("photo.png");
+		
+		// A few solid blocks compress best losslessly; noise with widely spread colors shrinks a lot when quantized.
+		let colors: [[u8; 4]; 3] = [[200, 30, 30, 255], [30, 200, 30, 255], [30, 30, 200, 255]];
+		image::RgbaImage::from_fn(96, 96, |x, y| image::Rgba(colors[((y / 8) * 12 + x / 8) as usize % 3])).save(&flat_path).expect("Failed to write test image");
+		crate::test_utils::photo_like(96, 96).save(&photo_path).expect("Failed to write test image");
+		
+		let options: MinifyOptions = MinifyOptions::builder().quality(40).fast_deflate(true).auto_mode(true).build().expect("Valid options");
+		let (flat, _): (ProcessingResult, Option<MinificationInfo>) = minify_png(&flat_path, &flat_path, &options, false, None).expect("Processing failed");
+		let (photo, _): (ProcessingResult, Option<MinificationInfo>) = minify_png(&photo_path, &photo_path, &options, false, None).expect("Processing failed");
+		
+		assert!(flat.auto_mode && photo.auto_mode);
+		assert!(flat.lossless);
+		assert_eq!(flat.quality, None);
+		assert!(!photo.lossless);
+		assert_eq!(photo.quality, Some(40));
+		assert!(photo.new_size < photo.original_size / 2);
+		
+		// The marker records the mode that was kept.
+		let (_, info): (bool, Option<MinificationInfo>) = is_already_minified(&fs::read(&flat_path).expect("Failed to read output")).expect("Marker check failed");
+		assert!(info.expect("Marker info").lossless);
+		
+		let _ = fs::remove_dir_all(&dir);
+	}
+	
+	#[test]
+	fn a_file_given_twice_is_listed_once()
+	{
+		let dir: PathBuf = scratch_dir("dedupe");
+		image::RgbaImage::new(4, 4).save(dir.join("a.png")).expect("Failed to write test image");
+		
+		let paths: Vec<PathBuf> = vec![dir.join("a.png"), dir.join("a.png"), dir.join(".").join("a.png")];
+		let mut files: Vec<PngFile> = prepare_specific_png_files(&paths, false);
+		assert_eq!(dedupe_png_files(&mut files), 2);
+		assert_eq!(files.len(), 1);
+		assert_eq!(files[0].source_path, dir.join("a.png"));
+		
+		let _ = fs::remove_dir_all(&dir);
+	}
+	
+	#[test]
+	fn two_scans_of_the_same_tree_list_files_in_the_same_order()
+	{
+		let dir: PathBuf = scratch_dir("scan-order");
+		fs::create_dir_all(dir.join("Sub")).expect("Failed to create subdirectory");
+		for path in ["b.png", "C.png", "a.PNG", "Sub/z.png", "Sub/Y.png", "d.png"]
+		{
+			fs::write(dir.join(path), PNG_SIGNATURE).expect("Failed to write test file");
+		}
+		
+		let first: Vec<PathBuf> = find_files_recursive(&dir, is_png_file, |_: &Path| true, false).expect("Scan failed");
+		let second: Vec<PathBuf> = find_files_recursive(&dir, is_png_file, |_: &Path| true, false).expect("Scan failed");
+		assert_eq!(first, second);
+		
+		let found: Vec<PngFile> = find_png_files_in_dir(Some(&dir), true, &[], false, false, true).expect("Scan failed");
+		assert_eq!(relative_names(&found, &dir), vec!["a.PNG", "b.png", "C.png", "d.png", "Sub/Y.png", "Sub/z.png"]);
+		
+		let _ = fs::remove_dir_all(&dir);
+	}
+	
+	#[cfg(unix)]
+	#[test]
+	fn symlink_loops_terminate_and_links_are_skipped_by_default()
+	{
+		let dir: PathBuf = scratch_dir("symlink-loop");
+		fs::create_dir_all(dir.join("sub")).expect("Failed to create subdirectory");
+		for path in ["a.png", "sub/b.png"]
+		{
+			fs::write(dir.join(path), PNG_SIGNATURE).expect("Failed to write test file");
+		}
+		std::os::unix::fs::symlink(&dir, dir.join("sub/loop")).expect("Failed to create directory symlink");
+		std::os::unix::fs::symlink(dir.join("a.png"), dir.join("link.png")).expect("Failed to create file symlink");
+		
+		let followed: Vec<PngFile> = find_png_files_in_dir(Some(&dir), true, &[], false, true, true).expect("Scan failed");
+		assert_eq!(relative_names(&followed, &dir), vec!["a.png", "link.png", "sub/b.png"]);
+		
+		let not_followed: Vec<PngFile> = find_png_files_in_dir(Some(&dir), true, &[], false, false, true).expect("Scan failed");
+		assert_eq!(relative_names(&not_followed, &dir), vec!["a.png", "sub/b.png"]);
+		
+		let _ = fs::remove_dir_all(&dir);
+	}
+	
+	#[test]
+	fn if_exists_policies_handle_an_existing_target()
+	{
+		let dir: PathBuf = scratch_dir("if-exists");
+		let source: PathBuf = dir.join("source.png");
+		let rgba: image::RgbaImage = image::RgbaImage::from_fn(64, 64, |x: u32, y: u32| image::Rgba([(x * 4) as u8, (y * 4) as u8, ((x * y) % 251) as u8, 255]));
+		rgba.save(&source).expect("Failed to write test image");
+		let existing: &[u8] = b"existing target";
+		
+		for policy in [IfExists::Skip, IfExists::Overwrite, IfExists::Error]
+		{
+			let target: PathBuf = dir.join("target.png");
+			fs::write(&target, existing).expect("Failed to write existing target");
+			let options: MinifyOptions = MinifyOptions::builder().lossless(true).fast_deflate(true).if_exists(policy).build().expect("Valid options");
+			
+			let outcome: Result<(ProcessingResult, Option<MinificationInfo>)> = minify_png(&source, &target, &options, false, None);
+			match policy
+			{
+				IfExists::Skip =>
+				{
+					let (result, _): (ProcessingResult, Option<MinificationInfo>) = outcome.expect("Skipping failed");
+					assert_eq!(result.skip_reason, Some(SkipReason::TargetExists));
+					assert_eq!(fs::read(&target).expect("Failed to read target"), existing);
+				},
+				IfExists::Overwrite =>
+				{
+					let (result, _): (ProcessingResult, Option<MinificationInfo>) = outcome.expect("Overwriting failed");
+					assert_eq!(result.skip_reason, None);
+					assert_eq!(image::open(&target).expect("Target is not a PNG").to_rgba8(), rgba);
+				},
+				IfExists::Error =>
+				{
+					let error: String = outcome.expect_err("An existing target should be an error").to_string();
+					assert!(error.contains("already exists"), "{}", error);
+					assert_eq!(fs::read(&target).expect("Failed to read target"), existing);
+				},
+			}
+		}
+		
+		let _ = fs::remove_dir_all(&dir);
+	}
+	
+	#[test]
+	fn bogus_files_are_skipped_as_not_png_while_the_batch_continues()
+	{
+		let dir: PathBuf = scratch_dir("not-png");
+		for name in ["a.png", "c.png"]
+		{
+			let rgba: image::RgbaImage = image::RgbaImage::from_fn(32, 32, |x: u32, y: u32| image::Rgba([(x * 8) as u8, (y * 8) as u8, 64, 255]));
+			rgba.save(dir.join(name)).expect("Failed to write test image");
+		}
+		fs::write(dir.join("b.png"), b"This is not a PNG file").expect("Failed to write bogus file");
+		let options: MinifyOptions = MinifyOptions::builder().lossless(true).fast_deflate(true).build().expect("Valid options");
+		
+		let files: Vec<PngFile> = find_png_files_in_dir(Some(&dir), true, &[], false, false, true).expect("Scan failed");
+		assert_eq!(files.len(), 3);
+		let mut not_png: Vec<String> = Vec::new();
+		let mut processed: usize = 0;
+		for file in &files
+		{
+			let (result, _): (ProcessingResult, Option<MinificationInfo>) = process_file(&file.source_path, &file.target_path, &options, false, None, None, None).expect("Processing failed");
+			match result.skip_reason
+			{
+				Some(SkipReason::NotPng) => not_png.push(file.source_path.file_name().expect("Missing file name").to_string_lossy().into_owned()),
+				None => processed += 1,
+				Some(reason) => panic!("Unexpected skip reason {:?}", reason),
+			}
+		}
+		
+		assert_eq!(not_png, ["b.png"]);
+		assert_eq!(processed, 2);
+		assert_eq!(fs::read(dir.join("b.png")).expect("Failed to read bogus file"), b"This is not a PNG file");
+		
+		let _ = fs::remove_dir_all(&dir);
+	}
+	
+	#[test]
+	fn reuse_settings_keeps_the_recorded_quality()
+	{
+		let dir: PathBuf = scratch_dir("reuse-settings");
+		let path: PathBuf = dir.join("photo.png");
+		let rgba: image::RgbaImage = photo(96, 64);
+		rgba.save(&path).expect("Failed to write test image");
+		
+		let first: MinifyOptions = MinifyOptions::builder().quality(55).fast_deflate(true).build().expect("Valid options");
+		minify_png(&path, &path, &first, false, None).expect("First minification failed");
+		let (marked, info): (bool, Option<MinificationInfo>) = is_already_minified(&fs::read(&path).expect("Failed to read output")).expect("Marker check failed");
+		assert!(marked);
+		assert_eq!(info.expect("Missing marker info").quality, Some(55));
+		
+		for (reuse, expected_quality) in [(true, 55), (false, 20)]
+		{
+			let options: MinifyOptions = MinifyOptions::builder().quality(20).fast_deflate(true).reuse_settings(reuse).build().expect("Valid options");
+			let (result, _): (ProcessingResult, Option<MinificationInfo>) = minify_png(&path, &dir.join(format!("reminified-{}.png", reuse)), &options, true, None).expect("Re-minification failed");
+			assert_eq!(result.quality, Some(expected_quality));
+		}
+		
+		let _ = fs::remove_dir_all(&dir);
+	}
+	
+	#[cfg(unix)]
+	#[test]
+	fn read_only_targets_are_skipped_or_written_with_force_writable()
+	{
+		use std::os::unix::fs::PermissionsExt;
+		
+		let dir: PathBuf = scratch_dir("read-only");
+		let path: PathBuf = dir.join("vendored.png");
+		let rgba: image::RgbaImage = image::RgbaImage::from_fn(64, 64, |x: u32, y: u32| image::Rgba([(x * 4) as u8, (y * 4) as u8, ((x * y) % 251) as u8, 255]));
+		rgba.save(&path).expect("Failed to write test image");
+		let original: Vec<u8> = fs::read(&path).expect("Failed to read test image");
+		fs::set_permissions(&path, fs::Permissions::from_mode(0o444)).expect("Failed to make the file read-only");
+		
+		let options: MinifyOptions = MinifyOptions::builder().lossless(true).fast_deflate(true).build().expect("Valid options");
+		let (result, _): (ProcessingResult, Option<MinificationInfo>) = minify_png(&path, &path, &options, false, None).expect("Processing failed");
+		assert_eq!(result.skip_reason, Some(SkipReason::ReadOnly));
+		assert_eq!(fs::read(&path).expect("Failed to read target"), original);
+		
+		let options: MinifyOptions = MinifyOptions::builder().lossless(true).fast_deflate(true).force_writable(true).build().expect("Valid options");
+		let (result, _): (ProcessingResult, Option<MinificationInfo>) = minify_png(&path, &path, &options, false, None).expect("Processing failed");
+		assert_eq!(result.skip_reason, None);
+		assert!(result.new_size < result.original_size);
+		assert_eq!(image::open(&path).expect("Target is not a PNG").to_rgba8(), rgba);
+		assert_eq!(fs::metadata(&path).expect("Failed to read metadata").permissions().mode() & 0o777, 0o444);
+		
+		fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).expect("Failed to make the file writable");
+		let _ = fs::remove_dir_all(&dir);
+	}
+	
+	#[test]
+	fn always_write_replaces_a_file_the_lossless_pass_cannot_shrink()
+	{
+		let dir: PathBuf = scratch_dir("always-write");
+		let path: PathBuf = dir.join("optimal.png");
+		let rgba: image::RgbaImage = image::RgbaImage::from_fn(32, 32, |x: u32, y: u32| image::Rgba([(x * 8) as u8, (y * 8) as u8, 128, 255]));
+		rgba.save(&path).expect("Failed to write test image");
+		
+		// A tiny smooth image is saved in under 200 bytes, which the lossless pass plus the marker can't beat.
+		let data: Vec<u8> = fs::read(&path).expect("Failed to read test image");
+		
+		let options: MinifyOptions = MinifyOptions::builder().lossless(true).fast_deflate(true).build().expect("Valid options");
+		let (result, _): (ProcessingResult, Option<MinificationInfo>) = minify_png(&path, &path, &options, false, None).expect("Processing failed");
+		assert!(!result.re_encoded);
+		assert_eq!(fs::read(&path).expect("Failed to read file"), data);
+		
+		let options: MinifyOptions = MinifyOptions::builder().lossless(true).fast_deflate(true).always_write(true).build().expect("Valid options");
+		let (result, _): (ProcessingResult, Option<MinificationInfo>) = minify_png(&path, &path, &options, false, None).expect("Processing failed");
+		assert!(result.re_encoded);
+		assert!(result.new_size >= result.original_size);
+		assert!(is_already_minified(&fs::read(&path).expect("Failed to read output")).expect("Marker check failed").0);
+		
+		let _ = fs::remove_dir_all(&dir);
+	}
+	
+	#[test]
+	fn reminify_before_reprocesses_only_files_marked_before_the_cutoff()
+	{
+		let dir: PathBuf = scratch_dir("reminify-before");
+		let old_path: PathBuf = dir.join("old.png");
+		let new_path: PathBuf = dir.join("new.png");
+		let first: MinifyOptions = MinifyOptions::builder().quality(55).fast_deflate(true).build().expect("Valid options");
+		for path in [&old_path, &new_path]
+		{
+			let rgba: image::RgbaImage = photo(96, 64);
+			rgba.save(path).expect("Failed to write test image");
+			minify_png(path, path, &first, false, None).expect("First minification failed");
+		}
+		
+		// Backdate the marker of one file (timestamps have a fixed length, so only the CRC changes).
+		let mut data: Vec<u8> = fs::read(&old_path).expect("Failed to read output");
+		let timestamp_pos: usize = data.windows(10).position(|window: &[u8]| window == b"timestamp=").expect("Missing marker timestamp") + 10;
+		data[timestamp_pos..timestamp_pos + 20].copy_from_slice(b"2020-01-01T00:00:00Z");
+		let chunk_pos: usize = data.windows(4).position(|window: &[u8]| window == b"tEXt").expect("Missing marker") - 4;
+		let length: usize = u32::from_be_bytes([data[chunk_pos], data[chunk_pos + 1], data[chunk_pos + 2], data[chunk_pos + 3]]) as usize;
+		let crc: u32 = minipng::utils::crc_utils::hash(&data[chunk_pos + 4..chunk_pos + 8 + length]);
+		data[chunk_pos + 8 + length..chunk_pos + 12 + length].copy_from_slice(&crc.to_be_bytes());
+		fs::write(&old_path, &data).expect("Failed to write test image");
+		
+		let cutoff: Option<u64> = minipng::utils::time_utils::parse_iso8601("2025-01-01");
+		let options: MinifyOptions = MinifyOptions::builder().quality(30).fast_deflate(true).reminify_before(cutoff).build().expect("Valid options");
+		let (old_result, _): (ProcessingResult, Option<MinificationInfo>) = minify_png(&old_path, &old_path, &options, false, None).expect("Processing failed");
+		let (new_result, _): (ProcessingResult, Option<MinificationInfo>) = minify_png(&new_path, &new_path, &options, false, None).expect("Processing failed");
+		assert_eq!(old_result.skip_reason, None);
+		assert_eq!(old_result.quality, Some(30));
+		assert_eq!(new_result.skip_reason, Some(SkipReason::AlreadyMinified));
+		
+		let _ = fs::remove_dir_all(&dir);
+	}
+	
+	#[test]
+	fn safe_mode_minifies_a_64_color_image_losslessly()
+	{
+		let dir: PathBuf = scratch_dir("safe-quantized");
+		let path: PathBuf = dir.join("quantized.png");
+		
+		// A true-color image another tool reduced to 64 colors (4 levels per channel).
+		let rgba: image::RgbaImage = image::RgbaImage::from_fn(96, 64, |x: u32, y: u32| image::Rgba([(x / 24 * 85) as u8, (y / 16 * 85) as u8, ((x + y) % 4 * 85) as u8, 255]));
+		rgba.save(&path).expect("Failed to write test image");
+		
+		let options: MinifyOptions = MinifyOptions::builder().quality(20).fast_deflate(true).safe(true).build().expect("Valid options");
+		let (result, _): (ProcessingResult, Option<MinificationInfo>) = minify_png(&path, &dir.join("safe.png"), &options, false, None).expect("Processing failed");
+		assert!(result.already_quantized);
+		assert!(result.lossless);
+		assert_eq!(result.quality, None);
+		
+		// Without safe, the colors aren't counted and the image is quantized again.
+		let options: MinifyOptions = MinifyOptions::builder().quality(20).fast_deflate(true).build().expect("Valid options");
+		let (result, _): (ProcessingResult, Option<MinificationInfo>) = minify_png(&path, &dir.join("lossy.png"), &options, false, None).expect("Processing failed");
+		assert!(!result.already_quantized);
+		assert!(!result.lossless);
+		
+		let _ = fs::remove_dir_all(&dir);
+	}
+	
+	#[test]
+	fn output_names_a_file_for_a_single_input_and_a_directory_otherwise()
+	{
+		let dir: PathBuf = scratch_dir("redirect-targets");
+		let root: PathBuf = dir.join("images");
+		fs::create_dir_all(root.join("nested")).expect("Failed to create test directory");
+		let in_place = |paths: &[PathBuf]| -> Vec<PngFile>
+		{
+			let mut files: Vec<PngFile> = Vec::new();
+			for path in paths
+			{
+				fs::write(path, b"").expect("Failed to write test file");
+				files.push(PngFile { source_path: path.clone(), target_path: path.clone() });
+			}
+			files
+		};
+		
+		// A single input file is written to the given name, with or without a .png extension.
+		for output in [dir.join("out.png"), dir.join("out")]
+		{
+			let mut files: Vec<PngFile> = in_place(&[root.join("a.png")]);
+			redirect_targets(&mut files, &output, &root, true).expect("Redirect failed");
+			assert_eq!(files[0].target_path, output);
+		}
+		
+		// Several inputs can't share a file name, but keep their relative paths below a directory.
+		let sources: [PathBuf; 2] = [root.join("a.png"), root.join("nested").join("b.png")];
+		let mut files: Vec<PngFile> = in_place(&sources);
+		let error: String = redirect_targets(&mut files, &dir.join("out.png"), &root, true).expect_err("A file name should be rejected for several inputs").to_string();
+		assert!(error.contains("2 input files"), "{}", error);
+		let output_dir: PathBuf = dir.join("minified");
+		redirect_targets(&mut files, &output_dir, &root, false).expect("Redirect failed");
+		assert_eq!(files[0].target_path, output_dir.join("a.png"));
+		assert_eq!(files[1].target_path, output_dir.join("nested").join("b.png"));
+		
+		// Files outside the root keep only their name, so two with the same name would overwrite each other.
+		let elsewhere: PathBuf = dir.join("elsewhere");
+		fs::create_dir_all(&elsewhere).expect("Failed to create test directory");
+		let mut files: Vec<PngFile> = in_place(&[root.join("nested").join("b.png"), elsewhere.join("b.png")]);
+		let error: String = redirect_targets(&mut files, &output_dir, &root.join("nested"), false).expect_err("Colliding targets should be rejected").to_string();
+		assert!(error.contains("would both be written to"), "{}", error);
+		
+		let _ = fs::remove_dir_all(&dir);
+	}
+	
+	#[test]
+	#[cfg(feature = "webp")]
+	fn also_webp_writes_the_quantized_pixels_next_to_the_output()
+	{
+		let dir: PathBuf = scratch_dir("also-webp");
+		let path: PathBuf = dir.join("photo.png");
+		image::RgbaImage::from_fn(48, 32, |x, y| image::Rgba([(x * 5) as u8, (y * 7) as u8, ((x * y) % 256) as u8, 255])).save(&path).expect("Failed to write test image");
+		let target: PathBuf = dir.join("out").join("photo.png");
+		
+		let options: MinifyOptions = MinifyOptions::builder().quality(40).fast_deflate(true).also_webp(true).build().expect("Valid options");
+		let (result, _): (ProcessingResult, Option<MinificationInfo>) = minify_png(&path, &target, &options, false, None).expect("Processing failed");
+		
+		let webp_data: Vec<u8> = fs::read(dir.join("out").join("photo.webp")).expect("Missing WebP output");
+		assert_eq!(result.webp_size, Some(webp_data.len() as u64));
+		let mut decoder: image_webp::WebPDecoder<std::io::Cursor<Vec<u8>>> = image_webp::WebPDecoder::new(std::io::Cursor::new(webp_data)).expect("Failed to decode WebP");
+		assert_eq!(decoder.dimensions(), (48, 32));
+		
+		// The WebP shows exactly the quantized pixels of the PNG output.
+		let mut webp_pixels: Vec<u8> = vec![0; decoder.output_buffer_size().expect("WebP too large")];
+		decoder.read_image(&mut webp_pixels).expect("Failed to decode WebP");
+		let png: image::DynamicImage = image::open(&target).expect("Failed to decode PNG output");
+		let png_pixels: Vec<u8> = if decoder.has_alpha() { png.to_rgba8().into_raw() } else { png.to_rgb8().into_raw() };
+		assert!(webp_pixels == png_pixels, "WebP pixels differ from the PNG output");
+		
+		let _ = fs::remove_dir_all(&dir);
+	}
 }
@@ -0,0 +1,180 @@
+// SHA-256 implementation based on FIPS 180-4.
+// Content hashes for build manifests, written out here like crc_utils instead of adding a dependency.
+
+// Round constants: the first 32 bits of the fractional parts of the cube roots of the first 64 primes.
+static ROUND_CONSTANTS: [u32; 64] =
+[
+	0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+	0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+	0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+	0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+	0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+	0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+	0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+	0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+// Initial hash value: the first 32 bits of the fractional parts of the square roots of the first 8 primes.
+const INITIAL_STATE: [u32; 8] = [0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19];
+
+/// Incremental SHA-256 hasher, for data that is streamed rather than held in memory.
+/// It also implements io::Write, so anything written to a writer can be hashed on the way.
+pub struct Sha256
+{
+	state: [u32; 8],
+	block: [u8; 64],
+	block_len: usize,
+	total_len: u64,
+}
+
+impl Sha256
+{
+	/// Start a new hash.
+	#[allow(clippy::new_without_default)]
+	pub fn new() -> Self
+	{
+		Sha256 { state: INITIAL_STATE, block: [0; 64], block_len: 0, total_len: 0 }
+	}
+	
+	/// Add data to the hash.
+	pub fn update(&mut self, mut data: &[u8])
+	{
+		self.total_len += data.len() as u64;
+		while !data.is_empty()
+		{
+			let taken: usize = (64 - self.block_len).min(data.len());
+			self.block[self.block_len..self.block_len + taken].copy_from_slice(&data[..taken]);
+			self.block_len += taken;
+			data = &data[taken..];
+			
+			if self.block_len == 64
+			{
+				let block: [u8; 64] = self.block;
+				self.compress(&block);
+				self.block_len = 0;
+			}
+		}
+	}
+	
+	/// Pad the data and return the 32-byte hash.
+	pub fn finish(mut self) -> [u8; 32]
+	{
+		// Padding: a 1 bit, zeros up to 8 bytes before a block boundary, then the length in bits.
+		let bit_len: u64 = self.total_len * 8;
+		let padding_len: usize = if self.block_len < 56 { 56 - self.block_len } else { 120 - self.block_len };
+		let mut padding: [u8; 72] = [0; 72];
+		padding[0] = 0x80;
+		self.update(&padding[..padding_len]);
+		self.update(&bit_len.to_be_bytes());
+		
+		let mut hash: [u8; 32] = [0; 32];
+		for (i, word) in self.state.iter().enumerate()
+		{
+			hash[i * 4..i * 4 + 4].copy_from_slice(&word.to_be_bytes());
+		}
+		
+		hash
+	}
+	
+	/// Process one 64-byte block.
+	fn compress(&mut self, block: &[u8; 64])
+	{
+		let mut schedule: [u32; 64] = [0; 64];
+		for i in 0..16
+		{
+			schedule[i] = u32::from_be_bytes([block[i * 4], block[i * 4 + 1], block[i * 4 + 2], block[i * 4 + 3]]);
+		}
+		for i in 16..64
+		{
+			let s0: u32 = schedule[i - 15].rotate_right(7) ^ schedule[i - 15].rotate_right(18) ^ (schedule[i - 15] >> 3);
+			let s1: u32 = schedule[i - 2].rotate_right(17) ^ schedule[i - 2].rotate_right(19) ^ (schedule[i - 2] >> 10);
+			schedule[i] = schedule[i - 16].wrapping_add(s0).wrapping_add(schedule[i - 7]).wrapping_add(s1);
+		}
+		
+		let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h]: [u32; 8] = self.state;
+		for i in 0..64
+		{
+			let s1: u32 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+			let choice: u32 = (e & f) ^ (!e & g);
+			let temp1: u32 = h.wrapping_add(s1).wrapping_add(choice).wrapping_add(ROUND_CONSTANTS[i]).wrapping_add(schedule[i]);
+			let s0: u32 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+			let majority: u32 = (a & b) ^ (a & c) ^ (b & c);
+			let temp2: u32 = s0.wrapping_add(majority);
+			
+			h = g;
+			g = f;
+			f = e;
+			e = d.wrapping_add(temp1);
+			d = c;
+			c = b;
+			b = a;
+			a = temp1.wrapping_add(temp2);
+		}
+		
+		for (word, value) in self.state.iter_mut().zip([a, b, c, d, e, f, g, h])
+		{
+			*word = word.wrapping_add(value);
+		}
+	}
+}
+
+impl std::io::Write for Sha256
+{
+	fn write(&mut self, data: &[u8]) -> std::io::Result<usize>
+	{
+		self.update(data);
+		Ok(data.len())
+	}
+	
+	fn flush(&mut self) -> std::io::Result<()>
+	{
+		Ok(())
+	}
+}
+
+/// Calculates the SHA-256 hash of a byte slice.
+pub fn sha256(data: &[u8]) -> [u8; 32]
+{
+	let mut hasher: Sha256 = Sha256::new();
+	hasher.update(data);
+	hasher.finish()
+}
+
+/// Format a hash as lowercase hexadecimal, as printed by sha256sum.
+pub fn to_hex(hash: &[u8]) -> String
+{
+	let mut hex: String = String::with_capacity(hash.len() * 2);
+	for byte in hash
+	{
+		hex.push_str(&format!("{:02x}", byte));
+	}
+	hex
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+	use std::io::Write;
+	
+	#[test]
+	fn sha256_matches_the_standard_test_vectors()
+	{
+		assert_eq!(to_hex(&sha256(b"")), "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+		assert_eq!(to_hex(&sha256(b"abc")), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+		assert_eq!(to_hex(&sha256(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq")), "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1");
+		
+		// Written in uneven pieces, across block boundaries.
+		let mut data: Vec<u8> = Vec::with_capacity(1000);
+		for i in 0..1000u32
+		{
+			data.push((i * 7) as u8);
+		}
+		let mut hasher: Sha256 = Sha256::new();
+		for piece in data.chunks(37)
+		{
+			hasher.write_all(piece).expect("Hashing failed");
+		}
+		assert_eq!(hasher.finish(), sha256(&data));
+	}
+}
@@ -0,0 +1,166 @@
+use anyhow::{anyhow, Result};
+use std::fs;
+use std::path::Path;
+
+use crate::utils::file_utils::wildcard_match;
+
+/// Name of the ignore file read from the root of a directory scan.
+pub const IGNORE_FILE_NAME: &str = ".minipngignore";
+
+/// A single pattern from an ignore file.
+#[derive(Debug, Clone)]
+struct IgnorePattern
+{
+	/// Pattern text without the leading '!' or trailing '/'.
+	pattern: String,
+	
+	/// Match against the path relative to the scan root instead of the entry name.
+	anchored: bool,
+	
+	/// Only match directories (pattern written with a trailing '/').
+	dir_only: bool,
+	
+	/// Re-include matching paths (pattern written with a leading '!').
+	negated: bool,
+}
+
+/// Ignore rules loaded from a .minipngignore file.
+/// Supports the common subset of .gitignore syntax: comments, wildcards, `dir/` patterns, leading `/` anchors and `!` negation.
+#[derive(Debug, Clone, Default)]
+pub struct IgnoreRules
+{
+	patterns: Vec<IgnorePattern>,
+}
+
+impl IgnoreRules
+{
+	/// Create an empty rule set that ignores nothing.
+	pub fn new() -> Self
+	{
+		Self { patterns: Vec::new() }
+	}
+	
+	/// Load the ignore file from the scan root, if there is one.
+	pub fn load(root: &Path) -> Result<Self>
+	{
+		let path: std::path::PathBuf = root.join(IGNORE_FILE_NAME);
+		if !path.is_file()
+		{
+			return Ok(Self::new());
+		}
+		
+		let text: String = fs::read_to_string(&path)
+			.map_err(|e| anyhow!("Failed to read ignore file {}: {}", path.display(), e))?;
+		
+		Ok(Self::parse(&text))
+	}
+	
+	/// Parse ignore file text, one pattern per line.
+	pub fn parse(text: &str) -> Self
+	{
+		let mut patterns: Vec<IgnorePattern> = Vec::new();
+		for raw_line in text.lines()
+		{
+			let line: &str = raw_line.trim();
+			if line.is_empty() || line.starts_with('#')
+			{
+				continue;
+			}
+			
+			let (negated, line): (bool, &str) = match line.strip_prefix('!')
+			{
+				Some(rest) => (true, rest),
+				None => (false, line),
+			};
+			
+			let dir_only: bool = line.ends_with('/');
+			let line: String = line.trim_end_matches('/').replace('\\', "/");
+			
+			// Like .gitignore, a slash anywhere but the end anchors the pattern to the scan root.
+			let anchored: bool = line.contains('/');
+			let pattern: String = line.trim_start_matches('/').to_string();
+			if pattern.is_empty()
+			{
+				continue;
+			}
+			
+			patterns.push(IgnorePattern { pattern, anchored, dir_only, negated });
+		}
+		
+		Self { patterns }
+	}
+	
+	/// Checks if a path under the scan root is ignored. The last matching pattern wins.
+	pub fn is_ignored(&self, path: &Path, root: &Path, is_dir: bool) -> bool
+	{
+		if self.patterns.is_empty()
+		{
+			return false;
+		}
+		
+		let relative: &Path = path.strip_prefix(root).unwrap_or(path);
+		let relative_str: String = relative.to_string_lossy().replace('\\', "/");
+		let name: String = match path.file_name()
+		{
+			Some(name) => name.to_string_lossy().to_string(),
+			None => relative_str.clone(),
+		};
+		
+		let mut ignored: bool = false;
+		for rule in &self.patterns
+		{
+			if rule.dir_only && !is_dir
+			{
+				continue;
+			}
+			
+			let candidate: &str = if rule.anchored { &relative_str } else { &name };
+			if wildcard_match(rule.pattern.as_bytes(), candidate.as_bytes())
+			{
+				ignored = !rule.negated;
+			}
+		}
+		
+		ignored
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+	use crate::utils::file_utils::{find_png_files_in_dir, PngFile};
+	use crate::utils::file_utils::tests::scratch_dir;
+	
+	#[test]
+	fn ignore_file_excludes_a_subtree_from_the_scan()
+	{
+		let dir: std::path::PathBuf = scratch_dir("ignore-file");
+		fs::create_dir_all(dir.join("dist/icons")).expect("Failed to create subdirectory");
+		fs::create_dir_all(dir.join("src")).expect("Failed to create subdirectory");
+		for path in ["a.png", "dist/b.png", "dist/icons/c.png", "src/d.png", "src/dist.png"]
+		{
+			image::RgbaImage::new(4, 4).save(dir.join(path)).expect("Failed to write test image");
+		}
+		fs::write(dir.join(IGNORE_FILE_NAME), "# Build output\ndist/\n").expect("Failed to write ignore file");
+		
+		let names = |files: &[PngFile]| -> Vec<String>
+		{
+			let mut names: Vec<String> = Vec::new();
+			for file in files
+			{
+				names.push(file.source_path.strip_prefix(&dir).expect("Found file outside the directory").to_string_lossy().replace('\\', "/"));
+			}
+			names
+		};
+		
+		let scanned: Vec<PngFile> = find_png_files_in_dir(Some(&dir), true, &[], true, false, true).expect("Scan failed");
+		assert_eq!(names(&scanned), vec!["a.png", "src/d.png", "src/dist.png"]);
+		
+		// --no-ignore scans the ignored subtree too.
+		let unfiltered: Vec<PngFile> = find_png_files_in_dir(Some(&dir), true, &[], false, false, true).expect("Scan failed");
+		assert_eq!(unfiltered.len(), 5);
+		
+		let _ = fs::remove_dir_all(&dir);
+	}
+}
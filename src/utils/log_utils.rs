@@ -0,0 +1,139 @@
+use anyhow::{anyhow, Result};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+
+use crate::utils::time_utils;
+
+// Log file of --log-file, mirroring the printed lines without colors, one timestamped line each.
+static LOG_FILE: Mutex<Option<File>> = Mutex::new(None);
+
+/// Open the log file, appending to it if it exists, so a series of runs can share one log.
+pub fn open_log(path: &Path) -> Result<()>
+{
+	let file: File = OpenOptions::new().create(true).append(true).open(path)
+		.map_err(|e| anyhow!("Failed to open log file {}: {}", path.display(), e))?;
+	*LOG_FILE.lock().expect("Log file mutex poisoned") = Some(file);
+	Ok(())
+}
+
+/// Checks if a log file is open, so callers can skip formatting lines nobody reads.
+pub fn log_enabled() -> bool
+{
+	LOG_FILE.lock().expect("Log file mutex poisoned").is_some()
+}
+
+/// Append printed text to the log file, if one is open.
+/// Each non-empty line is prefixed with the current ISO 8601 timestamp and stripped of ANSI color codes.
+/// Write failures are ignored: the log mirrors the console output and must not abort the run.
+pub fn log_line(text: &str)
+{
+	let mut log_file: std::sync::MutexGuard<Option<File>> = LOG_FILE.lock().expect("Log file mutex poisoned");
+	let file: &mut File = match log_file.as_mut()
+	{
+		Some(file) => file,
+		None => return,
+	};
+	
+	let timestamp: String = time_utils::get_iso8601_timestamp().unwrap_or_default();
+	let mut lines: String = String::new();
+	for line in strip_ansi(text).lines()
+	{
+		if line.trim().is_empty()
+		{
+			continue;
+		}
+		lines.push_str(&format!("{} {}\n", timestamp, line));
+	}
+	
+	let _ = file.write_all(lines.as_bytes());
+}
+
+/// Remove ANSI escape sequences (colors) from text.
+fn strip_ansi(text: &str) -> String
+{
+	let mut plain: String = String::with_capacity(text.len());
+	let mut chars: std::str::Chars = text.chars();
+	while let Some(c) = chars.next()
+	{
+		if c != '\x1b'
+		{
+			plain.push(c);
+			continue;
+		}
+		
+		// Skip up to the final letter of the sequence, e.g. "\x1b[32m".
+		for next in chars.by_ref()
+		{
+			if next.is_ascii_alphabetic()
+			{
+				break;
+			}
+		}
+	}
+	
+	plain
+}
+
+#[cfg(test)]
+pub(crate) mod tests
+{
+	use super::*;
+	use std::fs;
+	use std::path::PathBuf;
+	
+	use crate::utils::file_utils::tests::scratch_dir;
+	
+	// Tests that open a log take this lock, as the log file is shared by the whole process.
+	pub static LOG_LOCK: Mutex<()> = Mutex::new(());
+	
+	/// Close the log file opened by a test.
+	pub fn close_log()
+	{
+		*LOG_FILE.lock().expect("Log file mutex poisoned") = None;
+	}
+	
+	#[test]
+	fn log_file_gets_a_timestamped_line_per_file_with_its_counter()
+	{
+		let dir: PathBuf = scratch_dir("log-file");
+		let log_path: PathBuf = std::env::temp_dir().join(format!("minipng-log-file-{}.log", std::process::id()));
+		for name in ["a.png", "b.png"]
+		{
+			let rgba: image::RgbaImage = image::RgbaImage::from_fn(32, 32, |x: u32, y: u32| image::Rgba([(x * 8) as u8, (y * 8) as u8, 100, 255]));
+			rgba.save(dir.join(name)).expect("Failed to write test image");
+		}
+		
+		// Interactive mode prints the counter and the result of each file separately.
+		let _guard: std::sync::MutexGuard<()> = LOG_LOCK.lock().expect("Log test mutex poisoned");
+		let dir_arg: String = dir.display().to_string();
+		let log_arg: String = log_path.display().to_string();
+		let args: crate::Args = crate::utils::arg_utils::tests::parse(&["--interactive", "--dir", &dir_arg, "--log-file", &log_arg, "--quality", "40"]).expect("Failed to parse arguments");
+		let outcome: Result<std::process::ExitCode> = crate::run(args, std::time::Instant::now());
+		close_log();
+		assert_eq!(outcome.expect("Run failed"), std::process::ExitCode::SUCCESS);
+		
+		// Other tests may print into the log meanwhile, so only the result lines of this run's files are checked.
+		let log: String = fs::read_to_string(&log_path).expect("Failed to read log");
+		let mut counters: Vec<&str> = Vec::new();
+		for line in log.lines()
+		{
+			if !line.contains(&dir_arg) || !line.contains("Minified")
+			{
+				continue;
+			}
+			
+			let (timestamp, text): (&str, &str) = line.split_once(' ').expect("Missing timestamp");
+			assert!(time_utils::parse_iso8601(timestamp).is_some(), "{}", line);
+			let (counter, _): (&str, &str) = text.split_once(' ').expect("Missing counter");
+			counters.push(counter);
+		}
+		counters.sort();
+		assert_eq!(counters, ["[1/2]", "[2/2]"], "{}", log);
+		assert!(!log.contains('\x1b'));
+		
+		let _ = fs::remove_file(&log_path);
+		let _ = fs::remove_dir_all(&dir);
+	}
+}
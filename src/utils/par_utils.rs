@@ -0,0 +1,65 @@
+// Row-parallel and thread budget helpers.
+// With the "cli" feature, work is processed on rayon thread pools.
+// Without it (e.g. wasm builds), it is processed sequentially on the calling thread.
+
+/// Map every row index in 0..height, in parallel. Results are returned in row order.
+#[cfg(feature = "cli")]
+pub fn map_rows<T, F>(height: u32, f: F) -> Vec<T> where T: Send, F: Fn(u32) -> T + Sync + Send
+{
+	use rayon::prelude::*;
+	
+	(0..height).into_par_iter().map(f).collect()
+}
+
+/// Map every row index in 0..height, sequentially. Results are returned in row order.
+#[cfg(not(feature = "cli"))]
+pub fn map_rows<T, F>(height: u32, f: F) -> Vec<T> where F: Fn(u32) -> T
+{
+	let mut rows: Vec<T> = Vec::with_capacity(height as usize);
+	for y in 0..height
+	{
+		rows.push(f(y));
+	}
+	
+	rows
+}
+
+/// Map every item of a slice, in parallel. Results are returned in item order.
+#[cfg(feature = "cli")]
+pub fn map_items<I, T, F>(items: &[I], f: F) -> Vec<T> where I: Sync, T: Send, F: Fn(&I) -> T + Sync + Send
+{
+	use rayon::prelude::*;
+	
+	items.par_iter().map(f).collect()
+}
+
+/// Map every item of a slice, sequentially. Results are returned in item order.
+#[cfg(not(feature = "cli"))]
+pub fn map_items<I, T, F>(items: &[I], f: F) -> Vec<T> where F: Fn(&I) -> T
+{
+	items.iter().map(f).collect()
+}
+
+/// Run a task on a dedicated pool of the given number of threads, so the parallel work it starts is bounded.
+/// With 0 threads, or if the pool can't be created, the task runs on the caller's thread pool.
+#[cfg(feature = "cli")]
+pub fn run_with_threads<T, F>(threads: usize, f: F) -> T where T: Send, F: FnOnce() -> T + Send
+{
+	if threads == 0
+	{
+		return f();
+	}
+	
+	match rayon::ThreadPoolBuilder::new().num_threads(threads).build()
+	{
+		Ok(pool) => pool.install(f),
+		Err(_) => f(),
+	}
+}
+
+/// Run a task on the calling thread (there are no thread pools without the "cli" feature).
+#[cfg(not(feature = "cli"))]
+pub fn run_with_threads<T, F>(_threads: usize, f: F) -> T where F: FnOnce() -> T
+{
+	f()
+}
@@ -0,0 +1,144 @@
+use anyhow::{anyhow, Result};
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::utils::file_utils::{canonical_or_original, PngFile};
+
+/// Name of the progress file in the scan root, listing the files completed by an interrupted run.
+pub const PROGRESS_FILE_NAME: &str = ".minipng-progress";
+
+/// Files completed by the current run and earlier interrupted ones (--resume).
+/// Each completed source path is appended to the progress file as soon as it is done, so a run that is
+/// killed part way can be restarted without processing those files again.
+/// The file is removed once a run completes without errors.
+#[derive(Debug)]
+pub struct ProgressLog
+{
+	/// Path of the progress file.
+	path: PathBuf,
+	
+	/// Canonical source paths completed by earlier runs.
+	done: HashSet<PathBuf>,
+	
+	/// Progress file opened for appending.
+	file: Mutex<File>,
+}
+
+impl ProgressLog
+{
+	/// Open the progress file in a directory, reading the paths completed by earlier runs.
+	pub fn open(root: &Path) -> Result<Self>
+	{
+		let path: PathBuf = root.join(PROGRESS_FILE_NAME);
+		let contents: String = match fs::read_to_string(&path)
+		{
+			Ok(contents) => contents,
+			Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+			Err(e) => return Err(anyhow!("Failed to read progress file {}: {}", path.display(), e)),
+		};
+		let mut done: HashSet<PathBuf> = HashSet::new();
+		for line in contents.lines()
+		{
+			if !line.is_empty()
+			{
+				done.insert(PathBuf::from(line));
+			}
+		}
+		
+		let file: File = OpenOptions::new().create(true).append(true).open(&path)
+			.map_err(|e| anyhow!("Failed to open progress file {}: {}", path.display(), e))?;
+		
+		Ok(Self { path, done, file: Mutex::new(file) })
+	}
+	
+	/// Checks if a file was completed by an earlier run.
+	pub fn is_done(&self, path: &Path) -> bool
+	{
+		self.done.contains(&canonical_or_original(path))
+	}
+	
+	/// Drop the files completed by earlier runs from the list, returning how many were dropped.
+	pub fn retain_pending(&self, files: &mut Vec<PngFile>) -> usize
+	{
+		let count: usize = files.len();
+		files.retain(|file| !self.is_done(&file.source_path));
+		count - files.len()
+	}
+	
+	/// Append a completed file to the progress file, flushed right away so it survives the process being killed.
+	pub fn record(&self, path: &Path) -> Result<()>
+	{
+		let mut file: std::sync::MutexGuard<File> = self.file.lock().expect("Progress file mutex poisoned");
+		writeln!(file, "{}", canonical_or_original(path).display())
+			.and_then(|_| file.flush())
+			.map_err(|e| anyhow!("Failed to write progress file {}: {}", self.path.display(), e))
+	}
+	
+	/// Remove the progress file after a run completed every file.
+	pub fn clear(&self) -> Result<()>
+	{
+		match fs::remove_file(&self.path)
+		{
+			Ok(()) => Ok(()),
+			Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+			Err(e) => Err(anyhow!("Failed to remove progress file {}: {}", self.path.display(), e)),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+	use crate::minify::MinifyOptions;
+	
+	use crate::utils::file_utils::{find_png_files_in_dir, process_file};
+	use crate::utils::file_utils::tests::scratch_dir;
+	
+	#[test]
+	fn resuming_processes_only_the_files_left_by_an_interrupted_run()
+	{
+		let dir: PathBuf = scratch_dir("resume");
+		for name in ["a.png", "b.png", "c.png", "d.png"]
+		{
+			let rgba: image::RgbaImage = image::RgbaImage::from_fn(32, 32, |x: u32, y: u32| image::Rgba([(x * 8) as u8, (y * 8) as u8, 128, 255]));
+			rgba.save(dir.join(name)).expect("Failed to write test image");
+		}
+		let options: MinifyOptions = MinifyOptions::builder().lossless(true).fast_deflate(true).build().expect("Valid options");
+		
+		// The first run completes two files and is interrupted.
+		let mut files: Vec<PngFile> = find_png_files_in_dir(Some(&dir), true, &[], false, false, true).expect("Scan failed");
+		files.sort_by(|a: &PngFile, b: &PngFile| a.source_path.cmp(&b.source_path));
+		let progress: ProgressLog = ProgressLog::open(&dir).expect("Failed to open progress file");
+		assert_eq!(progress.retain_pending(&mut files), 0);
+		for file in &files[..2]
+		{
+			process_file(&file.source_path, &file.target_path, &options, false, None, None, Some(&progress)).expect("Processing failed");
+		}
+		drop(progress);
+		
+		// The resumed run only has the other two left.
+		let mut files: Vec<PngFile> = find_png_files_in_dir(Some(&dir), true, &[], false, false, true).expect("Scan failed");
+		let progress: ProgressLog = ProgressLog::open(&dir).expect("Failed to open progress file");
+		assert_eq!(progress.retain_pending(&mut files), 2);
+		let mut remaining: Vec<String> = Vec::new();
+		for file in &files
+		{
+			remaining.push(file.source_path.file_name().expect("Missing file name").to_string_lossy().into_owned());
+		}
+		remaining.sort();
+		assert_eq!(remaining, ["c.png", "d.png"]);
+		
+		for file in &files
+		{
+			process_file(&file.source_path, &file.target_path, &options, false, None, None, Some(&progress)).expect("Processing failed");
+		}
+		progress.clear().expect("Failed to clear progress file");
+		assert!(!dir.join(PROGRESS_FILE_NAME).exists());
+		
+		let _ = fs::remove_dir_all(&dir);
+	}
+}
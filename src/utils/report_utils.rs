@@ -0,0 +1,493 @@
+use anyhow::{anyhow, Result};
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use crate::minify::{self, ContentHashes, ProcessingResult, SkipReason};
+use minipng::utils::hash_utils;
+use crate::utils::cache_utils::escape_json;
+
+/// What the end-of-run summary emits (--report).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReportFormat
+{
+	/// The human-readable summary on stdout (default).
+	Human,
+	
+	/// A JSON document on stdout; human-readable output goes to stderr.
+	Json,
+	
+	/// A CSV table on stdout (as written by --csv); human-readable output goes to stderr.
+	Csv,
+	
+	/// No summary; human-readable output goes to stderr, leaving stdout empty.
+	None,
+}
+
+/// Parse a --report format name.
+pub fn parse_report_format(format: &str) -> Option<ReportFormat>
+{
+	match format
+	{
+		"human" => Some(ReportFormat::Human),
+		"json" => Some(ReportFormat::Json),
+		"csv" => Some(ReportFormat::Csv),
+		"none" => Some(ReportFormat::None),
+		_ => None,
+	}
+}
+
+/// Write the machine-readable summary for a report format (nothing for human and none, which have none).
+pub fn write_report<W>(out: &mut W, format: ReportFormat, results: &[ProcessingResult], errors: &[(String, String)]) -> Result<()> where W: Write
+{
+	let report: String = match format
+	{
+		ReportFormat::Json => format_json_report(results, errors),
+		ReportFormat::Csv => format_csv_report(results, errors),
+		ReportFormat::Human | ReportFormat::None => return Ok(()),
+	};
+	
+	out.write_all(report.as_bytes()).and_then(|_| out.flush()).map_err(|e| anyhow!("Failed to write report: {}", e))
+}
+
+/// Write a CSV report with one row per processed file.
+/// The status column tells minified, skipped and errored files apart; errored files have blank size columns.
+pub fn write_csv_report(path: &Path, results: &[ProcessingResult], errors: &[(String, String)]) -> Result<()>
+{
+	fs::write(path, format_csv_report(results, errors)).map_err(|e| anyhow!("Failed to write CSV report {}: {}", path.display(), e))
+}
+
+/// Build a CSV report with one row per processed file (see write_csv_report).
+pub fn format_csv_report(results: &[ProcessingResult], errors: &[(String, String)]) -> String
+{
+	let mut csv: String = String::new();
+	csv.push_str("path,original_size,new_size,reduction_pct,mode,quality,skipped,status\n");
+	
+	for r in results
+	{
+		let quality: String = match r.quality
+		{
+			Some(q) => q.to_string(),
+			None => String::new(),
+		};
+		
+		let status: &str = if r.skip_reason.is_some() { "skipped" } else { "minified" };
+		let fields: [String; 8] =
+		[
+			r.path.display().to_string(),
+			r.original_size.to_string(),
+			r.new_size.to_string(),
+			format!("{:.1}", calculate_reduction_pct(r.original_size, r.new_size)),
+			result_mode_name(r).to_string(),
+			quality,
+			r.skip_reason.is_some().to_string(),
+			status.to_string(),
+		];
+		push_csv_row(&mut csv, &fields);
+	}
+	
+	for (file, _) in errors
+	{
+		let fields: [String; 8] =
+		[
+			file.clone(),
+			String::new(),
+			String::new(),
+			String::new(),
+			String::new(),
+			String::new(),
+			String::new(),
+			"error".to_string(),
+		];
+		push_csv_row(&mut csv, &fields);
+	}
+	
+	csv
+}
+
+/// Build a JSON report: one object per processed file, the errors and the totals.
+/// Skipped files carry the skip reason, and missing values (mode, quality, skip reason) are null.
+pub fn format_json_report(results: &[ProcessingResult], errors: &[(String, String)]) -> String
+{
+	let json_string = |value: Option<&str>| -> String
+	{
+		match value
+		{
+			Some(text) if !text.is_empty() => format!("\"{}\"", escape_json(text)),
+			_ => "null".to_string(),
+		}
+	};
+	
+	let mut json: String = String::new();
+	json.push_str("{\n  \"files\": [");
+	for (index, r) in results.iter().enumerate()
+	{
+		let separator: &str = if index == 0 { "" } else { "," };
+		let quality: String = r.quality.map_or("null".to_string(), |quality| quality.to_string());
+		json.push_str(&format!("{}\n    {{\"path\": {}, \"original_size\": {}, \"new_size\": {}, \"reduction_pct\": {:.1}, \"mode\": {}, \"quality\": {}, \"skipped\": {}}}", separator, json_string(Some(&r.path.display().to_string())), r.original_size, r.new_size, calculate_reduction_pct(r.original_size, r.new_size), json_string(Some(result_mode_name(r))), quality, json_string(r.skip_reason.map(skip_reason_name))));
+	}
+	json.push_str(if results.is_empty() { "],\n" } else { "\n  ],\n" });
+	
+	json.push_str("  \"errors\": [");
+	for (index, (file, error)) in errors.iter().enumerate()
+	{
+		let separator: &str = if index == 0 { "" } else { "," };
+		json.push_str(&format!("{}\n    {{\"path\": {}, \"error\": {}}}", separator, json_string(Some(file)), json_string(Some(error))));
+	}
+	json.push_str(if errors.is_empty() { "],\n" } else { "\n  ],\n" });
+	
+	let mut original_size: u64 = 0;
+	let mut new_size: u64 = 0;
+	let mut minified: usize = 0;
+	let mut skipped: usize = 0;
+	for r in results
+	{
+		original_size += r.original_size;
+		new_size += r.new_size;
+		if r.skip_reason.is_some()
+		{
+			skipped += 1;
+		}
+		else if r.new_size < r.original_size
+		{
+			minified += 1;
+		}
+	}
+	json.push_str(&format!("  \"totals\": {{\"processed\": {}, \"minified\": {}, \"skipped\": {}, \"errors\": {}, \"original_size\": {}, \"new_size\": {}, \"reduction_pct\": {:.1}}}\n}}\n", results.len(), minified, skipped, errors.len(), original_size, new_size, calculate_reduction_pct(original_size, new_size)));
+	
+	json
+}
+
+/// Write a JSON manifest mapping each processed file to its output, with the sizes and SHA-256 hashes of both,
+/// so a build step can tell which outputs actually changed. Files without hashes (not read, or without an output) are left out.
+pub fn write_manifest(path: &Path, results: &[ProcessingResult]) -> Result<()>
+{
+	fs::write(path, format_manifest(results)).map_err(|e| anyhow!("Failed to write manifest {}: {}", path.display(), e))
+}
+
+/// Build the JSON manifest (see write_manifest).
+pub fn format_manifest(results: &[ProcessingResult]) -> String
+{
+	let mut json: String = String::new();
+	json.push_str("{\n  \"files\": [");
+	let mut first: bool = true;
+	for r in results
+	{
+		let hashes: &ContentHashes = match &r.hashes
+		{
+			Some(hashes) => hashes,
+			None => continue,
+		};
+		
+		let separator: &str = if first { "" } else { "," };
+		first = false;
+		json.push_str(&format!("{}\n    {{\"source\": \"{}\", \"source_size\": {}, \"source_sha256\": \"{}\", \"target\": \"{}\", \"target_size\": {}, \"target_sha256\": \"{}\"}}", separator, escape_json(&r.path.display().to_string()), r.original_size, hash_utils::to_hex(&hashes.source), escape_json(&hashes.target.display().to_string()), r.new_size, hash_utils::to_hex(&hashes.output)));
+	}
+	json.push_str(if first { "]\n}\n" } else { "\n  ]\n}\n" });
+	
+	json
+}
+
+/// Machine-readable name of a skip reason.
+pub fn skip_reason_name(reason: SkipReason) -> &'static str
+{
+	match reason
+	{
+		SkipReason::AlreadyMinified => "already_minified",
+		SkipReason::TargetExists => "target_exists",
+		SkipReason::NotPng => "not_png",
+		SkipReason::ReadOnly => "read_only",
+		SkipReason::TooLarge => "too_large",
+		SkipReason::UnsupportedFormat => "unsupported_format",
+	}
+}
+
+/// Calculate size reduction percentage.
+pub fn calculate_reduction_pct(original_size: u64, new_size: u64) -> f64
+{
+	if original_size > 0
+	{
+		(1.0 - (new_size as f64 / original_size as f64)) * 100.0
+	}
+	else
+	{
+		0.0
+	}
+}
+
+/// Describe how a file was (or had previously been) minified.
+pub fn result_mode_name(result: &ProcessingResult) -> &'static str
+{
+	if result.lossless
+	{
+		return "lossless";
+	}
+	
+	match result.dithering_mode
+	{
+		Some(mode) => minify::dithering_mode_to_string(mode),
+		None => "",
+	}
+}
+
+/// Collect the results that saved space (or all of them with include_unsaved), sorted descending by bytes saved.
+/// Files that saved the same number of bytes are sorted by path.
+pub fn sort_by_bytes_saved(results: &[ProcessingResult], include_unsaved: bool) -> Vec<&ProcessingResult>
+{
+	let mut savers: Vec<&ProcessingResult> = Vec::new();
+	for r in results
+	{
+		if include_unsaved || r.new_size < r.original_size
+		{
+			savers.push(r);
+		}
+	}
+	
+	savers.sort_by(|a, b| b.original_size.saturating_sub(b.new_size).cmp(&a.original_size.saturating_sub(a.new_size)).then_with(|| a.path.cmp(&b.path)));
+	savers
+}
+
+/// Append one CSV row, quoting fields as needed.
+fn push_csv_row(csv: &mut String, fields: &[String])
+{
+	for (index, field) in fields.iter().enumerate()
+	{
+		if index > 0
+		{
+			csv.push(',');
+		}
+		
+		csv.push_str(&escape_csv_field(field));
+	}
+	
+	csv.push('\n');
+}
+
+/// Quote a CSV field if it contains a comma, quote or line break (RFC 4180).
+fn escape_csv_field(field: &str) -> String
+{
+	if field.contains(',') || field.contains('"') || field.contains('\n') || field.contains('\r')
+	{
+		return format!("\"{}\"", field.replace('"', "\"\""));
+	}
+	
+	field.to_string()
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+	use std::path::PathBuf;
+	
+	use crate::minify::DitheringMode;
+	
+	/// Result of a lossy file, optionally skipped.
+	fn result(path: &str, original_size: u64, new_size: u64, skip_reason: Option<SkipReason>) -> ProcessingResult
+	{
+		ProcessingResult
+		{
+			path: PathBuf::from(path),
+			original_size,
+			new_size,
+			skip_reason,
+			lossless: false,
+			quality: Some(40),
+			dithering_mode: Some(DitheringMode::FloydSteinberg),
+			re_encoded: false,
+			auto_quality: false,
+			already_quantized: false,
+			noop_marked: false,
+			lossless_fallback: false,
+			auto_mode: false,
+			webp_size: None,
+			trailing_stripped: 0,
+			hashes: None,
+			image_class: None,
+		}
+	}
+	
+	/// Minimal JSON syntax check: consumes one value starting at pos and returns the position after it.
+	fn parse_value(bytes: &[u8], pos: usize) -> Option<usize>
+	{
+		let pos: usize = skip_whitespace(bytes, pos);
+		match *bytes.get(pos)?
+		{
+			b'{' => parse_sequence(bytes, pos + 1, b'}', true),
+			b'[' => parse_sequence(bytes, pos + 1, b']', false),
+			b'"' => parse_string(bytes, pos + 1),
+			b'n' => bytes[pos..].starts_with(b"null").then_some(pos + 4),
+			b't' => bytes[pos..].starts_with(b"true").then_some(pos + 4),
+			b'f' => bytes[pos..].starts_with(b"false").then_some(pos + 5),
+			_ =>
+			{
+				let end: usize = pos + bytes[pos..].iter().take_while(|byte| byte.is_ascii_digit() || b"-+.eE".contains(byte)).count();
+				std::str::from_utf8(&bytes[pos..end]).ok()?.parse::<f64>().ok().map(|_| end)
+			},
+		}
+	}
+	
+	/// Elements of an object (key: value pairs) or array up to the closing bracket.
+	fn parse_sequence(bytes: &[u8], pos: usize, close: u8, object: bool) -> Option<usize>
+	{
+		let mut pos: usize = skip_whitespace(bytes, pos);
+		if bytes.get(pos) == Some(&close)
+		{
+			return Some(pos + 1);
+		}
+		
+		loop
+		{
+			if object
+			{
+				pos = skip_whitespace(bytes, pos);
+				if bytes.get(pos) != Some(&b'"')
+				{
+					return None;
+				}
+				pos = skip_whitespace(bytes, parse_string(bytes, pos + 1)?);
+				if bytes.get(pos) != Some(&b':')
+				{
+					return None;
+				}
+				pos += 1;
+			}
+			
+			pos = skip_whitespace(bytes, parse_value(bytes, pos)?);
+			match bytes.get(pos)
+			{
+				Some(b',') => pos += 1,
+				Some(byte) if *byte == close => return Some(pos + 1),
+				_ => return None,
+			}
+		}
+	}
+	
+	/// String contents up to the closing quote, with escapes.
+	fn parse_string(bytes: &[u8], mut pos: usize) -> Option<usize>
+	{
+		loop
+		{
+			match *bytes.get(pos)?
+			{
+				b'"' => return Some(pos + 1),
+				b'\\' => pos += 2,
+				byte if byte < 0x20 => return None,
+				_ => pos += 1,
+			}
+		}
+	}
+	
+	fn skip_whitespace(bytes: &[u8], pos: usize) -> usize
+	{
+		pos + bytes[pos.min(bytes.len())..].iter().take_while(|byte| byte.is_ascii_whitespace()).count()
+	}
+	
+	#[test]
+	fn json_report_is_valid_json()
+	{
+		let results: Vec<ProcessingResult> = vec![result("a \"quoted\" name.png", 1000, 400, None), result("b.png", 500, 500, Some(SkipReason::AlreadyMinified))];
+		let errors: Vec<(String, String)> = vec![("c.png".to_string(), "Failed to decode PNG:\n\tbad data".to_string())];
+		
+		let mut stdout: Vec<u8> = Vec::new();
+		write_report(&mut stdout, ReportFormat::Json, &results, &errors).expect("Report failed");
+		
+		assert_eq!(parse_value(&stdout, 0).map(|end| skip_whitespace(&stdout, end)), Some(stdout.len()));
+		let text: String = String::from_utf8(stdout).expect("Report is UTF-8");
+		assert!(text.contains("\"skipped\": \"already_minified\""));
+		assert!(text.contains("\"totals\": {\"processed\": 2, \"minified\": 1, \"skipped\": 1, \"errors\": 1, \"original_size\": 1500, \"new_size\": 900"));
+		
+		// Empty runs are valid too.
+		let empty: String = format_json_report(&[], &[]);
+		assert_eq!(parse_value(empty.as_bytes(), 0).map(|end| skip_whitespace(empty.as_bytes(), end)), Some(empty.len()));
+	}
+	
+	#[test]
+	fn none_and_human_reports_write_nothing()
+	{
+		let results: Vec<ProcessingResult> = vec![result("a.png", 1000, 400, None)];
+		for format in [ReportFormat::None, ReportFormat::Human]
+		{
+			let mut stdout: Vec<u8> = Vec::new();
+			write_report(&mut stdout, format, &results, &[]).expect("Report failed");
+			assert!(stdout.is_empty(), "{:?}", format);
+		}
+		
+		let mut stdout: Vec<u8> = Vec::new();
+		write_report(&mut stdout, ReportFormat::Csv, &results, &[]).expect("Report failed");
+		assert_eq!(String::from_utf8(stdout).expect("Report is UTF-8"), format_csv_report(&results, &[]));
+	}
+	
+	#[test]
+	fn top_savers_are_sorted_by_bytes_saved()
+	{
+		let results: Vec<ProcessingResult> = vec![result("small.png", 1000, 900, None), result("grown.png", 1000, 1200, None), result("big.png", 50000, 45000, None), result("skipped.png", 2000, 2000, Some(SkipReason::AlreadyMinified)), result("best-ratio.png", 2000, 200, None)];
+		
+		let mut paths: Vec<&str> = Vec::new();
+		for r in sort_by_bytes_saved(&results, false)
+		{
+			paths.push(r.path.to_str().expect("Invalid path"));
+		}
+		
+		// Ranked by bytes, not percentage; files that didn't shrink aren't listed.
+		assert_eq!(paths, ["big.png", "best-ratio.png", "small.png"]);
+		
+		// With every file included, the ones that saved nothing follow by path.
+		let mut paths: Vec<&str> = Vec::new();
+		for r in sort_by_bytes_saved(&results, true)
+		{
+			paths.push(r.path.to_str().expect("Invalid path"));
+		}
+		assert_eq!(paths, ["big.png", "best-ratio.png", "small.png", "grown.png", "skipped.png"]);
+	}
+	
+	#[test]
+	fn csv_report_parses_back_with_a_status_per_row()
+	{
+		let results: Vec<ProcessingResult> = vec![result("a, with comma.png", 1000, 600, None), result("b.png", 500, 500, Some(SkipReason::AlreadyMinified))];
+		let errors: Vec<(String, String)> = vec![("broken \"file\".png".to_string(), "Not a PNG".to_string())];
+		let csv: String = format_csv_report(&results, &errors);
+		
+		let mut rows: Vec<Vec<String>> = Vec::new();
+		for line in csv.lines()
+		{
+			rows.push(split_csv_line(line));
+		}
+		
+		assert_eq!(rows.len(), 4);
+		for row in &rows
+		{
+			assert_eq!(row.len(), 8, "{:?}", row);
+		}
+		assert_eq!(rows[0][7], "status");
+		assert_eq!((rows[1][0].as_str(), rows[1][7].as_str()), ("a, with comma.png", "minified"));
+		assert_eq!((rows[2][6].as_str(), rows[2][7].as_str()), ("true", "skipped"));
+		assert_eq!((rows[3][0].as_str(), rows[3][1].as_str(), rows[3][7].as_str()), ("broken \"file\".png", "", "error"));
+	}
+	
+	/// Split one CSV line into its fields, undoing the quoting of escape_csv_field.
+	fn split_csv_line(line: &str) -> Vec<String>
+	{
+		let mut fields: Vec<String> = Vec::new();
+		let mut field: String = String::new();
+		let mut quoted: bool = false;
+		let mut chars: std::iter::Peekable<std::str::Chars> = line.chars().peekable();
+		while let Some(c) = chars.next()
+		{
+			match (c, quoted)
+			{
+				('"', true) if chars.peek() == Some(&'"') =>
+				{
+					field.push('"');
+					chars.next();
+				},
+				('"', _) => quoted = !quoted,
+				(',', false) => fields.push(std::mem::take(&mut field)),
+				_ => field.push(c),
+			}
+		}
+		fields.push(field);
+		
+		fields
+	}
+}
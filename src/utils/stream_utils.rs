@@ -0,0 +1,159 @@
+use anyhow::{anyhow, Result};
+use rayon::prelude::*;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::thread::JoinHandle;
+
+use crate::minify::ProcessingResult;
+use crate::utils::file_utils::{for_each_png_file_in_dir, PngFile};
+
+/// Number of discovered files that can wait for a worker; the directory walk pauses while the queue is full.
+const QUEUE_CAPACITY: usize = 256;
+
+/// Running totals of a streamed run (--stream).
+/// Each file's result is folded in and then dropped, so memory use doesn't grow with the number of files.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct StreamTotals
+{
+	/// Files processed without an error, including skipped ones.
+	pub processed: usize,
+	
+	/// Files that got smaller.
+	pub minified: usize,
+	
+	/// Files left untouched for a skip reason (already minified, not a PNG, ...).
+	pub skipped: usize,
+	
+	/// Files that failed with an error.
+	pub errors: usize,
+	
+	/// Total size of the processed files before processing.
+	pub original_size: u64,
+	
+	/// Total size of the processed files afterward.
+	pub new_size: u64,
+}
+
+impl StreamTotals
+{
+	/// Fold the outcome of one file into the totals.
+	pub fn add(mut self, outcome: &Result<ProcessingResult>) -> Self
+	{
+		match outcome
+		{
+			Ok(result) =>
+			{
+				self.processed += 1;
+				self.original_size += result.original_size;
+				self.new_size += result.new_size;
+				if result.skip_reason.is_some()
+				{
+					self.skipped += 1;
+				}
+				else if result.new_size < result.original_size
+				{
+					self.minified += 1;
+				}
+			},
+			Err(_) => self.errors += 1,
+		}
+		
+		self
+	}
+}
+
+/// Walk a directory on a background thread and hand its PNG files over through a bounded queue as they are found.
+/// Process them with fold_stream, then pass the handle to finish_walk.
+pub fn stream_png_files(directory: PathBuf, excludes: Vec<String>, use_ignore_file: bool, follow_symlinks: bool, recurse: bool) -> (Receiver<PngFile>, JoinHandle<Result<()>>)
+{
+	let (sender, receiver): (SyncSender<PngFile>, Receiver<PngFile>) = mpsc::sync_channel(QUEUE_CAPACITY);
+	let walker: JoinHandle<Result<()>> = std::thread::spawn(move ||
+	{
+		// Sending only fails once the receiver is gone, and then nobody is left to process the file anyway.
+		for_each_png_file_in_dir(&directory, &excludes, use_ignore_file, follow_symlinks, recurse, |file: PngFile|
+		{
+			let _ = sender.send(file);
+		})
+	});
+	
+	(receiver, walker)
+}
+
+/// Process the streamed files on the thread pool as they arrive, folding each outcome into the totals.
+/// Files for which process returns None (such as files inside the backup directory) are left out of the totals.
+pub fn fold_stream<F>(files: Receiver<PngFile>, process: F) -> StreamTotals where F: Fn(PngFile) -> Option<Result<ProcessingResult>> + Sync
+{
+	let totals: Mutex<StreamTotals> = Mutex::new(StreamTotals::default());
+	files.into_iter().par_bridge().for_each(|file: PngFile|
+	{
+		if let Some(outcome) = process(file)
+		{
+			let mut totals: std::sync::MutexGuard<StreamTotals> = totals.lock().expect("Stream totals mutex poisoned");
+			*totals = totals.add(&outcome);
+		}
+	});
+	
+	totals.into_inner().expect("Stream totals mutex poisoned")
+}
+
+/// Wait for the directory walk to end and return its result.
+pub fn finish_walk(walker: JoinHandle<Result<()>>) -> Result<()>
+{
+	walker.join().map_err(|_| anyhow!("Directory walk panicked"))?
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+	use std::fs;
+	use std::path::Path;
+	
+	use crate::minify::{DitheringMode, MinifyOptions};
+	use crate::utils::file_utils::{find_png_files_in_dir, process_file};
+	use crate::utils::file_utils::tests::scratch_dir;
+	
+	/// Write the same mix of files to a directory: noisy images that shrink, a non-PNG and a corrupt PNG.
+	fn write_sample_files(dir: &Path)
+	{
+		fs::create_dir_all(dir.join("nested")).expect("Failed to create subdirectory");
+		for name in ["a.png", "b.png", "nested/c.png"]
+		{
+			crate::test_utils::noisy_ramps(64, 64).save(dir.join(name)).expect("Failed to write test image");
+		}
+		fs::write(dir.join("text.png"), b"not a png").expect("Failed to write test file");
+		fs::write(dir.join("nested/corrupt.png"), [crate::minify::PNG_SIGNATURE.as_slice(), b"garbage"].concat()).expect("Failed to write test file");
+	}
+	
+	#[test]
+	fn streamed_totals_match_batch_processing()
+	{
+		let dir: PathBuf = scratch_dir("stream");
+		let (batch_dir, stream_dir): (PathBuf, PathBuf) = (dir.join("batch"), dir.join("stream"));
+		write_sample_files(&batch_dir);
+		write_sample_files(&stream_dir);
+		
+		let options: MinifyOptions = MinifyOptions::builder().dithering(DitheringMode::None).fast_deflate(true).build().expect("Valid options");
+		let process = |file: &PngFile| -> Result<ProcessingResult>
+		{
+			process_file(&file.source_path, &file.target_path, &options, false, None, None, None).map(|(result, _)| result)
+		};
+		
+		let batch_files: Vec<PngFile> = find_png_files_in_dir(Some(&batch_dir), true, &[], false, false, true).expect("Scan failed");
+		let mut batch: StreamTotals = StreamTotals::default();
+		for file in &batch_files
+		{
+			batch = batch.add(&process(file));
+		}
+		
+		let (files, walker): (Receiver<PngFile>, JoinHandle<Result<()>>) = stream_png_files(stream_dir, Vec::new(), false, false, true);
+		let streamed: StreamTotals = fold_stream(files, |file: PngFile| Some(process(&file)));
+		finish_walk(walker).expect("Walk failed");
+		
+		assert_eq!((batch.processed, batch.minified, batch.skipped, batch.errors), (4, 3, 1, 1));
+		assert_eq!(streamed, batch);
+		
+		let _ = fs::remove_dir_all(&dir);
+	}
+}
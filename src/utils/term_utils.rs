@@ -0,0 +1,84 @@
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Whether ANSI colors are written to the terminal.
+static COLOR_ENABLED: AtomicBool = AtomicBool::new(false);
+
+// Whether human-readable output goes to stderr, because a machine-readable report (--report) owns stdout.
+static HUMAN_TO_STDERR: AtomicBool = AtomicBool::new(false);
+
+/// Colors used for per-file status labels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StatusColor
+{
+	/// Files that were reduced.
+	Green,
+	
+	/// Files that were skipped or could not be reduced.
+	Yellow,
+	
+	/// Files that failed.
+	Red,
+}
+
+/// Send human-readable output (progress, settings, summary) to stderr instead of stdout.
+/// Call before init_color, which checks the stream the output goes to.
+pub fn set_human_output_to_stderr(enabled: bool)
+{
+	HUMAN_TO_STDERR.store(enabled, Ordering::Relaxed);
+}
+
+/// Checks if human-readable output goes to stderr.
+pub fn human_output_to_stderr() -> bool
+{
+	HUMAN_TO_STDERR.load(Ordering::Relaxed)
+}
+
+/// Decide whether to use colors.
+/// Colors are used only when the human-readable output goes to a terminal, --no-color was not given and NO_COLOR is not set.
+pub fn init_color(no_color: bool)
+{
+	let no_color_env: bool = std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+	let terminal: bool = if human_output_to_stderr() { std::io::stderr().is_terminal() } else { std::io::stdout().is_terminal() };
+	let enabled: bool = !no_color && !no_color_env && terminal;
+	COLOR_ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Wrap text in ANSI color codes when colors are enabled.
+pub fn paint(text: &str, color: StatusColor) -> String
+{
+	if !COLOR_ENABLED.load(Ordering::Relaxed)
+	{
+		return text.to_string();
+	}
+	
+	let code: &str = match color
+	{
+		StatusColor::Green => "32",
+		StatusColor::Yellow => "33",
+		StatusColor::Red => "31",
+	};
+	
+	format!("\x1b[{}m{}\x1b[0m", code, text)
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+	use crate::utils::arg_utils::tests::parse;
+	
+	#[test]
+	fn no_color_output_has_no_escape_sequences()
+	{
+		let args: crate::utils::arg_utils::Args = parse(&["--no-color"]).expect("Failed to parse arguments");
+		init_color(args.no_color);
+		
+		for color in [StatusColor::Green, StatusColor::Yellow, StatusColor::Red]
+		{
+			let label: String = paint("Minified", color);
+			assert_eq!(label, "Minified");
+			assert!(!label.contains('\x1b'));
+		}
+	}
+}
@@ -1,10 +1,20 @@
 /// Returns the current timestamp in ISO 8601 format.
-pub fn get_iso8601_timestamp() -> String
+/// Returns None on wasm32-unknown-unknown, which has no system clock.
+pub fn get_iso8601_timestamp() -> Option<String>
 {
+	if cfg!(all(target_arch = "wasm32", target_os = "unknown"))
+	{
+		return None;
+	}
+	
 	let now: std::time::SystemTime = std::time::SystemTime::now();
 	let datetime: std::time::Duration = now.duration_since(std::time::UNIX_EPOCH).unwrap_or_default();
-	let secs: u64 = datetime.as_secs();
-	
+	Some(format_iso8601(datetime.as_secs()))
+}
+
+/// Format seconds since the Unix epoch as an ISO 8601 UTC timestamp (e.g., "2026-02-06T20:15:30Z").
+pub fn format_iso8601(secs: u64) -> String
+{
 	// Convert to UTC datetime components manually.
 	let days_since_epoch: u64 = secs / 86400;
 	let seconds_in_day: u64 = secs % 86400;
@@ -20,6 +30,75 @@ pub fn get_iso8601_timestamp() -> String
 	format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z", year, month, day, hours, minutes, seconds)
 }
 
+/// Parse an ISO 8601 UTC date or timestamp into seconds since the Unix epoch.
+/// Accepts "YYYY-MM-DD", "YYYY-MM-DDTHH:MM" and "YYYY-MM-DDTHH:MM:SS", each optionally followed by Z.
+/// Returns None for anything else, including dates before 1970.
+pub fn parse_iso8601(text: &str) -> Option<u64>
+{
+	let text: &str = text.trim().trim_end_matches('Z');
+	let (date_part, time_part): (&str, &str) = text.split_once('T').unwrap_or((text, ""));
+	
+	let date: Vec<&str> = date_part.split('-').collect();
+	if date.len() != 3 || date[0].len() != 4 || date[1].len() != 2 || date[2].len() != 2
+	{
+		return None;
+	}
+	let year: u64 = date[0].parse::<u64>().ok()?;
+	let month: u64 = date[1].parse::<u64>().ok()?;
+	let day: u64 = date[2].parse::<u64>().ok()?;
+	if year < 1970 || !(1..=12).contains(&month) || day < 1 || day > days_in_month(year, month)
+	{
+		return None;
+	}
+	
+	let mut seconds_in_day: u64 = 0;
+	if !time_part.is_empty()
+	{
+		let time: Vec<&str> = time_part.split(':').collect();
+		if time.len() < 2 || time.len() > 3 || time.iter().any(|part| part.len() != 2)
+		{
+			return None;
+		}
+		let hours: u64 = time[0].parse::<u64>().ok()?;
+		let minutes: u64 = time[1].parse::<u64>().ok()?;
+		let seconds: u64 = match time.get(2)
+		{
+			Some(part) => part.parse::<u64>().ok()?,
+			None => 0,
+		};
+		if hours > 23 || minutes > 59 || seconds > 59
+		{
+			return None;
+		}
+		seconds_in_day = hours * 3600 + minutes * 60 + seconds;
+	}
+	
+	// Count the days before this date, the inverse of convert_days_to_date.
+	let mut days: u64 = 0;
+	for y in 1970..year
+	{
+		days += if is_leap_year(y) { 366 } else { 365 };
+	}
+	for m in 1..month
+	{
+		days += days_in_month(year, m);
+	}
+	days += day - 1;
+	
+	Some(days * 86400 + seconds_in_day)
+}
+
+/// Number of days in a month (1-12) of a year.
+fn days_in_month(year: u64, month: u64) -> u64
+{
+	match month
+	{
+		2 => if is_leap_year(year) { 29 } else { 28 },
+		4 | 6 | 9 | 11 => 30,
+		_ => 31,
+	}
+}
+
 /// Convert days since Unix epoch (Jan 1, 1970) to (year, month, day).
 fn convert_days_to_date(days_since_epoch: u64) -> (u32, u32, u32)
 {
@@ -67,12 +146,39 @@ fn convert_days_to_date(days_since_epoch: u64) -> (u32, u32, u32)
 }
 
 /// Check if a year is a leap year.
+#[allow(clippy::manual_is_multiple_of)]
 fn is_leap_year(year: u64) -> bool
 {
 	(year % 4 == 0 && year % 100 != 0) || (year % 400 == 0)
 }
 
+/// Format an elapsed duration in a human-readable way (e.g., "850 ms", "12.4 s", "3m 07s").
+pub fn format_duration(duration: std::time::Duration) -> String
+{
+	let total_secs: u64 = duration.as_secs();
+	if total_secs == 0
+	{
+		return format!("{} ms", duration.as_millis());
+	}
+	
+	if total_secs < 60
+	{
+		return format!("{:.1} s", duration.as_secs_f64());
+	}
+	
+	let hours: u64 = total_secs / 3600;
+	let minutes: u64 = (total_secs % 3600) / 60;
+	let seconds: u64 = total_secs % 60;
+	if hours > 0
+	{
+		return format!("{}h {:02}m {:02}s", hours, minutes, seconds);
+	}
+	
+	format!("{}m {:02}s", minutes, seconds)
+}
+
 /// Format ISO timestamp to human-readable format.
+#[allow(clippy::collapsible_if)]
 pub fn format_timestamp(iso_timestamp: &str) -> String
 {
 	// Parse ISO 8601 timestamp (e.g., "2026-02-06T20:15:30Z").